@@ -47,3 +47,26 @@ view-offset = Camera Offset [ { $x }, { $y } ]
 cursor-position = Cursor Position [ { $x }, { $y } ]
 view-reset = Reset View
 show-grid = Show Grid
+
+select = Select
+rename = Rename
+undo = Undo
+redo = Redo
+map-editor-select-hint = Click an element to select it, and drag it to move it. Press Delete to delete it.
+map-editor-tiles-hint = Left click to paint the selected tile, right click to erase tiles.
+map-editor-elements-hint = Left click to place the selected element, right click to delete elements.
+map-editor-view-hint = Drag with the middle mouse button to move the view, and scroll to zoom.
+map-editor-no-selection = No element selected.
+map-editor-no-tilemap = Pick a tilemap to paint the tiles of this layer.
+map-saved = Saved to { $path }
+map-save-failed = Couldn't save the map: { $error }
+
+map-check = Check Map
+map-check-ok = No problems found.
+map-issue-too-few-spawners = The map has { $count } player spawners, but there may be { $players } players.
+map-issue-spawner-in-solid-tile = The player spawner at [ { $x }, { $y } ] is inside of a solid tile.
+map-issue-unreachable-spawner = The player spawner at [ { $x }, { $y } ] can't be reached from the other spawners.
+map-issue-element-out-of-bounds = { $element } at [ { $x }, { $y } ] is outside of the map.
+map-issue-tile-out-of-range = The tile at [ { $x }, { $y } ] on { $layer } uses tile { $idx }, but its tileset only has { $count } tiles.
+map-issue-missing-element = The element at [ { $x }, { $y } ] on { $layer } isn't loaded.
+map-issue-missing-tilemap = The tileset of { $layer } isn't loaded.
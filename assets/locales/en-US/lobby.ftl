@@ -0,0 +1,16 @@
+lobby = Lobby
+lobby-maps = Maps
+lobby-chat = Chat
+lobby-send = Send
+lobby-ready = Ready
+lobby-not-ready = Not Ready
+lobby-unready = Unready
+lobby-start-match = Start Match
+lobby-waiting-for-host = Waiting for the host to start the match...
+lobby-leave = Leave
+lobby-player = Player { $player }
+lobby-spectator = Spectator { $spectator }
+lobby-left-match = Left the Match
+lobby-downloading-map = Downloading map: { $progress }%
+lobby-ping = Ping: { $ping } ms
+lobby-network-tuning = Input delay: { $delay } frames, prediction window: { $window } frames
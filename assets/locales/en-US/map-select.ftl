@@ -4,4 +4,13 @@ waiting-for-map = Waiting for other player to select map...
 default-maps = Default Maps
 experimental-maps = Experimental Maps
 user-maps = User Maps
-builtin-maps = Builtin Maps
\ No newline at end of file
+builtin-maps = Builtin Maps
+match-rules = Match Rules
+lives = Lives
+time-limit = Time Limit
+no-time-limit = None
+sudden-death = Sudden Death
+time-up = Time Up
+friendly-fire = Friendly Fire
+on = On
+off = Off
@@ -8,6 +8,9 @@ map-editor = Map Editor
 settings = Settings
 paused = Paused
 credits = Credits
+replays = Replays
+no-replays = No replays saved yet.
+replays-one-round = A replay only holds the round that was playing when it was saved.
 
 # Actions
 close = Close
@@ -22,3 +25,4 @@ quit = Quit
 export = Export
 reload = Reload
 restart = Restart
+save-replay = Save Round Replay
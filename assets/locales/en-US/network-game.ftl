@@ -4,6 +4,7 @@ host = Host
 join = Join
 servers = Servers
 players = Players
+joined = Joined
 no-servers = No Servers
 server-name = Server Name
 start-server = Start Server
@@ -11,6 +12,8 @@ stop-server = Stop Server
 fish-fight = Fish Fight
 configure-match = Configure Match
 player-count = Player Count
+spectator-count = Spectator Slots
+spectate = Spectate
 search = Search
 searching = Searching...
 search-for-match = Search for Match
@@ -25,3 +28,4 @@ error = Error
 disconnected = Disconnected
 disconnected-from-all = Disconnected from all other players.
 exit-match = Exit Match
+player-left-ai-takeover = Player { $player } left the match, an AI player took over.
@@ -6,6 +6,8 @@ pick-a-hat = Pick a Hat
 
 player-select-ready = Ready!
 player-select-title = Player Select
+spectating-match = You are spectating this match. Press jump during the match to change the camera.
+spectating-in-session = You are spectating this match. You joined before some of the players, so the match waits for you like it waits for them.
 player-select-unready = Press { $button } to Unready
 
 press-button-to-join = Press { $button } to Join
@@ -17,3 +19,22 @@ press-button-to-remove = Press { $button } to Remove
 add-ai-player = Add AI Player
 remove-ai-player = Remove AI Player
 ai-player = AI Player
+
+teams = Teams
+team-red = Red Team
+team-blue = Blue Team
+switch-team = Switch Team
+teams-need-opponents = Put at least one player on each team.
+
+ai-difficulty = Difficulty
+ai-difficulty-easy = Easy
+ai-difficulty-normal = Normal
+ai-difficulty-hard = Hard
+ai-difficulty-custom = Custom
+ai-reaction-time = Reaction Time
+ai-pause-chance = Hesitation
+ai-speed = Speed
+ai-aim-accuracy = Aim
+ai-item-usage = Item Usage
+ai-behavior = Behavior
+ai-behavior-built-in = Built-in
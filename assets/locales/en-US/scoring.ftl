@@ -10,3 +10,26 @@ score = Score
 
 match-complete = Match Complete
 tied-for-win = Tied For Win
+rounds-played = { $rounds ->
+    [one] { $rounds } round played
+   *[other] { $rounds } rounds played
+}
+rounds-won = Rounds Won
+podium-place = { $place ->
+    [1] 1st
+    [2] 2nd
+    [3] 3rd
+   *[other] { $place }th
+}
+rematch = Rematch
+change-players = Change Players
+player-left-match = A player has left the match.
+rematch-waits-for-all-players = The rematch starts once every player has chosen to rematch.
+kills-deaths = K/D: { $kills }/{ $deaths }
+suicides = Suicides
+items-picked-up = Items Picked Up
+shots-fired = Shots Fired
+favorite-element = { $element }: { $kills ->
+    [one] { $kills } kill
+   *[other] { $kills } kills
+}
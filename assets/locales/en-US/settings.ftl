@@ -18,6 +18,10 @@ action = Action
 # Networking settings
 networking = Networking
 matchmaking-server = Matchmaking Server
+input-delay = Input Delay
+prediction-window = Prediction Window
+network-tuning-auto = Auto
+network-tuning-hint = Auto picks the value from the ping to the other players when the match starts.
 
 # Audio settings
 audio = Audio
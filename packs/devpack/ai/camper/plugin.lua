@@ -0,0 +1,38 @@
+local Entities = s"Entities"
+local Transform = s"Transform"
+local GlobalRng = s"GlobalRng"
+local LuaAiPlayer = s"LuaAiPlayer"
+local CamperMeta = s"CamperMeta"
+
+-- The camper holds the position that it spawned at, shooting and jumping at random.
+local function update()
+  local entities = resources:get(Entities)
+  local rng = resources:get(GlobalRng)
+
+  for ent, ai in entities:iter_with(LuaAiPlayer) do
+    local behavior = assets:get(ai.behavior)
+    local camper_meta = assets:get(behavior.data)
+
+    if schema_of(camper_meta) == CamperMeta then
+      -- Camp where we spawned
+      if not ai.has_goal then
+        local trans = components:get(ent, Transform)
+        ai.goal.x = trans.translation.x
+        ai.goal.y = trans.translation.y
+        ai.has_goal = true
+      end
+
+      -- Walk back to the camping spot if we've been knocked off of it
+      local control = ai.control
+      local path_control = ai.path_control
+      control.left = path_control.left
+      control.right = path_control.right
+      control.up = path_control.up
+      control.down = path_control.down
+      control.jump_pressed = path_control.jump_pressed or rng:f32() < camper_meta.jump_chance
+      control.shoot_pressed = rng:f32() < camper_meta.shoot_chance
+    end
+  end
+end
+
+session:add_system_to_stage(CoreStage.First, update)
@@ -104,6 +104,7 @@ local function update()
             
                 local bullet = Bullet:create()
                 bullet.owner = player_ent
+                bullet.source = element_handle[0]
                 local direction = Vec2:create()
                 if player_sprite.flip_x then
                     direction.x = -1
@@ -1,6 +1,7 @@
 pub mod attachment;
 pub mod bullet;
 pub mod camera;
+pub mod checksum;
 pub mod damage;
 pub mod debug;
 pub mod editor;
@@ -12,11 +13,13 @@ pub mod lifetime;
 pub mod map;
 pub mod map_constructor;
 pub mod map_pool;
+pub mod match_rules;
 pub mod metadata;
 pub mod physics;
 pub mod player;
 pub mod random;
 pub mod scoring;
+pub mod teams;
 pub mod utils;
 pub mod win_indicator;
 
@@ -24,7 +27,7 @@ pub mod win_indicator;
 pub const FPS: f32 = 60.0;
 
 /// The maximum number of players per match.
-pub const MAX_PLAYERS: u32 = 4;
+pub const MAX_PLAYERS: u32 = 8;
 
 use std::time::Duration;
 
@@ -32,10 +35,10 @@ use crate::{prelude::*, settings::PlayerControlMapping};
 
 pub mod prelude {
     pub use super::{
-        attachment::*, bullet::*, camera::*, damage::*, debug::*, editor::*, elements::prelude::*,
-        flappy_jellyfish::*, globals::*, input::*, item::*, lifetime::*, map::*,
-        map_constructor::*, map_pool::*, metadata::*, physics::*, player::*, random::*, scoring::*,
-        utils::*, win_indicator::*, FPS, MAX_PLAYERS,
+        attachment::*, bullet::*, camera::*, checksum::*, damage::*, debug::*, editor::*,
+        elements::prelude::*, flappy_jellyfish::*, globals::*, input::*, item::*, lifetime::*,
+        map::*, map_constructor::*, map_pool::*, match_rules::*, metadata::*, physics::*,
+        player::*, random::*, scoring::*, teams::*, utils::*, win_indicator::*, FPS, MAX_PLAYERS,
     };
 }
 
@@ -43,6 +46,7 @@ pub fn game_plugin(game: &mut Game) {
     PlayerMeta::register_schema();
     AudioSource::register_schema();
     HatMeta::register_schema();
+    AiBehaviorMeta::register_schema();
     MapMeta::register_schema();
     game.install_plugin(elements::game_plugin)
         .install_plugin(bullet::game_plugin)
@@ -61,6 +65,12 @@ pub struct MatchPlugin {
     /// should be inputted from previous session resourc.
     pub score: MatchScore,
 
+    /// The rules the match is played with.
+    pub rules: MatchRules,
+
+    /// The seed of the [`GlobalRng`]. Network matches must use the same seed on every peer.
+    pub random_seed: u64,
+
     pub session_runner: Box<dyn SessionRunner>,
 }
 
@@ -79,6 +89,10 @@ impl SessionPlugin for MatchPlugin {
 
         physics::install(session);
         input::install(session);
+        #[cfg(not(target_arch = "wasm32"))]
+        crate::replay::session_plugin(session);
+        #[cfg(not(target_arch = "wasm32"))]
+        crate::spectate::session_plugin(session);
         map::install(session);
         player::plugin(session);
         elements::session_plugin(session);
@@ -91,7 +105,10 @@ impl SessionPlugin for MatchPlugin {
         attachment::install(session);
         bullet::session_plugin(session);
         editor::install(session);
+        match_rules::install(session);
         scoring::session_plugin(session);
+        // Installed last so that the checksum includes the changes of every other system.
+        checksum::install(session);
 
         let current_map = self.maps.current_map;
         session.insert_resource(self.maps);
@@ -109,6 +126,9 @@ impl SessionPlugin for MatchPlugin {
             players: self.player_info,
         });
         session.insert_resource(self.score);
+        session.insert_resource(self.rules);
+        session.insert_resource(GlobalRng::with_seed(self.random_seed));
+        session.insert_resource(RandomSeed(self.random_seed));
         session.set_session_runner(self.session_runner);
     }
 }
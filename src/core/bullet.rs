@@ -28,6 +28,8 @@ pub struct Bullet {
     pub direction: Vec2,
     /// The player entity that shot the bullet.
     pub owner: Entity,
+    /// The element that shot the bullet, credited with the kill if it hits a player.
+    pub source: Handle<ElementMeta>,
 }
 
 #[derive(HasSchema, Clone, Debug, Default)]
@@ -60,6 +62,9 @@ fn hydrate(
     mut lifetimes: CompMut<Lifetime>,
     mut atlas_sprites: CompMut<AtlasSprite>,
     bullet_handles: Comp<BulletHandle>,
+    bullets: Comp<Bullet>,
+    player_indexes: Comp<PlayerIdx>,
+    mut score: ResMutInit<MatchScore>,
     assets: Res<AssetServer>,
 ) {
     // We consider all entities with bullet handles, but that don't have physics actors on them to
@@ -97,6 +102,14 @@ fn hydrate(
         );
 
         lifetimes.insert(entity, Lifetime::new(bullet_meta.lifetime));
+
+        // Count the shot in the owner's statistics
+        if let Some(idx) = bullets
+            .get(entity)
+            .and_then(|bullet| player_indexes.get(bullet.owner))
+        {
+            score.stats_mut(*idx).shots_fired += 1;
+        }
     }
 }
 
@@ -113,6 +126,8 @@ fn update(
     invincibles: CompMut<Invincibility>,
     mut emote_regions: CompMut<EmoteRegion>,
     asset_server: Res<AssetServer>,
+    rules: Res<MatchRules>,
+    player_inputs: Res<MatchInputs>,
     time: Res<Time>,
 ) {
     for (entity, (bullet, bullet_handle)) in entities.iter_with((&mut bullets, &bullet_handles)) {
@@ -150,9 +165,23 @@ fn update(
             })
             .into_iter()
             .filter(|player| *player != bullet.owner)
+            // Bullets pass through teammates unless friendly fire is on.
+            .filter(|player| {
+                match (
+                    player_indexes.get(*player),
+                    player_indexes.get(bullet.owner),
+                ) {
+                    (Some(victim), Some(owner)) => rules.can_kill(&player_inputs, *owner, *victim),
+                    _ => true,
+                }
+            })
             .for_each(|player| {
                 hit_player = true;
-                commands.add(PlayerCommand::kill(player, Some(position.translation.xy())));
+                commands.add(PlayerCommand::kill_by(
+                    player,
+                    Some(position.translation.xy()),
+                    KillSource::new(Some(bullet.owner), Some(bullet.source)),
+                ));
             });
 
         // check solid tile collisions
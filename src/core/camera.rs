@@ -1,6 +1,11 @@
 //! Camera controller and parallax.
 
 use crate::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::spectate::Spectating;
+
+/// How fast the free spectator camera moves, in pixels per frame.
+const FREE_CAMERA_SPEED: f32 = 6.0;
 
 /// Install this module.
 pub fn install(session: &mut SessionBuilder) {
@@ -45,6 +50,45 @@ pub struct CameraState {
     pub disable_controller: bool,
 }
 
+/// The camera of a peer that is spectating a network match instead of playing.
+///
+/// The spectator cycles through the camera modes by pressing jump, and moves the free camera
+/// around with the movement controls. Their controls aren't part of the match inputs, the camera
+/// isn't part of the simulation.
+#[derive(Clone, Copy, Debug, Default, HasSchema)]
+pub struct SpectatorCamera {
+    pub mode: SpectatorCameraMode,
+}
+
+/// How the camera of a spectator moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpectatorCameraMode {
+    /// Keep all of the players in view, like the players' camera does.
+    #[default]
+    Overview,
+    /// Keep a single player in view.
+    Follow(PlayerIdx),
+    /// Move the camera with the spectator's movement controls.
+    Free,
+}
+
+impl SpectatorCameraMode {
+    /// Get the mode that comes after this one: the overview, then following each of the players,
+    /// then the free camera.
+    pub fn next(self, player_inputs: &MatchInputs) -> Self {
+        let mut players = (0..MAX_PLAYERS)
+            .filter(|i| player_inputs.players[*i as usize].active)
+            .map(PlayerIdx);
+        match self {
+            Self::Overview => players.next().map_or(Self::Free, Self::Follow),
+            Self::Follow(followed) => players
+                .find(|player| player.0 > followed.0)
+                .map_or(Self::Free, Self::Follow),
+            Self::Free => Self::Overview,
+        }
+    }
+}
+
 /// Implemenets the camera controller.
 fn camera_controller(
     meta: Root<GameMeta>,
@@ -56,10 +100,26 @@ fn camera_controller(
     mut camera_subjects: CompMut<CameraSubject>,
     transforms: Comp<Transform>,
     bodies: Comp<KinematicBody>,
+    player_indices: Comp<PlayerIdx>,
+    player_inputs: Res<MatchInputs>,
+    mut spectator_camera: ResMutInit<SpectatorCamera>,
     window: Res<Window>,
+    #[cfg(not(target_arch = "wasm32"))] spectating: Option<Res<Spectating>>,
 ) {
     let meta = &meta.core.camera;
 
+    #[cfg(not(target_arch = "wasm32"))]
+    let spectator_control = spectating.map(|spectating| spectating.control);
+    #[cfg(target_arch = "wasm32")]
+    let spectator_control = None::<PlayerControl>;
+
+    if let Some(control) = &spectator_control {
+        if control.jump_just_pressed {
+            spectator_camera.mode = spectator_camera.mode.next(&player_inputs);
+        }
+    }
+    let spectator_mode = spectator_control.map(|_| spectator_camera.mode);
+
     let Some((_ent, (camera, camera_shake, camera_state))) = entities
         .iter_with((&mut cameras, &mut camera_shakes, &camera_states))
         .next()
@@ -128,10 +188,32 @@ fn camera_controller(
     let mut scale = camera_height / default_height;
     let map_size = map.grid_size.as_vec2() * map.tile_size;
 
+    let camera_pos = &mut camera_shake.center;
+
+    // Move the free spectator camera without zooming.
+    if spectator_mode == Some(SpectatorCameraMode::Free) {
+        let movement = spectator_control.unwrap().move_direction * FREE_CAMERA_SPEED;
+        camera_pos.x = (camera_pos.x + movement.x).clamp(0.0, map_size.x);
+        camera_pos.y = (camera_pos.y + movement.y).clamp(0.0, map_size.y);
+        camera.size = CameraSize::FixedHeight(scale * default_height);
+        return;
+    }
+    let followed_player = match spectator_mode {
+        Some(SpectatorCameraMode::Follow(player)) => Some(player),
+        _ => None,
+    };
+
     let mut min = Vec2::MAX;
     let mut max = Vec2::MIN;
+    let mut subject_count = 0;
 
-    for CameraSubject { rect } in camera_subjects.iter_mut() {
+    for (_ent, (CameraSubject { rect }, player_idx)) in
+        entities.iter_with((&camera_subjects, &Optional(&player_indices)))
+    {
+        if followed_player.is_some() && player_idx != followed_player.as_ref() {
+            continue;
+        }
+        subject_count += 1;
         min = (rect.min - vec2(meta.border_left, meta.border_bottom))
             .min(min)
             .max(Vec2::ZERO);
@@ -139,9 +221,6 @@ fn camera_controller(
         max.x = max.x.min(map_size.x)
     }
 
-    let camera_pos = &mut camera_shake.center;
-
-    let subject_count = camera_subjects.iter().count();
     let mut middle_point = if subject_count == 0 {
         camera_pos.truncate()
     } else {
@@ -151,6 +230,14 @@ fn camera_controller(
     let size = max - min;
     let size = size.max(meta.min_camera_size);
 
+    // However spread out the players are, don't zoom out further than needed to show the whole map.
+    let map_view_size = map_size
+        + vec2(
+            meta.border_left + meta.border_right,
+            meta.border_bottom + meta.border_top,
+        );
+    let size = size.min(map_view_size.max(meta.min_camera_size));
+
     let rh = size.y / default_height;
     let rw = size.x / default_width;
     let r_target = if rh > rw { rh } else { rw };
@@ -0,0 +1,323 @@
+//! Per-frame checksum of the simulation state, used to detect desyncs.
+//!
+//! Every frame, the simulation relevant components and resources are hashed into the
+//! [`FrameChecksum`] resource, which session runners may read after stepping the world. In network
+//! play the checksums of confirmed frames are exchanged with the other players, and the first frame
+//! on which they differ is logged.
+
+use std::{
+    collections::VecDeque,
+    hash::{Hash, Hasher},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use bones_framework::networking::{NetworkSocket, SocketTarget, SyncingInfo};
+use fxhash::FxHasher64;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::network_messages::{ReliableMessage, ReliableMessages};
+use crate::prelude::*;
+
+/// Checksums of confirmed frames are exchanged in network play every this many frames.
+pub const CHECKSUM_EXCHANGE_INTERVAL: i32 = 30;
+
+/// The number of frames to keep checksums for in the [`ChecksumHistory`]. Must be larger than the
+/// maximum prediction window.
+pub const CHECKSUM_HISTORY_LEN: usize = 64;
+
+pub fn install(session: &mut SessionBuilder) {
+    session
+        .init_resource::<FrameChecksum>()
+        .init_resource::<ChecksumHistory>()
+        .add_system_to_stage(CoreStage::Last, update_checksum);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    session
+        .init_resource::<DesyncDetector>()
+        .add_system_to_stage(CoreStage::Last, exchange_checksums);
+}
+
+/// The checksum of the simulation state at the end of the last frame.
+#[derive(HasSchema, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct FrameChecksum {
+    /// The simulation frame the checksum was taken on. In network play this is the network frame.
+    pub frame: i32,
+    pub checksum: u64,
+}
+
+/// The checksums of the most recent frames.
+#[derive(HasSchema, Clone, Default, Deref, DerefMut)]
+pub struct ChecksumHistory(pub VecDeque<FrameChecksum>);
+
+impl ChecksumHistory {
+    /// Get the checksum recorded for the given frame, if it is still in the history.
+    pub fn checksum_for_frame(&self, frame: i32) -> Option<u64> {
+        self.iter()
+            .find(|entry| entry.frame == frame)
+            .map(|entry| entry.checksum)
+    }
+}
+
+/// Hash the simulation state for the current frame.
+///
+/// The checksums are compared between peers that may run different builds of the game, so the
+/// hasher must give the same output on every platform and with every Rust release.
+fn update_checksum(
+    entities: Res<Entities>,
+    transforms: Comp<Transform>,
+    kinematic_bodies: Comp<KinematicBody>,
+    player_states: Comp<PlayerState>,
+    inventories: Comp<Inventory>,
+    cameras: Comp<Camera>,
+    rng: Res<GlobalRng>,
+    score: Res<MatchScore>,
+    mut checksum: ResMut<FrameChecksum>,
+    mut history: ResMut<ChecksumHistory>,
+    #[cfg(not(target_arch = "wasm32"))] syncing_info: Option<Res<SyncingInfo>>,
+    #[cfg(not(target_arch = "wasm32"))] mut recorder: ResMut<crate::replay::ReplayRecorder>,
+) {
+    let mut hasher = FxHasher64::default();
+
+    for (ent, (transform, body, state, inventory)) in entities.iter_with((
+        &Optional(&transforms),
+        &Optional(&kinematic_bodies),
+        &Optional(&player_states),
+        &Optional(&inventories),
+    )) {
+        if transform.is_none() && body.is_none() && state.is_none() && inventory.is_none() {
+            continue;
+        }
+        // The camera isn't simulation state, spectators move it around on their own.
+        if cameras.contains(ent) {
+            continue;
+        }
+        (ent.index(), ent.generation()).hash(&mut hasher);
+
+        if let Some(transform) = transform {
+            hash_floats(&mut hasher, &transform.translation.to_array());
+            hash_floats(&mut hasher, &transform.rotation.to_array());
+            hash_floats(&mut hasher, &transform.scale.to_array());
+        }
+        if let Some(body) = body {
+            hash_floats(&mut hasher, &body.velocity.to_array());
+            hash_floats(&mut hasher, &[body.angular_velocity]);
+            (body.is_on_ground, body.is_on_platform, body.is_deactivated).hash(&mut hasher);
+        }
+        if let Some(state) = state {
+            (state.current.as_str(), state.age, state.last.as_str()).hash(&mut hasher);
+        }
+        if let Some(inventory) = inventory {
+            inventory
+                .map(|item| (item.index(), item.generation()))
+                .hash(&mut hasher);
+        }
+    }
+
+    // Sample a clone of the generator, so that hashing does not advance the real one.
+    let rng: AtomicRng = (**rng).clone();
+    rng.u64(..).hash(&mut hasher);
+
+    for i in 0..MAX_PLAYERS {
+        score.score(PlayerIdx(i)).hash(&mut hasher);
+    }
+    score.rounds_completed().hash(&mut hasher);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let frame = match syncing_info {
+        Some(syncing_info) => syncing_info.current_frame(),
+        None => checksum.frame + 1,
+    };
+    #[cfg(target_arch = "wasm32")]
+    let frame = checksum.frame + 1;
+
+    *checksum = FrameChecksum {
+        frame,
+        checksum: hasher.finish(),
+    };
+
+    // The frame may be re-simulated after a rollback, so replace any older checksum for it.
+    history.retain(|entry| entry.frame < frame);
+    history.push_back(*checksum);
+    while history.len() > CHECKSUM_HISTORY_LEN {
+        history.pop_front();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    recorder.record_checksum(checksum.checksum);
+}
+
+/// Hash floats by their bit patterns, since floats don't implement [`Hash`].
+fn hash_floats(hasher: &mut impl Hasher, floats: &[f32]) {
+    for float in floats {
+        float.to_bits().hash(hasher);
+    }
+}
+
+/// Network message containing the checksum of a confirmed frame.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Serialize, Deserialize)]
+pub struct ChecksumMessage {
+    /// The number of rounds completed before the round of the frame. Frames are counted from the
+    /// start of each round, so checksums from another round are ignored.
+    pub round: u32,
+    pub frame: i32,
+    pub checksum: u64,
+}
+
+/// Compares the checksums of confirmed frames with the other players in network play.
+///
+/// The state is shared between clones of the resource so that it is not rolled back.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(HasSchema, Clone, Default)]
+pub struct DesyncDetector(Arc<std::sync::Mutex<DesyncDetectorState>>);
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct DesyncDetectorState {
+    /// The last frame we sent the checksum for.
+    last_sent_frame: i32,
+    /// Our checksums for confirmed frames.
+    local: HashMap<i32, u64>,
+    /// Checksums for confirmed frames received from other players.
+    remote: HashMap<(u32, i32), u64>,
+    /// The first frame a desync was detected on.
+    desync_frame: Option<i32>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DesyncDetector {
+    /// The first frame on which our state differed from another player's, if any.
+    pub fn desync_frame(&self) -> Option<i32> {
+        self.0.lock().unwrap().desync_frame
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn exchange_checksums(
+    history: Res<ChecksumHistory>,
+    detector: Res<DesyncDetector>,
+    score: Res<MatchScore>,
+    messages: ResInit<ReliableMessages>,
+    syncing_info: Option<Res<SyncingInfo>>,
+) {
+    let Some(syncing_info) = syncing_info else {
+        return;
+    };
+    let Some(socket) = syncing_info.socket() else {
+        return;
+    };
+    let round = score.rounds_completed();
+
+    let mut state = detector.0.lock().unwrap();
+    let last_confirmed_frame = syncing_info.last_confirmed_frame();
+
+    // Send checksums for frames that have been confirmed since the last exchange.
+    for entry in history.iter() {
+        if entry.frame > last_confirmed_frame
+            || entry.frame <= state.last_sent_frame
+            || entry.frame % CHECKSUM_EXCHANGE_INTERVAL != 0
+        {
+            continue;
+        }
+        state.last_sent_frame = entry.frame;
+        state.local.insert(entry.frame, entry.checksum);
+        socket.send_reliable(
+            SocketTarget::All,
+            &ReliableMessage::Checksum(ChecksumMessage {
+                round,
+                frame: entry.frame,
+                checksum: entry.checksum,
+            })
+            .to_bytes(),
+        );
+    }
+
+    messages.receive(socket.recv_reliable());
+    for (player, message) in messages.take_checksum() {
+        if message.round == round {
+            state
+                .remote
+                .insert((player, message.frame), message.checksum);
+        }
+    }
+
+    // Compare the checksums we have from both sides.
+    let state = &mut *state;
+    let mut compared = Vec::new();
+    for (&(player, frame), &remote_checksum) in &state.remote {
+        let Some(&local_checksum) = state.local.get(&frame) else {
+            continue;
+        };
+        compared.push((player, frame));
+        if local_checksum != remote_checksum && state.desync_frame.map_or(true, |f| frame < f) {
+            error!(
+                "Desync detected on frame {frame}: our checksum {local_checksum:#018x} does not \
+                match player {player}'s checksum {remote_checksum:#018x}."
+            );
+            state.desync_frame = Some(frame);
+        }
+    }
+    for key in compared {
+        state.remote.remove(&key);
+    }
+    state
+        .local
+        .retain(|frame, _| *frame > last_confirmed_frame - CHECKSUM_HISTORY_LEN as i32 * 4);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Create a world with two moving bodies.
+    fn test_world() -> (World, Vec<Entity>) {
+        let mut world = World::new();
+        world.insert_resource(Entities::default());
+        world.insert_resource(GlobalRng::default());
+        world.insert_resource(MatchScore::default());
+        world.insert_resource(FrameChecksum::default());
+        world.insert_resource(ChecksumHistory::default());
+        #[cfg(not(target_arch = "wasm32"))]
+        world.insert_resource(crate::replay::ReplayRecorder::default());
+
+        let mut bodies = Vec::new();
+        for i in 0..2 {
+            let ent = world.resource_mut::<Entities>().create();
+            bodies.push(ent);
+            world.components.get::<Transform>().borrow_mut().insert(
+                ent,
+                Transform::from_translation(vec3(i as f32 * 10.0, 0.0, 0.0)),
+            );
+            world.components.get::<KinematicBody>().borrow_mut().insert(
+                ent,
+                KinematicBody {
+                    velocity: vec2(1.0, -2.0),
+                    ..default()
+                },
+            );
+        }
+        (world, bodies)
+    }
+
+    fn checksum(world: &World) -> u64 {
+        world.run_system(update_checksum, ());
+        world.resource::<FrameChecksum>().checksum
+    }
+
+    #[test]
+    fn checksum_only_changes_with_the_simulation_state() {
+        let (a, _) = test_world();
+        let (b, bodies) = test_world();
+        assert_eq!(checksum(&a), checksum(&b));
+
+        // Move one of the bodies of the second world.
+        b.components
+            .get::<Transform>()
+            .borrow_mut()
+            .get_mut(bodies[1])
+            .unwrap()
+            .translation
+            .y += 0.5;
+        assert_ne!(checksum(&a), checksum(&b));
+    }
+}
@@ -10,6 +10,7 @@ use super::utils::Rect;
 pub fn install(session: &mut SessionBuilder) {
     DamageRegion::register_schema();
     DamageRegionOwner::register_schema();
+    KillSource::register_schema();
 
     session.add_system_to_stage(CoreStage::PostUpdate, kill_players_in_damage_region);
 }
@@ -40,6 +41,24 @@ impl DamageRegion {
 #[repr(C)]
 pub struct DamageRegionOwner(pub Entity);
 
+/// What caused a player to be killed, used to attribute kills in the [`MatchScore`] statistics.
+///
+/// May be added to a damage region or bullet entity to attribute the kills it causes.
+#[derive(Debug, Clone, Copy, HasSchema, Default)]
+pub struct KillSource {
+    /// The entity of the player responsible for the kill.
+    pub player: Option<Entity>,
+    /// The element that did the killing.
+    pub element: Option<Handle<ElementMeta>>,
+}
+
+impl KillSource {
+    /// A kill caused by the given player, with the given element.
+    pub fn new(player: Option<Entity>, element: Option<Handle<ElementMeta>>) -> Self {
+        Self { player, element }
+    }
+}
+
 /// System that will eliminate players that are intersecting with a damage region.
 fn kill_players_in_damage_region(
     entities: Res<Entities>,
@@ -48,6 +67,7 @@ fn kill_players_in_damage_region(
     transforms: Comp<Transform>,
     damage_regions: Comp<DamageRegion>,
     damage_region_owners: Comp<DamageRegionOwner>,
+    kill_sources: Comp<KillSource>,
     bodies: Comp<KinematicBody>,
     invincibles: CompMut<Invincibility>,
 ) {
@@ -73,9 +93,16 @@ fn kill_players_in_damage_region(
 
             let damage_rect = damage_region.collider_rect(transform.translation);
             if player_rect.overlaps(&damage_rect) {
-                commands.add(PlayerCommand::kill(
+                // Attribute the kill to the owner of the damage region if it doesn't say otherwise.
+                let mut source = kill_sources.get(ent).copied().unwrap_or_default();
+                if source.player.is_none() {
+                    source.player = owner.map(|owner| owner.0);
+                }
+
+                commands.add(PlayerCommand::kill_by(
                     player_ent,
                     Some(transform.translation.xy()),
+                    source,
                 ));
             }
         }
@@ -5,6 +5,9 @@
 use crate::core::map_constructor::{shiftnanigans::ShiftnanigansMapConstructor, MapConstructor};
 use crate::prelude::*;
 
+/// The number of edits that can be undone.
+const MAX_EDIT_HISTORY: usize = 200;
+
 /// Install this module.
 pub fn install(session: &mut SessionBuilder) {
     session
@@ -27,6 +30,7 @@ impl_system_param! {
         spawned_map_meta: ResMutInit<'a, SpawnedMapMeta>,
         element_handles: CompMut<'a, ElementHandle>,
         transforms: CompMut<'a, Transform>,
+        element_spawn_positions: CompMut<'a, ElementSpawnPos>,
         spawned_map_layer_metas: CompMut<'a, SpawnedMapLayerMeta>,
         tile_layers: CompMut<'a, TileLayer>,
         tiles: CompMut<'a, Tile>,
@@ -34,17 +38,261 @@ impl_system_param! {
         map: Res<'a, LoadedMap>,
         element_kill_callbacks: Comp<'a, ElementKillCallback>,
         spawner_manager: SpawnerManager<'a>,
+        nav_graph_outdated: ResMutInit<'a, NavGraphOutdated>,
+        history: ResMutInit<'a, MapEditHistory>,
+    }
+}
+
+/// A change made to the map by the [`MapManager`], along with what is needed to revert it.
+#[derive(Clone, Debug)]
+pub enum MapEdit {
+    CreateElement {
+        entity: Entity,
+        handle: Handle<ElementMeta>,
+        pos: Vec2,
+        layer: u32,
+    },
+    DeleteElement {
+        entity: Entity,
+        handle: Handle<ElementMeta>,
+        pos: Vec2,
+        layer: u32,
+    },
+    MoveElement {
+        entity: Entity,
+        from: Vec2,
+        to: Vec2,
+    },
+    /// Insert an empty layer. The tiles and elements of a deleted layer are recorded as separate
+    /// edits before the layer is removed.
+    InsertLayer {
+        layer: u32,
+        name: Ustr,
+    },
+    RemoveLayer {
+        layer: u32,
+        name: Ustr,
+    },
+    RenameLayer {
+        layer: u32,
+        from: Ustr,
+        to: Ustr,
+    },
+    SwapLayers {
+        layer: u32,
+        down: bool,
+    },
+    SetTilemap {
+        layer: u32,
+        from: Option<Handle<Atlas>>,
+        to: Option<Handle<Atlas>>,
+    },
+    /// Set a tile, where `None` is an empty tile.
+    SetTile {
+        layer: u32,
+        pos: UVec2,
+        from: Option<(u32, TileCollisionKind)>,
+        to: Option<(u32, TileCollisionKind)>,
+    },
+    RenameMap {
+        from: Ustr,
+        to: Ustr,
+    },
+}
+
+impl MapEdit {
+    /// Get the edit that reverts this one.
+    pub fn inverse(&self) -> MapEdit {
+        match self.clone() {
+            MapEdit::CreateElement {
+                entity,
+                handle,
+                pos,
+                layer,
+            } => MapEdit::DeleteElement {
+                entity,
+                handle,
+                pos,
+                layer,
+            },
+            MapEdit::DeleteElement {
+                entity,
+                handle,
+                pos,
+                layer,
+            } => MapEdit::CreateElement {
+                entity,
+                handle,
+                pos,
+                layer,
+            },
+            MapEdit::MoveElement { entity, from, to } => MapEdit::MoveElement {
+                entity,
+                from: to,
+                to: from,
+            },
+            MapEdit::InsertLayer { layer, name } => MapEdit::RemoveLayer { layer, name },
+            MapEdit::RemoveLayer { layer, name } => MapEdit::InsertLayer { layer, name },
+            MapEdit::RenameLayer { layer, from, to } => MapEdit::RenameLayer {
+                layer,
+                from: to,
+                to: from,
+            },
+            MapEdit::SwapLayers { layer, down } => MapEdit::SwapLayers {
+                layer: if down { layer + 1 } else { layer - 1 },
+                down: !down,
+            },
+            MapEdit::SetTilemap { layer, from, to } => MapEdit::SetTilemap {
+                layer,
+                from: to,
+                to: from,
+            },
+            MapEdit::SetTile {
+                layer,
+                pos,
+                from,
+                to,
+            } => MapEdit::SetTile {
+                layer,
+                pos,
+                from: to,
+                to: from,
+            },
+            MapEdit::RenameMap { from, to } => MapEdit::RenameMap { from: to, to: from },
+        }
+    }
+
+    /// Replace an element entity that was deleted and created again.
+    fn replace_entity(&mut self, old: Entity, new: Entity) {
+        match self {
+            MapEdit::CreateElement { entity, .. }
+            | MapEdit::DeleteElement { entity, .. }
+            | MapEdit::MoveElement { entity, .. } => {
+                if *entity == old {
+                    *entity = new;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The edits made to the map through [`EditorInput`]s, which can be undone and redone.
+///
+/// Each editor input makes up a single step of the history, however many edits it is made of.
+#[derive(HasSchema, Clone, Default)]
+pub struct MapEditHistory {
+    undo: Vec<Vec<MapEdit>>,
+    redo: Vec<Vec<MapEdit>>,
+    /// The edits of the step being recorded, if one is.
+    recording: Option<Vec<MapEdit>>,
+}
+
+impl MapEditHistory {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    fn replace_entity(&mut self, old: Entity, new: Entity) {
+        for edit in self.undo.iter_mut().chain(&mut self.redo).flatten() {
+            edit.replace_entity(old, new);
+        }
     }
 }
 
 impl<'a> MapManager<'a> {
+    /// Start recording the edits made to the map as a step of the [`MapEditHistory`].
+    pub fn begin_history_step(&mut self) {
+        self.history.recording = Some(Vec::new());
+    }
+    /// Finish recording the edits made since [`begin_history_step`][Self::begin_history_step],
+    /// so that they can be undone.
+    pub fn end_history_step(&mut self) {
+        let Some(edits) = self.history.recording.take() else {
+            return;
+        };
+        if edits.is_empty() {
+            return;
+        }
+        self.history.undo.push(edits);
+        if self.history.undo.len() > MAX_EDIT_HISTORY {
+            self.history.undo.remove(0);
+        }
+        self.history.redo.clear();
+    }
+    /// Undo the last step of the history.
+    pub fn undo(&mut self) {
+        if let Some(mut edits) = self.history.undo.pop() {
+            for i in (0..edits.len()).rev() {
+                self.apply_history_edit(&mut edits, i, true);
+            }
+            self.history.redo.push(edits);
+        }
+    }
+    /// Redo the last undone step of the history.
+    pub fn redo(&mut self) {
+        if let Some(mut edits) = self.history.redo.pop() {
+            for i in 0..edits.len() {
+                self.apply_history_edit(&mut edits, i, false);
+            }
+            self.history.undo.push(edits);
+        }
+    }
+    /// Apply, or revert, an edit from a step of the history.
+    fn apply_history_edit(&mut self, edits: &mut [MapEdit], i: usize, revert: bool) {
+        let edit = if revert {
+            edits[i].inverse()
+        } else {
+            edits[i].clone()
+        };
+        match edit {
+            MapEdit::CreateElement {
+                entity,
+                handle,
+                pos,
+                layer,
+            } => {
+                // The element is created again as a new entity, which replaces the old one in the
+                // history.
+                let new_entity = self.create_element(&handle, &pos, layer);
+                for edit in edits.iter_mut() {
+                    edit.replace_entity(entity, new_entity);
+                }
+                self.history.replace_entity(entity, new_entity);
+            }
+            MapEdit::DeleteElement { entity, .. } => self.delete_element(entity),
+            MapEdit::MoveElement { entity, to, .. } => self.move_element(entity, &to),
+            MapEdit::InsertLayer { layer, name } => self.insert_layer(layer, name),
+            MapEdit::RemoveLayer { layer, .. } => self.delete_layer(layer),
+            MapEdit::RenameLayer { layer, to, .. } => self.rename_layer(layer, &to),
+            MapEdit::SwapLayers { layer, down } => self.swap_layer(layer, down),
+            MapEdit::SetTilemap { layer, to, .. } => self.set_layer_tilemap(layer, &to),
+            MapEdit::SetTile { layer, pos, to, .. } => self.set_tile(
+                layer,
+                pos,
+                &to.map(|(idx, _)| idx),
+                to.map_or(TileCollisionKind::Empty, |(_, collision)| collision),
+            ),
+            MapEdit::RenameMap { to, .. } => self.rename_map(&to),
+        }
+    }
+    /// Add an edit to the step of the history being recorded.
+    fn record(&mut self, edit: MapEdit) {
+        if let Some(edits) = &mut self.history.recording {
+            edits.push(edit);
+        }
+    }
     /// Create a new map element at the given location on the given layer.
     pub fn create_element(
         &mut self,
         element_meta_handle: &Handle<ElementMeta>,
         translation: &Vec2,
         layer_index: u32,
-    ) {
+    ) -> Entity {
         let entity = self.entities.create();
         // TODO remove element handles as the underlying elements are removed
         self.element_handles
@@ -54,25 +302,46 @@ impl<'a> MapManager<'a> {
             entity,
             Transform::from_translation(translation.extend(z_depth)),
         );
+        self.element_spawn_positions
+            .insert(entity, ElementSpawnPos(*translation));
         self.spawned_map_layer_metas.insert(
             entity,
             SpawnedMapLayerMeta {
                 layer_idx: layer_index,
             },
         );
+        **self.nav_graph_outdated = true;
+        self.record(MapEdit::CreateElement {
+            entity,
+            handle: *element_meta_handle,
+            pos: *translation,
+            layer: layer_index,
+        });
+        entity
     }
     /// Create a new layer with the given name.
     pub fn create_layer(&mut self, name: Ustr) {
-        let entity = self.entities.create();
         let layer_index = self.spawned_map_meta.layer_names.len() as u32;
-        self.spawned_map_meta.layer_names = self
-            .spawned_map_meta
-            .layer_names
-            .clone()
-            .iter()
-            .cloned()
-            .chain([name])
-            .collect();
+        self.insert_layer(layer_index, name);
+    }
+    /// Create a new layer with the given name at the given index, moving the layers from that
+    /// index up.
+    pub fn insert_layer(&mut self, layer_index: u32, name: Ustr) {
+        let mut layer_names = self.spawned_map_meta.layer_names.to_vec();
+        let layer_index = layer_index.min(layer_names.len() as u32);
+        layer_names.insert(layer_index as usize, name);
+        self.spawned_map_meta.layer_names = layer_names.into_iter().collect();
+        for (_, (transform, layer_meta)) in self
+            .entities
+            .iter_with((&mut self.transforms, &mut self.spawned_map_layer_metas))
+        {
+            if layer_meta.layer_idx >= layer_index {
+                layer_meta.layer_idx += 1;
+                transform.translation.z = z_depth_for_map_layer(layer_meta.layer_idx);
+            }
+        }
+
+        let entity = self.entities.create();
         self.spawned_map_layer_metas.insert(
             entity,
             SpawnedMapLayerMeta {
@@ -91,9 +360,16 @@ impl<'a> MapManager<'a> {
             entity,
             Transform::from_translation(Vec3::new(0.0, 0.0, z_depth_for_map_layer(layer_index))),
         );
+        self.record(MapEdit::InsertLayer {
+            layer: layer_index,
+            name,
+        });
     }
     /// Delete the layer with the given index.
     pub fn delete_layer(&mut self, layer_index: u32) {
+        if self.history.recording.is_some() {
+            self.record_layer_removal(layer_index);
+        }
         let layer_count = self.spawned_map_meta.layer_names.len() as u32;
         let layers_to_decrement = layer_count - layer_index;
         self.spawned_map_meta.layer_names = self
@@ -132,9 +408,87 @@ impl<'a> MapManager<'a> {
         to_kill.into_iter().for_each(|ent| {
             self.entities.kill(ent);
         });
+        **self.nav_graph_outdated = true;
+    }
+    /// Record the contents of a layer that is about to be deleted, so that it can be restored.
+    fn record_layer_removal(&mut self, layer_index: u32) {
+        let Some(name) = self
+            .spawned_map_meta
+            .layer_names
+            .get(layer_index as usize)
+            .copied()
+        else {
+            return;
+        };
+        let mut edits = Vec::new();
+        for (entity, (handle, spawn_pos, layer_meta)) in self.entities.iter_with((
+            &self.element_handles,
+            &self.element_spawn_positions,
+            &self.spawned_map_layer_metas,
+        )) {
+            if layer_meta.layer_idx == layer_index {
+                edits.push(MapEdit::DeleteElement {
+                    entity,
+                    handle: handle.0,
+                    pos: **spawn_pos,
+                    layer: layer_index,
+                });
+            }
+        }
+        for (_, (tile_layer, layer_meta)) in self
+            .entities
+            .iter_with((&self.tile_layers, &self.spawned_map_layer_metas))
+        {
+            if layer_meta.layer_idx != layer_index {
+                continue;
+            }
+            for y in 0..self.spawned_map_meta.grid_size.y {
+                for x in 0..self.spawned_map_meta.grid_size.x {
+                    let pos = UVec2::new(x, y);
+                    if let Some(tile) = tile_layer.get(pos).and_then(|ent| self.tiles.get(ent)) {
+                        let collision = tile_layer
+                            .get(pos)
+                            .and_then(|ent| self.tile_collisions.get(ent).copied())
+                            .unwrap_or_default();
+                        edits.push(MapEdit::SetTile {
+                            layer: layer_index,
+                            pos,
+                            from: Some((tile.idx, collision)),
+                            to: None,
+                        });
+                    }
+                }
+            }
+            if tile_layer.atlas != Handle::default() {
+                edits.push(MapEdit::SetTilemap {
+                    layer: layer_index,
+                    from: Some(tile_layer.atlas),
+                    to: None,
+                });
+            }
+        }
+        edits.push(MapEdit::RemoveLayer {
+            layer: layer_index,
+            name,
+        });
+        for edit in edits {
+            self.record(edit);
+        }
     }
     /// Rename the layer with the given index.
     pub fn rename_layer(&mut self, layer_index: u32, name: &str) {
+        if let Some(from) = self
+            .spawned_map_meta
+            .layer_names
+            .get(layer_index as usize)
+            .copied()
+        {
+            self.record(MapEdit::RenameLayer {
+                layer: layer_index,
+                from,
+                to: ustr(name),
+            });
+        }
         self.spawned_map_meta.layer_names = self
             .spawned_map_meta
             .layer_names
@@ -153,12 +507,37 @@ impl<'a> MapManager<'a> {
     }
     /// Move an element to a new position on the map.
     pub fn move_element(&mut self, entity: Entity, position: &Vec2) {
-        let transform = self.transforms.get_mut(entity).unwrap();
+        let Some(transform) = self.transforms.get_mut(entity) else {
+            return;
+        };
+        let from = match self.element_spawn_positions.get_mut(entity) {
+            Some(spawn_pos) => std::mem::replace(&mut **spawn_pos, *position),
+            None => transform.translation.truncate(),
+        };
         transform.translation.x = position.x;
         transform.translation.y = position.y;
+        **self.nav_graph_outdated = true;
+        self.record(MapEdit::MoveElement {
+            entity,
+            from,
+            to: *position,
+        });
     }
     /// Delete an element off of the map.
     pub fn delete_element(&mut self, entity: Entity) {
+        if let (Some(handle), Some(spawn_pos), Some(layer_meta)) = (
+            self.element_handles.get(entity),
+            self.element_spawn_positions.get(entity),
+            self.spawned_map_layer_metas.get(entity),
+        ) {
+            let edit = MapEdit::DeleteElement {
+                entity,
+                handle: handle.0,
+                pos: **spawn_pos,
+                layer: layer_meta.layer_idx,
+            };
+            self.record(edit);
+        }
         if let Some(element_kill_callback) = self.element_kill_callbacks.get(entity) {
             let system = element_kill_callback.system.clone();
             self.commands
@@ -174,9 +553,26 @@ impl<'a> MapManager<'a> {
         } else {
             self.entities.kill(entity);
         }
+        **self.nav_graph_outdated = true;
     }
     /// Set the tilemap for the given layer.
+    ///
+    /// Layers that were spawned without a tilemap don't have a tile layer yet, so one is created.
     pub fn set_layer_tilemap(&mut self, layer_index: u32, tilemap: &Option<Handle<Atlas>>) {
+        let from = self
+            .entities
+            .iter_with((&self.tile_layers, &self.spawned_map_layer_metas))
+            .find(|x| x.1 .1.layer_idx == layer_index)
+            .map(|(_, (tile_layer, _))| tile_layer.atlas)
+            .filter(|atlas| *atlas != Handle::default());
+        if from != *tilemap {
+            self.record(MapEdit::SetTilemap {
+                layer: layer_index,
+                from,
+                to: *tilemap,
+            });
+        }
+
         if let Some((_, (tile_layer, _))) = self
             .entities
             .iter_with((&mut self.tile_layers, &self.spawned_map_layer_metas))
@@ -187,7 +583,31 @@ impl<'a> MapManager<'a> {
             } else {
                 tile_layer.atlas = default();
             }
-        };
+        } else if let Some(handle) = tilemap {
+            let entity = self.entities.create();
+            self.spawned_map_layer_metas.insert(
+                entity,
+                SpawnedMapLayerMeta {
+                    layer_idx: layer_index,
+                },
+            );
+            self.tile_layers.insert(
+                entity,
+                TileLayer::new(
+                    self.spawned_map_meta.grid_size,
+                    self.spawned_map_meta.tile_size,
+                    *handle,
+                ),
+            );
+            self.transforms.insert(
+                entity,
+                Transform::from_translation(Vec3::new(
+                    0.0,
+                    0.0,
+                    z_depth_for_map_layer(layer_index),
+                )),
+            );
+        }
     }
     /// Set the tile index of a tile on the given layer.
     pub fn set_tile(
@@ -197,11 +617,27 @@ impl<'a> MapManager<'a> {
         tilemap_tile_index: &Option<u32>,
         tile_collision_kind: TileCollisionKind,
     ) {
+        let mut edit = None;
         if let Some((_, (tile_layer, _))) = self
             .entities
             .iter_with((&mut self.tile_layers, &self.spawned_map_layer_metas))
             .find(|x| x.1 .1.layer_idx == layer_index)
         {
+            let from = tile_layer.get(position).and_then(|entity| {
+                let idx = self.tiles.get(entity)?.idx;
+                let collision = self.tile_collisions.get(entity).copied();
+                Some((idx, collision.unwrap_or_default()))
+            });
+            let to = tilemap_tile_index.map(|idx| (idx, tile_collision_kind));
+            if from != to {
+                edit = Some(MapEdit::SetTile {
+                    layer: layer_index,
+                    pos: position,
+                    from,
+                    to,
+                });
+            }
+
             if let Some(entity) = tile_layer.get(position) {
                 if let Some(idx) = tilemap_tile_index.as_ref() {
                     self.tiles.get_mut(entity).unwrap().idx = *idx;
@@ -243,7 +679,11 @@ impl<'a> MapManager<'a> {
                 .add(move |mut collision_world: CollisionWorld| {
                     collision_world.update_tile(layer_index, position);
                 });
+            **self.nav_graph_outdated = true;
         };
+        if let Some(edit) = edit {
+            self.record(edit);
+        }
     }
     /// Swap the position of two layers.
     pub fn swap_layer(&mut self, layer_index: u32, is_downward: bool) {
@@ -253,6 +693,10 @@ impl<'a> MapManager<'a> {
         } else {
             origin_layer_index - 1
         };
+        self.record(MapEdit::SwapLayers {
+            layer: layer_index,
+            down: is_downward,
+        });
         let mut layer_names = self.spawned_map_meta.layer_names.to_vec();
         layer_names.swap(origin_layer_index as usize, other_layer_index as usize);
         self.spawned_map_meta.layer_names = layer_names.into_iter().collect();
@@ -272,6 +716,10 @@ impl<'a> MapManager<'a> {
     }
     /// Rename the map.
     pub fn rename_map(&mut self, name: &str) {
+        self.record(MapEdit::RenameMap {
+            from: self.spawned_map_meta.name,
+            to: ustr(name),
+        });
         self.spawned_map_meta.name = ustr(name);
     }
     /// Get the size of the map.
@@ -282,6 +730,73 @@ impl<'a> MapManager<'a> {
     pub fn get_layers_total(&self) -> usize {
         self.spawned_map_meta.layer_names.len()
     }
+    /// Collect the current contents of the map into map metadata, so that it can be saved.
+    pub fn to_map_meta(&self) -> MapMeta {
+        let mut layers = self
+            .spawned_map_meta
+            .layer_names
+            .iter()
+            .map(|name| MapLayerMeta {
+                id: *name,
+                ..default()
+            })
+            .collect::<Vec<_>>();
+
+        for (_, (tile_layer, layer_meta)) in self
+            .entities
+            .iter_with((&self.tile_layers, &self.spawned_map_layer_metas))
+        {
+            let Some(layer) = layers.get_mut(layer_meta.layer_idx as usize) else {
+                continue;
+            };
+            if tile_layer.atlas == Handle::default() {
+                continue;
+            }
+            layer.tilemap = Set(tile_layer.atlas);
+            for y in 0..self.spawned_map_meta.grid_size.y {
+                for x in 0..self.spawned_map_meta.grid_size.x {
+                    let pos = UVec2::new(x, y);
+                    let Some(tile) = tile_layer.get(pos).and_then(|ent| {
+                        self.tiles.get(ent).map(|tile| MapTileMeta {
+                            pos,
+                            idx: tile.idx,
+                            collision: self.tile_collisions.get(ent).copied().unwrap_or_default(),
+                        })
+                    }) else {
+                        continue;
+                    };
+                    layer.tiles.push(tile);
+                }
+            }
+        }
+
+        // Elements are saved where they were placed, not where they have moved to since.
+        for (_, (element_handle, spawn_pos, layer_meta)) in self.entities.iter_with((
+            &self.element_handles,
+            &self.element_spawn_positions,
+            &self.spawned_map_layer_metas,
+        )) {
+            if let Some(layer) = layers.get_mut(layer_meta.layer_idx as usize) {
+                layer.elements.push(ElementSpawn {
+                    pos: **spawn_pos,
+                    element: element_handle.0,
+                });
+            }
+        }
+
+        let mut meta = MapMeta {
+            name: self.spawned_map_meta.name,
+            background: (*self.spawned_map_meta.background).clone(),
+            background_color: self.spawned_map_meta.background_color,
+            grid_size: self.spawned_map_meta.grid_size,
+            tile_size: self.spawned_map_meta.tile_size,
+            layers: default(),
+        };
+        for layer in layers {
+            meta.layers.push(layer);
+        }
+        meta
+    }
     /// Clear all the tiles on the map.
     pub fn clear_tiles(&mut self) {
         let empty_tile: Option<u32> = Option::None;
@@ -310,10 +825,19 @@ impl<'a> MapManager<'a> {
 }
 
 /// Handles user input comming from the editor and makes the required changes to the map.
-fn handle_editor_input(player_inputs: Res<MatchInputs>, mut map_manager: MapManager) {
-    for player in &player_inputs.players {
-        if let Some(editor_input) = &player.editor_input {
+///
+/// Each editor input is only applied once, so it is taken out of the player inputs. The changes
+/// made by each input are recorded as a step of the [`MapEditHistory`].
+fn handle_editor_input(mut player_inputs: ResMut<MatchInputs>, mut map_manager: MapManager) {
+    for player in &mut player_inputs.players {
+        if let Some(editor_input) = &player.editor_input.take() {
+            let record = !matches!(editor_input, EditorInput::Undo | EditorInput::Redo);
+            if record {
+                map_manager.begin_history_step();
+            }
             match editor_input {
+                EditorInput::Undo => map_manager.undo(),
+                EditorInput::Redo => map_manager.redo(),
                 EditorInput::SpawnElement {
                     handle,
                     translation,
@@ -368,6 +892,260 @@ fn handle_editor_input(player_inputs: Res<MatchInputs>, mut map_manager: MapMana
                     map_constructor.construct_map(&mut map_manager);
                 }
             }
+            map_manager.end_history_step();
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use super::*;
+    use crate::headless::{FrameControls, HeadlessGame};
+
+    /// Start a match on the first core map, with two idle players.
+    fn start_match() -> HeadlessGame {
+        let mut headless = HeadlessGame::for_tests();
+        let meta = headless.meta();
+        let maps = MapPool::from_single_map(headless.find_map(None).unwrap());
+        let player_info = std::array::from_fn(|i| PlayerInput {
+            active: i < 2,
+            selected_player: meta.core.players[0],
+            ..default()
+        });
+        headless.start_match(maps, player_info, default(), Vec::<FrameControls>::new());
+        // Let the map spawn.
+        headless.run(5);
+        headless
+    }
+
+    /// Send an editor input and run the frame that applies it.
+    fn edit(headless: &mut HeadlessGame, input: EditorInput) {
+        headless
+            .match_world()
+            .unwrap()
+            .resource_mut::<MatchInputs>()
+            .players[0]
+            .editor_input = Some(input);
+        headless.step();
+    }
+
+    fn map_meta(headless: &HeadlessGame) -> MapMeta {
+        headless
+            .match_world()
+            .unwrap()
+            .run_system(|map_manager: MapManager| map_manager.to_map_meta(), ())
+    }
+
+    fn history(headless: &HeadlessGame) -> MapEditHistory {
+        headless
+            .match_world()
+            .unwrap()
+            .resource::<MapEditHistory>()
+            .clone()
+    }
+
+    /// The contents of the map as they would be saved. The elements of each layer are sorted,
+    /// since the order they are found in changes when they are created again.
+    fn map_contents(headless: &HeadlessGame) -> Vec<String> {
+        let map = map_meta(headless);
+        let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+        let mut contents = vec![format!("{} {}", map.name, map.grid_size)];
+        for layer in map.layers.iter() {
+            let tilemap = layer.tilemap.option().map(|tilemap| {
+                let atlas = asset_server.get(tilemap);
+                (atlas.tile_size, atlas.columns, atlas.rows)
+            });
+            contents.push(format!("layer {} {tilemap:?}", layer.id));
+            for tile in layer.tiles.iter() {
+                contents.push(format!(
+                    "tile {} {} {:?}",
+                    tile.pos, tile.idx, tile.collision
+                ));
+            }
+            let mut elements = layer
+                .elements
+                .iter()
+                .map(|spawn| {
+                    let element = asset_server.get(spawn.element);
+                    format!("element {} {}", spawn.pos, element.name)
+                })
+                .collect::<Vec<_>>();
+            elements.sort();
+            contents.extend(elements);
+        }
+        contents
+    }
+
+    /// Find a layer of the map that has both tiles and elements.
+    fn layer_with_elements(headless: &HeadlessGame) -> (u8, MapLayerMeta) {
+        map_meta(headless)
+            .layers
+            .iter()
+            .enumerate()
+            .find(|(_, layer)| !layer.tiles.is_empty() && !layer.elements.is_empty())
+            .map(|(i, layer)| (i as u8, layer.clone()))
+            .expect("The map has no layer with both tiles and elements")
+    }
+
+    /// Find the entity of the element placed at the given position.
+    fn element_at(headless: &HeadlessGame, pos: Vec2) -> Entity {
+        headless.match_world().unwrap().run_system(
+            move |entities: Res<Entities>,
+                  element_handles: Comp<ElementHandle>,
+                  spawn_positions: Comp<ElementSpawnPos>| {
+                entities
+                    .iter_with((&element_handles, &spawn_positions))
+                    .find(|(_, (_, spawn_pos))| ***spawn_pos == pos)
+                    .map(|(entity, _)| entity)
+                    .expect("No element at the given position")
+            },
+            (),
+        )
+    }
+
+    #[test]
+    fn deleting_and_moving_elements_round_trips() {
+        let mut headless = start_match();
+        let original = map_contents(&headless);
+        let (_, layer_meta) = layer_with_elements(&headless);
+        let pos = layer_meta.elements.iter().next().unwrap().pos;
+        let moved_pos = pos + vec2(32.0, 16.0);
+
+        let entity = element_at(&headless, pos);
+        edit(&mut headless, EditorInput::DeleteEntity { entity });
+        let deleted = map_contents(&headless);
+        assert_ne!(deleted, original);
+        edit(&mut headless, EditorInput::Undo);
+        assert_eq!(map_contents(&headless), original);
+
+        // The element was created again as a new entity, which is the one that is moved.
+        let entity = element_at(&headless, pos);
+        edit(
+            &mut headless,
+            EditorInput::MoveEntity {
+                entity,
+                pos: moved_pos,
+            },
+        );
+        let moved = map_contents(&headless);
+        assert_ne!(moved, original);
+        // Making a new edit forgets the undone deletion.
+        assert!(!history(&headless).can_redo());
+
+        edit(&mut headless, EditorInput::Undo);
+        assert_eq!(map_contents(&headless), original);
+        edit(&mut headless, EditorInput::Redo);
+        assert_eq!(map_contents(&headless), moved);
+        assert!(!history(&headless).can_redo());
+
+        // Undo a deletion and then the move before it, which has to find the element under the
+        // entity it was created again as.
+        let entity = element_at(&headless, moved_pos);
+        edit(&mut headless, EditorInput::DeleteEntity { entity });
+        assert_eq!(map_contents(&headless), deleted);
+        edit(&mut headless, EditorInput::Undo);
+        assert_eq!(map_contents(&headless), moved);
+        edit(&mut headless, EditorInput::Undo);
+        assert_eq!(map_contents(&headless), original);
+        edit(&mut headless, EditorInput::Redo);
+        assert_eq!(map_contents(&headless), moved);
+        edit(&mut headless, EditorInput::Redo);
+        assert_eq!(map_contents(&headless), deleted);
+    }
+
+    #[test]
+    fn deleting_a_layer_round_trips_its_tiles_and_elements() {
+        let mut headless = start_match();
+        let original = map_contents(&headless);
+        let (layer, _) = layer_with_elements(&headless);
+
+        edit(&mut headless, EditorInput::DeleteLayer { layer });
+        let deleted = map_contents(&headless);
+        assert!(deleted.len() < original.len());
+
+        edit(&mut headless, EditorInput::Undo);
+        assert_eq!(map_contents(&headless), original);
+        edit(&mut headless, EditorInput::Redo);
+        assert_eq!(map_contents(&headless), deleted);
+        edit(&mut headless, EditorInput::Undo);
+        assert_eq!(map_contents(&headless), original);
+    }
+
+    #[test]
+    fn undoing_every_edit_restores_the_map() {
+        let mut headless = start_match();
+        let original = map_contents(&headless);
+        let (layer, layer_meta) = layer_with_elements(&headless);
+        let pos = layer_meta.elements.iter().next().unwrap().pos;
+        let moved_pos = pos + vec2(0.0, 32.0);
+        let mut tiles = layer_meta.tiles.iter();
+        let (changed_tile, removed_tile) = (tiles.next().unwrap(), tiles.next().unwrap());
+
+        let mut edits = vec![
+            EditorInput::SetTile {
+                layer,
+                pos: changed_tile.pos,
+                tilemap_tile_idx: Some(changed_tile.idx + 1),
+                collision: TileCollisionKind::Solid,
+            },
+            EditorInput::SetTile {
+                layer,
+                pos: removed_tile.pos,
+                tilemap_tile_idx: None,
+                collision: TileCollisionKind::Empty,
+            },
+            EditorInput::RenameLayer {
+                layer,
+                name: "renamed".into(),
+            },
+            EditorInput::CreateLayer { id: "new".into() },
+            EditorInput::SetTilemap {
+                layer: 0,
+                handle: None,
+            },
+            EditorInput::MoveLayer { layer, down: true },
+            EditorInput::RenameMap {
+                name: "edited".into(),
+            },
+        ];
+        if let Set(tilemap) = layer_meta.tilemap {
+            edits.push(EditorInput::SetTilemap {
+                layer: 0,
+                handle: Some(tilemap),
+            });
+        }
+        // The element and its layer are edited last, since the entity of the element is needed.
+        let edit_count = edits.len() + 3;
+        for input in edits {
+            edit(&mut headless, input);
+        }
+        let entity = element_at(&headless, pos);
+        edit(
+            &mut headless,
+            EditorInput::MoveEntity {
+                entity,
+                pos: moved_pos,
+            },
+        );
+        let entity = element_at(&headless, moved_pos);
+        edit(&mut headless, EditorInput::DeleteEntity { entity });
+        // The layer was moved down by one.
+        edit(&mut headless, EditorInput::DeleteLayer { layer: layer + 1 });
+        let edited = map_contents(&headless);
+        assert_ne!(edited, original);
+
+        for _ in 0..edit_count {
+            assert!(history(&headless).can_undo());
+            edit(&mut headless, EditorInput::Undo);
+        }
+        assert!(!history(&headless).can_undo());
+        assert_eq!(map_contents(&headless), original);
+
+        for _ in 0..edit_count {
+            assert!(history(&headless).can_redo());
+            edit(&mut headless, EditorInput::Redo);
         }
+        assert!(!history(&headless).can_redo());
+        assert_eq!(map_contents(&headless), edited);
     }
 }
@@ -225,6 +225,7 @@ fn update(
                 let shoot_atlas = *shoot_atlas;
 
                 let bullet_meta = *bullet_meta;
+                let source = element_handle.0;
                 let bullet_spread = *bullet_spread;
                 let bullet_count = *bullet_count;
 
@@ -270,6 +271,7 @@ fn update(
                                     ent,
                                     Bullet {
                                         owner: player,
+                                        source,
                                         direction: if player_flip_x {
                                             vec2(-1.0, (rng.f32() - 0.5) * bullet_spread)
                                         } else {
@@ -269,10 +269,12 @@ fn update_thrown_crates(
             .into_iter()
             .collect::<Vec<_>>();
 
+        let kill_source = KillSource::new(Some(thrown_crate.owner), Some(element_handle.0));
         for player_entity in &colliding_with_players {
-            commands.add(PlayerCommand::kill(
+            commands.add(PlayerCommand::kill_by(
                 *player_entity,
                 Some(transform.translation.xy()),
+                kill_source,
             ));
         }
         let kill_nearby_colliding: bool = kill_all_colliding_if_freshly_thrown(
@@ -282,6 +284,7 @@ fn update_thrown_crates(
             &invincibles,
             &mut commands,
             transform,
+            kill_source,
         );
 
         if !colliding_with_players.is_empty()
@@ -332,6 +335,7 @@ fn kill_all_colliding_if_freshly_thrown(
     invincibles: &CompMut<Invincibility>,
     commands: &mut Commands,
     transform: &Transform,
+    kill_source: KillSource,
 ) -> bool {
     if thrown_crate.damage_delay.finished() {
         return false;
@@ -345,15 +349,17 @@ fn kill_all_colliding_if_freshly_thrown(
     if !colliding_with_players.is_empty() {
         for player_entity in &colliding_with_players {
             if invincibles.get(*player_entity).is_none() {
-                commands.add(PlayerCommand::kill(
+                commands.add(PlayerCommand::kill_by(
                     *player_entity,
                     Some(transform.translation.xy()),
+                    kill_source,
                 ));
             }
         }
-        commands.add(PlayerCommand::kill(
+        commands.add(PlayerCommand::kill_by(
             thrown_crate.owner,
             Some(transform.translation.xy()),
+            kill_source,
         ));
         true
     } else {
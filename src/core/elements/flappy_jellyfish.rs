@@ -231,6 +231,7 @@ fn explode_flappy_jellyfish(
     mut sprites: CompMut<AtlasSprite>,
     mut animated_sprites: CompMut<AnimatedSprite>,
     mut damage_regions: CompMut<DamageRegion>,
+    mut kill_sources: CompMut<KillSource>,
     mut lifetimes: CompMut<Lifetime>,
     mut dehydrate_jellyfish: CompMut<DehydrateJellyfish>,
 ) {
@@ -340,6 +341,13 @@ fn explode_flappy_jellyfish(
                     size: flappy_meta.damage_region_size,
                 },
             );
+            kill_sources.insert(
+                damage_ent,
+                KillSource::new(
+                    Some(flappy.owner),
+                    element_handles.get(flappy.jellyfish).map(|handle| handle.0),
+                ),
+            );
             lifetimes.insert(
                 damage_ent,
                 Lifetime::new(flappy_meta.damage_region_lifetime),
@@ -282,10 +282,12 @@ fn update_lit_grenades(
             let explosion_atlas = *explosion_atlas;
             let explosion_fps = *explosion_fps;
             let explosion_frames = *explosion_frames;
+            let kill_source = KillSource::new(Some(grenade.owner), Some(element_handle.0));
             commands.add(
                 move |mut entities: ResMutInit<Entities>,
                       mut transforms: CompMut<Transform>,
                       mut damage_regions: CompMut<DamageRegion>,
+                      mut kill_sources: CompMut<KillSource>,
                       mut lifetimes: CompMut<Lifetime>,
                       mut sprites: CompMut<AtlasSprite>,
                       mut animated_sprites: CompMut<AnimatedSprite>| {
@@ -301,6 +303,7 @@ fn update_lit_grenades(
                             size: damage_region_size,
                         },
                     );
+                    kill_sources.insert(ent, kill_source);
                     lifetimes.insert(ent, Lifetime::new(damage_region_lifetime));
 
                     // Spawn the explosion animation
@@ -55,6 +55,8 @@ pub struct LitKickBomb {
     fuse_time: Timer,
     kicking: bool,
     kicks: u32,
+    /// The player that lit the kick bomb, if any.
+    owner: Option<Entity>,
 }
 
 /// Component containing the kick bombs's metadata handle.
@@ -161,6 +163,7 @@ impl KickBombCommand {
                         fuse_time: Timer::new(fuse_time, TimerMode::Once),
                         kicking: false,
                         kicks: 0,
+                        owner: None,
                     },
                 );
 
@@ -259,7 +262,7 @@ fn update_idle_kick_bombs(
             ..
         } = *kick_bomb_meta;
 
-        if items_used.remove(entity).is_some() {
+        if let Some(ItemUsed { owner }) = items_used.remove(entity) {
             audio_center.play_sound(fuse_sound, fuse_sound_volume);
             let animated_sprite = animated_sprites.get_mut(entity).unwrap();
             animated_sprite.frames = (lit_frames_start..lit_frames_end).collect();
@@ -275,6 +278,7 @@ fn update_idle_kick_bombs(
                             fuse_time: Timer::new(fuse_time, TimerMode::Once),
                             kicking: false,
                             kicks: 0,
+                            owner: Some(owner),
                         },
                     );
                 },
@@ -286,6 +290,7 @@ fn update_idle_kick_bombs(
 fn update_lit_kick_bombs(
     entities: Res<Entities>,
     kick_bomb_handles: Comp<KickBombHandle>,
+    element_handles: Comp<ElementHandle>,
     assets: Res<AssetServer>,
     collision_world: CollisionWorld,
     player_indexes: Comp<PlayerIdx>,
@@ -415,10 +420,15 @@ fn update_lit_kick_bombs(
             explosion_transform.translation.z = -10.0; // On top of almost everything
             explosion_transform.rotation = Quat::IDENTITY;
 
+            let kill_source = KillSource::new(
+                kick_bomb.owner,
+                element_handles.get(entity).map(|handle| handle.0),
+            );
             commands.add(
                 move |mut entities: ResMutInit<Entities>,
                       mut transforms: CompMut<Transform>,
                       mut damage_regions: CompMut<DamageRegion>,
+                      mut kill_sources: CompMut<KillSource>,
                       mut lifetimes: CompMut<Lifetime>,
                       mut sprites: CompMut<AtlasSprite>,
                       mut animated_sprites: CompMut<AnimatedSprite>| {
@@ -434,6 +444,7 @@ fn update_lit_kick_bombs(
                             size: damage_region_size,
                         },
                     );
+                    kill_sources.insert(ent, kill_source);
                     lifetimes.insert(ent, Lifetime::new(damage_region_lifetime));
 
                     // Spawn the explosion animation
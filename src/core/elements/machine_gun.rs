@@ -257,6 +257,7 @@ fn update(
                     };
 
                     let bullet_meta = *bullet_meta;
+                    let source = element_handle.0;
                     let bullet_spread = *bullet_spread;
 
                     commands.add(
@@ -272,6 +273,7 @@ fn update(
                                     ent,
                                     Bullet {
                                         owner: player,
+                                        source,
                                         direction: if player_flip_x {
                                             vec2(-1.0, (rng.f32() - 0.5) * bullet_spread)
                                         } else {
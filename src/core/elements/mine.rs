@@ -49,6 +49,8 @@ pub struct IdleMine;
 pub struct ThrownMine {
     // The mine won't explode until this timer finishes.
     arm_delay: Timer,
+    /// The player that threw the mine.
+    owner: Entity,
 }
 
 fn hydrate(
@@ -159,6 +161,7 @@ fn update_idle_mines(
                                     Duration::from_secs_f32(arm_delay),
                                     TimerMode::Once,
                                 ),
+                                owner: player,
                             },
                         );
                     },
@@ -239,10 +242,12 @@ fn update_thrown_mines(
 
             trauma_events.send(6.0);
 
+            let kill_source = KillSource::new(Some(thrown_mine.owner), Some(element_handle.0));
             for player in &colliding_with_players {
-                commands.add(PlayerCommand::kill(
+                commands.add(PlayerCommand::kill_by(
                     *player,
                     Some(mine_transform.translation.xy()),
+                    kill_source,
                 ));
             }
 
@@ -261,6 +266,7 @@ fn update_thrown_mines(
                 move |mut entities: ResMutInit<Entities>,
                       mut transforms: CompMut<Transform>,
                       mut damage_regions: CompMut<DamageRegion>,
+                      mut kill_sources: CompMut<KillSource>,
                       mut lifetimes: CompMut<Lifetime>,
                       mut sprites: CompMut<AtlasSprite>,
                       mut animated_sprites: CompMut<AnimatedSprite>| {
@@ -280,6 +286,7 @@ fn update_thrown_mines(
                             size: damage_region_size,
                         },
                     );
+                    kill_sources.insert(damage_ent, kill_source);
                     lifetimes.insert(damage_ent, Lifetime::new(damage_region_lifetime));
 
                     // Spawn the explosion animation
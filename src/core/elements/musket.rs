@@ -221,6 +221,7 @@ fn update(
                 let shoot_atlas = *shoot_atlas;
 
                 let bullet_meta = *bullet_meta;
+                let source = element_handle.0;
 
                 commands.add(
                     move |mut entities: ResMutInit<Entities>,
@@ -262,6 +263,7 @@ fn update(
                                 ent,
                                 Bullet {
                                     owner: player,
+                                    source,
                                     direction: if player_flip_x {
                                         vec2(-1.0, 0.0)
                                     } else {
@@ -222,6 +222,7 @@ fn update(
                 let shoot_atlas = *shoot_atlas;
 
                 let bullet_meta = *bullet_meta;
+                let source = element_handle.0;
 
                 commands.add(
                     move |mut entities: ResMutInit<Entities>,
@@ -263,6 +264,7 @@ fn update(
                                 ent,
                                 Bullet {
                                     owner: player,
+                                    source,
                                     direction: if player_flip_x {
                                         vec2(-1.0, 0.0)
                                     } else {
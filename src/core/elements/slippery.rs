@@ -36,6 +36,7 @@ fn hydrate(
     mut slippery: CompMut<Slippery>,
     mut atlas_sprites: CompMut<AtlasSprite>,
     mut bodies: CompMut<KinematicBody>,
+    mut nav_graph_outdated: ResMutInit<NavGraphOutdated>,
 ) {
     let mut not_hydrated_bitset = hydrated.bitset().clone();
     not_hydrated_bitset.bit_not();
@@ -69,6 +70,9 @@ fn hydrate(
                     body_friction: *body_friction,
                 },
             );
+
+            // The AI slides differently on slippery ground
+            **nav_graph_outdated = true;
         }
     }
 }
@@ -38,15 +38,12 @@ fn hydrate(
     mut sproingers: CompMut<Sproinger>,
     mut atlas_sprites: CompMut<AtlasSprite>,
     mut bodies: CompMut<KinematicBody>,
-    mut nav_graph: ResMutInit<NavGraph>,
-    transforms: Comp<Transform>,
-    map: Res<LoadedMap>,
+    mut nav_graph_outdated: ResMutInit<NavGraphOutdated>,
 ) {
     let mut not_hydrated_bitset = hydrated.bitset().clone();
     not_hydrated_bitset.bit_not();
     not_hydrated_bitset.bit_and(element_handles.bitset());
 
-    let mut new_sproingers = Vec::new();
     for entity in entities.iter_with_bitset(&not_hydrated_bitset) {
         let element_handle = element_handles.get(entity).unwrap();
         let element_meta = assets.get(element_handle.0);
@@ -55,7 +52,6 @@ fn hydrate(
             atlas, body_size, ..
         }) = assets.get(element_meta.data).try_cast_ref()
         {
-            new_sproingers.push(entity);
             hydrated.insert(entity, MapElementHydrated);
             atlas_sprites.insert(entity, AtlasSprite::new(*atlas));
             bodies.insert(
@@ -67,28 +63,10 @@ fn hydrate(
                 },
             );
             sproingers.insert(entity, sproinger::default());
-        }
-    }
-
-    // Update the navigation graph with the new sproingers
-    if !new_sproingers.is_empty() {
-        let mut new_graph = nav_graph.as_ref().clone();
 
-        for ent in new_sproingers {
-            let pos = transforms.get(ent).unwrap().translation;
-            let node = NavNode((pos.truncate() / map.tile_size).as_ivec2());
-            let sproing_to = node.above().above().above().above().above().above();
-
-            new_graph.add_edge(
-                node,
-                sproing_to,
-                NavGraphEdge {
-                    inputs: [PlayerControl::default()].into(),
-                    distance: node.distance(&sproing_to),
-                },
-            );
+            // The AI can use the sproinger to get around
+            **nav_graph_outdated = true;
         }
-        **nav_graph = Arc::new(new_graph);
     }
 }
 
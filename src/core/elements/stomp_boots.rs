@@ -191,6 +191,7 @@ fn update_wearer(
     mut hydrated: CompMut<MapElementHydrated>,
     spawners: Comp<DehydrateOutOfBounds>,
     transforms: Comp<Transform>,
+    element_handles: Comp<ElementHandle>,
 ) {
     for (entity, WearingStompBoots { stomp_boots }) in entities.iter_with(&wearing_stomp_boots) {
         if killed_players.get(entity).is_some() {
@@ -227,9 +228,13 @@ fn update_wearer(
                         .center()
                         .y
                 {
-                    commands.add(PlayerCommand::kill(
+                    commands.add(PlayerCommand::kill_by(
                         player,
                         Some(player_transform.translation.xy()),
+                        KillSource::new(
+                            Some(entity),
+                            element_handles.get(*stomp_boots).map(|handle| handle.0),
+                        ),
                     ))
                 }
             });
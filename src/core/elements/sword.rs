@@ -35,6 +35,8 @@ pub fn session_plugin(session: &mut SessionBuilder) {
 pub struct Sword {
     pub state: SwordState,
     pub dropped_time: f32,
+    /// The player that threw the sword, who is credited with the kills of the thrown sword.
+    pub thrower: Option<Entity>,
 }
 
 #[derive(Default, Clone, Copy, Debug)]
@@ -155,6 +157,7 @@ fn update(
         let element_meta = assets.get(element_handle.0);
 
         // Helper to spawn a damage region for the sword attack
+        let element = element_handle.0;
         let mut spawn_damage_region = |pos: Vec3, size: Vec2, owner: Entity| {
             commands.add(
                 move |mut entities: ResMutInit<Entities>,
@@ -162,6 +165,7 @@ fn update(
                       mut emote_regions: CompMut<EmoteRegion>,
                       mut damage_regions: CompMut<DamageRegion>,
                       mut damage_region_owners: CompMut<DamageRegionOwner>,
+                      mut kill_sources: CompMut<KillSource>,
                       mut lifetimes: CompMut<Lifetime>| {
                     let entity = entities.create();
 
@@ -181,6 +185,7 @@ fn update(
                     damage_regions.insert(entity, DamageRegion { size });
                     transforms.insert(entity, Transform::from_translation(pos));
                     damage_region_owners.insert(entity, DamageRegionOwner(owner));
+                    kill_sources.insert(entity, KillSource::new(Some(owner), Some(element)));
                 },
             );
         };
@@ -206,6 +211,9 @@ fn update(
 
             let player_layer = player_layers.get_mut(player).unwrap();
 
+            // The player holding the sword is the one that throws it.
+            sword.thrower = Some(player);
+
             // Reset the sword animation if we're not swinging it
             if !matches!(sword.state, SwordState::Swinging { .. }) {
                 sprite.index = 4;
@@ -309,9 +317,10 @@ fn update(
                     })
                     .into_iter()
                     .for_each(|player| {
-                        commands.add(PlayerCommand::kill(
+                        commands.add(PlayerCommand::kill_by(
                             player,
                             Some(sword_transform.translation.xy()),
+                            KillSource::new(sword.thrower, Some(element_handle.0)),
                         ))
                     });
             }
@@ -321,9 +330,13 @@ fn update(
 
 fn sword_drop(entity: Entity) -> StaticSystem<(), ()> {
     (move |mut swords: CompMut<Sword>, mut sprites: CompMut<AtlasSprite>| {
-        // Put sword in rest position
+        // Put sword in rest position, keeping track of who threw it
         sprites.get_mut(entity).unwrap().index = 0;
-        *swords.get_mut(entity).unwrap() = default();
+        let sword = swords.get_mut(entity).unwrap();
+        *sword = Sword {
+            thrower: sword.thrower,
+            ..default()
+        };
     })
     .system()
 }
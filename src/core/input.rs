@@ -70,6 +70,12 @@ pub struct PlayerInput {
 
     /// Whether or not this is an AI player.
     pub is_ai: bool,
+    /// The difficulty of the AI, if this is an AI player.
+    pub ai_difficulty: AiDifficulty,
+    /// The lua behavior controlling the AI, or [`None`] for the built-in AI.
+    pub ai_behavior: Option<Handle<AiBehaviorMeta>>,
+    /// The team the player is on, if playing in teams.
+    pub team: Option<Team>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -153,4 +159,8 @@ pub enum EditorInput {
         element_layers: Vec<ElementLayer>,
         tile_size: Vec2,
     },
+    /// Undo the last change made to the map.
+    Undo,
+    /// Redo the last change that was undone.
+    Redo,
 }
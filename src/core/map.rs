@@ -1,14 +1,15 @@
 //! Map and navigation mesh implementation.
 
-use std::{
-    cmp::{max, min},
-    collections::VecDeque,
-};
-
 use super::physics::collisions::{CollisionWorld, TileCollisionKind, TileDynamicCollider};
 use crate::prelude::*;
 
+mod nav_graph;
+mod validation;
+pub use nav_graph::*;
+pub use validation::*;
+
 pub fn install(session: &mut SessionBuilder) {
+    nav_graph::install(session);
     session
         .stages
         .add_system_to_stage(CoreStage::First, spawn_map)
@@ -70,78 +71,12 @@ pub struct SpawnedMapLayerMeta {
     pub layer_idx: u32,
 }
 
-/// The map navigation graph resource.
-#[derive(Clone, Debug, Deref, DerefMut, HasSchema, Default)]
-pub struct NavGraph(pub Arc<NavGraphInner>);
-
-/// The inner graph type of [`NavGraph`].
-pub type NavGraphInner = petgraph::graphmap::DiGraphMap<NavNode, NavGraphEdge>;
-
-/// The type of nodes in the map navigation graph.
+/// Component containing the position a map element was spawned at.
 ///
-/// This is merely a wrapper around [`UVec2`] to add an [`Ord`] implementation.
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, Deref, DerefMut)]
-pub struct NavNode(pub IVec2);
-
-impl NavNode {
-    /// Calculates the Pythagorean distance between two nodes.
-    pub fn distance(&self, other: &Self) -> f32 {
-        let dx = (max(self.x, other.x) - min(self.x, other.x)) as f32;
-        let dy = (max(self.y, other.y) - min(self.y, other.y)) as f32;
-        (dx * dx) + (dy * dy)
-    }
-
-    pub fn right(&self) -> NavNode {
-        NavNode(self.0 + ivec2(1, 0))
-    }
-
-    pub fn above(&self) -> NavNode {
-        NavNode(self.0 + ivec2(0, 1))
-    }
-
-    pub fn left(&self) -> NavNode {
-        NavNode(self.0 - ivec2(1, 0))
-    }
-
-    pub fn below(&self) -> NavNode {
-        NavNode(self.0 - ivec2(0, 1))
-    }
-}
-
-impl From<IVec2> for NavNode {
-    fn from(v: IVec2) -> Self {
-        Self(v)
-    }
-}
-impl From<NavNode> for IVec2 {
-    fn from(v: NavNode) -> Self {
-        v.0
-    }
-}
-impl std::cmp::Ord for NavNode {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        let xcmp = self.0.x.cmp(&other.0.x);
-        if xcmp == std::cmp::Ordering::Equal {
-            self.0.y.cmp(&other.0.y)
-        } else {
-            xcmp
-        }
-    }
-}
-impl std::cmp::PartialOrd for NavNode {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-/// Represents the way to get from one tile to another tile in the navigation graph.
-#[derive(Debug, Clone)]
-pub struct NavGraphEdge {
-    /// The sequence of inputs for each frame, required to get to the connected tile.
-    pub inputs: VecDeque<PlayerControl>,
-    /// The distance to the connected tile. This is used as the heuristic for pathfinding.
-    pub distance: f32,
-}
+/// Elements move around once the match is running, so this, and not their [`Transform`], is the
+/// position that is used when exporting the world to `MapMeta`.
+#[derive(HasSchema, Clone, Copy, Default, Deref, DerefMut)]
+pub struct ElementSpawnPos(pub Vec2);
 
 fn spawn_map(
     mut commands: Commands,
@@ -158,11 +93,12 @@ fn spawn_map(
     mut tile_dynamic_colliders: CompMut<TileDynamicCollider>,
     mut parallax_bg_sprites: CompMut<ParallaxBackgroundSprite>,
     mut sprites: CompMut<Sprite>,
-    mut nav_graph: ResMutInit<NavGraph>,
+    mut nav_graph_outdated: ResMutInit<NavGraphOutdated>,
     mut cameras: CompMut<Camera>,
     mut camera_shakes: CompMut<CameraShake>,
     mut camera_states: CompMut<CameraState>,
     mut spawned_map_layer_metas: CompMut<SpawnedMapLayerMeta>,
+    mut element_spawn_positions: CompMut<ElementSpawnPos>,
     mut spawned_map_meta: ResMutInit<SpawnedMapMeta>,
 ) {
     if map_spawned.0 {
@@ -197,8 +133,8 @@ fn spawn_map(
     map_spawned.0 = true;
     **clear_color = map.background_color;
 
-    // Load the navigation graph
-    nav_graph.0 = create_nav_graph(&map);
+    // Build the navigation graph once the tiles and elements are spawned
+    **nav_graph_outdated = true;
 
     // Spawn parallax backgrounds
     for layer in &map.background.layers {
@@ -297,6 +233,7 @@ fn spawn_map(
                 element_ent,
                 Transform::from_translation(element_meta.pos.extend(layer_z)),
             );
+            element_spawn_positions.insert(element_ent, ElementSpawnPos(element_meta.pos));
             element_handles.insert(element_ent, ElementHandle(element_meta.element));
         }
     }
@@ -317,438 +254,12 @@ fn handle_out_of_bounds_players(
     for (player_ent, (_player_idx, transform)) in entities.iter_with((&player_indexes, &transforms))
     {
         if map.is_out_of_bounds(&transform.translation) {
-            commands.add(PlayerCommand::kill(player_ent, None));
+            // Falling out of the map counts as a suicide.
+            commands.add(PlayerCommand::kill_by(
+                player_ent,
+                None,
+                KillSource::new(Some(player_ent), None),
+            ));
         }
     }
 }
-
-/// Helper method to create a navigation graph from the map metadata.
-fn create_nav_graph(meta: &MapMeta) -> Arc<NavGraphInner> {
-    // Load the navigation graph
-    let mut graph = NavGraphInner::default();
-
-    // Initialize set of traversable tiles, assuming all tiles are traversable
-    for x in 0..meta.grid_size.x as i32 {
-        for y in 0..meta.grid_size.y as i32 {
-            graph.add_node(NavNode(ivec2(x, y)));
-        }
-    }
-
-    // Find all solid tiles and remove them from the traversable tiles list, while also recording
-    // the jump-through tiles.
-    let mut semi_solids = HashSet::default();
-    for layer in &meta.layers {
-        for tile in &layer.tiles {
-            if tile.collision == TileCollisionKind::JumpThrough {
-                semi_solids.insert(NavNode(tile.pos.as_ivec2()));
-            } else if tile.collision != TileCollisionKind::Empty {
-                graph.remove_node(NavNode(tile.pos.as_ivec2()));
-            }
-        }
-    }
-
-    // Calculate possible movements from every node
-    macro_rules! is_solid {
-        ($node:expr) => {
-            !graph.contains_node($node) || semi_solids.contains(&$node)
-        };
-    }
-
-    for node in graph.nodes().collect::<Vec<_>>() {
-        // walk left or right along the ground
-        let has_ground = is_solid!(node.below());
-        let maybe_has_ground =
-            has_ground || is_solid!(node.below().left()) || is_solid!(node.below().right());
-
-        /////////////////
-        // Grounded
-        /////////////////
-
-        if maybe_has_ground {
-            // Moving Right
-            let right = node.right();
-            if graph.contains_node(right) {
-                graph.add_edge(
-                    node,
-                    right,
-                    NavGraphEdge {
-                        inputs: [PlayerControl {
-                            moving: true,
-                            move_direction: vec2(1.0, 0.0),
-                            ..default()
-                        }]
-                        .into(),
-                        distance: node.distance(&right),
-                    },
-                );
-            }
-
-            // Moving Left
-            let left = node.left();
-            if graph.contains_node(left) {
-                graph.add_edge(
-                    node,
-                    left,
-                    NavGraphEdge {
-                        inputs: [PlayerControl {
-                            moving: true,
-                            move_direction: vec2(-1.0, 0.0),
-                            ..default()
-                        }]
-                        .into(),
-                        distance: node.distance(&left),
-                    },
-                );
-            }
-        }
-
-        if has_ground {
-            /////////////////
-            // JUMPING
-            /////////////////
-            let above1 = node.above();
-            let above2 = above1.above();
-            let above3 = above2.above();
-            let contains_above1 = graph.contains_node(above1);
-            let contains_above2 = graph.contains_node(above2);
-            let contains_above3 = graph.contains_node(above3);
-
-            if contains_above1 {
-                // Jump staight up
-                graph.add_edge(
-                    node,
-                    above1,
-                    NavGraphEdge {
-                        inputs: [PlayerControl {
-                            jump_just_pressed: true,
-                            jump_pressed: true,
-                            ..default()
-                        }]
-                        .into(),
-                        distance: node.distance(&above1),
-                    },
-                );
-            }
-            if contains_above2 && contains_above1 {
-                // Jump staight up
-                graph.add_edge(
-                    node,
-                    above2,
-                    NavGraphEdge {
-                        inputs: [PlayerControl {
-                            jump_just_pressed: true,
-                            jump_pressed: true,
-                            ..default()
-                        }]
-                        .into(),
-                        distance: node.distance(&above2),
-                    },
-                );
-            }
-            if contains_above3 && contains_above2 && contains_above1 {
-                // Jump staight up
-                graph.add_edge(
-                    node,
-                    above2,
-                    NavGraphEdge {
-                        inputs: [PlayerControl {
-                            jump_just_pressed: true,
-                            jump_pressed: true,
-                            ..default()
-                        }]
-                        .into(),
-                        distance: node.distance(&above3),
-                    },
-                );
-            }
-
-            // Jump up and left
-            let above3l2 = above3.left().left();
-            let above2l = above2.left();
-            let contains_above2l = graph.contains_node(above2l);
-            let contains_above3l2 = graph.contains_node(above3l2);
-            if contains_above3l2 && contains_above2 && contains_above3 && contains_above2l {
-                graph.add_edge(
-                    node,
-                    above3l2,
-                    NavGraphEdge {
-                        inputs: std::iter::repeat(PlayerControl {
-                            move_direction: vec2(-1.0, 0.0),
-                            jump_just_pressed: true,
-                            jump_pressed: true,
-                            ..default()
-                        })
-                        .take(20)
-                        .collect(),
-                        distance: node.distance(&above3l2),
-                    },
-                );
-            }
-            let above3l3 = above3.left().left().left();
-            if graph.contains_node(above3l3)
-                && graph.contains_node(above3.left())
-                && contains_above3l2
-                && contains_above2
-                && contains_above3
-                && contains_above2l
-            {
-                graph.add_edge(
-                    node,
-                    above3l3,
-                    NavGraphEdge {
-                        inputs: std::iter::repeat(PlayerControl {
-                            move_direction: vec2(-1.0, 0.0),
-                            jump_just_pressed: true,
-                            jump_pressed: true,
-                            ..default()
-                        })
-                        .take(20)
-                        .collect(),
-                        distance: node.distance(&above3l3),
-                    },
-                );
-            }
-
-            // Jump up and right
-            let above3r2 = above3.right().right();
-            let above2r = above2.right();
-            let contains_above2r = graph.contains_node(above2r);
-            let contains_above3r2 = graph.contains_node(above3r2);
-            if contains_above3r2 && contains_above2 && contains_above3 && contains_above2r {
-                graph.add_edge(
-                    node,
-                    above3r2,
-                    NavGraphEdge {
-                        inputs: std::iter::repeat(PlayerControl {
-                            move_direction: vec2(1.0, 0.0),
-                            jump_just_pressed: true,
-                            jump_pressed: true,
-                            ..default()
-                        })
-                        .take(20)
-                        .collect(),
-                        distance: node.distance(&above3r2),
-                    },
-                );
-            }
-            let above3r3 = above3.right().right().right();
-            if graph.contains_node(above3r3)
-                && graph.contains_node(above3.right())
-                && contains_above3r2
-                && contains_above2
-                && contains_above3
-                && contains_above2r
-            {
-                graph.add_edge(
-                    node,
-                    above3r3,
-                    NavGraphEdge {
-                        inputs: std::iter::repeat(PlayerControl {
-                            move_direction: vec2(1.0, 0.0),
-                            jump_just_pressed: true,
-                            jump_pressed: true,
-                            ..default()
-                        })
-                        .take(20)
-                        .collect(),
-                        distance: node.distance(&above3r3),
-                    },
-                );
-            }
-        }
-
-        /////////////////
-        // Falling Down
-        /////////////////
-
-        // Fall straight down
-        let below = node.below();
-        if graph.contains_node(below) {
-            if semi_solids.contains(&below) {
-                graph.add_edge(
-                    node,
-                    below,
-                    NavGraphEdge {
-                        inputs: [
-                            PlayerControl {
-                                move_direction: vec2(0.0, -1.0),
-                                jump_just_pressed: true,
-                                jump_pressed: true,
-                                ..default()
-                            },
-                            default(),
-                            default(),
-                            default(),
-                            default(),
-                        ]
-                        .into(),
-                        distance: node.distance(&below),
-                    },
-                );
-            } else {
-                graph.add_edge(
-                    node,
-                    below,
-                    NavGraphEdge {
-                        inputs: [PlayerControl::default()].into(),
-                        distance: node.distance(&below),
-                    },
-                );
-            }
-        }
-
-        // Fall diagonally down right
-        let below_right = node.below().right();
-        if graph.contains_node(below_right) {
-            if semi_solids.contains(&below_right) {
-                graph.add_edge(
-                    node,
-                    below_right,
-                    NavGraphEdge {
-                        inputs: [
-                            PlayerControl {
-                                move_direction: vec2(1.0, -1.0),
-                                jump_just_pressed: true,
-                                jump_pressed: true,
-                                ..default()
-                            },
-                            PlayerControl {
-                                move_direction: vec2(1.0, -1.0),
-                                jump_pressed: true,
-                                ..default()
-                            },
-                            PlayerControl {
-                                move_direction: vec2(1.0, 0.0),
-                                ..default()
-                            },
-                            PlayerControl {
-                                move_direction: vec2(1.0, 0.0),
-                                ..default()
-                            },
-                        ]
-                        .into(),
-                        distance: node.distance(&below_right),
-                    },
-                );
-            } else {
-                graph.add_edge(
-                    node,
-                    below_right,
-                    NavGraphEdge {
-                        inputs: [PlayerControl {
-                            move_direction: vec2(1.0, 0.0),
-                            ..default()
-                        }]
-                        .into(),
-                        distance: node.distance(&below_right),
-                    },
-                );
-            }
-        }
-        // Fall diagonally down left
-        let below_left = node.below().left();
-        if graph.contains_node(below_left) {
-            if semi_solids.contains(&below_left) {
-                graph.add_edge(
-                    node,
-                    below_left,
-                    NavGraphEdge {
-                        inputs: [
-                            PlayerControl {
-                                move_direction: vec2(-1.0, -1.0),
-                                jump_just_pressed: true,
-                                jump_pressed: true,
-                                ..default()
-                            },
-                            PlayerControl {
-                                move_direction: vec2(-1.0, -1.0),
-                                jump_pressed: true,
-                                ..default()
-                            },
-                            PlayerControl {
-                                move_direction: vec2(-1.0, 0.0),
-                                ..default()
-                            },
-                            PlayerControl {
-                                move_direction: vec2(-1.0, 0.0),
-                                ..default()
-                            },
-                        ]
-                        .into(),
-                        distance: node.distance(&below_left),
-                    },
-                );
-            } else {
-                graph.add_edge(
-                    node,
-                    below_left,
-                    NavGraphEdge {
-                        inputs: [PlayerControl {
-                            move_direction: vec2(-1.0, 0.0),
-                            ..default()
-                        }]
-                        .into(),
-                        distance: node.distance(&below_left),
-                    },
-                );
-            }
-        }
-
-        // Slow fall right
-        let far_right_below = node.right().right().right().right().below();
-        let path = [
-            node.right(),
-            node.right().right(),
-            node.right().right().right(),
-            node.right().right().right().right(),
-            far_right_below,
-        ];
-        if path.iter().all(|x| graph.contains_node(*x)) {
-            graph.add_edge(
-                node,
-                far_right_below,
-                NavGraphEdge {
-                    inputs: std::iter::repeat(PlayerControl {
-                        move_direction: vec2(1.0, 0.0),
-                        jump_pressed: true,
-                        ..default()
-                    })
-                    .take(20)
-                    .collect(),
-                    // Bias against using this move because it doesn't always work, by adding an
-                    // extra distance.
-                    distance: node.distance(&far_right_below) + 1.0,
-                },
-            );
-        }
-        // Slow fall left
-        let far_left_below = node.left().left().left().left().below();
-        let path = [
-            node.left(),
-            node.left().left(),
-            node.left().left().left(),
-            node.left().left().left().left(),
-            far_left_below,
-        ];
-        if path.iter().all(|x| graph.contains_node(*x)) {
-            graph.add_edge(
-                node,
-                far_left_below,
-                NavGraphEdge {
-                    inputs: std::iter::repeat(PlayerControl {
-                        move_direction: vec2(-1.0, 0.0),
-                        jump_pressed: true,
-                        ..default()
-                    })
-                    .take(20)
-                    .collect(),
-                    // Bias against using this move because it doesn't always work, by adding an
-                    // extra distance.
-                    distance: node.distance(&far_left_below) + 1.0,
-                },
-            );
-        }
-    }
-
-    Arc::new(graph)
-}
@@ -0,0 +1,922 @@
+//! Map navigation graph implementation.
+//!
+//! The [`NavGraph`] is built from the collisions of the map tiles, and kept up to date as the map
+//! is edited: whenever [`NavGraphOutdated`] is set, the tiles and elements that the graph depends
+//! on are collected into a [`NavGrid`], and only the nodes around the tiles that changed since the
+//! last update get their edges rebuilt.
+//!
+//! Besides walking, jumping and falling, the graph has edges for being launched by sproingers,
+//! dropping through jump-through platforms, and sliding across slippery elements.
+
+use std::{
+    cmp::{max, min},
+    collections::VecDeque,
+};
+
+use crate::prelude::*;
+
+/// How far a sproinger launches a player, in tiles.
+const SPROINGER_LAUNCH_HEIGHT: i32 = 6;
+/// The extra distance added to walking edges on slippery ground.
+const SLIPPERY_WALK_COST: f32 = 2.0;
+/// How far a player slides across slippery ground after letting go of the controls, in tiles.
+const SLIPPERY_SLIDE_DIST: i32 = 3;
+/// The number of frames it takes to slide [`SLIPPERY_SLIDE_DIST`] tiles.
+const SLIPPERY_SLIDE_FRAMES: usize = 20;
+/// How far the edges of a node reach horizontally, in tiles. Changing a tile affects the edges of
+/// every node within this reach.
+const EDGE_REACH_X: i32 = 5;
+/// How far the edges of a node reach below it, in tiles.
+const EDGE_REACH_DOWN: i32 = 3;
+/// How far the edges of a node reach above it, in tiles.
+const EDGE_REACH_UP: i32 = SPROINGER_LAUNCH_HEIGHT + 1;
+
+pub fn install(session: &mut SessionBuilder) {
+    session
+        .stages
+        .add_system_to_stage(CoreStage::PostUpdate, update_nav_graph);
+}
+
+/// The map navigation graph resource.
+#[derive(Clone, Debug, Deref, DerefMut, HasSchema, Default)]
+pub struct NavGraph(pub Arc<NavGraphInner>);
+
+/// The inner graph type of [`NavGraph`].
+pub type NavGraphInner = petgraph::graphmap::DiGraphMap<NavNode, NavGraphEdge>;
+
+/// The type of nodes in the map navigation graph.
+///
+/// This is merely a wrapper around [`UVec2`] to add an [`Ord`] implementation.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, Deref, DerefMut)]
+pub struct NavNode(pub IVec2);
+
+impl NavNode {
+    /// Calculates the Pythagorean distance between two nodes.
+    pub fn distance(&self, other: &Self) -> f32 {
+        let dx = (max(self.x, other.x) - min(self.x, other.x)) as f32;
+        let dy = (max(self.y, other.y) - min(self.y, other.y)) as f32;
+        (dx * dx) + (dy * dy)
+    }
+
+    pub fn right(&self) -> NavNode {
+        NavNode(self.0 + ivec2(1, 0))
+    }
+
+    pub fn above(&self) -> NavNode {
+        NavNode(self.0 + ivec2(0, 1))
+    }
+
+    pub fn left(&self) -> NavNode {
+        NavNode(self.0 - ivec2(1, 0))
+    }
+
+    pub fn below(&self) -> NavNode {
+        NavNode(self.0 - ivec2(0, 1))
+    }
+}
+
+impl From<IVec2> for NavNode {
+    fn from(v: IVec2) -> Self {
+        Self(v)
+    }
+}
+impl From<NavNode> for IVec2 {
+    fn from(v: NavNode) -> Self {
+        v.0
+    }
+}
+impl std::cmp::Ord for NavNode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let xcmp = self.0.x.cmp(&other.0.x);
+        if xcmp == std::cmp::Ordering::Equal {
+            self.0.y.cmp(&other.0.y)
+        } else {
+            xcmp
+        }
+    }
+}
+impl std::cmp::PartialOrd for NavNode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Represents the way to get from one tile to another tile in the navigation graph.
+#[derive(Debug, Clone)]
+pub struct NavGraphEdge {
+    /// The sequence of inputs for each frame, required to get to the connected tile.
+    pub inputs: VecDeque<PlayerControl>,
+    /// The distance to the connected tile. This is used as the heuristic for pathfinding.
+    pub distance: f32,
+}
+
+/// Resource indicating that the map has changed and the [`NavGraph`] needs to be updated.
+///
+/// This is set by the [`MapManager`] when the map is edited, and when map elements that the graph
+/// depends on are hydrated.
+#[derive(Clone, HasSchema, Default, Deref, DerefMut)]
+pub struct NavGraphOutdated(pub bool);
+
+/// The tiles and elements that the [`NavGraph`] was last built from.
+#[derive(Clone, HasSchema, Default)]
+pub struct NavGrid {
+    pub grid_size: UVec2,
+    /// Tiles that can't be passed through.
+    pub solids: HashSet<NavNode>,
+    /// Jump-through tiles, that can be stood on and dropped through.
+    pub semi_solids: HashSet<NavNode>,
+    /// Tiles with a sproinger in them.
+    pub sproingers: HashSet<NavNode>,
+    /// Tiles covered by slippery elements.
+    pub slippery: HashSet<NavNode>,
+}
+
+impl NavGrid {
+    /// Whether the node is inside of the map and can be passed through.
+    pub fn is_open(&self, node: NavNode) -> bool {
+        node.x >= 0
+            && node.y >= 0
+            && node.x < self.grid_size.x as i32
+            && node.y < self.grid_size.y as i32
+            && !self.solids.contains(&node)
+    }
+
+    /// Whether the node can be stood on.
+    pub fn is_solid(&self, node: NavNode) -> bool {
+        !self.is_open(node) || self.semi_solids.contains(&node)
+    }
+
+    /// Whether a player in the node is standing on slippery ground.
+    pub fn is_slippery(&self, node: NavNode) -> bool {
+        self.slippery.contains(&node) || self.slippery.contains(&node.below())
+    }
+
+    /// Collect the grid from map metadata, without spawning the map.
+    ///
+    /// Slippery elements are left out, since their size is only known once they are spawned.
+    pub fn from_map_meta(map: &MapMeta, asset_server: &AssetServer) -> Self {
+        let mut grid = NavGrid {
+            grid_size: map.grid_size,
+            ..default()
+        };
+        for layer in map.layers.iter() {
+            // Layers without a tilemap have no tiles, but they may still have elements.
+            if layer.tilemap.option().is_some() {
+                for tile in layer.tiles.iter() {
+                    let node = NavNode(tile.pos.as_ivec2());
+                    match tile.collision {
+                        TileCollisionKind::Empty => (),
+                        TileCollisionKind::JumpThrough => {
+                            grid.semi_solids.insert(node);
+                        }
+                        TileCollisionKind::Solid => {
+                            grid.solids.insert(node);
+                        }
+                    }
+                }
+            }
+            for spawn in layer.elements.iter() {
+                let is_sproinger = asset_server
+                    .try_get(spawn.element)
+                    .and_then(Result::ok)
+                    .and_then(|element| asset_server.try_get(element.data).and_then(Result::ok))
+                    .is_some_and(|data| data.try_cast_ref::<SproingerMeta>().is_ok());
+                if is_sproinger {
+                    let node = NavNode((spawn.pos / map.tile_size).as_ivec2());
+                    grid.sproingers.insert(node);
+                }
+            }
+        }
+        grid
+    }
+
+    /// Build the navigation graph for the grid.
+    pub fn create_graph(&self) -> Arc<NavGraphInner> {
+        create_nav_graph(self)
+    }
+
+    /// Update a graph that was built for the `previous` grid to match this grid, rebuilding only the
+    /// edges of the nodes around the tiles that changed.
+    fn update_graph(&self, graph: &mut Arc<NavGraphInner>, previous: &NavGrid) {
+        let changed = previous.changed_nodes(self);
+        if changed.is_empty() {
+            return;
+        }
+
+        // Find every node that may have an edge from or to a changed node.
+        let affected = changed
+            .iter()
+            .flat_map(|node| {
+                (-EDGE_REACH_X..=EDGE_REACH_X).flat_map(move |dx| {
+                    (-EDGE_REACH_UP..=EDGE_REACH_DOWN)
+                        .map(move |dy| NavNode(node.0 + ivec2(dx, dy)))
+                })
+            })
+            .filter(|node| {
+                node.x >= 0
+                    && node.y >= 0
+                    && node.x < self.grid_size.x as i32
+                    && node.y < self.grid_size.y as i32
+            })
+            .collect::<HashSet<_>>();
+        // Sort the nodes so that the graph is updated in the same order on every machine.
+        let mut affected = affected.into_iter().collect::<Vec<_>>();
+        affected.sort();
+
+        let graph = Arc::make_mut(graph);
+        for &node in &affected {
+            if graph.contains_node(node) {
+                let targets = graph.neighbors(node).collect::<Vec<_>>();
+                for target in targets {
+                    graph.remove_edge(node, target);
+                }
+            }
+            if self.is_open(node) {
+                graph.add_node(node);
+            } else {
+                graph.remove_node(node);
+            }
+        }
+        for &node in &affected {
+            if self.is_open(node) {
+                add_node_edges(graph, self, node);
+            }
+        }
+    }
+
+    /// Get the nodes that are different between the two grids.
+    fn changed_nodes(&self, other: &NavGrid) -> HashSet<NavNode> {
+        [
+            (&self.solids, &other.solids),
+            (&self.semi_solids, &other.semi_solids),
+            (&self.sproingers, &other.sproingers),
+            (&self.slippery, &other.slippery),
+        ]
+        .into_iter()
+        .flat_map(|(a, b)| a.symmetric_difference(b).copied())
+        .collect()
+    }
+}
+
+/// Collect the [`NavGrid`] from the map tiles and elements, and update the [`NavGraph`] if it has
+/// changed.
+fn update_nav_graph(
+    entities: Res<Entities>,
+    map: Res<LoadedMap>,
+    tile_layers: Comp<TileLayer>,
+    tile_collisions: Comp<TileCollisionKind>,
+    transforms: Comp<Transform>,
+    bodies: Comp<KinematicBody>,
+    sproingers: Comp<Sproinger>,
+    slippery: Comp<Slippery>,
+    mut nav_graph: ResMutInit<NavGraph>,
+    mut nav_grid: ResMutInit<NavGrid>,
+    mut outdated: ResMutInit<NavGraphOutdated>,
+) {
+    if !**outdated {
+        return;
+    }
+    **outdated = false;
+
+    let mut grid = NavGrid {
+        grid_size: map.grid_size,
+        ..default()
+    };
+    for (_ent, tile_layer) in entities.iter_with(&tile_layers) {
+        for x in 0..tile_layer.grid_size.x {
+            for y in 0..tile_layer.grid_size.y {
+                let pos = uvec2(x, y);
+                let Some(tile_ent) = tile_layer.get(pos) else {
+                    continue;
+                };
+                let node = NavNode(pos.as_ivec2());
+                match tile_collisions.get(tile_ent).copied().unwrap_or_default() {
+                    TileCollisionKind::Empty => (),
+                    TileCollisionKind::JumpThrough => {
+                        grid.semi_solids.insert(node);
+                    }
+                    TileCollisionKind::Solid => {
+                        grid.solids.insert(node);
+                    }
+                }
+            }
+        }
+    }
+    for (_ent, (_sproinger, transform)) in entities.iter_with((&sproingers, &transforms)) {
+        let node = NavNode((transform.translation.truncate() / map.tile_size).as_ivec2());
+        grid.sproingers.insert(node);
+    }
+    for (_ent, (_slippery, transform, body)) in
+        entities.iter_with((&slippery, &transforms, &bodies))
+    {
+        let rect = body.bounding_box(*transform);
+        let min = (rect.min / map.tile_size).floor().as_ivec2();
+        let max = (rect.max / map.tile_size).floor().as_ivec2();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                grid.slippery.insert(NavNode(ivec2(x, y)));
+            }
+        }
+    }
+
+    // Build the whole graph if the map has been (re)spawned.
+    if grid.grid_size != nav_grid.grid_size {
+        nav_graph.0 = create_nav_graph(&grid);
+        *nav_grid = grid;
+        return;
+    }
+
+    grid.update_graph(&mut nav_graph.0, &nav_grid);
+    *nav_grid = grid;
+}
+
+/// Helper method to create a navigation graph from the map tiles.
+fn create_nav_graph(grid: &NavGrid) -> Arc<NavGraphInner> {
+    let mut graph = NavGraphInner::default();
+
+    // Add every tile that can be passed through
+    for x in 0..grid.grid_size.x as i32 {
+        for y in 0..grid.grid_size.y as i32 {
+            let node = NavNode(ivec2(x, y));
+            if grid.is_open(node) {
+                graph.add_node(node);
+            }
+        }
+    }
+
+    // Calculate possible movements from every node
+    for node in graph.nodes().collect::<Vec<_>>() {
+        add_node_edges(&mut graph, grid, node);
+    }
+
+    Arc::new(graph)
+}
+
+/// Add the edges for every possible movement from the given node.
+fn add_node_edges(graph: &mut NavGraphInner, grid: &NavGrid, node: NavNode) {
+    // walk left or right along the ground
+    let on_slippery = grid.is_slippery(node);
+    // Bias against walking on slippery ground, where the player tends to overshoot.
+    let walk_cost = if on_slippery { SLIPPERY_WALK_COST } else { 0.0 };
+    let has_ground = grid.is_solid(node.below());
+    let maybe_has_ground =
+        has_ground || grid.is_solid(node.below().left()) || grid.is_solid(node.below().right());
+
+    /////////////////
+    // Grounded
+    /////////////////
+
+    if maybe_has_ground {
+        // Moving Right
+        let right = node.right();
+        if grid.is_open(right) {
+            graph.add_edge(
+                node,
+                right,
+                NavGraphEdge {
+                    inputs: [PlayerControl {
+                        moving: true,
+                        move_direction: vec2(1.0, 0.0),
+                        ..default()
+                    }]
+                    .into(),
+                    distance: node.distance(&right) + walk_cost,
+                },
+            );
+        }
+
+        // Moving Left
+        let left = node.left();
+        if grid.is_open(left) {
+            graph.add_edge(
+                node,
+                left,
+                NavGraphEdge {
+                    inputs: [PlayerControl {
+                        moving: true,
+                        move_direction: vec2(-1.0, 0.0),
+                        ..default()
+                    }]
+                    .into(),
+                    distance: node.distance(&left) + walk_cost,
+                },
+            );
+        }
+    }
+
+    if has_ground {
+        /////////////////
+        // JUMPING
+        /////////////////
+        let above1 = node.above();
+        let above2 = above1.above();
+        let above3 = above2.above();
+        let contains_above1 = grid.is_open(above1);
+        let contains_above2 = grid.is_open(above2);
+        let contains_above3 = grid.is_open(above3);
+
+        if contains_above1 {
+            // Jump staight up
+            graph.add_edge(
+                node,
+                above1,
+                NavGraphEdge {
+                    inputs: [PlayerControl {
+                        jump_just_pressed: true,
+                        jump_pressed: true,
+                        ..default()
+                    }]
+                    .into(),
+                    distance: node.distance(&above1),
+                },
+            );
+        }
+        if contains_above2 && contains_above1 {
+            // Jump staight up
+            graph.add_edge(
+                node,
+                above2,
+                NavGraphEdge {
+                    inputs: [PlayerControl {
+                        jump_just_pressed: true,
+                        jump_pressed: true,
+                        ..default()
+                    }]
+                    .into(),
+                    distance: node.distance(&above2),
+                },
+            );
+        }
+        if contains_above3 && contains_above2 && contains_above1 {
+            // Jump staight up
+            graph.add_edge(
+                node,
+                above2,
+                NavGraphEdge {
+                    inputs: [PlayerControl {
+                        jump_just_pressed: true,
+                        jump_pressed: true,
+                        ..default()
+                    }]
+                    .into(),
+                    distance: node.distance(&above3),
+                },
+            );
+        }
+
+        // Jump up and left
+        let above3l2 = above3.left().left();
+        let above2l = above2.left();
+        let contains_above2l = grid.is_open(above2l);
+        let contains_above3l2 = grid.is_open(above3l2);
+        if contains_above3l2 && contains_above2 && contains_above3 && contains_above2l {
+            graph.add_edge(
+                node,
+                above3l2,
+                NavGraphEdge {
+                    inputs: std::iter::repeat(PlayerControl {
+                        move_direction: vec2(-1.0, 0.0),
+                        jump_just_pressed: true,
+                        jump_pressed: true,
+                        ..default()
+                    })
+                    .take(20)
+                    .collect(),
+                    distance: node.distance(&above3l2),
+                },
+            );
+        }
+        let above3l3 = above3.left().left().left();
+        if grid.is_open(above3l3)
+            && grid.is_open(above3.left())
+            && contains_above3l2
+            && contains_above2
+            && contains_above3
+            && contains_above2l
+        {
+            graph.add_edge(
+                node,
+                above3l3,
+                NavGraphEdge {
+                    inputs: std::iter::repeat(PlayerControl {
+                        move_direction: vec2(-1.0, 0.0),
+                        jump_just_pressed: true,
+                        jump_pressed: true,
+                        ..default()
+                    })
+                    .take(20)
+                    .collect(),
+                    distance: node.distance(&above3l3),
+                },
+            );
+        }
+
+        // Jump up and right
+        let above3r2 = above3.right().right();
+        let above2r = above2.right();
+        let contains_above2r = grid.is_open(above2r);
+        let contains_above3r2 = grid.is_open(above3r2);
+        if contains_above3r2 && contains_above2 && contains_above3 && contains_above2r {
+            graph.add_edge(
+                node,
+                above3r2,
+                NavGraphEdge {
+                    inputs: std::iter::repeat(PlayerControl {
+                        move_direction: vec2(1.0, 0.0),
+                        jump_just_pressed: true,
+                        jump_pressed: true,
+                        ..default()
+                    })
+                    .take(20)
+                    .collect(),
+                    distance: node.distance(&above3r2),
+                },
+            );
+        }
+        let above3r3 = above3.right().right().right();
+        if grid.is_open(above3r3)
+            && grid.is_open(above3.right())
+            && contains_above3r2
+            && contains_above2
+            && contains_above3
+            && contains_above2r
+        {
+            graph.add_edge(
+                node,
+                above3r3,
+                NavGraphEdge {
+                    inputs: std::iter::repeat(PlayerControl {
+                        move_direction: vec2(1.0, 0.0),
+                        jump_just_pressed: true,
+                        jump_pressed: true,
+                        ..default()
+                    })
+                    .take(20)
+                    .collect(),
+                    distance: node.distance(&above3r3),
+                },
+            );
+        }
+    }
+
+    /////////////////
+    // Falling Down
+    /////////////////
+
+    // Fall straight down
+    let below = node.below();
+    if grid.is_open(below) {
+        if grid.semi_solids.contains(&below) {
+            graph.add_edge(
+                node,
+                below,
+                NavGraphEdge {
+                    inputs: [
+                        PlayerControl {
+                            move_direction: vec2(0.0, -1.0),
+                            jump_just_pressed: true,
+                            jump_pressed: true,
+                            ..default()
+                        },
+                        default(),
+                        default(),
+                        default(),
+                        default(),
+                    ]
+                    .into(),
+                    distance: node.distance(&below),
+                },
+            );
+        } else {
+            graph.add_edge(
+                node,
+                below,
+                NavGraphEdge {
+                    inputs: [PlayerControl::default()].into(),
+                    distance: node.distance(&below),
+                },
+            );
+        }
+    }
+
+    // Fall diagonally down right
+    let below_right = node.below().right();
+    if grid.is_open(below_right) {
+        if grid.semi_solids.contains(&below_right) {
+            graph.add_edge(
+                node,
+                below_right,
+                NavGraphEdge {
+                    inputs: [
+                        PlayerControl {
+                            move_direction: vec2(1.0, -1.0),
+                            jump_just_pressed: true,
+                            jump_pressed: true,
+                            ..default()
+                        },
+                        PlayerControl {
+                            move_direction: vec2(1.0, -1.0),
+                            jump_pressed: true,
+                            ..default()
+                        },
+                        PlayerControl {
+                            move_direction: vec2(1.0, 0.0),
+                            ..default()
+                        },
+                        PlayerControl {
+                            move_direction: vec2(1.0, 0.0),
+                            ..default()
+                        },
+                    ]
+                    .into(),
+                    distance: node.distance(&below_right),
+                },
+            );
+        } else {
+            graph.add_edge(
+                node,
+                below_right,
+                NavGraphEdge {
+                    inputs: [PlayerControl {
+                        move_direction: vec2(1.0, 0.0),
+                        ..default()
+                    }]
+                    .into(),
+                    distance: node.distance(&below_right),
+                },
+            );
+        }
+    }
+    // Fall diagonally down left
+    let below_left = node.below().left();
+    if grid.is_open(below_left) {
+        if grid.semi_solids.contains(&below_left) {
+            graph.add_edge(
+                node,
+                below_left,
+                NavGraphEdge {
+                    inputs: [
+                        PlayerControl {
+                            move_direction: vec2(-1.0, -1.0),
+                            jump_just_pressed: true,
+                            jump_pressed: true,
+                            ..default()
+                        },
+                        PlayerControl {
+                            move_direction: vec2(-1.0, -1.0),
+                            jump_pressed: true,
+                            ..default()
+                        },
+                        PlayerControl {
+                            move_direction: vec2(-1.0, 0.0),
+                            ..default()
+                        },
+                        PlayerControl {
+                            move_direction: vec2(-1.0, 0.0),
+                            ..default()
+                        },
+                    ]
+                    .into(),
+                    distance: node.distance(&below_left),
+                },
+            );
+        } else {
+            graph.add_edge(
+                node,
+                below_left,
+                NavGraphEdge {
+                    inputs: [PlayerControl {
+                        move_direction: vec2(-1.0, 0.0),
+                        ..default()
+                    }]
+                    .into(),
+                    distance: node.distance(&below_left),
+                },
+            );
+        }
+    }
+
+    // Slow fall right
+    let far_right_below = node.right().right().right().right().below();
+    let path = [
+        node.right(),
+        node.right().right(),
+        node.right().right().right(),
+        node.right().right().right().right(),
+        far_right_below,
+    ];
+    if path.iter().all(|x| grid.is_open(*x)) {
+        graph.add_edge(
+            node,
+            far_right_below,
+            NavGraphEdge {
+                inputs: std::iter::repeat(PlayerControl {
+                    move_direction: vec2(1.0, 0.0),
+                    jump_pressed: true,
+                    ..default()
+                })
+                .take(20)
+                .collect(),
+                // Bias against using this move because it doesn't always work, by adding an
+                // extra distance.
+                distance: node.distance(&far_right_below) + 1.0,
+            },
+        );
+    }
+    // Slow fall left
+    let far_left_below = node.left().left().left().left().below();
+    let path = [
+        node.left(),
+        node.left().left(),
+        node.left().left().left(),
+        node.left().left().left().left(),
+        far_left_below,
+    ];
+    if path.iter().all(|x| grid.is_open(*x)) {
+        graph.add_edge(
+            node,
+            far_left_below,
+            NavGraphEdge {
+                inputs: std::iter::repeat(PlayerControl {
+                    move_direction: vec2(-1.0, 0.0),
+                    jump_pressed: true,
+                    ..default()
+                })
+                .take(20)
+                .collect(),
+                // Bias against using this move because it doesn't always work, by adding an
+                // extra distance.
+                distance: node.distance(&far_left_below) + 1.0,
+            },
+        );
+    }
+
+    /////////////////
+    // Special Elements
+    /////////////////
+
+    // Drop down through the jump-through platform we are standing on
+    let below = node.below();
+    let below2 = below.below();
+    if grid.semi_solids.contains(&below) && grid.is_open(below) && grid.is_open(below2) {
+        graph.add_edge(
+            node,
+            below2,
+            NavGraphEdge {
+                inputs: [
+                    PlayerControl {
+                        move_direction: vec2(0.0, -1.0),
+                        jump_just_pressed: true,
+                        jump_pressed: true,
+                        ..default()
+                    },
+                    PlayerControl {
+                        move_direction: vec2(0.0, -1.0),
+                        jump_pressed: true,
+                        ..default()
+                    },
+                ]
+                .into_iter()
+                .chain(std::iter::repeat(default()).take(8))
+                .collect(),
+                distance: node.distance(&below2),
+            },
+        );
+    }
+
+    // Get launched upwards by a sproinger, as far as the tiles above let us
+    if grid.sproingers.contains(&node) {
+        let mut sproing_to = node;
+        for _ in 0..SPROINGER_LAUNCH_HEIGHT {
+            let above = sproing_to.above();
+            if !grid.is_open(above) {
+                break;
+            }
+            sproing_to = above;
+        }
+        if sproing_to != node {
+            graph.add_edge(
+                node,
+                sproing_to,
+                NavGraphEdge {
+                    inputs: [PlayerControl::default()].into(),
+                    distance: node.distance(&sproing_to),
+                },
+            );
+        }
+    }
+
+    // Slide across slippery ground by letting go after a single step
+    if on_slippery {
+        for direction in [-1, 1] {
+            let mut slide_to = node;
+            for _ in 0..SLIPPERY_SLIDE_DIST {
+                let next = NavNode(slide_to.0 + ivec2(direction, 0));
+                if !grid.is_open(next) || !grid.is_solid(next.below()) {
+                    break;
+                }
+                slide_to = next;
+            }
+            if slide_to != node {
+                graph.add_edge(
+                    node,
+                    slide_to,
+                    NavGraphEdge {
+                        inputs: std::iter::once(PlayerControl {
+                            moving: true,
+                            move_direction: vec2(direction as f32, 0.0),
+                            ..default()
+                        })
+                        .chain(std::iter::repeat(default()).take(SLIPPERY_SLIDE_FRAMES))
+                        .collect(),
+                        distance: node.distance(&slide_to),
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A 20x12 grid with a floor, a wall, and a jump-through platform.
+    fn test_grid() -> NavGrid {
+        let mut grid = NavGrid {
+            grid_size: uvec2(20, 12),
+            ..default()
+        };
+        grid.solids.extend((0..20).map(|x| NavNode(ivec2(x, 0))));
+        grid.solids.extend((1..4).map(|y| NavNode(ivec2(12, y))));
+        grid.semi_solids
+            .extend((4..9).map(|x| NavNode(ivec2(x, 4))));
+        grid
+    }
+
+    /// The nodes and edges of a graph, sorted so that graphs can be compared.
+    fn graph_contents(graph: &NavGraphInner) -> (Vec<NavNode>, Vec<String>) {
+        let mut nodes = graph.nodes().collect::<Vec<_>>();
+        nodes.sort();
+        let mut edges = graph
+            .all_edges()
+            .map(|(from, to, edge)| format!("{from:?} -> {to:?}: {edge:?}"))
+            .collect::<Vec<_>>();
+        edges.sort();
+        (nodes, edges)
+    }
+
+    #[test]
+    fn incremental_update_matches_full_rebuild() {
+        let mut grid = test_grid();
+        let mut graph = grid.create_graph();
+
+        let edits: [fn(&mut NavGrid); 5] = [
+            // Place a solid tile on the floor and one in the air.
+            |grid| {
+                grid.solids.insert(NavNode(ivec2(3, 1)));
+                grid.solids.insert(NavNode(ivec2(16, 6)));
+            },
+            // Knock a hole in the wall and the floor.
+            |grid| {
+                grid.solids.remove(&NavNode(ivec2(12, 2)));
+                grid.solids.remove(&NavNode(ivec2(17, 0)));
+            },
+            // Turn part of the platform solid.
+            |grid| {
+                grid.semi_solids.remove(&NavNode(ivec2(6, 4)));
+                grid.solids.insert(NavNode(ivec2(6, 4)));
+            },
+            // Add sproingers on the floor and the platform.
+            |grid| {
+                grid.sproingers.insert(NavNode(ivec2(2, 1)));
+                grid.sproingers.insert(NavNode(ivec2(5, 5)));
+            },
+            // Move a sproinger, and block its launch.
+            |grid| {
+                grid.sproingers.remove(&NavNode(ivec2(2, 1)));
+                grid.sproingers.insert(NavNode(ivec2(9, 1)));
+                grid.solids.insert(NavNode(ivec2(9, 4)));
+            },
+        ];
+        for edit in edits {
+            let mut edited = grid.clone();
+            edit(&mut edited);
+            edited.update_graph(&mut graph, &grid);
+            assert_eq!(
+                graph_contents(&graph),
+                graph_contents(&edited.create_graph())
+            );
+            grid = edited;
+        }
+    }
+
+    #[test]
+    fn unchanged_grid_keeps_the_graph() {
+        let grid = test_grid();
+        let mut graph = grid.create_graph();
+        let shared = graph.clone();
+        grid.clone().update_graph(&mut graph, &grid);
+        assert!(Arc::ptr_eq(&graph, &shared));
+    }
+}
@@ -0,0 +1,366 @@
+//! Map validation.
+//!
+//! Finds problems in maps that would otherwise only show up as odd behavior during a match, such as
+//! players spawning inside of walls or AI players that can't find their way to the others. Maps are
+//! checked from the editor, and from the command line with `jumpy lint-maps`.
+
+use petgraph::unionfind::UnionFind;
+
+use crate::prelude::*;
+
+/// A problem found in a map by [`validate_map`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapIssue {
+    /// The map has fewer player spawners than there may be players. This is only a warning, since
+    /// players take turns at the spawners when there are more players than spawners.
+    TooFewSpawners { count: usize },
+    /// A player spawner is inside of a solid tile.
+    SpawnerInSolidTile { pos: Vec2 },
+    /// Players spawned by a player spawner can't meet the players of most of the other spawners:
+    /// where they land isn't connected to where those players land.
+    UnreachableSpawner { pos: Vec2 },
+    /// An element is outside of the grid of the map.
+    ElementOutOfBounds { element: Ustr, pos: Vec2 },
+    /// A tile uses a tile index past the end of the tileset of its layer.
+    TileOutOfRange {
+        layer: Ustr,
+        pos: UVec2,
+        idx: u32,
+        tile_count: u32,
+    },
+    /// The element of an element spawn isn't loaded.
+    MissingElement { layer: Ustr, pos: Vec2 },
+    /// The tileset of a layer isn't loaded.
+    MissingTilemap { layer: Ustr },
+}
+
+impl MapIssue {
+    /// Whether the issue is only a warning, that doesn't make the map unplayable.
+    pub fn is_warning(&self) -> bool {
+        matches!(self, MapIssue::TooFewSpawners { .. })
+    }
+
+    /// Describe the issue in the language of the game. This is used both by the editor and by the
+    /// command line linter.
+    pub fn localized(&self, localization: &LocalizationAsset) -> String {
+        let pos = |pos: Vec2| (pos.x.round() as i32, pos.y.round() as i32);
+        match self {
+            MapIssue::TooFewSpawners { count } => localization.get_with(
+                "map-issue-too-few-spawners",
+                &fluent_args! { "count" => *count, "players" => MAX_PLAYERS },
+            ),
+            MapIssue::SpawnerInSolidTile { pos: p } => {
+                let (x, y) = pos(*p);
+                localization.get_with(
+                    "map-issue-spawner-in-solid-tile",
+                    &fluent_args! { "x" => x, "y" => y },
+                )
+            }
+            MapIssue::UnreachableSpawner { pos: p } => {
+                let (x, y) = pos(*p);
+                localization.get_with(
+                    "map-issue-unreachable-spawner",
+                    &fluent_args! { "x" => x, "y" => y },
+                )
+            }
+            MapIssue::ElementOutOfBounds { element, pos: p } => {
+                let (x, y) = pos(*p);
+                localization.get_with(
+                    "map-issue-element-out-of-bounds",
+                    &fluent_args! { "element" => element.as_str(), "x" => x, "y" => y },
+                )
+            }
+            MapIssue::TileOutOfRange {
+                layer,
+                pos,
+                idx,
+                tile_count,
+            } => localization.get_with(
+                "map-issue-tile-out-of-range",
+                &fluent_args! {
+                    "layer" => layer.as_str(),
+                    "x" => pos.x,
+                    "y" => pos.y,
+                    "idx" => *idx,
+                    "count" => *tile_count,
+                },
+            ),
+            MapIssue::MissingElement { layer, pos: p } => {
+                let (x, y) = pos(*p);
+                localization.get_with(
+                    "map-issue-missing-element",
+                    &fluent_args! { "layer" => layer.as_str(), "x" => x, "y" => y },
+                )
+            }
+            MapIssue::MissingTilemap { layer } => localization.get_with(
+                "map-issue-missing-tilemap",
+                &fluent_args! { "layer" => layer.as_str() },
+            ),
+        }
+    }
+}
+
+/// Check a map for problems.
+pub fn validate_map(map: &MapMeta, asset_server: &AssetServer) -> Vec<MapIssue> {
+    let mut issues = Vec::new();
+    let map_size = map.grid_size.as_vec2() * map.tile_size;
+
+    let mut spawners = Vec::new();
+    for layer in map.layers.iter() {
+        if let Some(tilemap) = layer.tilemap.option() {
+            match asset_server.try_get(tilemap).and_then(Result::ok) {
+                Some(atlas) => {
+                    let tile_count = atlas.columns * atlas.rows;
+                    for tile in layer.tiles.iter().filter(|tile| tile.idx >= tile_count) {
+                        issues.push(MapIssue::TileOutOfRange {
+                            layer: layer.id,
+                            pos: tile.pos,
+                            idx: tile.idx,
+                            tile_count,
+                        });
+                    }
+                }
+                None => issues.push(MapIssue::MissingTilemap { layer: layer.id }),
+            }
+        }
+
+        for spawn in layer.elements.iter() {
+            let Some(element) = asset_server.try_get(spawn.element).and_then(Result::ok) else {
+                issues.push(MapIssue::MissingElement {
+                    layer: layer.id,
+                    pos: spawn.pos,
+                });
+                continue;
+            };
+            if spawn.pos.cmplt(Vec2::ZERO).any() || spawn.pos.cmpge(map_size).any() {
+                issues.push(MapIssue::ElementOutOfBounds {
+                    element: element.name,
+                    pos: spawn.pos,
+                });
+            }
+            let is_player_spawner = asset_server
+                .try_get(element.data)
+                .and_then(Result::ok)
+                .is_some_and(|data| data.try_cast_ref::<PlayerSpawner>().is_ok());
+            if is_player_spawner {
+                spawners.push(spawn.pos);
+            }
+        }
+    }
+
+    if spawners.len() < MAX_PLAYERS as usize {
+        issues.push(MapIssue::TooFewSpawners {
+            count: spawners.len(),
+        });
+    }
+
+    // Find where the players of each spawner land, and check that they can all meet.
+    let grid = NavGrid::from_map_meta(map, asset_server);
+    let graph = grid.create_graph();
+    let landings = spawners
+        .iter()
+        .map(|pos| {
+            let node = NavNode((*pos / map.tile_size).floor().as_ivec2());
+            if grid.solids.contains(&node) {
+                issues.push(MapIssue::SpawnerInSolidTile { pos: *pos });
+            }
+            landing_node(&grid, node)
+        })
+        .collect::<Vec<_>>();
+    let node_components = connected_components(&graph);
+    let components = landings
+        .iter()
+        .map(|landing| landing.and_then(|node| node_components.get(&node).copied()))
+        .collect::<Vec<_>>();
+
+    // The landing nodes must all be in one component. Otherwise, the spawners outside of the
+    // component with the most spawners are the unreachable ones.
+    let mut main_component = None;
+    let mut main_count = 0;
+    for component in components.iter().flatten() {
+        let count = components
+            .iter()
+            .filter(|other| **other == Some(*component))
+            .count();
+        if count > main_count {
+            main_component = Some(*component);
+            main_count = count;
+        }
+    }
+    for (pos, component) in spawners.iter().zip(&components) {
+        if component.is_some() && *component != main_component {
+            issues.push(MapIssue::UnreachableSpawner { pos: *pos });
+        }
+    }
+
+    issues
+}
+
+/// Get the connected component of every node of the graph, ignoring the direction of the edges:
+/// players in the same component can meet, since one of them can reach the other.
+fn connected_components(graph: &NavGraphInner) -> HashMap<NavNode, usize> {
+    let nodes = graph.nodes().collect::<Vec<_>>();
+    let index = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (*node, i))
+        .collect::<HashMap<_, _>>();
+    let mut components = UnionFind::new(nodes.len());
+    for (from, to, _) in graph.all_edges() {
+        components.union(index[&from], index[&to]);
+    }
+    nodes
+        .into_iter()
+        .map(|node| (node, components.find(index[&node])))
+        .collect()
+}
+
+/// Get the node that a player falling from the given node lands on, if the node is open.
+fn landing_node(grid: &NavGrid, mut node: NavNode) -> Option<NavNode> {
+    while grid.is_open(node) {
+        if grid.is_solid(node.below()) {
+            return Some(node);
+        }
+        node = node.below();
+    }
+    None
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use super::*;
+    use crate::headless::HeadlessGame;
+
+    const TILE_SIZE: f32 = 32.0;
+
+    /// Find a core map element by name.
+    fn element(asset_server: &AssetServer, name: &str) -> Handle<ElementMeta> {
+        let meta = asset_server.root::<GameMeta>();
+        meta.core
+            .map_elements
+            .iter()
+            .copied()
+            .find(|handle| asset_server.get(*handle).name == name)
+            .unwrap()
+    }
+
+    /// The center of the tile at the given position, in pixels.
+    fn tile_center(x: u32, y: u32) -> Vec2 {
+        (UVec2::new(x, y).as_vec2() + 0.5) * TILE_SIZE
+    }
+
+    /// A 20x12 map with a floor and the given solid walls, and an element layer without a tilemap
+    /// with player spawners at the given positions.
+    fn test_map(
+        asset_server: &AssetServer,
+        walls: &[u32],
+        spawners: &[UVec2],
+        sproingers: &[UVec2],
+    ) -> MapMeta {
+        let grid_size = UVec2::new(20, 12);
+        let mut tiles = SVec::new();
+        for x in 0..grid_size.x {
+            for y in 0..grid_size.y {
+                if y == 0 || walls.contains(&x) {
+                    tiles.push(MapTileMeta {
+                        pos: UVec2::new(x, y),
+                        idx: 0,
+                        collision: TileCollisionKind::Solid,
+                    });
+                }
+            }
+        }
+        let mut elements = SVec::new();
+        let spawner = element(asset_server, "Player Spawner");
+        let sproinger = element(asset_server, "Sproinger");
+        for (handle, positions) in [(spawner, spawners), (sproinger, sproingers)] {
+            for pos in positions {
+                elements.push(ElementSpawn {
+                    pos: tile_center(pos.x, pos.y),
+                    element: handle,
+                });
+            }
+        }
+
+        let mut layers = SVec::new();
+        layers.push(MapLayerMeta {
+            id: ustr("ground"),
+            tilemap: Set(asset_server.root::<GameMeta>().core.map_tilesets[0]),
+            tiles,
+            elements: default(),
+        });
+        layers.push(MapLayerMeta {
+            id: ustr("elements"),
+            tilemap: Unset,
+            tiles: default(),
+            elements,
+        });
+        MapMeta {
+            name: ustr("test"),
+            background: default(),
+            background_color: Color::BLACK,
+            grid_size,
+            tile_size: Vec2::splat(TILE_SIZE),
+            layers,
+        }
+    }
+
+    #[test]
+    fn sproingers_of_layers_without_tiles_are_in_the_nav_grid() {
+        let headless = HeadlessGame::for_tests();
+        let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+        let map = test_map(&asset_server, &[], &[], &[UVec2::new(4, 1)]);
+
+        let grid = NavGrid::from_map_meta(&map, &asset_server);
+        assert!(grid.sproingers.contains(&NavNode(ivec2(4, 1))));
+        assert!(grid.solids.contains(&NavNode(ivec2(4, 0))));
+    }
+
+    #[test]
+    fn spawners_must_all_be_connected() {
+        let headless = HeadlessGame::for_tests();
+        let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+        let spawners = [UVec2::new(2, 1), UVec2::new(5, 1), UVec2::new(15, 1)];
+        let unreachable = |walls: &[u32]| {
+            let map = test_map(&asset_server, walls, &spawners, &[]);
+            validate_map(&map, &asset_server)
+                .into_iter()
+                .filter(|issue| matches!(issue, MapIssue::UnreachableSpawner { .. }))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(unreachable(&[]).is_empty());
+        // A wall as high as the map cuts the last spawner off from the others.
+        assert_eq!(
+            unreachable(&[10]),
+            [MapIssue::UnreachableSpawner {
+                pos: tile_center(15, 1)
+            }]
+        );
+        // With a second wall, every spawner is on its own, so only the first one counts as
+        // reachable.
+        assert_eq!(
+            unreachable(&[4, 10]),
+            [
+                MapIssue::UnreachableSpawner {
+                    pos: tile_center(5, 1)
+                },
+                MapIssue::UnreachableSpawner {
+                    pos: tile_center(15, 1)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn too_few_spawners_is_only_a_warning() {
+        let headless = HeadlessGame::for_tests();
+        let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+        let map = test_map(&asset_server, &[], &[UVec2::new(2, 1)], &[]);
+
+        let issues = validate_map(&map, &asset_server);
+        assert_eq!(issues, [MapIssue::TooFewSpawners { count: 1 }]);
+        assert!(issues[0].is_warning());
+        assert!(!MapIssue::SpawnerInSolidTile { pos: Vec2::ZERO }.is_warning());
+    }
+}
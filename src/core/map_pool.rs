@@ -18,13 +18,21 @@ impl MapPool {
 
     /// Convert [`MapPoolNetwork`] into a [`MapPool`]
     pub fn from_network(map_pool: MapPoolNetwork, assets: &AssetServer) -> MapPool {
+        Self::from_network_with(map_pool, assets, |_| None)
+    }
+
+    /// Convert [`MapPoolNetwork`] into a [`MapPool`], using the maps returned by `resolve` in
+    /// place of the maps of the network handles, such as maps that were transferred from the host.
+    pub fn from_network_with(
+        map_pool: MapPoolNetwork,
+        assets: &AssetServer,
+        resolve: impl Fn(&NetworkHandle<MapMeta>) -> Option<Handle<MapMeta>>,
+    ) -> MapPool {
+        let into_handle =
+            |h: &NetworkHandle<MapMeta>| resolve(h).unwrap_or_else(|| h.into_handle(assets));
         MapPool {
-            maps: map_pool
-                .maps
-                .iter()
-                .map(|h| h.into_handle(assets))
-                .collect(),
-            current_map: map_pool.current_map.into_handle(assets),
+            maps: map_pool.maps.iter().map(into_handle).collect(),
+            current_map: into_handle(&map_pool.current_map),
         }
     }
 
@@ -57,7 +65,7 @@ impl MapPool {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct MapPoolNetwork {
     pub maps: Vec<NetworkHandle<MapMeta>>,
     pub current_map: NetworkHandle<MapMeta>,
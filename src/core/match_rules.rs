@@ -0,0 +1,479 @@
+//! Configurable rules for how a round is played and won.
+//!
+//! By default the last player standing wins the round. [`MatchRules`] can additionally give
+//! players several lives, respawning them at a player spawner until they run out, and limit the
+//! length of a round. When the time runs out, the round is either decided by a tie-breaker, or goes
+//! into sudden death, where a kill zone shrinks towards the middle of the map.
+
+use crate::prelude::*;
+
+/// The round time limits, in seconds, that may be selected in the menu. `0` means no limit.
+pub const TIME_LIMIT_OPTIONS: [u32; 6] = [0, 60, 90, 120, 180, 300];
+
+/// The maximum number of lives that may be selected in the menu.
+pub const MAX_LIVES: u32 = 9;
+
+/// The Z depth of the sudden death kill zone outline. Far enough to be in front of the map layers.
+const KILL_ZONE_Z: f32 = -700.0;
+
+pub fn install(session: &mut SessionBuilder) {
+    session
+        .init_resource::<MatchRules>()
+        .init_resource::<RoundRulesState>()
+        .add_system_to_stage(CoreStage::PostUpdate, update_round_rules);
+}
+
+/// The rules of the match, selected before the match starts.
+#[derive(HasSchema, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchRules {
+    /// The number of lives each player has per round.
+    pub lives: u32,
+    /// The time limit of each round in seconds, or `0` for no time limit.
+    pub time_limit_secs: u32,
+    /// Whether to go into sudden death when the time runs out. If this is `false`, the player with
+    /// the most lives left wins the round instead, whether they are alive or waiting to respawn.
+    pub sudden_death: bool,
+    /// Whether players can kill their teammates when playing in teams.
+    pub friendly_fire: bool,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        Self {
+            lives: 1,
+            time_limit_secs: 0,
+            sudden_death: false,
+            friendly_fire: false,
+        }
+    }
+}
+
+impl MatchRules {
+    /// Get the round time limit, if any.
+    pub fn time_limit(&self) -> Option<Duration> {
+        (self.time_limit_secs > 0).then(|| Duration::from_secs(self.time_limit_secs as u64))
+    }
+}
+
+/// The phase of the round according to the [`MatchRules`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoundPhase {
+    /// The round is being played normally.
+    #[default]
+    Normal,
+    /// The time ran out and the kill zone is shrinking.
+    SuddenDeath,
+    /// The time ran out and the round was decided by the tie-breaker. `None` means a draw.
+    TimeUp { winner: Option<PlayerIdx> },
+}
+
+/// The state of the current round's rules.
+#[derive(HasSchema, Clone, Default)]
+pub struct RoundRulesState {
+    /// The lives each player has left.
+    pub lives: [u32; MAX_PLAYERS as usize],
+    /// The time that the round has been played for.
+    pub elapsed: Duration,
+    pub phase: RoundPhase,
+    /// The area outside of which players are killed during sudden death.
+    pub kill_zone: Option<Rect>,
+    /// The time that sudden death has been going on for.
+    pub sudden_death_elapsed: Duration,
+    kill_zone_outline: Option<Entity>,
+    initialized: bool,
+}
+
+impl RoundRulesState {
+    /// Whether the player has lives left and will respawn after dying.
+    pub fn will_respawn(&self, player: PlayerIdx) -> bool {
+        self.phase == RoundPhase::Normal && self.lives[player.0 as usize] > 0
+    }
+
+    /// Time left before the time limit of the round runs out, if it has one.
+    pub fn time_left(&self, rules: &MatchRules) -> Option<Duration> {
+        rules
+            .time_limit()
+            .map(|limit| limit.saturating_sub(self.elapsed))
+    }
+
+    /// Take a life from a player that died.
+    fn take_life(&mut self, player: PlayerIdx) {
+        let lives = &mut self.lives[player.0 as usize];
+        *lives = lives.saturating_sub(1);
+    }
+}
+
+/// Decide the round when the time runs out, from the lives left of the players in the match.
+///
+/// The player, or team, with the most lives left wins, or it's a draw on a tie. The lives of
+/// teammates are added together.
+fn tie_breaker(
+    players: impl IntoIterator<Item = (PlayerIdx, u32)>,
+    are_teammates: impl Fn(PlayerIdx, PlayerIdx) -> bool,
+) -> Option<PlayerIdx> {
+    let mut teams: Vec<(PlayerIdx, u32)> = Vec::new();
+    for (idx, lives) in players {
+        match teams
+            .iter_mut()
+            .find(|(other, _)| are_teammates(*other, idx))
+        {
+            Some((_, team_lives)) => *team_lives += lives,
+            None => teams.push((idx, lives)),
+        }
+    }
+    teams.sort_by_key(|(_, lives)| std::cmp::Reverse(*lives));
+    match teams.as_slice() {
+        [(winner, _)] => Some(*winner),
+        [(winner, most), (_, next), ..] if most > next => Some(*winner),
+        _ => None,
+    }
+}
+
+/// Get the sudden death kill zone of a map, once it has shrunk by `progress`, from `0.0` for the
+/// whole map to `1.0` for nothing left.
+fn kill_zone(map_size: Vec2, progress: f32) -> Rect {
+    let size = map_size * (1.0 - progress.clamp(0.0, 1.0));
+    let center = map_size / 2.0;
+    Rect::new(center.x, center.y, size.x, size.y)
+}
+
+/// Marker component for dead players whose death has already been counted against their lives.
+#[derive(HasSchema, Clone, Default)]
+pub struct DeathCounted;
+
+fn update_round_rules(
+    meta: Root<GameMeta>,
+    mut entities: ResMut<Entities>,
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Res<LoadedMap>,
+    rules: Res<MatchRules>,
+    player_inputs: Res<MatchInputs>,
+    scoring: ResInit<RoundScoringState>,
+    mut state: ResMut<RoundRulesState>,
+    player_indices: Comp<PlayerIdx>,
+    killed_players: Comp<PlayerKilled>,
+    mut deaths_counted: CompMut<DeathCounted>,
+    mut transforms: CompMut<Transform>,
+    mut paths: CompMut<Path2d>,
+) {
+    if !state.initialized {
+        state.initialized = true;
+        for (lives, player) in state.lives.iter_mut().zip(&player_inputs.players) {
+            *lives = if player.active { rules.lives.max(1) } else { 0 };
+        }
+    }
+
+    // Take a life from every player that died this frame.
+    let mut newly_killed = killed_players.bitset().clone();
+    newly_killed.bit_andnot(deaths_counted.bitset());
+    for ent in entities.iter_with_bitset(&newly_killed) {
+        let Some(player_idx) = player_indices.get(ent) else {
+            continue;
+        };
+        state.take_life(*player_idx);
+        deaths_counted.insert(ent, DeathCounted);
+    }
+
+    // Don't change phases once the round has been decided.
+    if scoring.timer.is_some() {
+        return;
+    }
+
+    match state.phase {
+        RoundPhase::Normal => {
+            let Some(time_limit) = rules.time_limit() else {
+                return;
+            };
+            state.elapsed += time.delta();
+            if state.elapsed < time_limit {
+                return;
+            }
+
+            if rules.sudden_death {
+                info!("Round time is up, starting sudden death.");
+                state.phase = RoundPhase::SuddenDeath;
+                state.lives = [0; MAX_PLAYERS as usize];
+            } else {
+                // Players waiting to respawn are still in the round, so only the lives left
+                // count, not who happens to be alive when the time runs out.
+                let players = player_inputs
+                    .players
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, player)| player.active)
+                    .map(|(i, _)| (PlayerIdx(i as u32), state.lives[i]));
+                let winner = tie_breaker(players, |a, b| player_inputs.are_teammates(a, b));
+                info!("Round time is up, winner by tie-breaker: {winner:?}");
+                state.phase = RoundPhase::TimeUp { winner };
+            }
+        }
+        RoundPhase::SuddenDeath => {
+            state.sudden_death_elapsed += time.delta();
+
+            // Shrink the kill zone from the map bounds to the middle of the map.
+            let map_size = map.grid_size.as_vec2() * map.tile_size;
+            let shrink_time = meta.core.config.sudden_death_shrink_time.as_secs_f32();
+            let progress = if shrink_time > 0.0 {
+                state.sudden_death_elapsed.as_secs_f32() / shrink_time
+            } else {
+                1.0
+            };
+            let kill_zone = kill_zone(map_size, progress);
+            state.kill_zone = Some(kill_zone);
+
+            for (ent, (_idx, transform, killed)) in
+                entities.iter_with((&player_indices, &transforms, &Optional(&killed_players)))
+            {
+                let pos = transform.translation.truncate();
+                if killed.is_none() && !kill_zone.contains(pos) {
+                    // Like falling out of the map, this counts as a suicide in the statistics.
+                    commands.add(PlayerCommand::kill_by(
+                        ent,
+                        Some(pos),
+                        KillSource::new(Some(ent), None),
+                    ));
+                }
+            }
+
+            // Outline the kill zone
+            let outline = *state.kill_zone_outline.get_or_insert_with(|| {
+                let ent = entities.create();
+                transforms.insert(
+                    ent,
+                    Transform::from_translation(vec3(0.0, 0.0, KILL_ZONE_Z)),
+                );
+                ent
+            });
+            paths.insert(
+                outline,
+                Path2d {
+                    points: vec![
+                        kill_zone.bottom_left(),
+                        kill_zone.top_left(),
+                        kill_zone.top_right(),
+                        kill_zone.bottom_right(),
+                        kill_zone.bottom_left(),
+                    ],
+                    thickness: 2.0,
+                    color: Color::RED,
+                    ..default()
+                },
+            );
+        }
+        RoundPhase::TimeUp { .. } => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn players_respawn_until_they_run_out_of_lives() {
+        let mut state = RoundRulesState::default();
+        state.lives[0] = 2;
+        state.take_life(PlayerIdx(0));
+        assert!(state.will_respawn(PlayerIdx(0)));
+        state.take_life(PlayerIdx(0));
+        assert!(!state.will_respawn(PlayerIdx(0)));
+        state.take_life(PlayerIdx(0));
+        assert_eq!(state.lives[0], 0);
+
+        // Nobody respawns once the time is up.
+        state.lives[1] = 3;
+        state.phase = RoundPhase::SuddenDeath;
+        assert!(!state.will_respawn(PlayerIdx(1)));
+    }
+
+    #[test]
+    fn tie_breaker_picks_the_most_lives() {
+        let no_teams = |_, _| false;
+        let players = |lives: &[u32]| {
+            lives
+                .iter()
+                .enumerate()
+                .map(|(i, lives)| (PlayerIdx(i as u32), *lives))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tie_breaker(players(&[1, 3, 2]), no_teams),
+            Some(PlayerIdx(1))
+        );
+        assert_eq!(tie_breaker(players(&[2]), no_teams), Some(PlayerIdx(0)));
+        assert_eq!(tie_breaker(players(&[3, 1, 3]), no_teams), None);
+        assert_eq!(tie_breaker(players(&[]), no_teams), None);
+
+        // The lives of teammates are added together.
+        let even_odd_teams = |a: PlayerIdx, b: PlayerIdx| a != b && a.0 % 2 == b.0 % 2;
+        assert_eq!(
+            tie_breaker(players(&[1, 3, 3]), even_odd_teams),
+            Some(PlayerIdx(0))
+        );
+    }
+
+    #[test]
+    fn kill_zone_shrinks_to_the_middle_of_the_map() {
+        let map_size = vec2(400.0, 200.0);
+        let zone = kill_zone(map_size, 0.0);
+        assert_eq!((zone.min, zone.max), (Vec2::ZERO, map_size));
+
+        let zone = kill_zone(map_size, 0.5);
+        assert_eq!(
+            (zone.min, zone.max),
+            (vec2(100.0, 50.0), vec2(300.0, 150.0))
+        );
+
+        let zone = kill_zone(map_size, 2.0);
+        assert_eq!(
+            (zone.min, zone.max),
+            (vec2(200.0, 100.0), vec2(200.0, 100.0))
+        );
+        assert!(!zone.contains(vec2(100.0, 100.0)));
+    }
+
+    /// Kill the given player in the match of a headless game.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn kill_player(headless: &crate::headless::HeadlessGame, player: PlayerIdx) {
+        headless.match_world().unwrap().run_system(
+            move |entities: Res<Entities>,
+                  player_indices: Comp<PlayerIdx>,
+                  mut commands: Commands| {
+                for (ent, idx) in entities.iter_with(&player_indices) {
+                    if *idx == player {
+                        commands.add(PlayerCommand::kill(ent, None));
+                    }
+                }
+            },
+            (),
+        );
+    }
+
+    /// Play a round with a time limit and no sudden death in a headless match, where the first
+    /// player loses a life.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn round_goes_to_the_most_lives_when_the_time_is_up() {
+        use crate::headless::{FrameControls, HeadlessGame};
+
+        let mut headless = HeadlessGame::for_tests();
+        let meta = headless.meta();
+        let maps = MapPool::from_single_map(headless.find_map(None).unwrap());
+        let player_info = std::array::from_fn(|i| PlayerInput {
+            active: i < 2,
+            selected_player: meta.core.players[0],
+            ..default()
+        });
+        let rules = MatchRules {
+            lives: 2,
+            time_limit_secs: 1,
+            ..default()
+        };
+        headless.start_match(maps, player_info, rules, Vec::<FrameControls>::new());
+
+        // Let the players spawn, then kill the first one.
+        headless.run(10);
+        kill_player(&headless, PlayerIdx(0));
+        headless.run(FPS as u32);
+
+        let world = headless.match_world().unwrap();
+        let state = world.resource::<RoundRulesState>();
+        assert_eq!(state.lives[..2], [1, 2]);
+        assert_eq!(
+            state.phase,
+            RoundPhase::TimeUp {
+                winner: Some(PlayerIdx(1))
+            }
+        );
+
+        // The round is scored for the winner of the tie-breaker.
+        for _ in 0..FPS as u32 * 10 {
+            if headless
+                .match_world()
+                .unwrap()
+                .resource::<MatchScore>()
+                .rounds_completed()
+                > 0
+            {
+                break;
+            }
+            headless.step();
+        }
+        let score = headless.match_world().unwrap().resource::<MatchScore>();
+        assert_eq!(score.rounds_completed(), 1);
+        assert_eq!(score.score(PlayerIdx(0)), 0);
+        assert_eq!(score.score(PlayerIdx(1)), 1);
+    }
+
+    /// A player that dies just before the time runs out is waiting to respawn when the tie-breaker
+    /// is decided, but still wins the round with the most lives left.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn tie_breaker_counts_players_waiting_to_respawn() {
+        use crate::headless::{FrameControls, HeadlessGame};
+
+        let mut headless = HeadlessGame::for_tests();
+        let meta = headless.meta();
+        let maps = MapPool::from_single_map(headless.find_map(None).unwrap());
+        let player_info = std::array::from_fn(|i| PlayerInput {
+            active: i < 2,
+            selected_player: meta.core.players[0],
+            ..default()
+        });
+        let rules = MatchRules {
+            lives: 3,
+            time_limit_secs: 1,
+            ..default()
+        };
+        headless.start_match(maps, player_info, rules, Vec::<FrameControls>::new());
+
+        // The first player has already lost two lives, and the second one dies right before the
+        // time runs out.
+        headless.run(10);
+        headless
+            .match_world()
+            .unwrap()
+            .resource_mut::<RoundRulesState>()
+            .lives[0] = 1;
+        headless.run(FPS as u32 - 20);
+        kill_player(&headless, PlayerIdx(1));
+        headless.run(20);
+
+        let world = headless.match_world().unwrap();
+        let second_player_alive = world.run_system(
+            |entities: Res<Entities>,
+             player_indices: Comp<PlayerIdx>,
+             killed_players: Comp<PlayerKilled>| {
+                entities
+                    .iter_with((&player_indices, &Optional(&killed_players)))
+                    .any(|(_, (idx, killed))| *idx == PlayerIdx(1) && killed.is_none())
+            },
+            (),
+        );
+        assert!(!second_player_alive);
+        let state = world.resource::<RoundRulesState>();
+        assert_eq!(state.lives[..2], [1, 2]);
+        assert_eq!(
+            state.phase,
+            RoundPhase::TimeUp {
+                winner: Some(PlayerIdx(1))
+            }
+        );
+
+        for _ in 0..FPS as u32 * 10 {
+            if headless
+                .match_world()
+                .unwrap()
+                .resource::<MatchScore>()
+                .rounds_completed()
+                > 0
+            {
+                break;
+            }
+            headless.step();
+        }
+        let score = headless.match_world().unwrap().resource::<MatchScore>();
+        assert_eq!(score.rounds_completed(), 1);
+        assert_eq!(score.score(PlayerIdx(1)), 1);
+    }
+}
@@ -120,4 +120,9 @@ pub struct CoreConfigMeta {
 
     /// How many rounds between intermissions
     pub rounds_between_intermission: u32,
+
+    /// How long it takes the sudden death kill zone to shrink to nothing
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub sudden_death_shrink_time: Duration,
 }
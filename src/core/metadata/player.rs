@@ -202,3 +202,17 @@ pub struct HatMeta {
     pub offset: Vec2,
     pub body_size: Vec2,
 }
+
+/// Metadata for an AI behavior shipped by a pack.
+///
+/// The behavior's plugin drives the AI players that were given this behavior in player select
+/// through their [`LuaAiPlayer`] component. Like map elements, the plugin can tell its own players
+/// apart by the schema of the behavior's `data`.
+#[derive(HasSchema, Clone, Debug, Default)]
+#[type_data(metadata_asset("ai"))]
+#[repr(C)]
+pub struct AiBehaviorMeta {
+    pub name: Ustr,
+    pub data: Handle<SchemaBox>,
+    pub plugin: Handle<LuaPlugin>,
+}
@@ -1,32 +1,47 @@
 //! Player controller, states, and animation implementation.
 
-use std::collections::VecDeque;
-
 use crate::prelude::*;
 
+mod ai;
+mod lua_ai;
 mod state;
+pub use ai::*;
+pub use lua_ai::*;
 pub use state::*;
-use turborand::GenCore;
 
-const PLAYER_COLORS: [Color; 4] = [
+const PLAYER_COLORS: [Color; MAX_PLAYERS as usize] = [
     Color::RED,
     Color::GREEN,
     Color::BLUE,
     Color::rgb(1.0, 0.0, 1.0),
+    Color::rgb(1.0, 0.5, 0.0),
+    Color::rgb(0.0, 1.0, 1.0),
+    Color::rgb(1.0, 1.0, 0.0),
+    Color::WHITE,
 ];
 
 pub fn plugin(session: &mut SessionBuilder) {
     session.install_plugin(state::plugin);
 
+    // Apply the lua AI controls in their own stage, after the behaviors have written them in
+    // `CoreStage::First`, and before the player states use them.
+    session
+        .stages
+        .insert_stage_before(PlayerStateStage, SimpleSystemStage::new(LuaAiStage))
+        .add_system_to_stage(LuaAiStage, apply_lua_ai_controls);
+
     // Add other player systems
     session
         .stages
         .add_system_to_stage(CoreStage::First, hydrate_players)
+        .add_system_to_stage(CoreStage::First, replace_disconnected_players)
+        .add_system_to_stage(CoreStage::First, update_ai_knowledge)
         .add_system_to_stage(CoreStage::First, player_ai_system)
         .add_system_to_stage(CoreStage::PostUpdate, play_itemless_fin_animations)
         .add_system_to_stage(CoreStage::PostUpdate, player_facial_animations)
         .add_system_to_stage(CoreStage::PostUpdate, equip_hats)
         .add_system_to_stage(CoreStage::Last, delete_dead_ai_swords)
+        .add_system_to_stage(CoreStage::Last, find_lua_ai_paths)
         .add_system_to_stage(CoreStage::Last, update_player_layers);
 }
 
@@ -203,6 +218,8 @@ impl Emote {
 #[derive(Clone, HasSchema, Default)]
 pub struct PlayerKilled {
     pub hit_from: Option<Vec2>,
+    /// What killed the player.
+    pub source: KillSource,
 }
 
 /// Events that can be used to trigger player actions, such as killing, setting inventory, etc.
@@ -214,10 +231,24 @@ impl PlayerCommand {
     ///
     /// > **Note:** This doesn't despawn the player, it just puts the player into it's death animation.
     pub fn kill(player: Entity, hit_from: Option<Vec2>) -> StaticSystem<(), ()> {
+        Self::kill_by(player, hit_from, default())
+    }
+
+    /// Kill a player, attributing the kill to the given source in the match statistics.
+    ///
+    /// If the source player is the killed player, the kill counts as a suicide.
+    pub fn kill_by(
+        player: Entity,
+        hit_from: Option<Vec2>,
+        source: KillSource,
+    ) -> StaticSystem<(), ()> {
         (move |entities: Res<Entities>,
                mut players_killed: CompMut<PlayerKilled>,
                mut items_dropped: CompMut<ItemDropped>,
                mut inventories: CompMut<Inventory>,
+               mut score: ResMutInit<MatchScore>,
+               rules: Res<MatchRules>,
+               player_inputs: Res<MatchInputs>,
                player_indexes: Comp<PlayerIdx>| {
             if players_killed.contains(player) {
                 // No need to kill him again
@@ -230,6 +261,17 @@ impl PlayerCommand {
                 return;
             };
 
+            let killer = source
+                .player
+                .and_then(|killer| player_indexes.get(killer))
+                .copied();
+            if let Some(killer) = killer {
+                if !rules.can_kill(&player_inputs, killer, *idx) {
+                    // Teammates can't kill each other without friendly fire.
+                    return;
+                }
+            }
+
             debug!("Killing player: {}", idx.0);
 
             // Drop any items the player was carrying
@@ -243,7 +285,9 @@ impl PlayerCommand {
             // Update the inventory
             inventories.insert(player, Inventory(None));
 
-            players_killed.insert(player, PlayerKilled { hit_from });
+            score.record_kill(*idx, killer, source.element);
+
+            players_killed.insert(player, PlayerKilled { hit_from, source });
         })
         .system()
     }
@@ -294,7 +338,9 @@ impl PlayerCommand {
     pub fn set_inventory(player: Entity, item: Option<Entity>) -> StaticSystem<(), ()> {
         (move |mut items_grabbed: CompMut<ItemGrabbed>,
                mut items_dropped: CompMut<ItemDropped>,
-               mut inventories: CompMut<Inventory>| {
+               mut inventories: CompMut<Inventory>,
+               mut score: ResMutInit<MatchScore>,
+               player_indexes: Comp<PlayerIdx>| {
             let inventory = inventories.get(player).cloned().unwrap_or_default();
 
             // If there was a previous item, drop it
@@ -305,6 +351,10 @@ impl PlayerCommand {
             // If there is a new item, grab it
             if let Some(item) = item {
                 items_grabbed.insert(item, ItemGrabbed { player });
+
+                if let Some(idx) = player_indexes.get(player) {
+                    score.stats_mut(*idx).items_picked_up += 1;
+                }
             }
 
             // Update the inventory
@@ -349,217 +399,6 @@ impl PlayerCommand {
     }
 }
 
-#[derive(Clone, Debug, HasSchema)]
-pub struct AiPlayer {
-    /// Tick timer that is used for AI pausing logic.
-    tick: Timer,
-    /// Indicates the player is taking pause for the given number of ticks.
-    pausing: u32,
-    /// Buffers planned AI movements
-    movement_buffer: Option<VecDeque<PlayerControl>>,
-    /// The player that the AI is targeting.
-    target_player: Option<Entity>,
-}
-
-impl Default for AiPlayer {
-    fn default() -> Self {
-        Self {
-            tick: Timer::from_seconds(0.5, TimerMode::Repeating),
-            pausing: 0,
-            movement_buffer: Default::default(),
-            target_player: Default::default(),
-        }
-    }
-}
-
-#[derive(Debug, HasSchema, Clone)]
-#[schema(no_default)]
-pub struct PathfindingDebugLines {
-    pub entities: Vec<Entity>,
-}
-
-impl FromWorld for PathfindingDebugLines {
-    fn from_world(world: &World) -> Self {
-        let entities = world.run_system(
-            |mut entities: ResMut<Entities>, mut transforms: CompMut<Transform>| {
-                (0..MAX_PLAYERS)
-                    .map(|_| {
-                        let ent = entities.create();
-
-                        transforms
-                            .insert(ent, Transform::from_translation(Vec3::new(0.0, 0.0, -1.0)));
-
-                        ent
-                    })
-                    .collect::<Vec<_>>()
-            },
-            (),
-        );
-
-        Self { entities }
-    }
-}
-
-fn player_ai_system(
-    entities: Res<Entities>,
-    nav_graph: ResMutInit<NavGraph>,
-    mut player_inputs: ResMutInit<MatchInputs>,
-    mut ai_players: CompMut<AiPlayer>,
-    player_indexes: Comp<PlayerIdx>,
-    map: Res<LoadedMap>,
-    transforms: Comp<Transform>,
-    pathfinding_debug_line: ResMutInit<PathfindingDebugLines>,
-    mut paths: CompMut<Path2d>,
-    bodies: Comp<KinematicBody>,
-    debug_settings: ResInit<DebugSettings>,
-    rng: Res<GlobalRng>,
-    time: Res<Time>,
-) {
-    const SWORD_SWING_DIST: f32 = 10.0;
-    const AI_SPEED_MULTIPLIER: f32 = 0.65;
-
-    for (ai_ent, (player_idx, transform, ai_player)) in
-        entities.iter_with((&player_indexes, &transforms, &mut ai_players))
-    {
-        // Tick the AI timer
-        ai_player.tick.tick(time.delta());
-
-        // If a tick has elapsed
-        if ai_player.tick.just_finished() {
-            // If the player isn't pausing, then there's a 40% chance
-            if ai_player.pausing == 0 && rng.chance(0.4) {
-                // That we will pause for a random number of ticks between 0 and 2
-                ai_player.pausing = (rng.f32_normalized() * 2.0).round() as u32
-            }
-
-            // If the player is pausing
-            if ai_player.pausing > 0 {
-                // Subtract a tick from how long they should pause.
-                ai_player.pausing -= 1;
-            }
-        }
-
-        // If the player is pausing, don't have the AI move this frame.
-        if ai_player.pausing > 0 {
-            continue;
-        }
-
-        let target_transform = match ai_player.target_player {
-            Some(target_player) if transforms.contains(target_player) => {
-                transforms.get(target_player).unwrap()
-            }
-            _ => {
-                let players = entities
-                    .iter_with((&player_indexes, &transforms))
-                    .filter(|(ent, _)| *ent != ai_ent)
-                    .collect::<Vec<_>>();
-                if players.is_empty() {
-                    continue;
-                }
-
-                let (target_player, (_, transform)) = players[rng.gen_usize() % players.len()];
-
-                ai_player.target_player = Some(target_player);
-                transform
-            }
-        };
-        let target_pos = target_transform.translation.truncate();
-        let tile = (target_pos / map.tile_size).floor().as_ivec2();
-        let target_node = NavNode(tile);
-        let ai_pos = transform.translation.truncate();
-        let tile = (ai_pos / map.tile_size).floor().as_ivec2();
-        let current_node = NavNode(tile);
-
-        // Complete any previous movement instructions if we are in the middle of any
-        if let Some(movement_buffer) = &mut ai_player.movement_buffer {
-            if let Some(control) = movement_buffer.pop_front() {
-                player_inputs.players[player_idx.0 as usize].control = control;
-                if movement_buffer.is_empty() {
-                    ai_player.movement_buffer = None;
-                }
-                continue;
-            }
-        }
-
-        let path = petgraph::algo::astar(
-            nav_graph.as_ref(),
-            current_node,
-            |x| x == target_node,
-            |(_, _, edge)| edge.distance,
-            |_| 0.0,
-        );
-
-        if let Some((_cost, path)) = path {
-            if debug_settings.show_pathfinding_lines {
-                paths.insert(
-                    pathfinding_debug_line.entities[player_idx.0 as usize],
-                    Path2d {
-                        points: path
-                            .iter()
-                            .map(|x| x.0.as_vec2() * map.tile_size + map.tile_size / 2.0)
-                            .collect(),
-                        thickness: 2.0,
-                        color: PLAYER_COLORS[player_idx.0 as usize],
-                        ..default()
-                    },
-                );
-            }
-
-            if let Some(&next_node) = path.get(1) {
-                let edge = nav_graph.edge_weight(current_node, next_node).unwrap();
-                let mut movement_buffer = edge.inputs.clone();
-                let mut first_movement = movement_buffer.pop_front().unwrap();
-
-                // Slow down the AI movement according to the fixed multiplier
-                first_movement.move_direction *= vec2(AI_SPEED_MULTIPLIER, 1.0);
-
-                // This is a hack to prevent us from getting stuck when we think we should be falling
-                // straight down and we actually need to move off of the block we're half-standing on.
-                //
-                // If we aren't moving at all, just move in the direction of player 1
-                if bodies.get(ai_ent).unwrap().velocity == Vec2::ZERO
-                    && first_movement.move_direction == Vec2::ZERO
-                {
-                    let sign = (path.get(2).unwrap_or(&next_node).x as f32 * map.tile_size.x
-                        - transform.translation.x)
-                        .signum();
-                    first_movement.move_direction.x = sign;
-                }
-
-                player_inputs.players[player_idx.0 as usize].control = first_movement;
-                if !movement_buffer.is_empty() {
-                    ai_player.movement_buffer = Some(movement_buffer)
-                }
-            }
-
-            if (target_pos - ai_pos).length() < SWORD_SWING_DIST {
-                player_inputs.players[player_idx.0 as usize]
-                    .control
-                    .shoot_just_pressed = true;
-                player_inputs.players[player_idx.0 as usize]
-                    .control
-                    .shoot_pressed = true;
-            }
-        } else if debug_settings.show_pathfinding_lines {
-            let pos =
-                current_node.0.as_vec2() * map.tile_size + map.tile_size / 2.0 - vec2(0.0, 4.0);
-            paths.insert(
-                pathfinding_debug_line.entities[player_idx.0 as usize],
-                Path2d {
-                    points: vec![pos, pos + vec2(0.0, 4.0)],
-                    thickness: 8.0,
-                    color: Color::RED,
-                    ..default()
-                },
-            );
-        }
-
-        if !debug_settings.show_pathfinding_lines {
-            paths.remove(pathfinding_debug_line.entities[player_idx.0 as usize]);
-        }
-    }
-}
-
 /// Resource that tracks which players have already been spawned before.
 ///
 /// This lets us handle re-spawns differently, like not spawning you with a hat on a re-spawn.
@@ -628,6 +467,12 @@ fn hydrate_players(
         let player_handle = player_inputs.players[player_idx.0 as usize].selected_player;
         let player_hat = &player_inputs.players[player_idx.0 as usize].selected_hat;
         let is_ai = player_inputs.players[player_idx.0 as usize].is_ai;
+        let ai_difficulty = player_inputs.players[player_idx.0 as usize].ai_difficulty;
+        let ai_behavior = player_inputs.players[player_idx.0 as usize].ai_behavior;
+        // Players on a team are tinted with the team's color
+        let color = player_inputs
+            .team(*player_idx)
+            .map_or(Color::WHITE, Team::color);
 
         let meta = assets.get(player_handle);
 
@@ -654,6 +499,7 @@ fn hydrate_players(
             player_entity,
             AtlasSprite {
                 atlas: meta.layers.body.atlas,
+                color,
                 ..default()
             },
         );
@@ -680,6 +526,7 @@ fn hydrate_players(
             fin_entity,
             AtlasSprite {
                 atlas: meta.layers.fin.atlas,
+                color,
                 ..default()
             },
         );
@@ -708,6 +555,7 @@ fn hydrate_players(
             face_entity,
             AtlasSprite {
                 atlas: meta.layers.face.atlas,
+                color,
                 ..default()
             },
         );
@@ -798,9 +646,25 @@ fn hydrate_players(
 
         *player_has_spawned = true;
 
+        // Handle AI players that are controlled by a lua behavior
+        if let Some(behavior) = ai_behavior.filter(|_| is_ai) {
+            let lua_ai_player = LuaAiPlayer {
+                behavior,
+                player: player_idx.0,
+                settings: ai_difficulty.settings(),
+                ..default()
+            };
+            commands.add(
+                move |mut lua_ai_players: CompMut<LuaAiPlayer>,
+                      mut lua_ai_paths: CompMut<LuaAiPath>| {
+                    lua_ai_players.insert(player_entity, lua_ai_player.clone());
+                    lua_ai_paths.insert(player_entity, default());
+                },
+            );
+
         // Handle AI players
-        if is_ai {
-            ai_players.insert(player_entity, default());
+        } else if is_ai {
+            ai_players.insert(player_entity, AiPlayer::new(ai_difficulty.settings()));
 
             // Give the player a sword NOTE: It's not good that we're duplicating the sword hydrate
             // functionality here, and this is pretty hacky, but the AI as it stands is temporary
@@ -817,7 +681,8 @@ fn hydrate_players(
                       mut transforms: CompMut<Transform>,
                       game_meta: Root<GameMeta>,
                       mut attachments: CompMut<PlayerBodyAttachment>,
-                      mut inventories: CompMut<Inventory>| {
+                      mut inventories: CompMut<Inventory>,
+                      mut ai_swords: CompMut<AiSword>| {
                     let element_handle = game_meta
                         .core
                         .map_elements
@@ -842,6 +707,7 @@ fn hydrate_players(
                         let sword_ent = entities.create();
                         inventories.insert(player_entity, Inventory(Some(sword_ent)));
                         items.insert(sword_ent, Item);
+                        ai_swords.insert(sword_ent, AiSword);
                         swords.insert(sword_ent, sword::Sword::default());
                         atlas_sprites.insert(sword_ent, AtlasSprite::new(*atlas));
                         transforms.insert(sword_ent, default());
@@ -901,15 +767,22 @@ fn player_kill_callback(player_entity: Entity) -> StaticSystem<(), ()> {
     .system()
 }
 
+/// Marker component for the swords that AI players are given when they spawn.
+#[derive(Clone, Copy, HasSchema, Default)]
+struct AiSword;
+
 /// System that makes sure the swords held by AI are despawned when they are killed.
+///
+/// Only the swords that the AI spawned with are despawned, the items that the AI picks up are
+/// dropped and thrown like any other.
 fn delete_dead_ai_swords(
     mut entities: ResMutInit<Entities>,
     ai_players: Comp<AiPlayer>,
-    items: Comp<Item>,
+    ai_swords: Comp<AiSword>,
     dropped: Comp<ItemDropped>,
 ) {
     let mut to_kill = Vec::new();
-    for (ent, (_item, dropped)) in entities.iter_with((&items, &dropped)) {
+    for (ent, (_sword, dropped)) in entities.iter_with((&ai_swords, &dropped)) {
         if ai_players.contains(dropped.player) {
             to_kill.push(ent);
         }
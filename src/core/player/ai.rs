@@ -0,0 +1,942 @@
+//! AI player controller.
+//!
+//! AI players path towards their target over the [`NavGraph`]. On the way they pick up the best
+//! item in reach, shoot ranged weapons when they have line of sight, and throw explosives at their
+//! target. Staying alive comes first though: they flee from damage regions, spikes and lit
+//! explosives, and they won't walk off of the edges of the map.
+//!
+//! How quickly AI players react, how fast they move, how well they aim and how often they use items
+//! is set by their [`AiDifficulty`].
+//!
+//! Network players whose peer leaves the match are handed to the AI, so that the match can go on.
+
+use std::collections::VecDeque;
+
+use turborand::GenCore;
+
+use crate::prelude::*;
+
+use super::PLAYER_COLORS;
+
+/// The distance to the target at which the AI swings a melee weapon that it's using. This is what
+/// controls attacking with a sword.
+const MELEE_RANGE: f32 = 30.0;
+/// The distance to the target at which the AI presses shoot while chasing it, whether it's holding
+/// an item it would use from there or not. This is the last resort of AI players that aren't using
+/// items, so it's shorter than the [`MELEE_RANGE`].
+const POINT_BLANK_DIST: f32 = 10.0;
+/// How far away the AI will go to pick up an item.
+const ITEM_SEARCH_DIST: f32 = 250.0;
+/// How close the AI needs to be to an item to grab it.
+const ITEM_GRAB_DIST: f32 = 12.0;
+/// The furthest distance the AI shoots ranged weapons from.
+const SHOOT_RANGE: f32 = 300.0;
+/// How far above or below the AI the target may be for a ranged weapon to hit it.
+const SHOOT_HEIGHT_TOLERANCE: f32 = 12.0;
+/// The furthest distance the AI throws items from.
+const THROW_RANGE: f32 = 150.0;
+/// The radius around lit explosives that the AI keeps clear of.
+const EXPLOSION_DANGER_RADIUS: f32 = 50.0;
+/// The margin around hazards that the AI keeps clear of.
+const HAZARD_MARGIN: f32 = 12.0;
+/// The horizontal movement the AI uses to turn around without walking away.
+const TURN_SPEED: f32 = 0.1;
+/// The number of frames in a row that a network player must be without input before they are
+/// handed to the AI. The first few frames of a network match are without input for every player
+/// while the input delay fills up, so this must be larger than the input delay.
+const DISCONNECT_TAKEOVER_FRAMES: u32 = 60;
+
+/// How hard an AI player is to play against.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum AiDifficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    /// Difficulty with hand-picked settings.
+    Custom(AiSettings),
+}
+
+impl AiDifficulty {
+    /// The preset difficulties, in order of difficulty.
+    pub const PRESETS: [AiDifficulty; 3] =
+        [AiDifficulty::Easy, AiDifficulty::Normal, AiDifficulty::Hard];
+
+    /// Get the settings that the AI plays with.
+    pub fn settings(self) -> AiSettings {
+        match self {
+            AiDifficulty::Easy => AiSettings {
+                reaction_time: 0.8,
+                pause_chance: 0.6,
+                speed: 0.5,
+                aim_accuracy: 0.3,
+                item_usage: 0.3,
+            },
+            AiDifficulty::Normal => AiSettings::default(),
+            AiDifficulty::Hard => AiSettings {
+                reaction_time: 0.25,
+                pause_chance: 0.1,
+                speed: 0.9,
+                aim_accuracy: 1.0,
+                item_usage: 1.0,
+            },
+            AiDifficulty::Custom(settings) => settings,
+        }
+    }
+
+    /// The localization key for the difficulty's name.
+    pub fn name_key(self) -> &'static str {
+        match self {
+            AiDifficulty::Easy => "ai-difficulty-easy",
+            AiDifficulty::Normal => "ai-difficulty-normal",
+            AiDifficulty::Hard => "ai-difficulty-hard",
+            AiDifficulty::Custom(_) => "ai-difficulty-custom",
+        }
+    }
+
+    /// The next difficulty, wrapping around. Custom difficulty starts from the settings of the
+    /// previous difficulty.
+    pub fn next(self) -> Self {
+        match self {
+            AiDifficulty::Easy => AiDifficulty::Normal,
+            AiDifficulty::Normal => AiDifficulty::Hard,
+            AiDifficulty::Hard => AiDifficulty::Custom(self.settings()),
+            AiDifficulty::Custom(_) => AiDifficulty::Easy,
+        }
+    }
+
+    /// The previous difficulty, wrapping around. Custom difficulty starts from the settings of the
+    /// previous difficulty.
+    pub fn prev(self) -> Self {
+        match self {
+            AiDifficulty::Easy => AiDifficulty::Custom(self.settings()),
+            AiDifficulty::Normal => AiDifficulty::Easy,
+            AiDifficulty::Hard => AiDifficulty::Normal,
+            AiDifficulty::Custom(_) => AiDifficulty::Hard,
+        }
+    }
+}
+
+/// The settings that an AI player plays with.
+#[derive(HasSchema, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[repr(C)]
+pub struct AiSettings {
+    /// The number of seconds between the AI's decisions to pause, and to aim or use items.
+    pub reaction_time: f32,
+    /// The chance that the AI pauses for a moment every reaction.
+    pub pause_chance: f32,
+    /// The multiplier applied to the AI's horizontal movement.
+    pub speed: f32,
+    /// The chance that the AI lines up its shots before shooting, instead of shooting wildly.
+    pub aim_accuracy: f32,
+    /// The chance that the AI picks up and uses items, instead of only chasing its target.
+    pub item_usage: f32,
+}
+
+impl Default for AiSettings {
+    fn default() -> Self {
+        Self {
+            reaction_time: 0.5,
+            pause_chance: 0.4,
+            speed: 0.65,
+            aim_accuracy: 0.7,
+            item_usage: 0.7,
+        }
+    }
+}
+
+#[derive(Clone, Debug, HasSchema)]
+pub struct AiPlayer {
+    /// The settings that the AI plays with.
+    settings: AiSettings,
+    /// Tick timer that is used for AI pausing logic.
+    tick: Timer,
+    /// Indicates the player is taking pause for the given number of ticks.
+    pausing: u32,
+    /// Buffers planned AI movements
+    movement_buffer: Option<VecDeque<PlayerControl>>,
+    /// The player that the AI is targeting.
+    target_player: Option<Entity>,
+    /// Whether the AI lines up its shots until the next tick.
+    aiming: bool,
+    /// Whether the AI picks up and uses items until the next tick.
+    using_items: bool,
+}
+
+impl Default for AiPlayer {
+    fn default() -> Self {
+        Self::new(default())
+    }
+}
+
+impl AiPlayer {
+    pub fn new(settings: AiSettings) -> Self {
+        Self {
+            settings,
+            tick: Timer::from_seconds(settings.reaction_time, TimerMode::Repeating),
+            pausing: 0,
+            movement_buffer: Default::default(),
+            target_player: Default::default(),
+            aiming: true,
+            using_items: true,
+        }
+    }
+}
+
+#[derive(Debug, HasSchema, Clone)]
+#[schema(no_default)]
+pub struct PathfindingDebugLines {
+    pub entities: Vec<Entity>,
+}
+
+impl FromWorld for PathfindingDebugLines {
+    fn from_world(world: &World) -> Self {
+        let entities = world.run_system(
+            |mut entities: ResMut<Entities>, mut transforms: CompMut<Transform>| {
+                (0..MAX_PLAYERS)
+                    .map(|_| {
+                        let ent = entities.create();
+
+                        transforms
+                            .insert(ent, Transform::from_translation(Vec3::new(0.0, 0.0, -1.0)));
+
+                        ent
+                    })
+                    .collect::<Vec<_>>()
+            },
+            (),
+        );
+
+        Self { entities }
+    }
+}
+
+/// Tracks the network players that have stopped receiving input, to hand them to the AI.
+#[derive(HasSchema, Clone, Default)]
+pub struct DisconnectedPlayerTakeover {
+    /// The number of frames in a row that each player has been without input.
+    frames_without_input: [u32; MAX_PLAYERS as usize],
+    /// The players that were handed to the AI in this round, in the order they left.
+    pub replaced: Vec<PlayerIdx>,
+}
+
+/// Hand the players whose peer left the network match to the AI.
+///
+/// The inputs of a peer that left are zeroed on the same frame for every remaining peer, so they
+/// all hand the player over on the same frame. The player stays an AI player for the rest of the
+/// match.
+pub fn replace_disconnected_players(
+    entities: Res<Entities>,
+    player_indices: Comp<PlayerIdx>,
+    mut ai_players: CompMut<AiPlayer>,
+    mut player_inputs: ResMutInit<MatchInputs>,
+    mut takeover: ResMutInit<DisconnectedPlayerTakeover>,
+) {
+    for (i, player) in player_inputs.players.iter_mut().enumerate() {
+        let frames_without_input = &mut takeover.frames_without_input[i];
+        if !player.active || player.is_ai || !player.control.disconnected {
+            *frames_without_input = 0;
+            continue;
+        }
+        *frames_without_input += 1;
+        if *frames_without_input < DISCONNECT_TAKEOVER_FRAMES {
+            continue;
+        }
+
+        let player_idx = PlayerIdx(i as u32);
+        player.is_ai = true;
+        player.ai_behavior = None;
+        if let Some((player_ent, _)) = entities
+            .iter_with(&player_indices)
+            .find(|(_, idx)| **idx == player_idx)
+        {
+            ai_players.insert(player_ent, AiPlayer::new(player.ai_difficulty.settings()));
+        }
+        takeover.replaced.push(player_idx);
+    }
+}
+
+/// What the AI players know about the items and hazards in the world, updated every frame.
+#[derive(HasSchema, Clone, Default)]
+pub struct AiKnowledge {
+    pub items: Vec<AiItem>,
+    pub hazards: Vec<AiHazard>,
+}
+
+impl AiKnowledge {
+    /// Get what the AI knows about the given item.
+    pub fn item(&self, entity: Entity) -> Option<&AiItem> {
+        self.items.iter().find(|item| item.entity == entity)
+    }
+}
+
+/// How the AI uses an item.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AiItemKind {
+    /// Shot at players with line of sight.
+    Ranged,
+    /// Swung at players up close.
+    Melee,
+    /// Lit and thrown at players.
+    Explosive,
+    /// Thrown at players.
+    Other,
+}
+
+impl AiItemKind {
+    /// How much the AI wants to pick up this kind of item. Other items aren't picked up at all.
+    fn priority(self) -> u32 {
+        match self {
+            AiItemKind::Ranged => 3,
+            AiItemKind::Explosive => 2,
+            AiItemKind::Melee => 1,
+            AiItemKind::Other => 0,
+        }
+    }
+}
+
+/// An item as seen by the AI.
+#[derive(Clone, Debug)]
+pub struct AiItem {
+    pub entity: Entity,
+    pub position: Vec2,
+    pub kind: AiItemKind,
+    /// Whether the item can still be used, i.e. it isn't out of ammo or already armed.
+    pub usable: bool,
+    /// Whether the item is a lit explosive.
+    pub lit: bool,
+    /// Whether a player is holding the item.
+    pub held: bool,
+}
+
+/// An area that kills players, as seen by the AI.
+#[derive(Clone, Debug)]
+pub struct AiHazard {
+    pub rect: Rect,
+    /// The entity that is the hazard.
+    pub source: Entity,
+    /// The player that caused the hazard, who is safe from it.
+    pub owner: Option<Entity>,
+}
+
+/// Collect the items and hazards that the AI players need to know about.
+pub fn update_ai_knowledge(
+    entities: Res<Entities>,
+    transforms: Comp<Transform>,
+    bodies: Comp<KinematicBody>,
+    items: Comp<Item>,
+    inventories: Comp<Inventory>,
+    muskets: Comp<Musket>,
+    machine_guns: Comp<MachineGun>,
+    cannons: Comp<Cannon>,
+    swords: Comp<Sword>,
+    idle_grenades: Comp<IdleGrenade>,
+    lit_grenades: Comp<LitGrenade>,
+    idle_kick_bombs: Comp<IdleKickBomb>,
+    lit_kick_bombs: Comp<LitKickBomb>,
+    idle_mines: Comp<IdleMine>,
+    thrown_mines: Comp<ThrownMine>,
+    spikes: Comp<Spike>,
+    damage_regions: Comp<DamageRegion>,
+    damage_region_owners: Comp<DamageRegionOwner>,
+    mut knowledge: ResMutInit<AiKnowledge>,
+) {
+    let held_items = entities
+        .iter_with(&inventories)
+        .filter_map(|(_ent, inventory)| inventory.0)
+        .collect::<Vec<_>>();
+
+    knowledge.items.clear();
+    for (entity, (_item, transform)) in entities.iter_with((&items, &transforms)) {
+        let (kind, usable) = if let Some(musket) = muskets.get(entity) {
+            (AiItemKind::Ranged, musket.ammo > 0)
+        } else if let Some(machine_gun) = machine_guns.get(entity) {
+            (AiItemKind::Ranged, machine_gun.ammo > 0)
+        } else if let Some(cannon) = cannons.get(entity) {
+            (AiItemKind::Ranged, cannon.ammo > 0)
+        } else if swords.contains(entity) {
+            (AiItemKind::Melee, true)
+        } else if idle_grenades.contains(entity)
+            || lit_grenades.contains(entity)
+            || idle_kick_bombs.contains(entity)
+            || lit_kick_bombs.contains(entity)
+            || idle_mines.contains(entity)
+        {
+            (AiItemKind::Explosive, true)
+        } else if thrown_mines.contains(entity) {
+            (AiItemKind::Explosive, false)
+        } else {
+            (AiItemKind::Other, true)
+        };
+
+        knowledge.items.push(AiItem {
+            entity,
+            position: transform.translation.truncate(),
+            kind,
+            usable,
+            lit: lit_grenades.contains(entity) || lit_kick_bombs.contains(entity),
+            held: held_items.contains(&entity),
+        });
+    }
+
+    knowledge.hazards.clear();
+    for (entity, (region, transform)) in entities.iter_with((&damage_regions, &transforms)) {
+        knowledge.hazards.push(AiHazard {
+            rect: region.collider_rect(transform.translation),
+            source: entity,
+            owner: damage_region_owners.get(entity).map(|owner| owner.0),
+        });
+    }
+    for (entity, (_spike, transform, body)) in entities.iter_with((&spikes, &transforms, &bodies)) {
+        knowledge.hazards.push(AiHazard {
+            rect: body.bounding_box(*transform),
+            source: entity,
+            owner: None,
+        });
+    }
+    let explosives = entities
+        .iter_with(&transforms)
+        .filter(|(entity, _)| {
+            lit_grenades.contains(*entity)
+                || lit_kick_bombs.contains(*entity)
+                || thrown_mines.contains(*entity)
+        })
+        .map(|(entity, transform)| (entity, transform.translation.truncate()))
+        .collect::<Vec<_>>();
+    for (entity, position) in explosives {
+        knowledge.hazards.push(AiHazard {
+            rect: Rect::new(
+                position.x,
+                position.y,
+                EXPLOSION_DANGER_RADIUS * 2.0,
+                EXPLOSION_DANGER_RADIUS * 2.0,
+            ),
+            source: entity,
+            owner: None,
+        });
+    }
+}
+
+pub fn player_ai_system(
+    entities: Res<Entities>,
+    nav_graph: ResMutInit<NavGraph>,
+    mut player_inputs: ResMutInit<MatchInputs>,
+    mut ai_players: CompMut<AiPlayer>,
+    player_indexes: Comp<PlayerIdx>,
+    map: Res<LoadedMap>,
+    transforms: Comp<Transform>,
+    pathfinding_debug_line: ResMutInit<PathfindingDebugLines>,
+    mut paths: CompMut<Path2d>,
+    bodies: Comp<KinematicBody>,
+    atlas_sprites: Comp<AtlasSprite>,
+    inventories: Comp<Inventory>,
+    killed_players: Comp<PlayerKilled>,
+    knowledge: ResInit<AiKnowledge>,
+    round_rules: ResInit<RoundRulesState>,
+    collision_world: CollisionWorld,
+    debug_settings: ResInit<DebugSettings>,
+    rng: Res<GlobalRng>,
+    time: Res<Time>,
+) {
+    let map_size = map.grid_size.as_vec2() * map.tile_size;
+
+    for (ai_ent, (player_idx, transform, ai_player)) in
+        entities.iter_with((&player_indexes, &transforms, &mut ai_players))
+    {
+        // Tick the AI timer
+        ai_player.tick.tick(time.delta());
+
+        // If a tick has elapsed
+        if ai_player.tick.just_finished() {
+            // If the player isn't pausing, then there's a chance
+            if ai_player.pausing == 0 && rng.chance(ai_player.settings.pause_chance as f64) {
+                // That we will pause for a random number of ticks between 0 and 2
+                ai_player.pausing = (rng.f32_normalized() * 2.0).round() as u32
+            }
+
+            // If the player is pausing
+            if ai_player.pausing > 0 {
+                // Subtract a tick from how long they should pause.
+                ai_player.pausing -= 1;
+            }
+
+            ai_player.aiming = rng.chance(ai_player.settings.aim_accuracy as f64);
+            ai_player.using_items = rng.chance(ai_player.settings.item_usage as f64);
+        }
+
+        // If the player is pausing, don't have the AI move this frame.
+        if ai_player.pausing > 0 {
+            continue;
+        }
+
+        let ai_pos = transform.translation.truncate();
+        let on_ground = bodies.get(ai_ent).map_or(false, |body| body.is_on_ground);
+        let facing_left = atlas_sprites
+            .get(ai_ent)
+            .map_or(false, |sprite| sprite.flip_x);
+        let held_item = inventories
+            .get(ai_ent)
+            .and_then(|inventory| inventory.0)
+            .and_then(|item| knowledge.item(item));
+        let set_control = |player_inputs: &mut MatchInputs, mut control: PlayerControl| {
+            avoid_map_edges(
+                &mut control,
+                ai_pos,
+                on_ground,
+                map_size,
+                &map,
+                &collision_world,
+            );
+            player_inputs.players[player_idx.0 as usize].control = control;
+        };
+
+        // Staying alive comes first.
+        if let Some(control) = flee_hazards(
+            ai_ent,
+            ai_pos,
+            on_ground,
+            held_item,
+            &knowledge,
+            round_rules.kill_zone,
+        ) {
+            ai_player.movement_buffer = None;
+            set_control(&mut player_inputs, control);
+            continue;
+        }
+
+        let target_transform = match ai_player.target_player {
+            Some(target_player)
+                if transforms.contains(target_player)
+                    && !killed_players.contains(target_player) =>
+            {
+                transforms.get(target_player).unwrap()
+            }
+            _ => {
+                let players = entities
+                    .iter_with((&player_indexes, &transforms))
+                    .filter(|(ent, _)| *ent != ai_ent && !killed_players.contains(*ent))
+                    .filter(|(_, (idx, _))| !player_inputs.are_teammates(*player_idx, **idx))
+                    .collect::<Vec<_>>();
+                if players.is_empty() {
+                    continue;
+                }
+
+                let (target_player, (_, transform)) = players[rng.gen_usize() % players.len()];
+
+                ai_player.target_player = Some(target_player);
+                transform
+            }
+        };
+        let target_pos = target_transform.translation.truncate();
+
+        // Complete any previous movement instructions if we are in the middle of any
+        if let Some(movement_buffer) = &mut ai_player.movement_buffer {
+            if let Some(control) = movement_buffer.pop_front() {
+                if movement_buffer.is_empty() {
+                    ai_player.movement_buffer = None;
+                }
+                set_control(&mut player_inputs, control);
+                continue;
+            }
+        }
+
+        // Use the item we are holding, if the target is in reach.
+        let to_target = target_pos - ai_pos;
+        let turn_to_target = if (to_target.x < 0.0) == facing_left {
+            0.0
+        } else {
+            to_target.x.signum() * TURN_SPEED
+        };
+        if let Some(item) = held_item.filter(|_| ai_player.using_items) {
+            let control = match item.kind {
+                // Drop items that we can't use anymore.
+                _ if !item.usable => Some(PlayerControl {
+                    grab_pressed: true,
+                    grab_just_pressed: true,
+                    ..default()
+                }),
+                // Without aiming, the AI shoots as soon as the target is in range.
+                AiItemKind::Ranged
+                    if to_target.length() < SHOOT_RANGE
+                        && (!ai_player.aiming
+                            || (to_target.y.abs() < SHOOT_HEIGHT_TOLERANCE
+                                && has_line_of_sight(
+                                    &collision_world,
+                                    ai_pos,
+                                    target_pos,
+                                    map.tile_size,
+                                ))) =>
+                {
+                    Some(PlayerControl {
+                        move_direction: vec2(turn_to_target, 0.0),
+                        shoot_pressed: true,
+                        shoot_just_pressed: true,
+                        ..default()
+                    })
+                }
+                AiItemKind::Melee if to_target.length() < MELEE_RANGE => Some(PlayerControl {
+                    move_direction: vec2(turn_to_target, 0.0),
+                    shoot_pressed: true,
+                    shoot_just_pressed: true,
+                    ..default()
+                }),
+                // Throw lit explosives and other items at the target.
+                AiItemKind::Explosive | AiItemKind::Other
+                    if (item.lit || item.kind == AiItemKind::Other)
+                        && to_target.length() < THROW_RANGE =>
+                {
+                    Some(PlayerControl {
+                        move_direction: vec2(to_target.x.signum() * TURN_SPEED, 0.0),
+                        grab_pressed: true,
+                        grab_just_pressed: true,
+                        ..default()
+                    })
+                }
+                // Light explosives, or drop mines, when the target gets close.
+                AiItemKind::Explosive if to_target.length() < THROW_RANGE => Some(PlayerControl {
+                    shoot_pressed: true,
+                    shoot_just_pressed: true,
+                    ..default()
+                }),
+                _ => None,
+            };
+            if let Some(control) = control {
+                set_control(&mut player_inputs, control);
+                continue;
+            }
+        }
+
+        // Go for the best item in reach if we aren't holding anything, and otherwise the target.
+        let target_item = (held_item.is_none() && ai_player.using_items)
+            .then(|| {
+                knowledge
+                    .items
+                    .iter()
+                    .filter(|item| {
+                        !item.held
+                            && item.usable
+                            && !item.lit
+                            && item.kind != AiItemKind::Other
+                            && (item.position - ai_pos).length() < ITEM_SEARCH_DIST
+                    })
+                    .max_by(|a, b| {
+                        a.kind.priority().cmp(&b.kind.priority()).then_with(|| {
+                            let a_dist = (a.position - ai_pos).length();
+                            let b_dist = (b.position - ai_pos).length();
+                            b_dist.total_cmp(&a_dist)
+                        })
+                    })
+            })
+            .flatten();
+        if let Some(item) = target_item {
+            if (item.position - ai_pos).length() < ITEM_GRAB_DIST {
+                set_control(
+                    &mut player_inputs,
+                    PlayerControl {
+                        grab_pressed: true,
+                        grab_just_pressed: true,
+                        ..default()
+                    },
+                );
+                continue;
+            }
+        }
+        let goal_pos = target_item.map_or(target_pos, |item| item.position);
+
+        let tile = (goal_pos / map.tile_size).floor().as_ivec2();
+        let target_node = NavNode(tile);
+        let tile = (ai_pos / map.tile_size).floor().as_ivec2();
+        let current_node = NavNode(tile);
+
+        let path = petgraph::algo::astar(
+            nav_graph.as_ref(),
+            current_node,
+            |x| x == target_node,
+            |(_, _, edge)| edge.distance,
+            |_| 0.0,
+        );
+
+        if let Some((_cost, path)) = path {
+            if debug_settings.show_pathfinding_lines {
+                paths.insert(
+                    pathfinding_debug_line.entities[player_idx.0 as usize],
+                    Path2d {
+                        points: path
+                            .iter()
+                            .map(|x| x.0.as_vec2() * map.tile_size + map.tile_size / 2.0)
+                            .collect(),
+                        thickness: 2.0,
+                        color: player_inputs
+                            .team(*player_idx)
+                            .map_or(PLAYER_COLORS[player_idx.0 as usize], Team::color),
+                        ..default()
+                    },
+                );
+            }
+
+            let mut control = player_inputs.players[player_idx.0 as usize].control;
+            if let Some(&next_node) = path.get(1) {
+                let edge = nav_graph.edge_weight(current_node, next_node).unwrap();
+                let mut movement_buffer = edge.inputs.clone();
+                let mut first_movement = movement_buffer.pop_front().unwrap();
+
+                // Slow down the AI movement according to its speed setting
+                first_movement.move_direction *= vec2(ai_player.settings.speed, 1.0);
+
+                // This is a hack to prevent us from getting stuck when we think we should be falling
+                // straight down and we actually need to move off of the block we're half-standing on.
+                //
+                // If we aren't moving at all, move towards the node after the next one on the path.
+                if bodies.get(ai_ent).unwrap().velocity == Vec2::ZERO
+                    && first_movement.move_direction == Vec2::ZERO
+                {
+                    let sign = (path.get(2).unwrap_or(&next_node).x as f32 * map.tile_size.x
+                        - transform.translation.x)
+                        .signum();
+                    first_movement.move_direction.x = sign;
+                }
+
+                control = first_movement;
+                if !movement_buffer.is_empty() {
+                    ai_player.movement_buffer = Some(movement_buffer)
+                }
+            }
+
+            if target_item.is_none() && to_target.length() < POINT_BLANK_DIST {
+                control.shoot_just_pressed = true;
+                control.shoot_pressed = true;
+            }
+            set_control(&mut player_inputs, control);
+        } else if debug_settings.show_pathfinding_lines {
+            let pos =
+                current_node.0.as_vec2() * map.tile_size + map.tile_size / 2.0 - vec2(0.0, 4.0);
+            paths.insert(
+                pathfinding_debug_line.entities[player_idx.0 as usize],
+                Path2d {
+                    points: vec![pos, pos + vec2(0.0, 4.0)],
+                    thickness: 8.0,
+                    color: Color::RED,
+                    ..default()
+                },
+            );
+        }
+
+        if !debug_settings.show_pathfinding_lines {
+            paths.remove(pathfinding_debug_line.entities[player_idx.0 as usize]);
+        }
+    }
+}
+
+/// Get the control that moves the AI away from the hazard it is too close to, if any.
+fn flee_hazards(
+    ai_ent: Entity,
+    ai_pos: Vec2,
+    on_ground: bool,
+    held_item: Option<&AiItem>,
+    knowledge: &AiKnowledge,
+    kill_zone: Option<Rect>,
+) -> Option<PlayerControl> {
+    let flee = |direction: f32, jump: bool| PlayerControl {
+        move_direction: vec2(direction, 0.0),
+        jump_pressed: jump,
+        jump_just_pressed: jump,
+        ..default()
+    };
+
+    for hazard in &knowledge.hazards {
+        // We are safe from our own hazards, and the explosive we're holding is thrown instead.
+        if hazard.owner == Some(ai_ent)
+            || held_item.is_some_and(|item| item.entity == hazard.source)
+        {
+            continue;
+        }
+        let danger_zone = Rect {
+            min: hazard.rect.min - Vec2::splat(HAZARD_MARGIN),
+            max: hazard.rect.max + Vec2::splat(HAZARD_MARGIN),
+        };
+        if danger_zone.contains(ai_pos) {
+            let center = hazard.rect.center();
+            return Some(flee(
+                (ai_pos.x - center.x).signum(),
+                on_ground && center.y <= ai_pos.y,
+            ));
+        }
+    }
+
+    // Stay inside of the sudden death kill zone.
+    if let Some(kill_zone) = kill_zone {
+        let safe_zone = Rect {
+            min: kill_zone.min + Vec2::splat(HAZARD_MARGIN),
+            max: kill_zone.max - Vec2::splat(HAZARD_MARGIN),
+        };
+        if !safe_zone.contains(ai_pos) {
+            let to_center = kill_zone.center() - ai_pos;
+            return Some(flee(to_center.x.signum(), on_ground && to_center.y > 0.0));
+        }
+    }
+
+    None
+}
+
+/// Keep the AI from walking off of the edges of the map, or into pits without a floor.
+fn avoid_map_edges(
+    control: &mut PlayerControl,
+    ai_pos: Vec2,
+    on_ground: bool,
+    map_size: Vec2,
+    map: &MapMeta,
+    collision_world: &CollisionWorld,
+) {
+    // Head back towards the middle if we've ended up outside of the map.
+    if ai_pos.x < 0.0 || ai_pos.x > map_size.x || ai_pos.y < 0.0 {
+        control.move_direction.x = (map_size.x / 2.0 - ai_pos.x).signum();
+        return;
+    }
+
+    // Jumps are planned by the nav graph, but don't walk off of a ledge that we can't land from.
+    if on_ground && !control.jump_pressed && control.move_direction.x != 0.0 {
+        let next_pos = ai_pos + vec2(control.move_direction.x.signum() * map.tile_size.x, 0.0);
+        if next_pos.x < 0.0
+            || next_pos.x > map_size.x
+            || !has_ground_below(collision_world, next_pos, map.tile_size)
+        {
+            control.move_direction.x = 0.0;
+        }
+    }
+}
+
+/// Whether there is any tile below the position to land on.
+fn has_ground_below(collision_world: &CollisionWorld, pos: Vec2, tile_size: Vec2) -> bool {
+    let mut pos = pos;
+    while pos.y >= 0.0 {
+        if collision_world.tile_collision_point(pos) != TileCollisionKind::Empty {
+            return true;
+        }
+        pos.y -= tile_size.y;
+    }
+    false
+}
+
+/// Whether there are no solid tiles between the two positions.
+fn has_line_of_sight(
+    collision_world: &CollisionWorld,
+    from: Vec2,
+    to: Vec2,
+    tile_size: Vec2,
+) -> bool {
+    let step = tile_size.min_element() / 2.0;
+    let steps = ((to - from).length() / step).ceil() as u32;
+    (1..steps).all(|i| {
+        let pos = from.lerp(to, i as f32 / steps as f32);
+        collision_world.tile_collision_point(pos) != TileCollisionKind::Solid
+    })
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use super::*;
+    use crate::headless::{FrameControls, HeadlessGame};
+
+    /// An AI player holding a sword next to its opponent swings it, and kills the opponent.
+    #[test]
+    fn ai_next_to_an_opponent_attacks_it() {
+        let mut headless = HeadlessGame::for_tests();
+        let meta = headless.meta();
+        let sword = {
+            let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+            meta.core
+                .map_elements
+                .iter()
+                .copied()
+                .find(|handle| asset_server.get(*handle).name == "Sword")
+                .unwrap()
+        };
+        let maps = MapPool::from_single_map(headless.find_map(None).unwrap());
+        // The AI reacts quickly and never pauses, so that it attacks right away.
+        let settings = AiSettings {
+            reaction_time: 0.1,
+            pause_chance: 0.0,
+            speed: 1.0,
+            aim_accuracy: 1.0,
+            item_usage: 1.0,
+        };
+        let player_info = std::array::from_fn(|i| PlayerInput {
+            active: i < 2,
+            is_ai: i == 0,
+            ai_difficulty: AiDifficulty::Custom(settings),
+            selected_player: meta.core.players[0],
+            ..default()
+        });
+        headless.start_match(maps, player_info, default(), Vec::<FrameControls>::new());
+        headless.run(10);
+
+        // Spawn a sword, and let it be hydrated.
+        headless.match_world().unwrap().run_system(
+            move |mut entities: ResMutInit<Entities>,
+                  mut element_handles: CompMut<ElementHandle>,
+                  mut transforms: CompMut<Transform>| {
+                let ent = entities.create();
+                element_handles.insert(ent, ElementHandle(sword));
+                transforms.insert(ent, default());
+            },
+            (),
+        );
+        headless.run(1);
+
+        // Put the AI right next to its opponent, with the sword in hand, and drop the spawn
+        // invincibility of the opponent.
+        headless.match_world().unwrap().run_system(
+            |entities: Res<Entities>,
+             map: Res<LoadedMap>,
+             player_indices: Comp<PlayerIdx>,
+             swords: Comp<Sword>,
+             inventories: Comp<Inventory>,
+             mut transforms: CompMut<Transform>,
+             mut invincibles: CompMut<Invincibility>,
+             mut commands: Commands| {
+                let player = |idx| {
+                    entities
+                        .iter_with(&player_indices)
+                        .find(|(_, player_idx)| **player_idx == PlayerIdx(idx))
+                        .unwrap()
+                        .0
+                };
+                let (ai, opponent) = (player(0), player(1));
+                let sword = entities
+                    .iter_with(&swords)
+                    .map(|(ent, _)| ent)
+                    .find(|ent| {
+                        !entities
+                            .iter_with(&inventories)
+                            .any(|(_, i)| i.0 == Some(*ent))
+                    })
+                    .unwrap();
+
+                let opponent_pos = transforms.get(opponent).unwrap().translation;
+                let map_width = map.grid_size.x as f32 * map.tile_size.x;
+                let side = if opponent_pos.x < map_width / 2.0 {
+                    1.0
+                } else {
+                    -1.0
+                };
+                let ai_transform = transforms.get_mut(ai).unwrap();
+                ai_transform.translation.x = opponent_pos.x + side * 20.0;
+                ai_transform.translation.y = opponent_pos.y;
+                invincibles.remove(opponent);
+                commands.add(PlayerCommand::set_inventory(ai, Some(sword)));
+            },
+            (),
+        );
+
+        for _ in 0..FPS as u32 * 2 {
+            let world = headless.match_world().unwrap();
+            if world.resource::<MatchScore>().stats(PlayerIdx(0)).kills > 0 {
+                break;
+            }
+            headless.step();
+        }
+        let score = headless.match_world().unwrap().resource::<MatchScore>();
+        assert_eq!(score.stats(PlayerIdx(0)).kills, 1);
+        assert_eq!(score.stats(PlayerIdx(1)).deaths, 1);
+    }
+}
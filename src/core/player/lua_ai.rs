@@ -0,0 +1,136 @@
+//! Lua-scriptable AI players.
+//!
+//! AI players that were given an [`AiBehaviorMeta`] in player select get a [`LuaAiPlayer`]
+//! component instead of an [`AiPlayer`], and are driven by the behavior's lua plugin instead of the
+//! built-in AI.
+//!
+//! Behaviors write the controls of their players to [`LuaAiPlayer::control`] during
+//! `CoreStage.First`. To get around the map, they set a [`LuaAiPlayer::goal`] and follow the
+//! [`LuaAiPlayer::path_control`] that is found for it over the [`NavGraph`]. Randomness should come
+//! from the `GlobalRng` resource so that the match stays deterministic.
+
+use std::collections::VecDeque;
+
+use crate::prelude::*;
+
+/// The stage in which the controls of the lua AI players are applied, between `CoreStage::First`
+/// and the [`PlayerStateStage`].
+#[derive(Debug, Clone, Copy)]
+pub struct LuaAiStage;
+
+impl StageLabel for LuaAiStage {
+    fn name(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn id(&self) -> Ulid {
+        Ulid(1514818111507966488210174247899898019)
+    }
+}
+
+/// A player controlled by a lua [`AiBehaviorMeta`].
+#[derive(HasSchema, Clone, Debug, Default)]
+#[repr(C)]
+pub struct LuaAiPlayer {
+    /// The behavior controlling the player.
+    pub behavior: Handle<AiBehaviorMeta>,
+    /// The index of the player.
+    pub player: u32,
+    /// The settings of the difficulty that was picked for the player. It is up to the behavior to
+    /// respect them.
+    pub settings: AiSettings,
+    /// The position that the player is trying to get to.
+    pub goal: Vec2,
+    /// Whether a path to the goal should be found. When this is `false`, `path_control` is empty.
+    pub has_goal: bool,
+    /// Whether a path to the goal was found.
+    pub path_found: bool,
+    /// The controls that move the player along the path to the goal, in this frame.
+    pub path_control: PlayerControl,
+    /// The controls written by the behavior. Only the `pressed` and `left`/`right`/`up`/`down`
+    /// values need to be filled in, the `just_pressed` flags and movement direction are computed
+    /// when the controls are applied.
+    pub control: PlayerControl,
+}
+
+/// The remaining controls of the nav graph edge that a [`LuaAiPlayer`] is following.
+#[derive(HasSchema, Clone, Debug, Default)]
+pub struct LuaAiPath {
+    movement_buffer: VecDeque<PlayerControl>,
+}
+
+/// Apply the controls written by the lua behaviors to the match inputs.
+pub fn apply_lua_ai_controls(
+    entities: Res<Entities>,
+    lua_ai_players: Comp<LuaAiPlayer>,
+    mut player_inputs: ResMutInit<MatchInputs>,
+) {
+    for (_ent, lua_ai_player) in entities.iter_with(&lua_ai_players) {
+        let player = &mut player_inputs.players[lua_ai_player.player as usize];
+        let mut control = lua_ai_player.control;
+        control.update_just_pressed(&player.control);
+        player.control = control;
+    }
+}
+
+/// Find the controls that move each lua AI player towards its goal in the next frame.
+pub fn find_lua_ai_paths(
+    entities: Res<Entities>,
+    nav_graph: ResMutInit<NavGraph>,
+    map: Res<LoadedMap>,
+    transforms: Comp<Transform>,
+    mut lua_ai_players: CompMut<LuaAiPlayer>,
+    mut lua_ai_paths: CompMut<LuaAiPath>,
+) {
+    for (_ent, (transform, lua_ai_player, lua_ai_path)) in
+        entities.iter_with((&transforms, &mut lua_ai_players, &mut lua_ai_paths))
+    {
+        if !lua_ai_player.has_goal {
+            lua_ai_path.movement_buffer.clear();
+            lua_ai_player.path_found = false;
+            lua_ai_player.path_control = default();
+            continue;
+        }
+
+        // Finish following the current edge before finding a new path.
+        let movement = match lua_ai_path.movement_buffer.pop_front() {
+            Some(movement) => Some(movement),
+            None => {
+                let target_node = NavNode((lua_ai_player.goal / map.tile_size).floor().as_ivec2());
+                let current_node = NavNode(
+                    (transform.translation.truncate() / map.tile_size)
+                        .floor()
+                        .as_ivec2(),
+                );
+                let path = petgraph::algo::astar(
+                    nav_graph.as_ref(),
+                    current_node,
+                    |x| x == target_node,
+                    |(_, _, edge)| edge.distance,
+                    |_| 0.0,
+                );
+
+                lua_ai_player.path_found = path.is_some();
+                path.and_then(|(_cost, path)| {
+                    let next_node = *path.get(1)?;
+                    let edge = nav_graph.edge_weight(current_node, next_node).unwrap();
+                    lua_ai_path.movement_buffer = edge.inputs.clone();
+                    lua_ai_path.movement_buffer.pop_front()
+                })
+            }
+        };
+
+        lua_ai_player.path_control = movement
+            .map(|movement| {
+                let direction = movement.move_direction * vec2(lua_ai_player.settings.speed, 1.0);
+                PlayerControl {
+                    left: (-direction.x).max(0.0),
+                    right: direction.x.max(0.0),
+                    up: direction.y.max(0.0),
+                    down: (-direction.y).max(0.0),
+                    ..movement
+                }
+            })
+            .unwrap_or_default();
+    }
+}
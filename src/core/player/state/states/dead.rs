@@ -33,6 +33,7 @@ pub fn handle_player_state(
     game_meta: Root<GameMeta>,
     mut collision_world: CollisionWorld,
     mut audio_center: ResMut<AudioCenter>,
+    round_rules: ResInit<RoundRulesState>,
 ) {
     for (player_ent, (state, animation, _killed_player, player_idx)) in entities.iter_with((
         &player_states,
@@ -99,7 +100,8 @@ pub fn handle_player_state(
 
         if state.age >= 80 {
             // If only one player in match, we wont' score / transition rounds, so respawn player.
-            if player_indices.bitset().bit_count() == 1 {
+            // Players that have lives left are respawned as well.
+            if player_indices.bitset().bit_count() == 1 || round_rules.will_respawn(*player_idx) {
                 commands.add(PlayerCommand::despawn(player_ent));
             }
         }
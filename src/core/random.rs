@@ -12,7 +12,9 @@ use bones_framework::{
 pub use turborand::prelude::*;
 
 pub fn plugin(session: &mut SessionBuilder) {
-    session.init_resource::<GlobalRng>();
+    session
+        .init_resource::<GlobalRng>()
+        .init_resource::<RandomSeed>();
 }
 
 /// Resource that can produce deterministic, pseudo-random numbers.
@@ -24,9 +26,26 @@ pub struct GlobalRng(AtomicRng);
 
 pub const DEFAULT_RANDOM_SEED: u32 = 7;
 
+/// The seed the [`GlobalRng`] of the match was created with.
+#[derive(HasSchema, Clone, Copy, Debug, Deref)]
+pub struct RandomSeed(pub u64);
+
+impl Default for RandomSeed {
+    fn default() -> Self {
+        Self(DEFAULT_RANDOM_SEED as u64)
+    }
+}
+
 impl Default for GlobalRng {
     fn default() -> Self {
-        Self(AtomicRng::with_seed(DEFAULT_RANDOM_SEED as u64))
+        Self::with_seed(DEFAULT_RANDOM_SEED as u64)
+    }
+}
+
+impl GlobalRng {
+    /// Create a random number generator with the given seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Self(AtomicRng::with_seed(seed))
     }
 }
 
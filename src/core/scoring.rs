@@ -38,7 +38,7 @@ impl RoundScoringState {
     }
 }
 
-/// Store player's match score's (rounds won)
+/// Store player's match score's (rounds won) and combat statistics
 #[derive(HasSchema, Clone, Default, Debug)]
 pub struct MatchScore {
     /// Map player to score, if no entry is 0.
@@ -46,6 +46,37 @@ pub struct MatchScore {
 
     /// How many rounds have completed this match
     rounds_completed: u32,
+
+    /// Map player to their statistics, if no entry all are 0.
+    player_stats: HashMap<PlayerIdx, PlayerStats>,
+
+    /// Every kill of the match, in order.
+    kills: Vec<KillRecord>,
+}
+
+/// A player's combat statistics over the match.
+#[derive(Clone, Default, Debug)]
+pub struct PlayerStats {
+    /// Other players killed by this player.
+    pub kills: u32,
+    /// Times this player was killed, including suicides.
+    pub deaths: u32,
+    /// Times this player was killed by themselves, such as by their own explosion or falling out
+    /// of the map.
+    pub suicides: u32,
+    pub items_picked_up: u32,
+    /// Bullets fired by this player.
+    pub shots_fired: u32,
+}
+
+/// A kill during the match.
+#[derive(Clone, Debug)]
+pub struct KillRecord {
+    pub victim: PlayerIdx,
+    /// The player responsible for the kill, if any. Is the victim for suicides.
+    pub killer: Option<PlayerIdx>,
+    /// The element that did the killing, if any.
+    pub element: Option<Handle<ElementMeta>>,
 }
 
 impl MatchScore {
@@ -54,18 +85,13 @@ impl MatchScore {
         self.player_score.get(&player).map_or(0, |s| *s)
     }
 
-    /// Mark round as completed and increment score of winner. None should be provided
-    /// on a draw.
-    pub fn complete_round(&mut self, winner: Option<PlayerIdx>) {
+    /// Mark round as completed and increment score of the winner, or of every player on the
+    /// winning team. An empty slice should be provided on a draw.
+    pub fn complete_round(&mut self, winners: &[PlayerIdx]) {
         self.rounds_completed += 1;
 
-        // Increment winner's score if not a draw
-        if let Some(winner) = winner {
-            if let Some(score) = self.player_score.get_mut(&winner) {
-                *score += 1;
-            } else {
-                self.player_score.insert(winner, 1);
-            }
+        for winner in winners {
+            *self.player_score.entry(*winner).or_insert(0) += 1;
         }
     }
 
@@ -73,6 +99,101 @@ impl MatchScore {
     pub fn rounds_completed(&self) -> u32 {
         self.rounds_completed
     }
+
+    /// Get player's statistics
+    pub fn stats(&self, player: PlayerIdx) -> PlayerStats {
+        self.player_stats.get(&player).cloned().unwrap_or_default()
+    }
+
+    /// Get player's statistics for modification
+    pub fn stats_mut(&mut self, player: PlayerIdx) -> &mut PlayerStats {
+        self.player_stats.entry(player).or_default()
+    }
+
+    /// Record the kill of a player, updating the killer's and victim's statistics.
+    pub fn record_kill(
+        &mut self,
+        victim: PlayerIdx,
+        killer: Option<PlayerIdx>,
+        element: Option<Handle<ElementMeta>>,
+    ) {
+        let victim_stats = self.stats_mut(victim);
+        victim_stats.deaths += 1;
+        match killer {
+            Some(killer) if killer == victim => victim_stats.suicides += 1,
+            Some(killer) => self.stats_mut(killer).kills += 1,
+            None => (),
+        }
+
+        self.kills.push(KillRecord {
+            victim,
+            killer,
+            element,
+        });
+    }
+
+    /// Every kill of the match, in order.
+    pub fn kills(&self) -> &[KillRecord] {
+        &self.kills
+    }
+
+    /// Get the number of other players that the player has killed with each element, sorted by
+    /// most kills first.
+    pub fn element_kills(&self, player: PlayerIdx) -> Vec<(Handle<ElementMeta>, u32)> {
+        let mut element_kills: Vec<(Handle<ElementMeta>, u32)> = Vec::new();
+        for kill in &self.kills {
+            if kill.killer != Some(player) || kill.victim == player {
+                continue;
+            }
+            let Some(element) = kill.element else {
+                continue;
+            };
+            match element_kills.iter_mut().find(|(e, _)| *e == element) {
+                Some((_, count)) => *count += 1,
+                None => element_kills.push((element, 1)),
+            }
+        }
+        element_kills.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        element_kills
+    }
+
+    /// Get the players with the highest score, if it is at least the winning score threshold.
+    /// There is more than one player if they are tied for the win.
+    pub fn leading_players(&self, win_threshold: u32) -> Vec<PlayerIdx> {
+        let Some(highest_score) = self
+            .player_score
+            .values()
+            .copied()
+            .max()
+            .filter(|score| *score >= win_threshold)
+        else {
+            return Vec::new();
+        };
+
+        let mut players = self
+            .player_score
+            .iter()
+            .filter(|(_, score)| **score == highest_score)
+            .map(|(player, _)| *player)
+            .collect::<Vec<_>>();
+        players.sort_by_key(|player| player.0);
+        players
+    }
+
+    /// Get the winners of the match, if a single player, or several players on the same team, have
+    /// reached the winning score threshold with the highest score.
+    pub fn winners(
+        &self,
+        win_threshold: u32,
+        player_inputs: &MatchInputs,
+    ) -> Option<Vec<PlayerIdx>> {
+        let leading_players = self.leading_players(win_threshold);
+        let (first, others) = leading_players.split_first()?;
+        others
+            .iter()
+            .all(|other| player_inputs.are_teammates(*first, *other))
+            .then_some(leading_players)
+    }
 }
 
 pub fn session_plugin(session: &mut SessionBuilder) {
@@ -93,39 +214,84 @@ pub fn round_end(
     mut scoring_menu: ResMut<ScoringMenuState>,
     killed_players: Comp<PlayerKilled>,
     player_indices: Comp<PlayerIdx>,
+    player_inputs: Res<MatchInputs>,
+    round_rules: ResInit<RoundRulesState>,
     #[cfg(not(target_arch = "wasm32"))] syncing_info: Option<Res<SyncingInfo>>,
 ) {
     // Count players so we can avoid ending round if it's a one player match
     let mut player_count = 0;
 
-    // Is Some if one player left, or none if all players dead.
-    // Exits function if >= 2 players left: otherwise we handle continue to handle
-    // round scoring.
-    let last_player_or_draw: Option<(PlayerIdx, Entity)> = {
-        let mut last_player: Option<(PlayerIdx, Entity)> = None;
-        for (ent, (player_idx, killed)) in
-            entities.iter_with((&player_indices, &Optional(&killed_players)))
-        {
-            player_count += 1;
-            if killed.is_none() {
-                if last_player.is_some() {
-                    // At least two players alive, not the round end.
-                    return;
+    // Contains the last player, or the last players of a team, left in the round, or is empty if
+    // all players are out of the round. Exits function if players from two or more teams are
+    // left: otherwise we continue to handle round scoring.
+    let last_players: Vec<(PlayerIdx, Entity)> =
+        if let RoundPhase::TimeUp { winner } = round_rules.phase {
+            // The time ran out and the round was decided by the tie-breaker. Players that were
+            // waiting to respawn have no entity, but are still in the match.
+            player_count = player_inputs.players.iter().filter(|p| p.active).count();
+            let mut winner_players = Vec::new();
+            for (ent, (player_idx, killed)) in
+                entities.iter_with((&player_indices, &Optional(&killed_players)))
+            {
+                let on_winning_team = winner.map_or(false, |winner| {
+                    winner == *player_idx || player_inputs.are_teammates(winner, *player_idx)
+                });
+                if on_winning_team && (Some(*player_idx) == winner || killed.is_none()) {
+                    winner_players.push((*player_idx, ent));
                 }
+            }
+            winner_players
+        } else {
+            let mut last_players: Vec<(PlayerIdx, Entity)> = Vec::new();
+            for (ent, (player_idx, killed)) in
+                entities.iter_with((&player_indices, &Optional(&killed_players)))
+            {
+                player_count += 1;
+
+                // Dead players are still in the round if they have lives left to respawn with, and
+                // the round can't end on a player that is waiting to respawn.
+                if killed.is_none() || round_rules.will_respawn(*player_idx) {
+                    let other_team_left = last_players
+                        .iter()
+                        .any(|(other, _)| !player_inputs.are_teammates(*other, *player_idx));
+                    if other_team_left || killed.is_some() {
+                        // At least two teams in the round, not the round end.
+                        return;
+                    }
+
+                    last_players.push((*player_idx, ent));
+                }
+            }
 
-                last_player = Some((*player_idx, ent));
+            // Players that have been despawned to respawn are still in the round as well.
+            for (i, player) in player_inputs.players.iter().enumerate() {
+                let player_idx = PlayerIdx(i as u32);
+                if player.active
+                    && round_rules.will_respawn(player_idx)
+                    && !entities
+                        .iter_with(&player_indices)
+                        .any(|(_, idx)| *idx == player_idx)
+                {
+                    return;
+                }
             }
-        }
 
-        // We either found only one player or None.
-        last_player
-    };
+            // We either found only one team or None.
+            last_players
+        };
 
     if player_count == 1 {
         // Single player match - don't end round.
         return;
     }
 
+    // The winner of a round decided by the tie-breaker may be waiting to respawn, and so not be
+    // among the last players.
+    let round_winner = match round_rules.phase {
+        RoundPhase::TimeUp { winner } => winner,
+        _ => last_players.first().map(|(player, _)| *player),
+    };
+
     // Tick any round end timer we have
     if let Some(timer) = state.timer.as_mut() {
         timer.tick(time.delta());
@@ -136,9 +302,21 @@ pub fn round_end(
     // Ready to score the round?
     if state.should_score_round() {
         state.round_scored = true;
-        score.complete_round(last_player_or_draw.map(|x| x.0));
-
-        if let Some((_, winner_ent)) = last_player_or_draw {
+        // The whole team wins the round, including teammates that are out of the round.
+        let winners = match round_winner {
+            Some(winner) => (0..MAX_PLAYERS)
+                .map(PlayerIdx)
+                .filter(|player_idx| {
+                    *player_idx == winner
+                        || (player_inputs.players[player_idx.0 as usize].active
+                            && player_inputs.are_teammates(winner, *player_idx))
+                })
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        score.complete_round(&winners);
+
+        for (_, winner_ent) in last_players {
             // commands.add(PlayerCommand::won_round(winner));
             commands.add(spawn_win_indicator(winner_ent));
         }
@@ -194,7 +372,13 @@ pub fn round_end(
         }
 
         if round_transition_synchronized {
-            if score.rounds_completed % meta.core.config.rounds_between_intermission == 0 {
+            let match_won = score
+                .winners(meta.core.config.winning_score_threshold, &player_inputs)
+                .is_some();
+            if match_won
+                || score.rounds_completed % meta.core.config.rounds_between_intermission == 0
+            {
+                // Show the scoring menu at intermissions, and when the match is complete.
                 scoring_menu.active = true;
                 scoring_menu.match_score = score.clone();
                 scoring_menu.next_maps = state.next_maps.clone();
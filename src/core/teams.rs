@@ -0,0 +1,73 @@
+//! Team play.
+//!
+//! When players are assigned a [`Team`] in player select, teammates share round wins, and the round
+//! is won when only one team is left standing. Unless [`MatchRules::friendly_fire`] is enabled,
+//! players can't kill their teammates.
+
+use crate::prelude::*;
+
+/// The team that a player is on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Team {
+    #[default]
+    Red,
+    Blue,
+}
+
+impl Team {
+    /// All of the teams.
+    pub const ALL: [Team; 2] = [Team::Red, Team::Blue];
+
+    /// The default team for the player in the given slot, alternating between teams so that the
+    /// players are split evenly.
+    pub fn for_slot(slot: usize) -> Self {
+        Self::ALL[slot % Self::ALL.len()]
+    }
+
+    /// The color that the team's players are tinted with.
+    pub fn color(self) -> Color {
+        match self {
+            Team::Red => Color::rgb(1.0, 0.6, 0.6),
+            Team::Blue => Color::rgb(0.6, 0.75, 1.0),
+        }
+    }
+
+    /// The localization key for the team's name.
+    pub fn name_key(self) -> &'static str {
+        match self {
+            Team::Red => "team-red",
+            Team::Blue => "team-blue",
+        }
+    }
+
+    /// The next team, wrapping around.
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|team| *team == self).unwrap();
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+impl MatchRules {
+    /// Whether the killer is allowed to kill the victim. Teammates may only kill each other with
+    /// friendly fire enabled.
+    pub fn can_kill(
+        &self,
+        player_inputs: &MatchInputs,
+        killer: PlayerIdx,
+        victim: PlayerIdx,
+    ) -> bool {
+        self.friendly_fire || !player_inputs.are_teammates(killer, victim)
+    }
+}
+
+impl MatchInputs {
+    /// Get the team of the player, if playing in teams.
+    pub fn team(&self, player: PlayerIdx) -> Option<Team> {
+        self.players[player.0 as usize].team
+    }
+
+    /// Whether the two players are different players on the same team.
+    pub fn are_teammates(&self, a: PlayerIdx, b: PlayerIdx) -> bool {
+        a != b && self.team(a).is_some() && self.team(a) == self.team(b)
+    }
+}
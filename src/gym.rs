@@ -0,0 +1,573 @@
+//! Gym-style reinforcement learning environment.
+//!
+//! [`GymEnv`] wraps a [`HeadlessGame`] so that bots can be trained offline: it resets a match on a
+//! given map, takes an [`Action`] for every player, steps the simulation a number of frames and
+//! returns an [`Observation`] of the match along with reward signals for each player.
+//!
+//! The environment can also be driven over stdin/stdout with `jumpy gym`, see [`run_cli`].
+
+use std::{
+    io::{BufRead, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crate::{
+    headless::{FrameControls, HeadlessError, HeadlessGame},
+    prelude::*,
+};
+
+/// How far from a player, in pixels, hazards are included in the player's observation.
+pub const HAZARD_OBSERVATION_RADIUS: f32 = 200.0;
+
+/// Errors that may occur while running the environment.
+#[derive(thiserror::Error, Debug)]
+pub enum GymError {
+    #[error(transparent)]
+    Headless(#[from] HeadlessError),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] serde_json::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The environment must be reset before it is stepped")]
+    NotReset,
+    #[error("Expected 1 to {MAX_PLAYERS} players, got {0}")]
+    InvalidPlayerCount(usize),
+}
+
+/// The setup of a match that the environment is reset to.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ResetConfig {
+    /// The name of the map to play on. The first stable map is used when this is `None`.
+    pub map: Option<String>,
+    /// The players in the match, in order of their [`PlayerIdx`].
+    pub players: Vec<GymPlayer>,
+    pub rules: MatchRules,
+}
+
+impl Default for ResetConfig {
+    fn default() -> Self {
+        Self {
+            map: None,
+            players: vec![GymPlayer::default(); 2],
+            rules: default(),
+        }
+    }
+}
+
+/// A player in a [`ResetConfig`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default)]
+pub struct GymPlayer {
+    /// Whether the player is controlled by the built-in AI instead of by the [`Action`]s given to
+    /// [`GymEnv::step`].
+    pub ai: bool,
+    /// The difficulty of the built-in AI, if the player is an AI player.
+    pub ai_difficulty: AiDifficulty,
+    pub team: Option<Team>,
+}
+
+/// The action taken by a player for the frames of a step.
+///
+/// Movement values are clamped to `-1.0..=1.0`, with positive values moving right and up.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Action {
+    pub move_x: f32,
+    pub move_y: f32,
+    pub jump: bool,
+    pub shoot: bool,
+    pub grab: bool,
+    pub slide: bool,
+}
+
+impl Action {
+    /// Get the player control that performs this action.
+    pub fn control(&self) -> PlayerControl {
+        let move_x = self.move_x.clamp(-1.0, 1.0);
+        let move_y = self.move_y.clamp(-1.0, 1.0);
+        PlayerControl {
+            left: (-move_x).max(0.0),
+            right: move_x.max(0.0),
+            up: move_y.max(0.0),
+            down: (-move_y).max(0.0),
+            jump_pressed: self.jump,
+            shoot_pressed: self.shoot,
+            grab_pressed: self.grab,
+            slide_pressed: self.slide,
+            ..default()
+        }
+    }
+}
+
+/// How much each reward signal contributes to [`PlayerObservation::reward`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct RewardWeights {
+    pub kill: f32,
+    pub death: f32,
+    pub suicide: f32,
+    pub round_won: f32,
+    pub round_lost: f32,
+}
+
+impl Default for RewardWeights {
+    fn default() -> Self {
+        Self {
+            kill: 1.0,
+            death: -1.0,
+            suicide: -1.0,
+            round_won: 5.0,
+            round_lost: -2.0,
+        }
+    }
+}
+
+/// The state of the match after a reset or step.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Observation {
+    /// The number of frames simulated since the last reset.
+    pub frame: u32,
+    /// The observations of the players in the match, in order of their [`PlayerIdx`].
+    pub players: Vec<PlayerObservation>,
+    /// Whether a round was completed during the step.
+    pub round_over: bool,
+    /// Whether the match is complete. Stepping a complete match does nothing, the environment must
+    /// be reset.
+    pub match_over: bool,
+}
+
+/// What a single player observes, and the rewards it earned during the step.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PlayerObservation {
+    pub player: u32,
+    /// Whether the player is in the map and alive. The position and velocity of players that
+    /// aren't alive are zero.
+    pub alive: bool,
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+    /// The kind of item the player is holding.
+    pub held_item: Option<AiItemKind>,
+    /// The hazards within [`HAZARD_OBSERVATION_RADIUS`] of the player.
+    pub hazards: Vec<HazardObservation>,
+    /// Other players killed during the step.
+    pub kills: u32,
+    /// Times the player was killed during the step, including suicides.
+    pub deaths: u32,
+    /// Times the player killed themselves during the step.
+    pub suicides: u32,
+    /// Whether the player, or their team, won a round during the step.
+    pub round_won: bool,
+    /// Whether a round was won by another player or team during the step.
+    pub round_lost: bool,
+    /// The reward signals combined with the environment's [`RewardWeights`].
+    pub reward: f32,
+}
+
+impl PlayerObservation {
+    /// Fill in what the player scored between the two snapshots, and the reward for it.
+    fn record_score(
+        &mut self,
+        player: PlayerIdx,
+        score: &ScoreSnapshot,
+        last_score: &ScoreSnapshot,
+        weights: &RewardWeights,
+    ) {
+        let i = player.0 as usize;
+        let stats = &score.stats[i];
+        let last_stats = &last_score.stats[i];
+        self.kills = stats.kills - last_stats.kills;
+        self.deaths = stats.deaths - last_stats.deaths;
+        self.suicides = stats.suicides - last_stats.suicides;
+        self.round_won = score.scores[i] > last_score.scores[i];
+        self.round_lost = score.rounds_completed > last_score.rounds_completed && !self.round_won;
+
+        self.reward = self.kills as f32 * weights.kill
+            + self.deaths as f32 * weights.death
+            + self.suicides as f32 * weights.suicide
+            + if self.round_won {
+                weights.round_won
+            } else {
+                0.0
+            }
+            + if self.round_lost {
+                weights.round_lost
+            } else {
+                0.0
+            };
+    }
+}
+
+/// An area that kills players.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct HazardObservation {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    /// Whether the hazard was caused by the observing player.
+    pub own: bool,
+}
+
+/// The scores that rewards are computed from.
+#[derive(Clone, Default)]
+struct ScoreSnapshot {
+    rounds_completed: u32,
+    scores: [u32; MAX_PLAYERS as usize],
+    stats: [PlayerStats; MAX_PLAYERS as usize],
+}
+
+impl ScoreSnapshot {
+    fn new(score: &MatchScore) -> Self {
+        Self {
+            rounds_completed: score.rounds_completed(),
+            scores: std::array::from_fn(|i| score.score(PlayerIdx(i as u32))),
+            stats: std::array::from_fn(|i| score.stats(PlayerIdx(i as u32))),
+        }
+    }
+}
+
+/// A reinforcement learning environment over headless matches.
+pub struct GymEnv {
+    headless: HeadlessGame,
+    /// The controls that the running match reads every frame.
+    controls: Arc<Mutex<FrameControls>>,
+    player_count: usize,
+    /// The number of frames simulated since the last reset.
+    frame: u32,
+    last_score: ScoreSnapshot,
+    pub reward_weights: RewardWeights,
+}
+
+impl GymEnv {
+    /// Create the environment, loading the game assets from the given directories.
+    pub fn new(asset_dir: PathBuf, packs_dir: PathBuf) -> Result<Self, GymError> {
+        Ok(Self {
+            headless: HeadlessGame::new(asset_dir, packs_dir)?,
+            controls: Arc::new(Mutex::new(std::array::from_fn(|_| default()))),
+            player_count: 0,
+            frame: 0,
+            last_score: default(),
+            reward_weights: default(),
+        })
+    }
+
+    /// End the current match, if any, and start a new one.
+    pub fn reset(&mut self, config: &ResetConfig) -> Result<Observation, GymError> {
+        let player_count = config.players.len();
+        if !(1..=MAX_PLAYERS as usize).contains(&player_count) {
+            return Err(GymError::InvalidPlayerCount(player_count));
+        }
+
+        self.headless.end_match();
+        let maps = MapPool::from_single_map(self.headless.find_map(config.map.as_deref())?);
+        let meta = self.headless.meta();
+        let player_info = std::array::from_fn(|i| match config.players.get(i) {
+            Some(player) => PlayerInput {
+                active: true,
+                selected_player: meta.core.players[i % meta.core.players.len()],
+                is_ai: player.ai,
+                ai_difficulty: player.ai_difficulty,
+                team: player.team,
+                ..default()
+            },
+            None => default(),
+        });
+
+        *self.controls.lock().unwrap() = std::array::from_fn(|_| default());
+        let controls = self.controls.clone();
+        self.headless
+            .start_match(maps, player_info, config.rules, move |_frame| {
+                *controls.lock().unwrap()
+            });
+
+        self.player_count = player_count;
+        self.frame = 0;
+        self.last_score = default();
+        Ok(self.observe())
+    }
+
+    /// Apply the given actions, indexed by [`PlayerIdx`], and simulate the given number of frames,
+    /// or until the match is complete. Players without an action don't press anything.
+    pub fn step(&mut self, actions: &[Action], frames: u32) -> Result<Observation, GymError> {
+        if self.headless.match_world().is_none() {
+            return Err(GymError::NotReset);
+        }
+
+        *self.controls.lock().unwrap() =
+            std::array::from_fn(|i| actions.get(i).map(Action::control).unwrap_or_default());
+        for _ in 0..frames {
+            if self.headless.match_winners().is_some() {
+                break;
+            }
+            self.headless.step();
+            self.frame += 1;
+        }
+
+        Ok(self.observe())
+    }
+
+    /// Observe the match and compute the rewards since the last observation.
+    fn observe(&mut self) -> Observation {
+        let frame = self.frame;
+        let match_over = self.headless.match_winners().is_some();
+        let Some(world) = self.headless.match_world() else {
+            return Observation { frame, ..default() };
+        };
+
+        let score = ScoreSnapshot::new(&world.resource::<MatchScore>());
+        let round_over = score.rounds_completed > self.last_score.rounds_completed;
+
+        let entities = world.resource::<Entities>();
+        // The AI knowledge isn't collected until the first frame is simulated.
+        let knowledge = world
+            .resources
+            .get::<AiKnowledge>()
+            .map(|knowledge| (*knowledge).clone())
+            .unwrap_or_default();
+        let player_indices = world.components.get::<PlayerIdx>().borrow();
+        let killed_players = world.components.get::<PlayerKilled>().borrow();
+        let transforms = world.components.get::<Transform>().borrow();
+        let bodies = world.components.get::<KinematicBody>().borrow();
+        let inventories = world.components.get::<Inventory>().borrow();
+
+        let players = (0..self.player_count)
+            .map(|i| {
+                let player_idx = PlayerIdx(i as u32);
+                let mut observation = PlayerObservation {
+                    player: i as u32,
+                    ..default()
+                };
+
+                let player_ent = entities
+                    .iter_with(&player_indices)
+                    .find(|(_, idx)| **idx == player_idx)
+                    .map(|(ent, _)| ent);
+                if let Some(player_ent) = player_ent {
+                    observation.alive = killed_players.get(player_ent).is_none();
+                }
+                if let Some(player_ent) = player_ent.filter(|_| observation.alive) {
+                    let position = transforms
+                        .get(player_ent)
+                        .map(|transform| transform.translation.truncate())
+                        .unwrap_or_default();
+                    observation.position = position.to_array();
+                    observation.velocity = bodies
+                        .get(player_ent)
+                        .map(|body| body.velocity.to_array())
+                        .unwrap_or_default();
+                    observation.held_item = inventories
+                        .get(player_ent)
+                        .and_then(|inventory| inventory.0)
+                        .and_then(|item| knowledge.item(item))
+                        .map(|item| item.kind);
+                    observation.hazards = knowledge
+                        .hazards
+                        .iter()
+                        .filter(|hazard| {
+                            let closest = position.clamp(hazard.rect.min, hazard.rect.max);
+                            closest.distance(position) <= HAZARD_OBSERVATION_RADIUS
+                        })
+                        .map(|hazard| HazardObservation {
+                            min: hazard.rect.min.to_array(),
+                            max: hazard.rect.max.to_array(),
+                            own: hazard.owner == Some(player_ent),
+                        })
+                        .collect();
+                }
+
+                observation.record_score(
+                    player_idx,
+                    &score,
+                    &self.last_score,
+                    &self.reward_weights,
+                );
+
+                observation
+            })
+            .collect();
+
+        self.last_score = score;
+        Observation {
+            frame,
+            players,
+            round_over,
+            match_over,
+        }
+    }
+}
+
+/// A request read by [`run_cli`].
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GymRequest {
+    /// Reset the environment, responding with the first observation.
+    Reset(ResetConfig),
+    /// Step the environment, responding with the resulting observation.
+    Step {
+        #[serde(default)]
+        actions: Vec<Action>,
+        #[serde(default = "default_step_frames")]
+        frames: u32,
+    },
+    /// Set the reward weights, responding with an empty observation.
+    RewardWeights(RewardWeights),
+    /// Stop the environment.
+    Close,
+}
+
+fn default_step_frames() -> u32 {
+    1
+}
+
+/// A response written by [`run_cli`].
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GymResponse {
+    Observation(Observation),
+    Error(String),
+}
+
+/// Run the environment over stdin/stdout.
+///
+/// Usage: `jumpy gym`
+///
+/// Every line of stdin is a JSON [`GymRequest`], for example
+/// `{"type": "reset", "map": "Bridge", "players": [{}, {"ai": true}]}` or
+/// `{"type": "step", "actions": [{"move_x": 1.0, "jump": true}], "frames": 4}`, and is answered
+/// with a single line of JSON [`GymResponse`] on stdout, either `{"observation": {...}}` or
+/// `{"error": "..."}`. The environment stops on a `close` request or when stdin is closed.
+pub fn run_cli(mut args: impl Iterator<Item = String>) -> Result<(), GymError> {
+    if let Some(arg) = args.next() {
+        return Err(HeadlessError::InvalidArgument(arg).into());
+    }
+
+    let mut env = GymEnv::new(crate::asset_dir(), crate::packs_dir())?;
+
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout().lock();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = serde_json::from_str::<GymRequest>(&line)
+            .map_err(GymError::from)
+            .and_then(|request| match request {
+                GymRequest::Reset(config) => env.reset(&config).map(Some),
+                GymRequest::Step { actions, frames } => env.step(&actions, frames).map(Some),
+                GymRequest::RewardWeights(weights) => {
+                    env.reward_weights = weights;
+                    Ok(Some(default()))
+                }
+                GymRequest::Close => Ok(None),
+            });
+        let response = match result {
+            Ok(Some(observation)) => GymResponse::Observation(observation),
+            Ok(None) => break,
+            Err(e) => GymResponse::Error(e.to_string()),
+        };
+
+        serde_json::to_writer(&mut stdout, &response).map_err(std::io::Error::from)?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn action_control_clamps_movement() {
+        let action = Action {
+            move_x: -3.0,
+            move_y: 0.5,
+            jump: true,
+            grab: true,
+            ..default()
+        };
+        let mut control = action.control();
+        assert_eq!((control.left, control.right), (1.0, 0.0));
+        assert_eq!((control.up, control.down), (0.5, 0.0));
+        assert!(control.jump_pressed && control.grab_pressed);
+        assert!(!control.shoot_pressed && !control.slide_pressed);
+
+        control.update_just_pressed(&default());
+        assert_eq!(control.move_direction, vec2(-1.0, 0.5));
+        assert!(control.jump_just_pressed);
+
+        let control = Action {
+            move_x: 0.25,
+            move_y: -2.0,
+            ..default()
+        }
+        .control();
+        assert_eq!((control.left, control.right), (0.0, 0.25));
+        assert_eq!((control.up, control.down), (0.0, 1.0));
+    }
+
+    /// A score snapshot after some rounds, with the given kills, deaths and suicides for player 0.
+    fn snapshot(rounds_completed: u32, scores: [u32; 2], stats: [u32; 3]) -> ScoreSnapshot {
+        let mut snapshot = ScoreSnapshot {
+            rounds_completed,
+            ..default()
+        };
+        snapshot.scores[..2].copy_from_slice(&scores);
+        let [kills, deaths, suicides] = stats;
+        snapshot.stats[0] = PlayerStats {
+            kills,
+            deaths,
+            suicides,
+            ..default()
+        };
+        snapshot
+    }
+
+    #[test]
+    fn rewards_count_what_happened_since_the_last_observation() {
+        let weights = RewardWeights::default();
+        let last = snapshot(1, [1, 0], [2, 1, 0]);
+
+        // Two kills and a round won.
+        let mut observation = PlayerObservation::default();
+        observation.record_score(
+            PlayerIdx(0),
+            &snapshot(2, [2, 0], [4, 1, 0]),
+            &last,
+            &weights,
+        );
+        assert_eq!(
+            (observation.kills, observation.deaths, observation.suicides),
+            (2, 0, 0)
+        );
+        assert!(observation.round_won && !observation.round_lost);
+        assert_eq!(observation.reward, 2.0 * weights.kill + weights.round_won);
+
+        // A suicide, and a round lost to the other player.
+        let mut observation = PlayerObservation::default();
+        observation.record_score(
+            PlayerIdx(0),
+            &snapshot(2, [1, 1], [2, 2, 1]),
+            &last,
+            &weights,
+        );
+        assert_eq!(
+            (observation.kills, observation.deaths, observation.suicides),
+            (0, 1, 1)
+        );
+        assert!(!observation.round_won && observation.round_lost);
+        assert_eq!(
+            observation.reward,
+            weights.death + weights.suicide + weights.round_lost
+        );
+
+        // Nothing happened.
+        let mut observation = PlayerObservation::default();
+        observation.record_score(PlayerIdx(0), &last, &last, &weights);
+        assert!(!observation.round_won && !observation.round_lost);
+        assert_eq!(observation.reward, 0.0);
+    }
+}
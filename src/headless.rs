@@ -0,0 +1,390 @@
+//! Headless match simulation.
+//!
+//! Runs a [`MatchPlugin`] session without a renderer, window, or audio output, stepping exactly one
+//! simulation frame at a time with scripted [`PlayerControl`]s. This is useful for bots, balance
+//! testing, and smoke testing the game in CI.
+
+use std::path::PathBuf;
+
+use crate::{core::MatchPlugin, prelude::*, ui::scoring::ScoringMenuState};
+
+/// The controls for every player slot in a single frame.
+pub type FrameControls = [PlayerControl; MAX_PLAYERS as usize];
+
+/// A source of scripted player controls for a [`HeadlessMatchRunner`].
+pub trait InputScript: Sync + Send + 'static {
+    /// Get the controls for every player on the given frame.
+    ///
+    /// Only the `pressed` and `left`/`right`/`up`/`down` values need to be filled in, the
+    /// `just_pressed` flags and movement direction are computed by the runner.
+    fn controls(&mut self, frame: u32) -> FrameControls;
+}
+
+impl<F: FnMut(u32) -> FrameControls + Sync + Send + 'static> InputScript for F {
+    fn controls(&mut self, frame: u32) -> FrameControls {
+        self(frame)
+    }
+}
+
+/// A fixed list of controls, one entry per frame. Once the script runs out, every player is given
+/// default (empty) controls.
+impl InputScript for Vec<FrameControls> {
+    fn controls(&mut self, frame: u32) -> FrameControls {
+        self.get(frame as usize)
+            .copied()
+            .unwrap_or_else(|| std::array::from_fn(|_| default()))
+    }
+}
+
+/// Session runner that advances the match by exactly one fixed update every time it is stepped,
+/// regardless of wall-clock time, and reads player controls from an [`InputScript`] instead of the
+/// keyboard and gamepads.
+///
+/// Note that the controls of AI players are still overwritten by the AI systems.
+pub struct HeadlessMatchRunner {
+    script: Box<dyn InputScript>,
+    /// The number of frames that have been simulated. This keeps counting across round restarts.
+    pub frame: u32,
+    last_controls: FrameControls,
+}
+
+impl HeadlessMatchRunner {
+    pub fn new(script: impl InputScript) -> Self {
+        Self {
+            script: Box::new(script),
+            frame: 0,
+            last_controls: std::array::from_fn(|_| default()),
+        }
+    }
+}
+
+impl SessionRunner for HeadlessMatchRunner {
+    fn step(&mut self, _frame_start: Instant, world: &mut World, stages: &mut SystemStages) {
+        world
+            .resource_mut::<Time>()
+            .advance_exact(Duration::from_secs_f64(1.0 / FPS as f64));
+
+        let mut controls = self.script.controls(self.frame);
+        {
+            let mut player_inputs = world.resource_mut::<MatchInputs>();
+            for (i, control) in controls.iter_mut().enumerate() {
+                control.update_just_pressed(&self.last_controls[i]);
+                player_inputs.players[i].control = *control;
+            }
+        }
+        self.last_controls = controls;
+
+        stages.run(world);
+        self.frame += 1;
+    }
+
+    fn restart_session(&mut self) {
+        // Keep the script and frame count so that the script continues into the next round.
+        self.last_controls = std::array::from_fn(|_| default());
+    }
+
+    fn disable_local_input(&mut self, _disable_input: bool) {}
+}
+
+/// Errors that may occur while setting up a headless game.
+#[derive(thiserror::Error, Debug)]
+pub enum HeadlessError {
+    #[error("Error loading {0} asset(s)")]
+    AssetLoad(usize),
+    #[error("Map not found: {0}")]
+    MapNotFound(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+/// A bones [`Game`] that is run without a renderer.
+pub struct HeadlessGame {
+    pub game: Game,
+}
+
+impl HeadlessGame {
+    /// Create the game and block until all of the assets in the given directories are loaded.
+    pub fn new(asset_dir: PathBuf, packs_dir: PathBuf) -> Result<Self, HeadlessError> {
+        let mut game = Game::new();
+        game.install_plugin(DefaultGamePlugin)
+            .install_plugin(crate::core::game_plugin)
+            .install_plugin(crate::ui::scoring::game_plugin)
+            .init_shared_resource::<AudioCenter>()
+            .insert_shared_resource(Window {
+                size: vec2(1280.0, 720.0),
+            })
+            .register_default_assets();
+
+        let mut asset_server = AssetServer::new(
+            FileAssetIo::new(&asset_dir, &packs_dir),
+            crate::game_version(),
+        );
+        asset_server.load_assets();
+        while !asset_server.load_progress.is_finished() {
+            let errored = asset_server.load_progress.errored();
+            if errored > 0 {
+                return Err(HeadlessError::AssetLoad(errored));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        game.insert_shared_resource(asset_server);
+
+        Ok(Self { game })
+    }
+
+    /// Create the game from the asset and packs directories of the repository, for tests.
+    #[cfg(test)]
+    pub fn for_tests() -> Self {
+        Self::new(crate::asset_dir(), crate::packs_dir()).unwrap()
+    }
+
+    /// Get the game metadata.
+    pub fn meta(&self) -> Arc<GameMeta> {
+        let asset_server = self.game.shared_resource::<AssetServer>().unwrap();
+        Arc::new(asset_server.root::<GameMeta>().clone())
+    }
+
+    /// Start a match that reads its player controls from the given script.
+    pub fn start_match(
+        &mut self,
+        maps: MapPool,
+        player_info: [PlayerInput; MAX_PLAYERS as usize],
+        rules: MatchRules,
+        script: impl InputScript,
+    ) {
+        self.start_match_with_runner(
+            maps,
+            player_info,
+            rules,
+            Box::new(HeadlessMatchRunner::new(script)),
+        );
+    }
+
+    /// Start a match that is run by the given session runner.
+    pub fn start_match_with_runner(
+        &mut self,
+        maps: MapPool,
+        player_info: [PlayerInput; MAX_PLAYERS as usize],
+        rules: MatchRules,
+        session_runner: Box<dyn SessionRunner>,
+    ) {
+        let plugins = {
+            let asset_server = self.game.shared_resource::<AssetServer>().unwrap();
+            asset_server.root::<GameMeta>().get_plugins(&asset_server)
+        };
+        self.game.sessions.start_game(MatchPlugin {
+            maps,
+            player_info,
+            plugins,
+            score: default(),
+            rules,
+            random_seed: DEFAULT_RANDOM_SEED as u64,
+            session_runner,
+        });
+    }
+
+    /// End the running match, if any, so that a new one can be started.
+    pub fn end_match(&mut self) {
+        self.game.sessions.end_game();
+        self.game
+            .shared_resource_mut::<ScoringMenuState>()
+            .unwrap()
+            .reset();
+    }
+
+    /// Find a stable or experimental map by name, or get the first stable map if no name is given.
+    pub fn find_map(&self, name: Option<&str>) -> Result<Handle<MapMeta>, HeadlessError> {
+        let asset_server = self.game.shared_resource::<AssetServer>().unwrap();
+        let core = &asset_server.root::<GameMeta>().core;
+        match name {
+            Some(name) => core
+                .stable_maps
+                .iter()
+                .chain(core.experimental_maps.iter())
+                .find(|map| asset_server.get(**map).name.as_str() == name)
+                .copied()
+                .ok_or_else(|| HeadlessError::MapNotFound(name.to_string())),
+            None => Ok(core.stable_maps[0]),
+        }
+    }
+
+    /// Simulate a single frame.
+    ///
+    /// There is nobody to click through the scoring menu, so when the match reaches an intermission
+    /// the next round is started immediately. Once the match is complete, stepping does nothing.
+    pub fn step(&mut self) {
+        if self.match_winners().is_some() {
+            return;
+        }
+        self.game.step(Instant::now());
+
+        let match_won = self.match_winners().is_some();
+        let next_round = {
+            let mut scoring = self.game.shared_resource_mut::<ScoringMenuState>().unwrap();
+            (scoring.active && !match_won).then(|| {
+                let next_maps = scoring.next_maps.clone();
+                scoring.reset();
+                next_maps
+            })
+        };
+        if let Some(next_maps) = next_round {
+            self.game.sessions.restart_game(next_maps, false);
+        }
+    }
+
+    /// Simulate the given number of frames, or until the match is complete.
+    pub fn run(&mut self, frames: u32) {
+        for _ in 0..frames {
+            if self.match_winners().is_some() {
+                break;
+            }
+            self.step();
+        }
+    }
+
+    /// Get the winners of the match once it is complete: the winning player, or the players of the
+    /// winning team.
+    pub fn match_winners(&self) -> Option<Vec<PlayerIdx>> {
+        let scoring = self.game.shared_resource::<ScoringMenuState>().unwrap();
+        if !scoring.active {
+            return None;
+        }
+        let match_inputs = self.match_world()?.resource::<MatchInputs>();
+        scoring
+            .match_score
+            .winners(self.win_threshold(), &match_inputs)
+    }
+
+    fn win_threshold(&self) -> u32 {
+        let asset_server = self.game.shared_resource::<AssetServer>().unwrap();
+        let threshold = asset_server
+            .root::<GameMeta>()
+            .core
+            .config
+            .winning_score_threshold;
+        threshold
+    }
+
+    /// Get the world of the match session, if a match is running.
+    pub fn match_world(&self) -> Option<&World> {
+        self.game
+            .sessions
+            .get(SessionNames::GAME)
+            .map(|session| &session.world)
+    }
+}
+
+/// Run a headless match from the command line and print the resulting score.
+///
+/// Usage: `jumpy headless [--frames <count>] [--players <count>] [--map <name>] [--lives <count>]
+/// [--time-limit <seconds>] [--sudden-death] [--teams] [--friendly-fire]
+/// [--ai-difficulty <easy|normal|hard>]`
+///
+/// All players are controlled by the AI. With `--teams`, the players alternate between teams.
+pub fn run_cli(mut args: impl Iterator<Item = String>) -> Result<(), HeadlessError> {
+    let mut frames = 60 * 60;
+    let mut player_count = 2;
+    let mut map_name = None;
+    let mut rules = MatchRules::default();
+    let mut teams = false;
+    let mut ai_difficulty = AiDifficulty::default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| HeadlessError::InvalidArgument(format!("{arg} expects a value")))
+        };
+        match arg.as_str() {
+            "--frames" => {
+                let value = value()?;
+                frames = value
+                    .parse()
+                    .map_err(|_| HeadlessError::InvalidArgument(value))?;
+            }
+            "--players" => {
+                let value = value()?;
+                player_count = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|count| (1..=MAX_PLAYERS).contains(count))
+                    .ok_or(HeadlessError::InvalidArgument(value))?;
+            }
+            "--map" => map_name = Some(value()?),
+            "--lives" => {
+                let value = value()?;
+                rules.lives = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|lives| (1..=MAX_LIVES).contains(lives))
+                    .ok_or(HeadlessError::InvalidArgument(value))?;
+            }
+            "--time-limit" => {
+                let value = value()?;
+                rules.time_limit_secs = value
+                    .parse()
+                    .map_err(|_| HeadlessError::InvalidArgument(value))?;
+            }
+            "--sudden-death" => rules.sudden_death = true,
+            "--teams" => teams = true,
+            "--friendly-fire" => rules.friendly_fire = true,
+            "--ai-difficulty" => {
+                let value = value()?;
+                ai_difficulty = match value.as_str() {
+                    "easy" => AiDifficulty::Easy,
+                    "normal" => AiDifficulty::Normal,
+                    "hard" => AiDifficulty::Hard,
+                    _ => return Err(HeadlessError::InvalidArgument(value)),
+                };
+            }
+            _ => return Err(HeadlessError::InvalidArgument(arg.clone())),
+        }
+    }
+
+    let mut headless = HeadlessGame::new(crate::asset_dir(), crate::packs_dir())?;
+    let meta = headless.meta();
+
+    let maps = MapPool::from_single_map(headless.find_map(map_name.as_deref())?);
+
+    let player_info = std::array::from_fn(|i| {
+        let i = i as u32;
+        PlayerInput {
+            active: i < player_count,
+            selected_player: meta.core.players[i as usize % meta.core.players.len()],
+            is_ai: true,
+            ai_difficulty,
+            team: (teams && i < player_count).then(|| Team::for_slot(i as usize)),
+            ..default()
+        }
+    });
+
+    headless.start_match(maps, player_info, rules, Vec::<FrameControls>::new());
+    headless.run(frames);
+
+    let world = headless.match_world().unwrap();
+    let score = world.resource::<MatchScore>();
+    println!(
+        "Simulated up to {frames} frames, {} rounds completed.",
+        score.rounds_completed()
+    );
+    for i in 0..player_count {
+        let stats = score.stats(PlayerIdx(i));
+        println!(
+            "Player {}: {} (kills: {}, deaths: {}, suicides: {}, items picked up: {}, shots fired: {})",
+            i + 1,
+            score.score(PlayerIdx(i)),
+            stats.kills,
+            stats.deaths,
+            stats.suicides,
+            stats.items_picked_up,
+            stats.shots_fired,
+        );
+    }
+    if let Some(winners) = headless.match_winners() {
+        let winners = winners
+            .iter()
+            .map(|winner| format!("Player {}", winner.0 + 1))
+            .collect::<Vec<_>>();
+        println!("{} won the match.", winners.join(" and "));
+    }
+
+    Ok(())
+}
@@ -181,6 +181,71 @@ pub struct PlayerControl {
 
     pub ragdoll_pressed: bool,
     pub ragdoll_just_pressed: bool,
+
+    /// Whether no input was received for this player, because their peer left the network match.
+    pub disconnected: bool,
+}
+
+impl PlayerControl {
+    /// Update the movement direction and the `just_pressed` / `just_moved` flags by comparing these
+    /// controls to the controls from the previous frame.
+    ///
+    /// The movement direction is derived from the `left`, `right`, `up`, and `down` values.
+    pub fn update_just_pressed(&mut self, last: &PlayerControl) {
+        self.move_direction = vec2(self.right - self.left, self.up - self.down);
+        self.moving = self.move_direction.length_squared() > 0.01;
+
+        for (just_pressed, current_pressed, last_pressed) in [
+            (
+                &mut self.pause_just_pressed,
+                self.pause_pressed,
+                last.pause_pressed,
+            ),
+            (
+                &mut self.jump_just_pressed,
+                self.jump_pressed,
+                last.jump_pressed,
+            ),
+            (
+                &mut self.shoot_just_pressed,
+                self.shoot_pressed,
+                last.shoot_pressed,
+            ),
+            (
+                &mut self.grab_just_pressed,
+                self.grab_pressed,
+                last.grab_pressed,
+            ),
+            (
+                &mut self.slide_just_pressed,
+                self.slide_pressed,
+                last.slide_pressed,
+            ),
+            (
+                &mut self.ragdoll_just_pressed,
+                self.ragdoll_pressed,
+                last.ragdoll_pressed,
+            ),
+            (
+                &mut self.menu_back_just_pressed,
+                self.menu_back_pressed,
+                last.menu_back_pressed,
+            ),
+            (
+                &mut self.menu_confirm_just_pressed,
+                self.menu_confirm_pressed,
+                last.menu_confirm_pressed,
+            ),
+            (
+                &mut self.menu_start_just_pressed,
+                self.menu_start_pressed,
+                last.menu_start_pressed,
+            ),
+            (&mut self.just_moved, self.moving, last.moving),
+        ] {
+            *just_pressed = current_pressed && !last_pressed;
+        }
+    }
 }
 
 #[derive(HasSchema, Clone)]
@@ -223,61 +288,7 @@ impl<'a>
             .iter_mut()
             .for_each(|(source, current)| {
                 let last = self.last_controls.entry(*source).or_default();
-
-                current.move_direction =
-                    vec2(current.right - current.left, current.up - current.down);
-                current.moving = current.move_direction.length_squared() > 0.01;
-
-                for (just_pressed, current_pressed, last_pressed) in [
-                    (
-                        &mut current.pause_just_pressed,
-                        current.pause_pressed,
-                        last.pause_pressed,
-                    ),
-                    (
-                        &mut current.jump_just_pressed,
-                        current.jump_pressed,
-                        last.jump_pressed,
-                    ),
-                    (
-                        &mut current.shoot_just_pressed,
-                        current.shoot_pressed,
-                        last.shoot_pressed,
-                    ),
-                    (
-                        &mut current.grab_just_pressed,
-                        current.grab_pressed,
-                        last.grab_pressed,
-                    ),
-                    (
-                        &mut current.slide_just_pressed,
-                        current.slide_pressed,
-                        last.slide_pressed,
-                    ),
-                    (
-                        &mut current.ragdoll_just_pressed,
-                        current.ragdoll_pressed,
-                        last.ragdoll_pressed,
-                    ),
-                    (
-                        &mut current.menu_back_just_pressed,
-                        current.menu_back_pressed,
-                        last.menu_back_pressed,
-                    ),
-                    (
-                        &mut current.menu_confirm_just_pressed,
-                        current.menu_confirm_pressed,
-                        last.menu_confirm_pressed,
-                    ),
-                    (
-                        &mut current.menu_start_just_pressed,
-                        current.menu_start_pressed,
-                        last.menu_start_pressed,
-                    ),
-                    (&mut current.just_moved, current.moving, last.moving),
-                ] {
-                    *just_pressed = current_pressed && !last_pressed;
-                }
+                current.update_just_pressed(last);
             });
     }
 
@@ -421,10 +432,22 @@ impl NetworkPlayerControl<DensePlayerControl> for PlayerControl {
         dense_control.set_shoot_pressed(self.shoot_pressed);
         dense_control.set_ragdoll_pressed(self.ragdoll_pressed);
         dense_control.set_move_direction(proto::DenseMoveDirection(self.move_direction));
+        dense_control.set_connected(!self.disconnected);
         dense_control
     }
 
     fn update_from_dense(&mut self, new_control: &DensePlayerControl) {
+        // The input of a peer that left the match is zeroed, which doesn't decode to meaningful
+        // controls.
+        if !new_control.connected() {
+            *self = PlayerControl {
+                disconnected: true,
+                ..default()
+            };
+            return;
+        }
+        self.disconnected = false;
+
         let jump_pressed = new_control.jump_pressed();
         self.jump_just_pressed = jump_pressed && !self.jump_pressed;
         self.jump_pressed = jump_pressed;
@@ -437,6 +460,10 @@ impl NetworkPlayerControl<DensePlayerControl> for PlayerControl {
         self.shoot_just_pressed = shoot_pressed && !self.shoot_pressed;
         self.shoot_pressed = shoot_pressed;
 
+        let slide_pressed = new_control.slide_pressed();
+        self.slide_just_pressed = slide_pressed && !self.slide_pressed;
+        self.slide_pressed = slide_pressed;
+
         let ragdoll_pressed = new_control.ragdoll_pressed();
         self.ragdoll_just_pressed = ragdoll_pressed && !self.ragdoll_pressed;
         self.ragdoll_pressed = ragdoll_pressed;
@@ -463,6 +490,9 @@ bitfield::bitfield! {
     pub slide_pressed, set_slide_pressed: 3;
     pub ragdoll_pressed, set_ragdoll_pressed: 4;
     pub from into DenseMoveDirection, move_direction, set_move_direction: 16, 5;
+    // Always set by connected players, so that the zeroed input of a disconnected player can be
+    // told apart.
+    pub connected, set_connected: 17;
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -0,0 +1,545 @@
+//! In-process loopback network transport.
+//!
+//! Connects several peers inside one process through in-memory queues, with configurable latency,
+//! jitter and packet loss. Like the real transport, it has a reliable channel, whose messages
+//! always arrive and arrive in order, and an unreliable channel, whose messages may be dropped or
+//! reordered.
+//!
+//! This makes it possible to test the network protocols of the game, and to reproduce bad network
+//! conditions, without real machines. A [`LoopbackSocket`] is a bones [`NetworkSocket`], for the
+//! reliable messages of the game, and a GGRS [`NonBlockingSocket`], which sends the GGRS messages
+//! over the unreliable channel. The bones [`GgrsSessionRunner`] only accepts the QUIC based socket,
+//! so matches over the loopback are run with a [`LoopbackSessionRunner`] instead. Like the bones
+//! runner, it inserts an online [`SyncingInfo`] before every frame, so the game takes the same
+//! network code paths, like waiting for the end of the round to be confirmed, as in a real match.
+//!
+//! [`GgrsSessionRunner`]: bones_framework::networking::GgrsSessionRunner
+
+use std::sync::Mutex;
+use std::time::Instant;
+
+use bones_framework::networking::{
+    input::NetworkPlayerControl, socket::Socket, NetworkSocket, SocketTarget, SyncingInfo,
+};
+use ggrs::{GgrsEvent, GgrsRequest, NonBlockingSocket, P2PSession, PlayerType, SessionState};
+use turborand::{rng::Rng, SeededCore, TurboRand};
+
+use crate::{core::checksum::FrameChecksum, headless::InputScript, prelude::*};
+
+/// The simulated network conditions of the peers created by [`loopback_sockets`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoopbackConditions {
+    /// The time it takes for a message to arrive.
+    pub latency: Duration,
+    /// The maximum random delay added to the latency of each message.
+    pub jitter: Duration,
+    /// The chance, from `0.0` to `1.0`, that an unreliable message is lost.
+    pub packet_loss: f32,
+}
+
+/// Create the sockets of the given number of peers, connected to each other in memory. The `seed`
+/// makes the simulated jitter and packet loss reproducible.
+pub fn loopback_sockets(
+    player_count: u32,
+    conditions: LoopbackConditions,
+    seed: u64,
+) -> Vec<LoopbackSocket> {
+    let hub = Arc::new(Mutex::new(LoopbackHub {
+        conditions,
+        rng: Rng::with_seed(seed),
+        in_flight: Vec::new(),
+        reliable_tail: HashMap::default(),
+        closed: vec![false; player_count as usize],
+    }));
+    (0..player_count)
+        .map(|player_idx| LoopbackSocket {
+            player_idx,
+            player_count,
+            hub: hub.clone(),
+        })
+        .collect()
+}
+
+/// The channel a message is sent on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Channel {
+    Reliable,
+    Unreliable,
+}
+
+/// A message that has been sent but not received yet.
+struct Packet {
+    from: u32,
+    to: u32,
+    channel: Channel,
+    deliver_at: Instant,
+    data: Vec<u8>,
+}
+
+/// The state shared between the sockets created by [`loopback_sockets`].
+struct LoopbackHub {
+    conditions: LoopbackConditions,
+    rng: Rng,
+    in_flight: Vec<Packet>,
+    /// The delivery time of the last reliable message between each pair of peers, used to keep the
+    /// reliable messages in order.
+    reliable_tail: HashMap<(u32, u32), Instant>,
+    closed: Vec<bool>,
+}
+
+impl LoopbackHub {
+    fn send(&mut self, from: u32, to: u32, channel: Channel, data: &[u8], now: Instant) {
+        if self.closed[from as usize] || self.closed[to as usize] {
+            return;
+        }
+        let conditions = self.conditions;
+        if channel == Channel::Unreliable && self.rng.f32() < conditions.packet_loss {
+            return;
+        }
+
+        let jitter = conditions.jitter.as_micros() as u64;
+        let jitter = Duration::from_micros(self.rng.u64(0..=jitter));
+        let mut deliver_at = now + conditions.latency + jitter;
+        if channel == Channel::Reliable {
+            let tail = self.reliable_tail.entry((from, to)).or_insert(deliver_at);
+            deliver_at = deliver_at.max(*tail);
+            *tail = deliver_at;
+        }
+
+        self.in_flight.push(Packet {
+            from,
+            to,
+            channel,
+            deliver_at,
+            data: data.to_vec(),
+        });
+    }
+
+    fn recv(&mut self, to: u32, channel: Channel, now: Instant) -> Vec<(u32, Vec<u8>)> {
+        let mut arrived = Vec::new();
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            let packet = &self.in_flight[i];
+            if packet.to == to && packet.channel == channel && packet.deliver_at <= now {
+                arrived.push(self.in_flight.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        // Packets are stored in the order they were sent, so sorting by delivery time keeps the
+        // order of messages that arrive at the same time.
+        arrived.sort_by_key(|packet| packet.deliver_at);
+        arrived
+            .into_iter()
+            .map(|packet| (packet.from, packet.data))
+            .collect()
+    }
+}
+
+/// One of the peers created by [`loopback_sockets`].
+#[derive(Clone)]
+pub struct LoopbackSocket {
+    player_idx: u32,
+    player_count: u32,
+    hub: Arc<Mutex<LoopbackHub>>,
+}
+
+impl LoopbackSocket {
+    /// Change the simulated network conditions for all of the peers.
+    pub fn set_conditions(&self, conditions: LoopbackConditions) {
+        self.hub.lock().unwrap().conditions = conditions;
+    }
+
+    /// Send a message that may be lost or arrive out of order.
+    pub fn send_unreliable(&self, target: SocketTarget, message: &[u8]) {
+        self.send(target, Channel::Unreliable, message, Instant::now());
+    }
+
+    /// Receive the unreliable messages that have arrived, along with the index of their sender.
+    pub fn recv_unreliable(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut hub = self.hub.lock().unwrap();
+        hub.recv(self.player_idx, Channel::Unreliable, Instant::now())
+    }
+
+    fn send(&self, target: SocketTarget, channel: Channel, message: &[u8], now: Instant) {
+        let mut hub = self.hub.lock().unwrap();
+        match target {
+            SocketTarget::Player(to) => hub.send(self.player_idx, to, channel, message, now),
+            SocketTarget::All => {
+                for to in (0..self.player_count).filter(|to| *to != self.player_idx) {
+                    hub.send(self.player_idx, to, channel, message, now);
+                }
+            }
+        }
+    }
+}
+
+impl NetworkSocket for LoopbackSocket {
+    /// Get a bones socket for the local peer without any connections, since the bones socket only
+    /// works over QUIC. Messages sent with it are dropped and it never receives any, so it can only
+    /// stand in for the socket of a match, like in the [`SyncingInfo`] of a
+    /// [`LoopbackSessionRunner`]. The GGRS messages of a match over the loopback go through the
+    /// [`NonBlockingSocket`] implementation of the loopback socket itself.
+    fn ggrs_socket(&self) -> Socket {
+        Socket::new(self.player_idx, Vec::new())
+    }
+
+    /// Send a message that is never lost, and arrives after the messages sent before it.
+    fn send_reliable(&self, target: SocketTarget, message: &[u8]) {
+        self.send(target, Channel::Reliable, message, Instant::now());
+    }
+
+    /// Receive the reliable messages that have arrived, along with the index of their sender.
+    fn recv_reliable(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut hub = self.hub.lock().unwrap();
+        hub.recv(self.player_idx, Channel::Reliable, Instant::now())
+    }
+
+    /// Disconnect this peer. Messages that are still in flight to or from it are dropped.
+    fn close(&self) {
+        let mut hub = self.hub.lock().unwrap();
+        hub.closed[self.player_idx as usize] = true;
+        let player_idx = self.player_idx;
+        hub.in_flight
+            .retain(|packet| packet.from != player_idx && packet.to != player_idx);
+    }
+
+    fn player_idx(&self) -> u32 {
+        self.player_idx
+    }
+
+    fn player_is_local(&self) -> [bool; MAX_PLAYERS as usize] {
+        std::array::from_fn(|i| i as u32 == self.player_idx)
+    }
+
+    fn player_count(&self) -> u32 {
+        self.player_count
+    }
+}
+
+/// The GGRS side of the socket, which sends the GGRS messages over the unreliable channel, with the
+/// player indices as addresses.
+impl NonBlockingSocket<usize> for LoopbackSocket {
+    fn send_to(&mut self, msg: &ggrs::Message, addr: &usize) {
+        self.send_unreliable(
+            SocketTarget::Player(*addr as u32),
+            &postcard::to_allocvec(msg).unwrap(),
+        );
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(usize, ggrs::Message)> {
+        self.recv_unreliable()
+            .into_iter()
+            .filter_map(|(from, data)| {
+                postcard::from_bytes(&data)
+                    .ok()
+                    .map(|msg| (from as usize, msg))
+            })
+            .collect()
+    }
+}
+
+/// The GGRS configuration of matches over the loopback.
+#[derive(Debug)]
+pub struct LoopbackGgrsConfig;
+
+impl ggrs::Config for LoopbackGgrsConfig {
+    type Input = u32;
+    type State = World;
+    type Address = usize;
+}
+
+/// Session runner that plays a match over a [`LoopbackSocket`] with a GGRS session, rolling the
+/// world back like the bones GGRS session runner does.
+///
+/// Like the [`HeadlessMatchRunner`][crate::headless::HeadlessMatchRunner], it advances the match by
+/// at most one frame every time it is stepped, regardless of wall-clock time, and reads the controls
+/// of the local player from an [`InputScript`]. The step is skipped while the session is
+/// synchronizing, or when the prediction window is full.
+pub struct LoopbackSessionRunner {
+    socket: LoopbackSocket,
+    /// The stand-in bones socket put in the [`SyncingInfo`], see [`LoopbackSocket::ggrs_socket`].
+    syncing_socket: Socket,
+    session: P2PSession<LoopbackGgrsConfig>,
+    /// The GGRS frame of the world, which goes back when GGRS rolls the world back.
+    simulated_frame: i32,
+    local_input_delay: usize,
+    max_prediction_window: usize,
+    script: Box<dyn InputScript>,
+    /// The number of frames the local controls have been read for. This keeps counting across round
+    /// restarts.
+    pub frame: u32,
+    last_control: PlayerControl,
+    /// The frames that GGRS has detected a desync on. This is shared, so that it can still be read
+    /// once the runner has been moved into the match session.
+    pub desyncs: Arc<Mutex<Vec<i32>>>,
+}
+
+impl LoopbackSessionRunner {
+    pub fn new(
+        socket: LoopbackSocket,
+        local_input_delay: usize,
+        max_prediction_window: usize,
+        script: impl InputScript,
+    ) -> Self {
+        Self {
+            session: Self::start_session(&socket, local_input_delay, max_prediction_window),
+            syncing_socket: socket.ggrs_socket(),
+            simulated_frame: 0,
+            socket,
+            local_input_delay,
+            max_prediction_window,
+            script: Box::new(script),
+            frame: 0,
+            last_control: default(),
+            desyncs: default(),
+        }
+    }
+
+    fn start_session(
+        socket: &LoopbackSocket,
+        local_input_delay: usize,
+        max_prediction_window: usize,
+    ) -> P2PSession<LoopbackGgrsConfig> {
+        let mut builder = ggrs::SessionBuilder::<LoopbackGgrsConfig>::new()
+            .with_num_players(socket.player_count as usize)
+            .with_input_delay(local_input_delay)
+            .with_fps(FPS as usize)
+            .unwrap()
+            .with_max_prediction_window(max_prediction_window)
+            .unwrap()
+            .with_desync_detection_mode(ggrs::DesyncDetection::On { interval: 1 });
+        for player in 0..socket.player_count as usize {
+            let player_type = if player == socket.player_idx as usize {
+                PlayerType::Local
+            } else {
+                PlayerType::Remote(player)
+            };
+            builder = builder.add_player(player_type, player).unwrap();
+        }
+        builder.start_p2p_session(socket.clone()).unwrap()
+    }
+}
+
+impl SessionRunner for LoopbackSessionRunner {
+    fn step(&mut self, _frame_start: Instant, world: &mut World, stages: &mut SystemStages) {
+        self.session.poll_remote_clients();
+        for event in self.session.events() {
+            if let GgrsEvent::DesyncDetected { frame, .. } = event {
+                error!("Desync detected over the loopback on frame {frame}");
+                self.desyncs.lock().unwrap().push(frame);
+            }
+        }
+        if self.session.current_state() != SessionState::Running {
+            return;
+        }
+
+        let mut control = self.script.controls(self.frame)[self.socket.player_idx as usize];
+        control.update_just_pressed(&self.last_control);
+        let dense: u32 = bytemuck::cast(control.get_dense_input());
+        self.session
+            .add_local_input(self.socket.player_idx as usize, dense)
+            .unwrap();
+
+        let requests = match self.session.advance_frame() {
+            Ok(requests) => requests,
+            // Wait for the other peers when too far ahead of them.
+            Err(ggrs::GgrsError::PredictionThreshold) => return,
+            Err(e) => panic!("GGRS session failed: {e}"),
+        };
+        self.last_control = control;
+        self.frame += 1;
+
+        for request in requests {
+            match request {
+                GgrsRequest::SaveGameState { cell, frame } => {
+                    let checksum = world.resource::<FrameChecksum>().checksum;
+                    cell.save(frame, Some(world.clone()), Some(checksum as u128));
+                }
+                GgrsRequest::LoadGameState { cell, frame } => {
+                    *world = cell.load().unwrap();
+                    self.simulated_frame = frame;
+                }
+                GgrsRequest::AdvanceFrame { inputs } => {
+                    let random_seed = **world.resource::<RandomSeed>();
+                    world.insert_resource(SyncingInfo::Online {
+                        current_frame: self.simulated_frame,
+                        last_confirmed_frame: self.session.confirmed_frame(),
+                        socket: self.syncing_socket.clone(),
+                        players_network_stats: default(),
+                        local_player_idx: self.socket.player_idx as usize,
+                        local_frame_delay: self.local_input_delay,
+                        disconnected_players: default(),
+                        random_seed,
+                    });
+                    world
+                        .resource_mut::<Time>()
+                        .advance_exact(Duration::from_secs_f64(1.0 / FPS as f64));
+                    {
+                        let mut player_inputs = world.resource_mut::<MatchInputs>();
+                        for (player, (input, _status)) in
+                            player_inputs.players.iter_mut().zip(inputs)
+                        {
+                            player.control.update_from_dense(&bytemuck::cast(input));
+                        }
+                    }
+                    stages.run(world);
+                    self.simulated_frame += 1;
+                }
+            }
+        }
+    }
+
+    fn restart_session(&mut self) {
+        self.session = Self::start_session(
+            &self.socket,
+            self.local_input_delay,
+            self.max_prediction_window,
+        );
+        self.simulated_frame = 0;
+        self.last_control = default();
+    }
+
+    fn disable_local_input(&mut self, _disable_input: bool) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reliable_messages_arrive_in_order_after_latency() {
+        let conditions = LoopbackConditions {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(30),
+            packet_loss: 1.0,
+        };
+        let sockets = loopback_sockets(2, conditions, 0);
+        let now = Instant::now();
+        for i in 0..10u8 {
+            sockets[0].send(SocketTarget::All, Channel::Reliable, &[i], now);
+        }
+
+        let mut hub = sockets[1].hub.lock().unwrap();
+        assert!(hub.recv(1, Channel::Reliable, now).is_empty());
+        let received = hub.recv(1, Channel::Reliable, now + Duration::from_millis(80));
+        assert_eq!(
+            received,
+            (0..10u8).map(|i| (0, vec![i])).collect::<Vec<_>>()
+        );
+    }
+
+    /// Two peers play a round over a laggy loopback, rolling back whenever an input was predicted
+    /// wrong, and must end up with the same simulation state, having ended the round on the same
+    /// frame.
+    #[test]
+    fn network_match_stays_in_sync_until_the_round_ends() {
+        use crate::{
+            core::{checksum::ChecksumHistory, scoring::RoundScoringState},
+            headless::{FrameControls, HeadlessGame},
+            ui::scoring::ScoringMenuState,
+        };
+
+        let conditions = LoopbackConditions {
+            latency: Duration::from_millis(20),
+            jitter: Duration::from_millis(10),
+            packet_loss: 0.0,
+        };
+        let mut games = Vec::new();
+        let mut desyncs = Vec::new();
+        for socket in loopback_sockets(2, conditions, 0) {
+            let mut headless = HeadlessGame::for_tests();
+            let meta = headless.meta();
+            let maps = MapPool::from_single_map(headless.find_map(None).unwrap());
+            let player_info = std::array::from_fn(|i| PlayerInput {
+                active: i < 2,
+                selected_player: meta.core.players[0],
+                ..default()
+            });
+            let rules = MatchRules {
+                time_limit_secs: 2,
+                ..default()
+            };
+            // The players run back and forth and jump, so that the predicted inputs are often wrong.
+            let script = |frame: u32| -> FrameControls {
+                std::array::from_fn(|i| {
+                    let forward = (frame / 20 + i as u32) % 2 == 0;
+                    PlayerControl {
+                        left: if forward { 0.0 } else { 1.0 },
+                        right: if forward { 1.0 } else { 0.0 },
+                        jump_pressed: frame % 30 < 4,
+                        ..default()
+                    }
+                })
+            };
+            let runner = LoopbackSessionRunner::new(socket, 2, 8, script);
+            desyncs.push(runner.desyncs.clone());
+            headless.start_match_with_runner(maps, player_info, rules, Box::new(runner));
+            games.push(headless);
+        }
+
+        let round_over = |game: &HeadlessGame| {
+            game.game
+                .shared_resource::<ScoringMenuState>()
+                .unwrap()
+                .active
+        };
+        let start = Instant::now();
+        while !games.iter().all(round_over) {
+            assert!(
+                start.elapsed() < Duration::from_secs(60),
+                "The round didn't end"
+            );
+            for game in games.iter_mut().filter(|game| !round_over(game)) {
+                game.game.step(Instant::now());
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        for desyncs in &desyncs {
+            assert!(desyncs.lock().unwrap().is_empty());
+        }
+        let histories = games
+            .iter()
+            .map(|game| game.match_world().unwrap().resource::<ChecksumHistory>())
+            .collect::<Vec<_>>();
+        let last = histories[0].back().copied().unwrap();
+        assert_eq!(
+            histories[1].checksum_for_frame(last.frame),
+            Some(last.checksum)
+        );
+
+        // The end of the round was synchronized over the network, instead of taking the local path.
+        let round_end_frames = games
+            .iter()
+            .map(|game| {
+                game.match_world()
+                    .unwrap()
+                    .resource::<RoundScoringState>()
+                    .network_round_end_frame
+            })
+            .collect::<Vec<_>>();
+        assert!(round_end_frames[0].is_some());
+        assert_eq!(round_end_frames[0], round_end_frames[1]);
+    }
+
+    #[test]
+    fn unreliable_messages_may_be_lost() {
+        let conditions = LoopbackConditions {
+            packet_loss: 0.5,
+            ..default()
+        };
+        let sockets = loopback_sockets(2, conditions, 0);
+        let now = Instant::now();
+        for i in 0..100u8 {
+            sockets[0].send(SocketTarget::Player(1), Channel::Unreliable, &[i], now);
+        }
+
+        let received = sockets[1]
+            .hub
+            .lock()
+            .unwrap()
+            .recv(1, Channel::Unreliable, now);
+        assert!(!received.is_empty() && received.len() < 100);
+    }
+}
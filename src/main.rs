@@ -16,10 +16,30 @@ pub mod audio;
 pub mod core;
 pub mod debug;
 pub mod fullscreen;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gym;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod headless;
 pub mod input;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod loopback;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod map_export;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod map_import;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod map_lint;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod network_messages;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pack_paths;
 pub mod profiler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod replay;
 pub mod sessions;
 pub mod settings;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod spectate;
 pub mod ui;
 
 mod prelude {
@@ -56,7 +76,7 @@ pub struct GameMeta {
     pub network: NetworkMeta,
 }
 
-#[derive(HasSchema, Copy, Clone, Debug)]
+#[derive(HasSchema, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(C)]
 pub struct NetworkMeta {
     pub max_prediction_window: usize,
@@ -93,6 +113,7 @@ pub struct PackMeta {
     pub map_tilesets: SVec<Handle<Atlas>>,
     pub players: SVec<Handle<PlayerMeta>>,
     pub player_hats: SVec<Handle<HatMeta>>,
+    pub ai_behaviors: SVec<Handle<AiBehaviorMeta>>,
     pub maps: SVec<Handle<MapMeta>>,
     pub map_elements: SVec<Handle<ElementMeta>>,
 }
@@ -113,6 +134,13 @@ impl GameMeta {
         for pack in asset_server.packs() {
             let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
             plugins.extend(pack_meta.plugins.iter().copied());
+            plugins.extend(
+                pack_meta
+                    .ai_behaviors
+                    .iter()
+                    .map(|behavior| asset_server.get(*behavior).plugin)
+                    .filter(|plugin_handle| plugin_handle != &Handle::default()),
+            );
             plugins.extend(
                 pack_meta
                     .map_elements
@@ -145,13 +173,39 @@ fn main() {
     // Register types that we will load from persistent storage.
     settings::Settings::register_schema();
 
-    // First create bones game.
-    let mut game = Game::new();
-
     // Register our game and pack meta types
     GameMeta::register_schema();
     PackMeta::register_schema();
 
+    // Run a command line tool instead of the game if one was requested. Any other arguments, such
+    // as the ones added by launchers, are left to the game.
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let mut args = std::env::args().skip(1);
+        let command = args.next();
+        let result = match command.as_deref() {
+            Some("headless") => Some(headless::run_cli(args).map_err(|e| e.to_string())),
+            Some("gym") => Some(gym::run_cli(args).map_err(|e| e.to_string())),
+            Some("import-tiled") => Some(map_import::run_cli(args).map_err(|e| e.to_string())),
+            Some("lint-maps") => Some(map_lint::run_cli(args).map_err(|e| e.to_string())),
+            _ => None,
+        };
+        if let Some(result) = result {
+            if let Err(e) = result {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
+    // Remove the maps received in previous network matches, before the packs are loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    ui::main_menu::map_transfer::remove_transferred_maps(&packs_dir());
+
+    // First create bones game.
+    let mut game = Game::new();
+
     game
         // Install game plugins
         .install_plugin(DefaultGamePlugin)
@@ -167,6 +221,10 @@ fn main() {
         .init_shared_resource::<AssetServer>()
         .register_default_assets();
 
+    // Messages received over the reliable channel of network matches are shared by every session.
+    #[cfg(not(target_arch = "wasm32"))]
+    game.init_shared_resource::<network_messages::ReliableMessages>();
+
     // Create a new session for the game menu. Each session is it's own bones world with it's own
     // plugins, systems, and entities.
     game.sessions.start_menu();
@@ -184,6 +242,13 @@ fn main() {
         .unwrap()
         .priority = 1;
 
+    // Create a session for the map editor, which only does anything while it is open on top of a
+    // running match.
+    game.sessions
+        .create_with(SessionNames::MAP_EDITOR, |builder| {
+            builder.install_plugin(ui::editor::session_plugin);
+        });
+
     // Scoring menu plugin, activated by game between round tarnsitions when appropriate
     game.sessions.create_with(SessionNames::SCORING, |builder| {
         builder.install_plugin(ui::scoring::session_plugin);
@@ -199,18 +264,10 @@ fn main() {
     BonesBevyRenderer {
         game,
         pixel_art: true,
-        game_version: Version::new(
-            env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap(),
-            env!("CARGO_PKG_VERSION_MINOR").parse().unwrap(),
-            env!("CARGO_PKG_VERSION_PATCH").parse().unwrap(),
-        ),
+        game_version: game_version(),
         app_namespace: ("org".into(), "fishfolk".into(), "jumpy".into()),
-        asset_dir: std::env::var("JUMPY_ASSETS")
-            .unwrap_or_else(|_| "assets".into())
-            .into(),
-        packs_dir: std::env::var("JUMPY_ASSET_PACKS")
-            .unwrap_or_else(|_| "packs".into())
-            .into(),
+        asset_dir: asset_dir(),
+        packs_dir: packs_dir(),
         custom_load_progress: Some(Box::new(load_progress)),
         preload: true,
     }
@@ -218,6 +275,29 @@ fn main() {
     .run();
 }
 
+/// The version of the game, used to check pack compatibility.
+pub fn game_version() -> Version {
+    Version::new(
+        env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap(),
+        env!("CARGO_PKG_VERSION_MINOR").parse().unwrap(),
+        env!("CARGO_PKG_VERSION_PATCH").parse().unwrap(),
+    )
+}
+
+/// The directory that the core game assets are loaded from.
+pub fn asset_dir() -> std::path::PathBuf {
+    std::env::var("JUMPY_ASSETS")
+        .unwrap_or_else(|_| "assets".into())
+        .into()
+}
+
+/// The directory that asset packs are loaded from.
+pub fn packs_dir() -> std::path::PathBuf {
+    std::env::var("JUMPY_ASSET_PACKS")
+        .unwrap_or_else(|_| "packs".into())
+        .into()
+}
+
 fn load_progress(assets: &AssetServer, ctx: &egui::Context) {
     let errored = assets.load_progress.errored();
     egui::CentralPanel::default()
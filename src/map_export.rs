@@ -0,0 +1,550 @@
+//! Saving maps edited in the map editor to a map pack.
+//!
+//! The map editor changes the map of a running match. To keep those changes, the contents of the
+//! map are collected with [`MapManager::to_map_meta`] and written, in the same format as the maps
+//! of the game, to the user map pack in the packs directory. The pack is created the first time a
+//! map is saved, and its maps can be played like the maps of any other pack.
+//!
+//! The map metadata refers to its tilesets, elements and background images by handle, so the paths
+//! that are written are found by reading the asset lists of the game and of the packs, and of the
+//! maps that use them. The handles are matched to the paths by their position in the lists, so a
+//! list without as many entries as assets were loaded from it fails the export.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    pack_paths::{find_pack_dir, parent_dir, resolve_path},
+    prelude::*,
+    PackMeta,
+};
+
+/// The folder, in the packs directory, of the pack that edited maps are saved to.
+pub const USER_MAP_PACK_FOLDER: &str = "user_maps";
+
+/// The id of the pack that edited maps are saved to.
+const USER_MAP_PACK_ID: &str = "usermaps_01hk3v8qzj5ty1wnmz4gy2c6sa";
+
+/// Errors that may occur while saving a map.
+#[derive(thiserror::Error, Debug)]
+pub enum MapExportError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("The map needs a name to be saved")]
+    NoName,
+    #[error("Couldn't find the file of {0}")]
+    UnknownAsset(String),
+    #[error(
+        "The assets listed in {0} don't match the loaded assets, restart the game if it changed"
+    )]
+    AssetListMismatch(String),
+}
+
+/// Save the map to the user map pack, replacing the map with the same name if it was saved before.
+/// Returns the path of the map file.
+pub fn export_map(map: &MapMeta, asset_server: &AssetServer) -> Result<PathBuf, MapExportError> {
+    export_map_to(map, asset_server, &crate::packs_dir())
+}
+
+/// Save the map to the user map pack in the given packs directory.
+fn export_map_to(
+    map: &MapMeta,
+    asset_server: &AssetServer,
+    packs_dir: &Path,
+) -> Result<PathBuf, MapExportError> {
+    let file_name = map
+        .name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if file_name.is_empty() {
+        return Err(MapExportError::NoName);
+    }
+    let map_file = MapFile::new(map, &AssetPaths::load(asset_server)?, asset_server)?;
+
+    let pack_dir = packs_dir.join(USER_MAP_PACK_FOLDER);
+    if !pack_dir.join("pack.yaml").exists() {
+        std::fs::create_dir_all(&pack_dir)?;
+        std::fs::write(
+            pack_dir.join("pack.yaml"),
+            format!(
+                "name: User Maps\nid: {USER_MAP_PACK_ID}\nversion: 0.1.0\ngame_version: {}\nroot: ./assets.yaml\n",
+                crate::game_version()
+            ),
+        )?;
+    }
+
+    let map_path = format!("maps/{file_name}.map.yaml");
+    std::fs::create_dir_all(pack_dir.join("maps"))?;
+    std::fs::write(pack_dir.join(&map_path), serde_yaml::to_string(&map_file)?)?;
+
+    // List the map in the pack, if it wasn't saved before.
+    let assets_path = pack_dir.join("assets.yaml");
+    let mut assets = match std::fs::read(&assets_path) {
+        Ok(data) => serde_yaml::from_slice(&data)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_yaml::Value::Mapping(default()),
+        Err(e) => return Err(e.into()),
+    };
+    let entry = serde_yaml::Value::from(format!("./{map_path}"));
+    if !assets["maps"].is_sequence() {
+        assets["maps"] = serde_yaml::Value::Sequence(default());
+    }
+    let maps = assets["maps"].as_sequence_mut().unwrap();
+    if !maps.contains(&entry) {
+        maps.push(entry);
+        std::fs::write(&assets_path, serde_yaml::to_string(&assets)?)?;
+    }
+
+    Ok(pack_dir.join(map_path))
+}
+
+/// A map file, in the format that [`MapMeta`] is loaded from.
+#[derive(Serialize)]
+struct MapFile {
+    name: String,
+    background: BackgroundFile,
+    background_color: String,
+    grid_size: [u32; 2],
+    tile_size: [f32; 2],
+    layers: Vec<MapLayerFile>,
+}
+
+#[derive(Serialize)]
+struct BackgroundFile {
+    speed: [f32; 2],
+    layers: Vec<ParallaxLayerFile>,
+}
+
+#[derive(Serialize)]
+struct ParallaxLayerFile {
+    image: String,
+    size: [f32; 2],
+    depth: f32,
+    scale: f32,
+    offset: [f32; 2],
+}
+
+#[derive(Serialize)]
+struct MapLayerFile {
+    id: String,
+    tilemap: Option<String>,
+    tiles: Vec<MapTileFile>,
+    elements: Vec<ElementSpawnFile>,
+}
+
+#[derive(Serialize)]
+struct MapTileFile {
+    pos: [u32; 2],
+    idx: u32,
+    collision: TileCollisionKind,
+}
+
+#[derive(Serialize)]
+struct ElementSpawnFile {
+    pos: [f32; 2],
+    element: String,
+}
+
+impl MapFile {
+    fn new(
+        map: &MapMeta,
+        paths: &AssetPaths,
+        asset_server: &AssetServer,
+    ) -> Result<Self, MapExportError> {
+        let [r, g, b, a] = map
+            .background_color
+            .as_rgba_f32()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+        Ok(Self {
+            name: map.name.to_string(),
+            background: BackgroundFile {
+                speed: map.background.speed.to_array(),
+                layers: map
+                    .background
+                    .layers
+                    .iter()
+                    .map(|layer| {
+                        Ok(ParallaxLayerFile {
+                            image: find_path(&paths.images, layer.image).ok_or_else(|| {
+                                MapExportError::UnknownAsset("a background image".into())
+                            })?,
+                            size: layer.size.to_array(),
+                            depth: layer.depth,
+                            scale: layer.scale,
+                            offset: layer.offset.to_array(),
+                        })
+                    })
+                    .collect::<Result<_, MapExportError>>()?,
+            },
+            background_color: format!("rgba({r}, {g}, {b}, {a})"),
+            grid_size: map.grid_size.to_array(),
+            tile_size: map.tile_size.to_array(),
+            layers: map
+                .layers
+                .iter()
+                .map(|layer| {
+                    Ok(MapLayerFile {
+                        id: layer.id.to_string(),
+                        tilemap: layer
+                            .tilemap
+                            .option()
+                            .map(|tilemap| {
+                                find_path(&paths.tilemaps, tilemap).ok_or_else(|| {
+                                    MapExportError::UnknownAsset(format!(
+                                        "the tileset of the layer {}",
+                                        layer.id
+                                    ))
+                                })
+                            })
+                            .transpose()?,
+                        tiles: layer
+                            .tiles
+                            .iter()
+                            .map(|tile| MapTileFile {
+                                pos: tile.pos.to_array(),
+                                idx: tile.idx,
+                                collision: tile.collision,
+                            })
+                            .collect(),
+                        elements: layer
+                            .elements
+                            .iter()
+                            .map(|spawn| {
+                                Ok(ElementSpawnFile {
+                                    pos: spawn.pos.to_array(),
+                                    element: find_path(&paths.elements, spawn.element).ok_or_else(
+                                        || {
+                                            MapExportError::UnknownAsset(format!(
+                                                "the element {}",
+                                                asset_server.get(spawn.element).name
+                                            ))
+                                        },
+                                    )?,
+                                })
+                            })
+                            .collect::<Result<_, MapExportError>>()?,
+                    })
+                })
+                .collect::<Result<_, MapExportError>>()?,
+        })
+    }
+}
+
+/// The paths, as referenced from the user map pack, of the assets that may be used by maps.
+#[derive(Default)]
+pub(crate) struct AssetPaths {
+    pub tilemaps: Vec<(Handle<Atlas>, String)>,
+    pub elements: Vec<(Handle<ElementMeta>, String)>,
+    pub images: Vec<(Handle<Image>, String)>,
+}
+
+/// Get the path of the asset with the given handle.
+fn find_path<T>(paths: &[(Handle<T>, String)], handle: Handle<T>) -> Option<String> {
+    paths
+        .iter()
+        .find(|(h, _)| *h == handle)
+        .map(|(_, path)| path.clone())
+}
+
+/// A pack that assets are read from.
+struct PackFiles {
+    /// The name the pack is referred to by, in paths from other packs.
+    name: String,
+    dir: PathBuf,
+}
+
+impl AssetPaths {
+    /// Read the asset lists of the game and of the loaded packs. Packs and maps whose files can't
+    /// be found are left out, but the lists that are read must match the loaded assets.
+    pub fn load(asset_server: &AssetServer) -> Result<Self, MapExportError> {
+        let mut paths = Self::default();
+        let meta = asset_server.root::<GameMeta>();
+
+        let core = PackFiles {
+            name: "core".into(),
+            dir: crate::asset_dir(),
+        };
+        if let Some(game_yaml) = core.read_yaml("game.yaml") {
+            let lists = &game_yaml["core"];
+            core.add_list(
+                &mut paths.tilemaps,
+                "game.yaml",
+                &lists["map_tilesets"],
+                meta.core.map_tilesets.iter().copied(),
+            )?;
+            core.add_list(
+                &mut paths.elements,
+                "game.yaml",
+                &lists["map_elements"],
+                meta.core.map_elements.iter().copied(),
+            )?;
+            for (list, maps) in [
+                (&lists["stable_maps"], &meta.core.stable_maps),
+                (&lists["experimental_maps"], &meta.core.experimental_maps),
+            ] {
+                core.add_maps(
+                    &mut paths,
+                    asset_server,
+                    "game.yaml",
+                    list,
+                    maps.iter().copied(),
+                )?;
+            }
+        }
+
+        for pack in asset_server.packs() {
+            let Some(files) = PackFiles::find(&pack.id.to_string()) else {
+                continue;
+            };
+            let Some(pack_yaml) = files.read_yaml("pack.yaml") else {
+                continue;
+            };
+            let Some(root) = pack_yaml["root"]
+                .as_str()
+                .and_then(|root| resolve_path("", root))
+            else {
+                continue;
+            };
+            let Some(root_yaml) = files.read_yaml(&root) else {
+                continue;
+            };
+            let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
+            files.add_list(
+                &mut paths.tilemaps,
+                &root,
+                &root_yaml["map_tilesets"],
+                pack_meta.map_tilesets.iter().copied(),
+            )?;
+            files.add_list(
+                &mut paths.elements,
+                &root,
+                &root_yaml["map_elements"],
+                pack_meta.map_elements.iter().copied(),
+            )?;
+            files.add_maps(
+                &mut paths,
+                asset_server,
+                &root,
+                &root_yaml["maps"],
+                pack_meta.maps.iter().copied(),
+            )?;
+        }
+
+        Ok(paths)
+    }
+}
+
+impl PackFiles {
+    /// Find the folder of the pack with the given id in the packs directory.
+    fn find(pack_id: &str) -> Option<Self> {
+        let dir = find_pack_dir(&crate::packs_dir(), pack_id)?;
+        Some(Self {
+            name: dir.file_name()?.to_string_lossy().into_owned(),
+            dir,
+        })
+    }
+
+    fn read_yaml(&self, path: &str) -> Option<serde_yaml::Value> {
+        let data = std::fs::read(self.dir.join(path)).ok()?;
+        serde_yaml::from_slice(&data).ok()
+    }
+
+    /// Get the path of an asset, referenced from a file in the given directory of this pack, as it
+    /// is referenced from the user map pack.
+    fn reference(&self, base_dir: &str, reference: &str) -> Option<String> {
+        if reference.contains(":/") {
+            return Some(reference.into());
+        }
+        let path = resolve_path(base_dir, reference)?;
+        Some(if self.name == USER_MAP_PACK_FOLDER {
+            format!("/{path}")
+        } else {
+            format!("{}:/{path}", self.name)
+        })
+    }
+
+    /// Get the entries of a list in the given file of this pack, which must have as many entries
+    /// as assets were loaded from it.
+    fn list<'a>(
+        &self,
+        file: &str,
+        list: &'a serde_yaml::Value,
+        loaded: usize,
+    ) -> Result<&'a [serde_yaml::Value], MapExportError> {
+        let list = list.as_sequence().map(Vec::as_slice).unwrap_or_default();
+        if list.len() != loaded {
+            return Err(MapExportError::AssetListMismatch(format!(
+                "{}:/{file}",
+                self.name
+            )));
+        }
+        Ok(list)
+    }
+
+    /// Add the paths of a list of assets in the given file of this pack, along with the handles
+    /// they were loaded as.
+    fn add_list<T>(
+        &self,
+        paths: &mut Vec<(Handle<T>, String)>,
+        file: &str,
+        list: &serde_yaml::Value,
+        handles: impl IntoIterator<Item = Handle<T>>,
+    ) -> Result<(), MapExportError> {
+        let handles = handles.into_iter().collect::<Vec<_>>();
+        let list = self.list(file, list, handles.len())?;
+        let base_dir = parent_dir(file);
+        for (handle, reference) in handles.into_iter().zip(list) {
+            if let Some(path) = reference
+                .as_str()
+                .and_then(|reference| self.reference(base_dir, reference))
+            {
+                paths.push((handle, path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the paths of the tilesets, elements and background images used by a list of maps in
+    /// the given file of this pack.
+    fn add_maps(
+        &self,
+        paths: &mut AssetPaths,
+        asset_server: &AssetServer,
+        file: &str,
+        list: &serde_yaml::Value,
+        maps: impl IntoIterator<Item = Handle<MapMeta>>,
+    ) -> Result<(), MapExportError> {
+        let maps = maps.into_iter().collect::<Vec<_>>();
+        let list = self.list(file, list, maps.len())?;
+        let base_dir = parent_dir(file);
+        for (handle, reference) in maps.into_iter().zip(list) {
+            let Some(map_path) = reference
+                .as_str()
+                .filter(|reference| !reference.contains(":/"))
+                .and_then(|reference| resolve_path(base_dir, reference))
+            else {
+                continue;
+            };
+            let Some(map_yaml) = self.read_yaml(&map_path) else {
+                continue;
+            };
+            let map = asset_server.get(handle);
+
+            let images = map
+                .background
+                .layers
+                .iter()
+                .map(|layer| layer.image)
+                .collect::<Vec<_>>();
+            let image_list = map_yaml["background"]["layers"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+                .map(|layer| layer["image"].clone())
+                .collect::<Vec<_>>();
+            self.add_list(&mut paths.images, &map_path, &image_list.into(), images)?;
+
+            let layers = self.list(&map_path, &map_yaml["layers"], map.layers.len())?;
+            for (layer, layer_yaml) in map.layers.iter().zip(layers) {
+                if let Some(tilemap) = layer.tilemap.option() {
+                    let list = vec![layer_yaml["tilemap"].clone()].into();
+                    self.add_list(&mut paths.tilemaps, &map_path, &list, [tilemap])?;
+                }
+                let elements = layer
+                    .elements
+                    .iter()
+                    .map(|spawn| spawn.element)
+                    .collect::<Vec<_>>();
+                let element_list = layer_yaml["elements"]
+                    .as_sequence()
+                    .into_iter()
+                    .flatten()
+                    .map(|spawn| spawn["element"].clone())
+                    .collect::<Vec<_>>();
+                self.add_list(
+                    &mut paths.elements,
+                    &map_path,
+                    &element_list.into(),
+                    elements,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::headless::HeadlessGame;
+
+    /// The contents of a map that must survive being saved and loaded again. Assets are described
+    /// by their contents, since their handles differ between games.
+    fn map_contents(map: &MapMeta, asset_server: &AssetServer) -> Vec<String> {
+        let mut contents = vec![format!(
+            "{} {} {} {}",
+            map.name,
+            map.grid_size,
+            map.tile_size,
+            map.background.layers.len()
+        )];
+        for layer in map.layers.iter() {
+            let tilemap = layer.tilemap.option().map(|tilemap| {
+                let atlas = asset_server.get(tilemap);
+                (atlas.tile_size, atlas.columns, atlas.rows)
+            });
+            contents.push(format!("layer {} {tilemap:?}", layer.id));
+            for tile in layer.tiles.iter() {
+                contents.push(format!(
+                    "tile {} {} {:?}",
+                    tile.pos, tile.idx, tile.collision
+                ));
+            }
+            for spawn in layer.elements.iter() {
+                let element = asset_server.get(spawn.element);
+                contents.push(format!("element {} {}", spawn.pos, element.name));
+            }
+        }
+        contents
+    }
+
+    #[test]
+    fn exported_map_loads_with_the_same_contents() {
+        let headless = HeadlessGame::for_tests();
+        let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+        let map = asset_server.get(headless.find_map(None).unwrap()).clone();
+        let expected = map_contents(&map, &asset_server);
+
+        let packs_dir =
+            std::env::temp_dir().join(format!("jumpy-map-export-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&packs_dir);
+        export_map_to(&map, &asset_server, &packs_dir).unwrap();
+
+        let reloaded = HeadlessGame::new(crate::asset_dir(), packs_dir.clone());
+        std::fs::remove_dir_all(&packs_dir).unwrap();
+        let reloaded = reloaded.unwrap();
+        let asset_server = reloaded.game.shared_resource::<AssetServer>().unwrap();
+        let mut exported = None;
+        for pack in asset_server.packs() {
+            let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
+            exported = exported.or_else(|| {
+                pack_meta
+                    .maps
+                    .iter()
+                    .find(|handle| asset_server.get(**handle).name == map.name)
+                    .copied()
+            });
+        }
+        let exported = asset_server.get(exported.expect("The exported map wasn't loaded"));
+        assert_eq!(map_contents(&exported, &asset_server), expected);
+    }
+}
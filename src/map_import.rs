@@ -0,0 +1,983 @@
+//! Importing maps made with the [Tiled](https://www.mapeditor.org) map editor.
+//!
+//! Orthogonal, finite Tiled maps saved as `.tmx` or `.tmj` files can be converted to a [`MapMeta`]
+//! and saved to the user map pack, see [`run_cli`]. Each Tiled layer becomes a map layer:
+//!
+//! - Tile layers use the map tileset whose file name matches the name or the image of their Tiled
+//!   tileset, or the tileset given by an `atlas` property on the Tiled tileset, such as
+//!   `core:/map/resources/ground_rock.atlas.yaml`. The Tiled tileset must have the same tile size
+//!   and number of columns as the map tileset. The tile data must use the CSV or XML format.
+//! - The collision of a tile is taken from a `collision` property on the tile in its tileset, or on
+//!   the layer, which may be `solid`, `jump_through` or `empty`. Tiles are empty by default.
+//! - The objects of object layers are spawned as the map element whose name, or file name, matches
+//!   the type of the object, or its name if it has no type, at the center of the object.
+
+use std::path::Path;
+
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{
+    headless::{HeadlessError, HeadlessGame},
+    map_export::{export_map, AssetPaths, MapExportError},
+    prelude::*,
+    PackMeta,
+};
+
+/// Tiled stores whether a tile is flipped in the highest bits of its global tile id.
+const TILED_GID_MASK: u32 = 0x0FFF_FFFF;
+
+/// Errors that may occur while importing a Tiled map.
+#[derive(thiserror::Error, Debug)]
+pub enum MapImportError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Xml(#[from] xml::reader::Error),
+    #[error(transparent)]
+    Headless(#[from] HeadlessError),
+    #[error(transparent)]
+    Export(#[from] MapExportError),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Invalid Tiled map: {0}")]
+    Invalid(String),
+    #[error("Only orthogonal maps that aren't infinite can be imported")]
+    UnsupportedMap,
+    #[error("The layer {0} uses an unsupported tile data encoding, save the map with the CSV tile layer format")]
+    UnsupportedEncoding(String),
+    #[error("The layer {0} uses tiles from more than one tileset")]
+    MixedTilesets(String),
+    #[error("No map tileset matches the Tiled tileset {0}")]
+    UnknownTileset(String),
+    #[error("The Tiled tileset {0} doesn't have the tile size and columns of its map tileset")]
+    TilesetMismatch(String),
+    #[error("No map element matches the object type {0}")]
+    UnknownElement(String),
+    #[error("Unknown collision {0}, expected solid, jump_through or empty")]
+    UnknownCollision(String),
+}
+
+/// Import a Tiled map and save it to the user map pack.
+///
+/// Usage: `jumpy import-tiled <map.tmx|map.tmj> [--name <name>]`
+///
+/// The map is named after the `name` property of the Tiled map, or after its file if it doesn't
+/// have one, unless a name is given.
+pub fn run_cli(mut args: impl Iterator<Item = String>) -> Result<(), MapImportError> {
+    let mut path = None;
+    let mut name = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => {
+                name = Some(args.next().ok_or_else(|| {
+                    MapImportError::InvalidArgument(format!("{arg} expects a value"))
+                })?)
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(MapImportError::InvalidArgument(arg)),
+        }
+    }
+    let path = path.ok_or_else(|| {
+        MapImportError::InvalidArgument("expected the path of a .tmx or .tmj file".into())
+    })?;
+
+    let headless = HeadlessGame::new(crate::asset_dir(), crate::packs_dir())?;
+    let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+    let mut map = import_tiled_map(Path::new(&path), &asset_server)?;
+    if let Some(name) = name {
+        map.name = ustr(&name);
+    }
+    let saved = export_map(&map, &asset_server)?;
+    println!("Imported {} to {}", map.name, saved.display());
+    Ok(())
+}
+
+/// Convert a Tiled `.tmx` or `.tmj` map to map metadata.
+pub fn import_tiled_map(
+    path: &Path,
+    asset_server: &AssetServer,
+) -> Result<MapMeta, MapImportError> {
+    let tiled = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tmx") => TiledMap::from_tmx(path)?,
+        Some("tmj" | "json") => TiledMap::from_tmj(path)?,
+        _ => {
+            return Err(MapImportError::InvalidArgument(format!(
+                "{} isn't a .tmx or .tmj file",
+                path.display()
+            )))
+        }
+    };
+    let default_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    tiled.to_map_meta(&default_name, asset_server)
+}
+
+/// The custom properties of a Tiled map, layer, tileset or tile.
+type Properties = Vec<(String, String)>;
+
+fn property<'a>(properties: &'a Properties, name: &str) -> Option<&'a str> {
+    properties
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// The parts of a Tiled map that can be imported.
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tile_width: f32,
+    tile_height: f32,
+    background_color: Option<String>,
+    properties: Properties,
+    tilesets: Vec<TiledTileset>,
+    layers: Vec<TiledLayer>,
+}
+
+struct TiledTileset {
+    /// The global tile id of the first tile of the tileset.
+    first_gid: u32,
+    name: String,
+    image: Option<String>,
+    tile_width: f32,
+    tile_height: f32,
+    columns: u32,
+    properties: Properties,
+    /// The properties of the tiles of the tileset, by the id of the tile in the tileset.
+    tiles: Vec<(u32, Properties)>,
+}
+
+enum TiledLayer {
+    Tiles {
+        name: String,
+        properties: Properties,
+        /// The global tile id of each tile of the layer, row by row from the top.
+        gids: Vec<u32>,
+    },
+    Objects {
+        name: String,
+        objects: Vec<TiledObject>,
+    },
+}
+
+struct TiledObject {
+    kind: String,
+    name: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    /// Whether the object is a tile, which is positioned by its bottom left corner instead of its
+    /// top left corner.
+    is_tile: bool,
+}
+
+impl TiledMap {
+    fn from_tmx(path: &Path) -> Result<Self, MapImportError> {
+        Self::parse_tmx(&std::fs::read(path)?, path)
+    }
+
+    /// Parse a `.tmx` map, read from the given path.
+    fn parse_tmx(data: &[u8], path: &Path) -> Result<Self, MapImportError> {
+        let map = XmlNode::parse(data)?;
+        if map.name != "map" {
+            return Err(MapImportError::Invalid("expected a <map> element".into()));
+        }
+        if map.attr("orientation") != Some("orthogonal") || map.attr("infinite") == Some("1") {
+            return Err(MapImportError::UnsupportedMap);
+        }
+
+        let mut tilesets = Vec::new();
+        for node in map.children("tileset") {
+            let first_gid = node.parse_attr("firstgid")?;
+            tilesets.push(match node.attr("source") {
+                Some(source) => TiledTileset::from_file(first_gid, &sibling_path(path, source))?,
+                None => TiledTileset::from_tsx(first_gid, node)?,
+            });
+        }
+        let mut layers = Vec::new();
+        tmx_layers(&map, &mut layers)?;
+
+        Ok(Self {
+            width: map.parse_attr("width")?,
+            height: map.parse_attr("height")?,
+            tile_width: map.parse_attr("tilewidth")?,
+            tile_height: map.parse_attr("tileheight")?,
+            background_color: map.attr("backgroundcolor").map(String::from),
+            properties: map.properties(),
+            tilesets,
+            layers,
+        })
+    }
+
+    fn from_tmj(path: &Path) -> Result<Self, MapImportError> {
+        Self::parse_tmj(&std::fs::read(path)?, path)
+    }
+
+    /// Parse a `.tmj` map, read from the given path.
+    fn parse_tmj(data: &[u8], path: &Path) -> Result<Self, MapImportError> {
+        let map: serde_json::Value = serde_json::from_slice(data)?;
+        if map["orientation"].as_str() != Some("orthogonal")
+            || map["infinite"].as_bool() == Some(true)
+        {
+            return Err(MapImportError::UnsupportedMap);
+        }
+
+        let mut tilesets = Vec::new();
+        for tileset in map["tilesets"].as_array().into_iter().flatten() {
+            let first_gid = json_number(tileset, "firstgid")? as u32;
+            tilesets.push(match tileset["source"].as_str() {
+                Some(source) => TiledTileset::from_file(first_gid, &sibling_path(path, source))?,
+                None => TiledTileset::from_tsj(first_gid, tileset)?,
+            });
+        }
+        let mut layers = Vec::new();
+        tmj_layers(&map, &mut layers)?;
+
+        Ok(Self {
+            width: json_number(&map, "width")? as u32,
+            height: json_number(&map, "height")? as u32,
+            tile_width: json_number(&map, "tilewidth")? as f32,
+            tile_height: json_number(&map, "tileheight")? as f32,
+            background_color: map["backgroundcolor"].as_str().map(String::from),
+            properties: json_properties(&map),
+            tilesets,
+            layers,
+        })
+    }
+
+    fn to_map_meta(
+        self,
+        default_name: &str,
+        asset_server: &AssetServer,
+    ) -> Result<MapMeta, MapImportError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(MapImportError::Invalid("the map is empty".into()));
+        }
+        let paths = AssetPaths::load(asset_server)?;
+        let mut map = MapMeta {
+            name: ustr(property(&self.properties, "name").unwrap_or(default_name)),
+            background: default(),
+            background_color: self
+                .background_color
+                .as_deref()
+                .and_then(parse_color)
+                .unwrap_or(Color::BLACK),
+            grid_size: UVec2::new(self.width, self.height),
+            tile_size: Vec2::new(self.tile_width, self.tile_height),
+            layers: default(),
+        };
+
+        for layer in &self.layers {
+            match layer {
+                TiledLayer::Tiles {
+                    name,
+                    properties,
+                    gids,
+                } => {
+                    let layer_collision = property(properties, "collision")
+                        .map(parse_collision)
+                        .transpose()?;
+                    let mut layer_tileset = None;
+                    let mut tiles = SVec::new();
+                    for (i, gid) in gids.iter().enumerate() {
+                        let gid = gid & TILED_GID_MASK;
+                        let (x, row) = (i as u32 % self.width, i as u32 / self.width);
+                        if gid == 0 || row >= self.height {
+                            continue;
+                        }
+                        let tileset = self
+                            .tilesets
+                            .iter()
+                            .filter(|tileset| tileset.first_gid <= gid)
+                            .max_by_key(|tileset| tileset.first_gid)
+                            .ok_or_else(|| {
+                                MapImportError::Invalid(format!("the tile {gid} has no tileset"))
+                            })?;
+                        if *layer_tileset.get_or_insert(tileset.first_gid) != tileset.first_gid {
+                            return Err(MapImportError::MixedTilesets(name.clone()));
+                        }
+
+                        let idx = gid - tileset.first_gid;
+                        let tile_collision = tileset
+                            .tiles
+                            .iter()
+                            .find(|(id, _)| *id == idx)
+                            .and_then(|(_, properties)| property(properties, "collision"))
+                            .map(parse_collision)
+                            .transpose()?;
+                        tiles.push(MapTileMeta {
+                            // Tiled counts rows from the top, but the map from the bottom.
+                            pos: UVec2::new(x, self.height - 1 - row),
+                            idx,
+                            collision: tile_collision.or(layer_collision).unwrap_or_default(),
+                        });
+                    }
+
+                    let tilemap = match layer_tileset {
+                        Some(first_gid) => {
+                            let tileset = self
+                                .tilesets
+                                .iter()
+                                .find(|tileset| tileset.first_gid == first_gid)
+                                .unwrap();
+                            Set(tileset.find_tilemap(&paths, asset_server)?)
+                        }
+                        None => Unset,
+                    };
+                    map.layers.push(MapLayerMeta {
+                        id: ustr(name),
+                        tilemap,
+                        tiles,
+                        elements: default(),
+                    });
+                }
+                TiledLayer::Objects { name, objects } => {
+                    let map_height = self.height as f32 * self.tile_height;
+                    let mut elements = SVec::new();
+                    for object in objects {
+                        let kind = if object.kind.is_empty() {
+                            &object.name
+                        } else {
+                            &object.kind
+                        };
+                        if kind.is_empty() {
+                            continue;
+                        }
+                        let center_y = if object.is_tile {
+                            object.y - object.height / 2.0
+                        } else {
+                            object.y + object.height / 2.0
+                        };
+                        elements.push(ElementSpawn {
+                            pos: Vec2::new(object.x + object.width / 2.0, map_height - center_y),
+                            element: find_element(kind, &paths, asset_server)?,
+                        });
+                    }
+                    map.layers.push(MapLayerMeta {
+                        id: ustr(name),
+                        tilemap: Unset,
+                        tiles: default(),
+                        elements,
+                    });
+                }
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+impl TiledTileset {
+    /// Read an external `.tsx` or `.tsj` tileset.
+    fn from_file(first_gid: u32, path: &Path) -> Result<Self, MapImportError> {
+        let data = std::fs::read(path)?;
+        if path.extension().and_then(|ext| ext.to_str()) == Some("tsx") {
+            Self::from_tsx(first_gid, &XmlNode::parse(&data)?)
+        } else {
+            Self::from_tsj(first_gid, &serde_json::from_slice(&data)?)
+        }
+    }
+
+    fn from_tsx(first_gid: u32, node: &XmlNode) -> Result<Self, MapImportError> {
+        Ok(Self {
+            first_gid,
+            name: node.attr("name").unwrap_or_default().into(),
+            image: node
+                .children("image")
+                .next()
+                .and_then(|image| image.attr("source"))
+                .map(String::from),
+            tile_width: node.parse_attr("tilewidth")?,
+            tile_height: node.parse_attr("tileheight")?,
+            columns: node.parse_attr("columns")?,
+            properties: node.properties(),
+            tiles: node
+                .children("tile")
+                .map(|tile| Ok((tile.parse_attr("id")?, tile.properties())))
+                .collect::<Result<_, MapImportError>>()?,
+        })
+    }
+
+    fn from_tsj(first_gid: u32, tileset: &serde_json::Value) -> Result<Self, MapImportError> {
+        Ok(Self {
+            first_gid,
+            name: tileset["name"].as_str().unwrap_or_default().into(),
+            image: tileset["image"].as_str().map(String::from),
+            tile_width: json_number(tileset, "tilewidth")? as f32,
+            tile_height: json_number(tileset, "tileheight")? as f32,
+            columns: json_number(tileset, "columns")? as u32,
+            properties: json_properties(tileset),
+            tiles: tileset["tiles"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|tile| Ok((json_number(tile, "id")? as u32, json_properties(tile))))
+                .collect::<Result<_, MapImportError>>()?,
+        })
+    }
+
+    /// Find the map tileset to use for this Tiled tileset. The tile indices of the two must match,
+    /// so the tiles must be laid out the same way in both.
+    fn find_tilemap(
+        &self,
+        paths: &AssetPaths,
+        asset_server: &AssetServer,
+    ) -> Result<Handle<Atlas>, MapImportError> {
+        let found = match property(&self.properties, "atlas") {
+            Some(atlas) => paths
+                .tilemaps
+                .iter()
+                .find(|(_, path)| path == atlas || without_pack(path) == without_pack(atlas)),
+            None => {
+                let names = [Some(self.name.as_str()), self.image.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|name| normalize_name(file_stem(name)))
+                    .collect::<Vec<_>>();
+                paths
+                    .tilemaps
+                    .iter()
+                    .find(|(_, path)| names.contains(&normalize_name(file_stem(path))))
+            }
+        };
+        let handle = found
+            .map(|(handle, _)| *handle)
+            .ok_or_else(|| MapImportError::UnknownTileset(self.name.clone()))?;
+
+        let atlas = asset_server.get(handle);
+        if atlas.tile_size != Vec2::new(self.tile_width, self.tile_height)
+            || atlas.columns != self.columns
+        {
+            return Err(MapImportError::TilesetMismatch(self.name.clone()));
+        }
+        Ok(handle)
+    }
+}
+
+/// Find the map element with the given name or file name.
+fn find_element(
+    kind: &str,
+    paths: &AssetPaths,
+    asset_server: &AssetServer,
+) -> Result<Handle<ElementMeta>, MapImportError> {
+    let kind_name = normalize_name(kind);
+    let meta = asset_server.root::<GameMeta>();
+    let mut elements = meta.core.map_elements.iter().copied().collect::<Vec<_>>();
+    for pack in asset_server.packs() {
+        let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
+        elements.extend(pack_meta.map_elements.iter().copied());
+    }
+
+    elements
+        .into_iter()
+        .find(|handle| normalize_name(&asset_server.get(*handle).name) == kind_name)
+        .or_else(|| {
+            paths
+                .elements
+                .iter()
+                .find(|(_, path)| normalize_name(file_stem(path)) == kind_name)
+                .map(|(handle, _)| *handle)
+        })
+        .ok_or_else(|| MapImportError::UnknownElement(kind.into()))
+}
+
+fn parse_collision(value: &str) -> Result<TileCollisionKind, MapImportError> {
+    match normalize_name(value).as_str() {
+        "solid" => Ok(TileCollisionKind::Solid),
+        "jumpthrough" => Ok(TileCollisionKind::JumpThrough),
+        "empty" | "none" => Ok(TileCollisionKind::Empty),
+        _ => Err(MapImportError::UnknownCollision(value.into())),
+    }
+}
+
+/// Parse a Tiled color, in the `#AARRGGBB` or `#RRGGBB` format.
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let [a, r, g, b] = match hex.len() {
+        6 => (value | 0xFF00_0000).to_be_bytes(),
+        8 => value.to_be_bytes(),
+        _ => return None,
+    };
+    Some(Color::rgba_u8(r, g, b, a))
+}
+
+/// Lowercase a name and remove anything but letters and digits, so that `Player Spawner`,
+/// `player_spawner` and `player-spawner` are the same.
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Get the part of the file name of a path before its first `.`.
+fn file_stem(path: &str) -> &str {
+    let file_name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    file_name.split('.').next().unwrap_or(file_name)
+}
+
+/// Remove the pack a path refers to, if any, and the leading `/`.
+fn without_pack(path: &str) -> &str {
+    path.rsplit_once(":/")
+        .map_or(path, |(_, path)| path)
+        .trim_start_matches('/')
+}
+
+/// Get the path of a file referenced from a Tiled file.
+fn sibling_path(path: &Path, reference: &str) -> std::path::PathBuf {
+    path.parent().unwrap_or(Path::new("")).join(reference)
+}
+
+/// Collect the tile and object layers of a `.tmx` map or group, including the ones in groups.
+fn tmx_layers(node: &XmlNode, layers: &mut Vec<TiledLayer>) -> Result<(), MapImportError> {
+    for child in &node.children {
+        let name = child.attr("name").unwrap_or_default().to_string();
+        match child.name.as_str() {
+            "layer" => {
+                let data = child.children("data").next().ok_or_else(|| {
+                    MapImportError::Invalid(format!("the layer {name} has no data"))
+                })?;
+                let gids = match data.attr("encoding") {
+                    Some("csv") => data
+                        .text
+                        .split(',')
+                        .map(|gid| gid.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| {
+                            MapImportError::Invalid(format!(
+                                "invalid tile data in the layer {name}"
+                            ))
+                        })?,
+                    None => data
+                        .children("tile")
+                        .map(|tile| tile.attr("gid").map_or(Ok(0), |_| tile.parse_attr("gid")))
+                        .collect::<Result<_, _>>()?,
+                    Some(_) => return Err(MapImportError::UnsupportedEncoding(name)),
+                };
+                layers.push(TiledLayer::Tiles {
+                    name,
+                    properties: child.properties(),
+                    gids,
+                });
+            }
+            "objectgroup" => {
+                let objects = child
+                    .children("object")
+                    .map(|object| {
+                        let number = |attr: &str| -> Result<f32, MapImportError> {
+                            object
+                                .attr(attr)
+                                .map_or(Ok(0.0), |_| object.parse_attr(attr))
+                        };
+                        Ok(TiledObject {
+                            kind: object
+                                .attr("type")
+                                .or(object.attr("class"))
+                                .unwrap_or_default()
+                                .into(),
+                            name: object.attr("name").unwrap_or_default().into(),
+                            x: number("x")?,
+                            y: number("y")?,
+                            width: number("width")?,
+                            height: number("height")?,
+                            is_tile: object.attr("gid").is_some(),
+                        })
+                    })
+                    .collect::<Result<_, MapImportError>>()?;
+                layers.push(TiledLayer::Objects { name, objects });
+            }
+            "group" => tmx_layers(child, layers)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Collect the tile and object layers of a `.tmj` map or group, including the ones in groups.
+fn tmj_layers(
+    node: &serde_json::Value,
+    layers: &mut Vec<TiledLayer>,
+) -> Result<(), MapImportError> {
+    for layer in node["layers"].as_array().into_iter().flatten() {
+        let name = layer["name"].as_str().unwrap_or_default().to_string();
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                if layer["encoding"]
+                    .as_str()
+                    .is_some_and(|encoding| encoding != "csv")
+                {
+                    return Err(MapImportError::UnsupportedEncoding(name));
+                }
+                let gids = layer["data"]
+                    .as_array()
+                    .ok_or_else(|| {
+                        MapImportError::Invalid(format!("the layer {name} has no data"))
+                    })?
+                    .iter()
+                    .map(|gid| gid.as_u64().map(|gid| gid as u32))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| {
+                        MapImportError::Invalid(format!("invalid tile data in the layer {name}"))
+                    })?;
+                layers.push(TiledLayer::Tiles {
+                    name,
+                    properties: json_properties(layer),
+                    gids,
+                });
+            }
+            Some("objectgroup") => {
+                let objects = layer["objects"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|object| TiledObject {
+                        kind: object["type"]
+                            .as_str()
+                            .filter(|kind| !kind.is_empty())
+                            .or(object["class"].as_str())
+                            .unwrap_or_default()
+                            .into(),
+                        name: object["name"].as_str().unwrap_or_default().into(),
+                        x: object["x"].as_f64().unwrap_or_default() as f32,
+                        y: object["y"].as_f64().unwrap_or_default() as f32,
+                        width: object["width"].as_f64().unwrap_or_default() as f32,
+                        height: object["height"].as_f64().unwrap_or_default() as f32,
+                        is_tile: object["gid"].is_u64(),
+                    })
+                    .collect();
+                layers.push(TiledLayer::Objects { name, objects });
+            }
+            Some("group") => tmj_layers(layer, layers)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn json_number(value: &serde_json::Value, key: &str) -> Result<f64, MapImportError> {
+    value[key]
+        .as_f64()
+        .ok_or_else(|| MapImportError::Invalid(format!("missing or invalid {key}")))
+}
+
+fn json_properties(value: &serde_json::Value) -> Properties {
+    value["properties"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|property| {
+            let name = property["name"].as_str()?.to_string();
+            let value = match &property["value"] {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            Some((name, value))
+        })
+        .collect()
+}
+
+/// An element of an XML document.
+#[derive(Default)]
+struct XmlNode {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlNode>,
+    text: String,
+}
+
+impl XmlNode {
+    /// Parse an XML document and get its root element.
+    fn parse(data: &[u8]) -> Result<Self, MapImportError> {
+        let mut stack = vec![XmlNode::default()];
+        for event in EventReader::new(data) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => stack.push(XmlNode {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|attribute| (attribute.name.local_name, attribute.value))
+                        .collect(),
+                    ..default()
+                }),
+                XmlEvent::EndElement { .. } => {
+                    let node = stack.pop().unwrap();
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(node);
+                    }
+                }
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some(node) = stack.last_mut() {
+                        node.text.push_str(&text);
+                    }
+                }
+                _ => (),
+            }
+        }
+        stack
+            .pop()
+            .and_then(|document| document.children.into_iter().next())
+            .ok_or_else(|| MapImportError::Invalid("empty document".into()))
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn parse_attr<T: std::str::FromStr>(&self, name: &str) -> Result<T, MapImportError> {
+        self.attr(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| {
+                MapImportError::Invalid(format!(
+                    "missing or invalid {name} attribute on <{}>",
+                    self.name
+                ))
+            })
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlNode> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn properties(&self) -> Properties {
+        self.children("properties")
+            .flat_map(|properties| properties.children("property"))
+            .filter_map(|property| {
+                let value = property.attr("value").unwrap_or(&property.text);
+                Some((property.attr("name")?.to_string(), value.to_string()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A map with a tile layer in the CSV format and one in the XML format, and an object layer.
+    /// Some of the tiles are flipped.
+    const TMX_MAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="3" height="2" tilewidth="32" tileheight="32" infinite="0">
+ <tileset firstgid="1" name="ground_rock" tilewidth="32" tileheight="32" tilecount="85" columns="17">
+  <image source="ground_rock.png" width="544" height="160"/>
+  <tile id="1">
+   <properties>
+    <property name="collision" value="jump_through"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer id="1" name="ground" width="3" height="2">
+  <properties>
+   <property name="collision" value="solid"/>
+  </properties>
+  <data encoding="csv">
+1,0,2147483650,
+0,0,3
+</data>
+ </layer>
+ <layer id="2" name="decoration" width="3" height="2">
+  <data>
+   <tile gid="1"/>
+   <tile/>
+   <tile/>
+   <tile/>
+   <tile gid="1073741826"/>
+   <tile/>
+  </data>
+ </layer>
+ <objectgroup id="3" name="spawns">
+  <object id="1" type="crab" x="16" y="8" width="32" height="16"/>
+ </objectgroup>
+</map>
+"#;
+
+    /// The same map as [`TMX_MAP`], in the JSON format.
+    const TMJ_MAP: &str = r#"{
+ "orientation": "orthogonal", "infinite": false,
+ "width": 3, "height": 2, "tilewidth": 32, "tileheight": 32,
+ "tilesets": [{
+  "firstgid": 1, "name": "ground_rock", "image": "ground_rock.png",
+  "tilewidth": 32, "tileheight": 32, "columns": 17,
+  "tiles": [{ "id": 1, "properties": [{ "name": "collision", "type": "string", "value": "jump_through" }] }]
+ }],
+ "layers": [
+  {
+   "type": "tilelayer", "name": "ground", "width": 3, "height": 2,
+   "properties": [{ "name": "collision", "type": "string", "value": "solid" }],
+   "data": [1, 0, 2147483650, 0, 0, 3]
+  },
+  { "type": "tilelayer", "name": "decoration", "width": 3, "height": 2, "data": [1, 0, 0, 0, 1073741826, 0] },
+  {
+   "type": "objectgroup", "name": "spawns",
+   "objects": [{ "id": 1, "type": "crab", "x": 16, "y": 8, "width": 32, "height": 16 }]
+  }
+ ]
+}"#;
+
+    fn import_tmx(tmx: &str, headless: &HeadlessGame) -> Result<MapMeta, MapImportError> {
+        let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+        TiledMap::parse_tmx(tmx.as_bytes(), Path::new("test.tmx"))?
+            .to_map_meta("test", &asset_server)
+    }
+
+    fn import_tmj(tmj: &str, headless: &HeadlessGame) -> Result<MapMeta, MapImportError> {
+        let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+        TiledMap::parse_tmj(tmj.as_bytes(), Path::new("test.tmj"))?
+            .to_map_meta("test", &asset_server)
+    }
+
+    fn tiles(layer: &MapLayerMeta) -> Vec<(UVec2, u32, TileCollisionKind)> {
+        let mut tiles = layer
+            .tiles
+            .iter()
+            .map(|tile| (tile.pos, tile.idx, tile.collision))
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|(pos, _, _)| (pos.y, pos.x));
+        tiles
+    }
+
+    #[test]
+    fn tile_rows_are_flipped_and_flip_bits_are_masked() {
+        let headless = HeadlessGame::for_tests();
+        let map = import_tmx(TMX_MAP, &headless).unwrap();
+        assert_eq!(map.grid_size, UVec2::new(3, 2));
+
+        // The top row of the Tiled map is the highest row of the map, and the flipped tiles keep
+        // their tile index.
+        let ground = tiles(&map.layers[0]);
+        let positions = ground.iter().map(|(pos, idx, _)| (*pos, *idx));
+        assert_eq!(
+            positions.collect::<Vec<_>>(),
+            [
+                (UVec2::new(2, 0), 2),
+                (UVec2::new(0, 1), 0),
+                (UVec2::new(2, 1), 1),
+            ]
+        );
+
+        let meta = headless.meta();
+        assert!(map.layers[0].tilemap.option() == Some(meta.core.map_tilesets[1]));
+    }
+
+    #[test]
+    fn object_centres_are_flipped() {
+        let headless = HeadlessGame::for_tests();
+        let map = import_tmx(TMX_MAP, &headless).unwrap();
+        let spawns = &map.layers[2];
+        assert_eq!(spawns.elements.len(), 1);
+        // The object spans 16 to 48 horizontally and 8 to 24 down from the top of the 64 high map.
+        assert_eq!(spawns.elements[0].pos, Vec2::new(32.0, 48.0));
+    }
+
+    #[test]
+    fn tile_collision_overrides_layer_collision() {
+        let headless = HeadlessGame::for_tests();
+        let map = import_tmx(TMX_MAP, &headless).unwrap();
+        let collisions = |layer| {
+            tiles(layer)
+                .into_iter()
+                .map(|(_, idx, collision)| (idx, collision))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            collisions(&map.layers[0]),
+            [
+                (2, TileCollisionKind::Solid),
+                (0, TileCollisionKind::Solid),
+                (1, TileCollisionKind::JumpThrough),
+            ]
+        );
+        // Tiles are empty unless their tile or their layer says otherwise.
+        assert_eq!(
+            collisions(&map.layers[1]),
+            [
+                (1, TileCollisionKind::JumpThrough),
+                (0, TileCollisionKind::Empty),
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_xml_and_json_tile_data_match() {
+        let headless = HeadlessGame::for_tests();
+        let csv_ground = import_tmx(TMX_MAP, &headless).unwrap();
+        let xml_ground = import_tmx(
+            &TMX_MAP.replace(
+                r#"<data encoding="csv">
+1,0,2147483650,
+0,0,3
+</data>"#,
+                r#"<data><tile gid="1"/><tile/><tile gid="2147483650"/><tile/><tile/><tile gid="3"/></data>"#,
+            ),
+            &headless,
+        )
+        .unwrap();
+        let json = import_tmj(TMJ_MAP, &headless).unwrap();
+
+        for (xml_layer, json_layer) in xml_ground.layers.iter().zip(&json.layers) {
+            assert_eq!(tiles(xml_layer), tiles(json_layer));
+            assert!(xml_layer.tilemap.option() == json_layer.tilemap.option());
+        }
+        assert_eq!(tiles(&csv_ground.layers[0]), tiles(&xml_ground.layers[0]));
+        assert_eq!(
+            json.layers[2].elements[0].pos,
+            csv_ground.layers[2].elements[0].pos
+        );
+    }
+
+    #[test]
+    fn layers_may_only_use_one_tileset() {
+        let headless = HeadlessGame::for_tests();
+        let tmx = TMX_MAP.replace(
+            " <layer id=\"1\"",
+            r#" <tileset firstgid="100" name="ground_wood" tilewidth="32" tileheight="32" tilecount="85" columns="17">
+  <image source="ground_wood.png" width="544" height="160"/>
+ </tileset>
+ <layer id="1""#,
+        );
+        assert!(import_tmx(&tmx, &headless).is_ok());
+
+        let tmx = tmx.replace("0,0,3", "0,0,100");
+        assert!(matches!(
+            import_tmx(&tmx, &headless),
+            Err(MapImportError::MixedTilesets(layer)) if layer == "ground"
+        ));
+    }
+
+    #[test]
+    fn tilesets_must_match_a_map_tileset() {
+        let headless = HeadlessGame::for_tests();
+        let tmx = TMX_MAP
+            .replace("name=\"ground_rock\"", "name=\"lava\"")
+            .replace("ground_rock.png", "lava.png");
+        assert!(matches!(
+            import_tmx(&tmx, &headless),
+            Err(MapImportError::UnknownTileset(tileset)) if tileset == "lava"
+        ));
+
+        // The map tileset has 17 columns of 32 by 32 tiles.
+        let tmx = TMX_MAP.replace("columns=\"17\"", "columns=\"16\"");
+        assert!(matches!(
+            import_tmx(&tmx, &headless),
+            Err(MapImportError::TilesetMismatch(tileset)) if tileset == "ground_rock"
+        ));
+        let tmx = TMX_MAP.replace(
+            r#"name="ground_rock" tilewidth="32" tileheight="32""#,
+            r#"name="ground_rock" tilewidth="16" tileheight="16""#,
+        );
+        assert!(matches!(
+            import_tmx(&tmx, &headless),
+            Err(MapImportError::TilesetMismatch(tileset)) if tileset == "ground_rock"
+        ));
+    }
+}
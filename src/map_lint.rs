@@ -0,0 +1,80 @@
+//! Command line tool that checks the maps of the game and of the installed packs for problems.
+
+use crate::{
+    headless::{HeadlessError, HeadlessGame},
+    prelude::*,
+    PackMeta,
+};
+
+/// Errors that may occur while checking maps.
+#[derive(thiserror::Error, Debug)]
+pub enum MapLintError {
+    #[error(transparent)]
+    Headless(#[from] HeadlessError),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Found {0} problem(s)")]
+    ProblemsFound(usize),
+}
+
+/// Check maps for problems, and print the problems that are found.
+///
+/// Usage: `jumpy lint-maps [--map <name>]...`
+///
+/// Every map of the game and of the installed packs is checked, unless maps are picked by name.
+/// Fails if any problem that isn't only a warning is found, so that it may be used in CI.
+pub fn run_cli(mut args: impl Iterator<Item = String>) -> Result<(), MapLintError> {
+    let mut names = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--map" => {
+                names.push(args.next().ok_or_else(|| {
+                    MapLintError::InvalidArgument(format!("{arg} expects a value"))
+                })?)
+            }
+            _ => return Err(MapLintError::InvalidArgument(arg)),
+        }
+    }
+
+    let headless = HeadlessGame::new(crate::asset_dir(), crate::packs_dir())?;
+    let asset_server = headless.game.shared_resource::<AssetServer>().unwrap();
+    let meta = asset_server.root::<GameMeta>();
+    let localization = asset_server.get(meta.localization);
+    let mut maps = Vec::new();
+    maps.extend(meta.core.stable_maps.iter().copied());
+    maps.extend(meta.core.experimental_maps.iter().copied());
+    for pack in asset_server.packs() {
+        let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
+        maps.extend(pack_meta.maps.iter().copied());
+    }
+
+    let mut checked = Vec::new();
+    let mut problems = 0;
+    for handle in maps {
+        let map = asset_server.get(handle);
+        if !names.is_empty() && !names.iter().any(|name| *name == map.name.as_str()) {
+            continue;
+        }
+        checked.push(map.name.to_string());
+        for issue in validate_map(&map, &asset_server) {
+            let text = issue.localized(&localization);
+            if issue.is_warning() {
+                println!("{}: warning: {text}", map.name);
+            } else {
+                println!("{}: {text}", map.name);
+                problems += 1;
+            }
+        }
+    }
+    if let Some(missing) = names.iter().find(|name| !checked.contains(name)) {
+        return Err(MapLintError::InvalidArgument(format!(
+            "Map not found: {missing}"
+        )));
+    }
+    println!("Checked {} map(s)", checked.len());
+
+    if problems > 0 {
+        return Err(MapLintError::ProblemsFound(problems));
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Messages sent over the reliable channel of network matches.
+//!
+//! The match, the scoring menu and the menus all share the reliable channel of the socket, and
+//! reading from the channel takes every message that has arrived. So that none of them takes the
+//! messages of another, every message is wrapped in a [`ReliableMessage`], which is decoded once by
+//! [`ReliableMessages::receive`] and put in the queue of the part of the game it is for. Each part
+//! then only takes the messages of its own queue, whenever it is running.
+
+use std::{collections::VecDeque, sync::Mutex};
+
+use crate::{
+    prelude::*,
+    spectate::SpectatorInputs,
+    ui::{main_menu::lobby::LobbyMessage, scoring::ScoringMessage},
+};
+
+/// A message sent over the reliable channel, tagged with the part of the game it is for.
+#[derive(Serialize, Deserialize)]
+pub enum ReliableMessage {
+    /// A message of the lobby, while the match is being set up. This must stay the first variant,
+    /// so that the handshake can be read by other versions of the game.
+    Lobby(LobbyMessage),
+    /// A message of the scoring menu, between rounds.
+    Scoring(ScoringMessage),
+    /// The checksum of a confirmed frame, used to detect desyncs.
+    Checksum(ChecksumMessage),
+    /// The inputs of confirmed frames, relayed by the host to the spectators.
+    SpectatorInputs(SpectatorInputs),
+}
+
+impl ReliableMessage {
+    /// Encode the message to be sent over the reliable channel.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).unwrap()
+    }
+}
+
+/// The messages received over the reliable channel that haven't been handled yet.
+///
+/// This is a shared resource, and the queues are shared between clones of it, so that messages
+/// stay queued when they are received in another session than the one that handles them, or when
+/// the match rolls back.
+#[derive(HasSchema, Clone, Default)]
+pub struct ReliableMessages(Arc<Mutex<MessageQueues>>);
+
+#[derive(Default)]
+struct MessageQueues {
+    lobby: VecDeque<(u32, LobbyMessage)>,
+    scoring: VecDeque<(u32, ScoringMessage)>,
+    checksum: VecDeque<(u32, ChecksumMessage)>,
+    spectator_inputs: VecDeque<(u32, SpectatorInputs)>,
+}
+
+impl ReliableMessages {
+    /// Sort the messages read from the reliable channel, along with the index of their sender, into
+    /// their queues.
+    pub fn receive(&self, messages: Vec<(u32, Vec<u8>)>) {
+        if messages.is_empty() {
+            return;
+        }
+        let mut queues = self.0.lock().unwrap();
+        for (sender, data) in messages {
+            match postcard::from_bytes::<ReliableMessage>(&data) {
+                Ok(ReliableMessage::Lobby(message)) => queues.lobby.push_back((sender, message)),
+                Ok(ReliableMessage::Scoring(message)) => {
+                    queues.scoring.push_back((sender, message))
+                }
+                Ok(ReliableMessage::Checksum(message)) => {
+                    queues.checksum.push_back((sender, message))
+                }
+                Ok(ReliableMessage::SpectatorInputs(message)) => {
+                    queues.spectator_inputs.push_back((sender, message))
+                }
+                Err(e) => {
+                    warn!(
+                        "Ignoring network message from peer {sender} that was not understood: {e}"
+                    )
+                }
+            }
+        }
+    }
+
+    /// Take the queued messages of the lobby.
+    pub fn take_lobby(&self) -> Vec<(u32, LobbyMessage)> {
+        self.0.lock().unwrap().lobby.drain(..).collect()
+    }
+
+    /// Take the queued messages of the scoring menu.
+    pub fn take_scoring(&self) -> Vec<(u32, ScoringMessage)> {
+        self.0.lock().unwrap().scoring.drain(..).collect()
+    }
+
+    /// Take the queued checksum messages.
+    pub fn take_checksum(&self) -> Vec<(u32, ChecksumMessage)> {
+        self.0.lock().unwrap().checksum.drain(..).collect()
+    }
+
+    /// Take the queued inputs relayed to a spectator.
+    pub fn take_spectator_inputs(&self) -> Vec<(u32, SpectatorInputs)> {
+        self.0.lock().unwrap().spectator_inputs.drain(..).collect()
+    }
+
+    /// Drop every queued message, when a new network match is joined.
+    pub fn clear(&self) {
+        *self.0.lock().unwrap() = default();
+    }
+}
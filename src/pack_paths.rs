@@ -0,0 +1,45 @@
+//! Finding packs in the packs directory and resolving the asset paths referenced by their files.
+//!
+//! The asset server only knows packs by id and assets by handle, so the parts of the game that read
+//! or write the files of packs, like the map export and the map transfer, find them here.
+
+use std::path::{Path, PathBuf};
+
+/// Find the folder of the pack with the given id in the packs directory.
+pub fn find_pack_dir(packs_dir: &Path, pack_id: &str) -> Option<PathBuf> {
+    std::fs::read_dir(packs_dir)
+        .ok()?
+        .flatten()
+        .find_map(|entry| {
+            let pack_yaml = std::fs::read(entry.path().join("pack.yaml")).ok()?;
+            let pack_yaml = serde_yaml::from_slice::<serde_yaml::Value>(&pack_yaml).ok()?;
+            (pack_yaml["id"].as_str() == Some(pack_id)).then(|| entry.path())
+        })
+}
+
+/// Resolve an asset path referenced from a file in the given directory of a pack, to a path
+/// relative to the pack. Returns `None` if the path leads out of the pack.
+///
+/// References to the assets of other packs, like `core:/`, must be handled by the caller.
+pub fn resolve_path(base_dir: &str, reference: &str) -> Option<String> {
+    let joined = match reference.strip_prefix('/') {
+        Some(path) => path.to_string(),
+        None => format!("{base_dir}/{reference}"),
+    };
+    let mut parts = Vec::new();
+    for part in joined.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Get the directory part of a path relative to a pack.
+pub fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
@@ -0,0 +1,377 @@
+//! Match recording and playback.
+//!
+//! Every match session records the dense controls of each player, every frame, into a
+//! [`ReplayRecorder`]. Together with the setup of the match, this is enough to reproduce the round
+//! deterministically, and can be saved to a [`Replay`] file and played back with a
+//! [`ReplayRunner`].
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use bones_framework::networking::input::NetworkPlayerControl;
+
+use crate::{core::MatchPlugin, prelude::*};
+
+/// The version of the replay file format. Increment this when the format changes.
+pub const REPLAY_FORMAT_VERSION: u32 = 8;
+
+/// The file extension used for replay files.
+pub const REPLAY_FILE_EXTENSION: &str = "replay";
+
+pub fn session_plugin(session: &mut SessionBuilder) {
+    session
+        .init_resource::<ReplayRecorder>()
+        .add_system_to_stage(CoreStage::First, record_inputs);
+}
+
+/// The dense controls of every player slot in a single frame.
+pub type ReplayFrame = [u32; MAX_PLAYERS as usize];
+
+/// Errors that may occur while saving or loading a replay.
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not (de)serialize replay: {0}")]
+    Serialization(#[from] postcard::Error),
+    #[error("Replay format version {0} is not supported")]
+    UnsupportedVersion(u32),
+}
+
+/// A player in a recorded match.
+#[derive(Serialize, Deserialize)]
+pub struct ReplayPlayer {
+    pub active: bool,
+    pub is_ai: bool,
+    pub ai_difficulty: AiDifficulty,
+    pub ai_behavior: Option<NetworkHandle<AiBehaviorMeta>>,
+    pub selected_player: NetworkHandle<PlayerMeta>,
+    pub selected_hat: Option<NetworkHandle<HatMeta>>,
+    pub team: Option<Team>,
+}
+
+/// A recorded round, containing the match setup and the inputs of every player for every frame.
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub format_version: u32,
+    /// The version of the game the replay was recorded with.
+    pub game_version: String,
+    pub maps: MapPoolNetwork,
+    pub players: Vec<ReplayPlayer>,
+    pub plugins: Vec<NetworkHandle<LuaPlugin>>,
+    pub rules: MatchRules,
+    pub random_seed: u64,
+    pub frames: Vec<ReplayFrame>,
+    /// The [`FrameChecksum`] at the end of every recorded frame, used to detect when playback
+    /// diverges from the recording.
+    pub checksums: Vec<u64>,
+}
+
+impl Replay {
+    /// Create a replay from the world of a running match session.
+    pub fn from_world(world: &World) -> Self {
+        let assets = world.resource::<AssetServer>();
+        let match_inputs = world.resource::<MatchInputs>();
+        let recorder = world.resource::<ReplayRecorder>();
+
+        Self {
+            format_version: REPLAY_FORMAT_VERSION,
+            game_version: crate::game_version().to_string(),
+            maps: world.resource::<MapPool>().into_network(&assets),
+            players: match_inputs
+                .players
+                .iter()
+                .map(|player| ReplayPlayer {
+                    active: player.active,
+                    is_ai: player.is_ai,
+                    ai_difficulty: player.ai_difficulty,
+                    ai_behavior: player.ai_behavior.map(|h| h.network_handle(&assets)),
+                    selected_player: player.selected_player.network_handle(&assets),
+                    selected_hat: player.selected_hat.map(|h| h.network_handle(&assets)),
+                    team: player.team,
+                })
+                .collect(),
+            plugins: world
+                .resource::<LuaPlugins>()
+                .0
+                .iter()
+                .map(|h| h.network_handle(&assets))
+                .collect(),
+            rules: *world.resource::<MatchRules>(),
+            random_seed: **world.resource::<RandomSeed>(),
+            frames: recorder.frames(),
+            checksums: recorder.checksums(),
+        }
+    }
+
+    /// Create the match plugin that will play back this replay.
+    ///
+    /// The match is restricted to the recorded map, so that the replay loops when the round ends.
+    pub fn into_match_plugin(self, assets: &AssetServer) -> MatchPlugin {
+        let map = self.maps.current_map.into_handle(assets);
+        MatchPlugin {
+            maps: MapPool::from_single_map(map),
+            player_info: std::array::from_fn(|i| {
+                let player = self.players.get(i);
+                PlayerInput {
+                    active: player.map_or(false, |p| p.active),
+                    is_ai: player.map_or(false, |p| p.is_ai),
+                    ai_difficulty: player.map(|p| p.ai_difficulty).unwrap_or_default(),
+                    ai_behavior: player
+                        .and_then(|p| p.ai_behavior.as_ref())
+                        .map(|h| h.into_handle(assets)),
+                    selected_player: player
+                        .map(|p| p.selected_player.into_handle(assets))
+                        .unwrap_or_default(),
+                    selected_hat: player
+                        .and_then(|p| p.selected_hat.as_ref())
+                        .map(|h| h.into_handle(assets)),
+                    team: player.and_then(|p| p.team),
+                    ..default()
+                }
+            }),
+            plugins: Arc::new(self.plugins.iter().map(|h| h.into_handle(assets)).collect()),
+            score: default(),
+            rules: self.rules,
+            random_seed: self.random_seed,
+            session_runner: Box::new(ReplayRunner::new(self)),
+        }
+    }
+
+    /// Save the replay into the replays directory, returning the path it was written to.
+    pub fn save(&self) -> Result<PathBuf, ReplayError> {
+        let dir = replays_dir();
+        std::fs::create_dir_all(&dir)?;
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = dir.join(format!("{timestamp}.{REPLAY_FILE_EXTENSION}"));
+        std::fs::write(&path, postcard::to_allocvec(self)?)?;
+
+        Ok(path)
+    }
+
+    /// Load a replay from the given path.
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let replay: Replay = postcard::from_bytes(&std::fs::read(path)?)?;
+        if replay.format_version != REPLAY_FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedVersion(replay.format_version));
+        }
+        if replay.game_version != crate::game_version().to_string() {
+            warn!(
+                "Replay was recorded with game version {}, it may not play back correctly.",
+                replay.game_version
+            );
+        }
+        Ok(replay)
+    }
+}
+
+/// The directory that replays are saved to.
+pub fn replays_dir() -> PathBuf {
+    std::env::var("JUMPY_REPLAYS")
+        .unwrap_or_else(|_| "replays".into())
+        .into()
+}
+
+/// List the replay files in the replays directory, newest first.
+pub fn list_replays() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(replays_dir()) else {
+        return Vec::new();
+    };
+    let mut replays = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |ext| ext == REPLAY_FILE_EXTENSION)
+        })
+        .collect::<Vec<_>>();
+    replays.sort_unstable_by(|a, b| b.cmp(a));
+    replays
+}
+
+/// Resource that records the dense controls of every player, and the resulting checksum, every
+/// frame.
+///
+/// The frames are shared between clones of the resource, so that the recording is not copied into
+/// every world snapshot. To stay correct when rolling back in network play, each frame is written
+/// at the index of the frame counter, which is restored along with the snapshot.
+#[derive(HasSchema, Clone, Default)]
+pub struct ReplayRecorder {
+    frame: usize,
+    frames: Arc<Mutex<Vec<RecordedFrame>>>,
+}
+
+#[derive(Clone, Copy)]
+struct RecordedFrame {
+    controls: ReplayFrame,
+    checksum: u64,
+}
+
+impl ReplayRecorder {
+    /// Record the controls for the current frame, replacing any frames recorded after it.
+    pub fn record(&mut self, controls: ReplayFrame) {
+        let mut frames = self.frames.lock().unwrap();
+        frames.truncate(self.frame);
+        frames.push(RecordedFrame {
+            controls,
+            checksum: 0,
+        });
+        self.frame += 1;
+    }
+
+    /// Record the checksum at the end of the current frame.
+    pub fn record_checksum(&mut self, checksum: u64) {
+        let mut frames = self.frames.lock().unwrap();
+        if let Some(frame) = self.frame.checked_sub(1).and_then(|i| frames.get_mut(i)) {
+            frame.checksum = checksum;
+        }
+    }
+
+    /// Get the controls of the frames recorded so far.
+    pub fn frames(&self) -> Vec<ReplayFrame> {
+        self.recorded().iter().map(|f| f.controls).collect()
+    }
+
+    /// Get the checksums of the frames recorded so far.
+    pub fn checksums(&self) -> Vec<u64> {
+        self.recorded().iter().map(|f| f.checksum).collect()
+    }
+
+    fn recorded(&self) -> Vec<RecordedFrame> {
+        let frames = self.frames.lock().unwrap();
+        frames[..self.frame.min(frames.len())].to_vec()
+    }
+}
+
+/// Records the player inputs for the current frame. Must run before the AI systems, so that only
+/// the inputs given to the simulation are recorded.
+fn record_inputs(inputs: Res<MatchInputs>, mut recorder: ResMut<ReplayRecorder>) {
+    recorder.record(std::array::from_fn(|i| {
+        bytemuck::cast(inputs.players[i].control.get_dense_input())
+    }));
+}
+
+/// Session runner that plays back a [`Replay`] in real time.
+///
+/// When the session is restarted, playback starts over from the first frame. If the checksum of a
+/// frame differs from the recording, the frame is logged as the point the replay desynced.
+pub struct ReplayRunner {
+    replay: Replay,
+    frame: usize,
+    /// The first frame that did not match the recorded checksum.
+    pub desync_frame: Option<usize>,
+    accumulator: f64,
+    last_run: Option<Instant>,
+}
+
+impl ReplayRunner {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            frame: 0,
+            desync_frame: None,
+            accumulator: 0.0,
+            last_run: None,
+        }
+    }
+
+    /// Whether all of the recorded frames have been played.
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.replay.frames.len()
+    }
+}
+
+impl SessionRunner for ReplayRunner {
+    fn step(&mut self, frame_start: Instant, world: &mut World, stages: &mut SystemStages) {
+        const STEP: f64 = 1.0 / FPS as f64;
+        let last_run = self.last_run.unwrap_or(frame_start);
+        self.accumulator += (frame_start - last_run).as_secs_f64();
+        self.last_run = Some(frame_start);
+
+        let loop_start = Instant::now();
+        while self.accumulator >= STEP {
+            let Some(frame) = self.replay.frames.get(self.frame) else {
+                // Hold on the last frame once the replay is over.
+                self.accumulator = 0.0;
+                break;
+            };
+            if (Instant::now() - loop_start).as_secs_f64() > STEP {
+                warn!("Frame took too long: couldn't keep up with replay playback.");
+                self.accumulator = 0.0;
+                break;
+            }
+            self.accumulator -= STEP;
+
+            if self.frame == 0 {
+                world
+                    .resources
+                    .insert(GlobalRng::with_seed(self.replay.random_seed));
+            }
+            world
+                .resource_mut::<Time>()
+                .advance_exact(Duration::from_secs_f64(STEP));
+            {
+                let mut player_inputs = world.resource_mut::<MatchInputs>();
+                for (player, dense) in player_inputs.players.iter_mut().zip(frame) {
+                    // AI players re-compute their inputs deterministically.
+                    if !player.is_ai {
+                        player.control.update_from_dense(&bytemuck::cast(*dense));
+                    }
+                }
+            }
+
+            stages.run(world);
+
+            let checksum = world.resource::<FrameChecksum>().checksum;
+            let recorded = self.replay.checksums.get(self.frame).copied();
+            if self.desync_frame.is_none() && recorded.map_or(false, |c| c != checksum) {
+                error!(
+                    "Replay desynced from its recording on frame {}: expected checksum \
+                    {:#018x}, got {checksum:#018x}.",
+                    self.frame,
+                    recorded.unwrap(),
+                );
+                self.desync_frame = Some(self.frame);
+            }
+
+            self.frame += 1;
+        }
+    }
+
+    fn restart_session(&mut self) {
+        self.frame = 0;
+        self.desync_frame = None;
+        self.accumulator = 0.0;
+        self.last_run = None;
+    }
+
+    fn disable_local_input(&mut self, _disable_input: bool) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recorder_overwrites_rolled_back_frames() {
+        let mut recorder = ReplayRecorder::default();
+        recorder.record([1; MAX_PLAYERS as usize]);
+        let snapshot = recorder.clone();
+        recorder.record([2; MAX_PLAYERS as usize]);
+        recorder.record([3; MAX_PLAYERS as usize]);
+
+        // Roll back to the snapshot and re-simulate with different inputs.
+        let mut recorder = snapshot;
+        recorder.record([4; MAX_PLAYERS as usize]);
+
+        assert_eq!(
+            recorder.frames(),
+            vec![[1; MAX_PLAYERS as usize], [4; MAX_PLAYERS as usize]]
+        );
+    }
+}
@@ -6,6 +6,7 @@ impl SessionNames {
     pub const DEBUG: &'static str = "debug";
     pub const GAME: &'static str = "game";
     pub const MAIN_MENU: &'static str = "main_menu";
+    pub const MAP_EDITOR: &'static str = "map_editor";
     pub const PAUSE_MENU: &'static str = "pause_menu";
     pub const PROFILER: &'static str = "profiler";
     pub const SCORING: &'static str = "scoring";
@@ -41,27 +42,37 @@ impl SessionExt for Sessions {
 
     #[track_caller]
     fn restart_game(&mut self, map_pool: Option<MapPool>, reset_score: bool) {
-        if let Some((existing_map_pool, player_info, plugins, mut session_runner, score)) =
-            self.get_mut(SessionNames::GAME).map(|session| {
-                let existing_map_pool = (*session.world.resource::<MapPool>()).clone();
-                let match_inputs = session.world.resource::<MatchInputs>();
-                let score = (*session.world.resource::<MatchScore>()).clone();
+        if let Some((
+            existing_map_pool,
+            player_info,
+            plugins,
+            mut session_runner,
+            score,
+            rules,
+            random_seed,
+        )) = self.get_mut(SessionNames::GAME).map(|session| {
+            let existing_map_pool = (*session.world.resource::<MapPool>()).clone();
+            let match_inputs = session.world.resource::<MatchInputs>();
+            let score = (*session.world.resource::<MatchScore>()).clone();
+            let rules = *session.world.resource::<MatchRules>();
+            let random_seed = **session.world.resource::<RandomSeed>();
 
-                // Take ownership of session runner (we want to preserve socket and such for network runner)
-                // by swapping a dummy one with session.
-                let mut session_runner: Box<dyn SessionRunner> =
-                    Box::<JumpyDefaultMatchRunner>::default();
-                std::mem::swap(&mut session.runner, &mut session_runner);
+            // Take ownership of session runner (we want to preserve socket and such for network runner)
+            // by swapping a dummy one with session.
+            let mut session_runner: Box<dyn SessionRunner> =
+                Box::<JumpyDefaultMatchRunner>::default();
+            std::mem::swap(&mut session.runner, &mut session_runner);
 
-                (
-                    existing_map_pool,
-                    match_inputs.players.clone(),
-                    session.world.resource::<LuaPlugins>().0.clone(),
-                    session_runner,
-                    score,
-                )
-            })
-        {
+            (
+                existing_map_pool,
+                match_inputs.players.clone(),
+                session.world.resource::<LuaPlugins>().0.clone(),
+                session_runner,
+                score,
+                rules,
+                random_seed,
+            )
+        }) {
             self.end_game();
 
             // Reset session runner
@@ -81,6 +92,8 @@ impl SessionExt for Sessions {
                     plugins,
                     session_runner,
                     score,
+                    rules,
+                    random_seed,
                 });
             });
         } else {
@@ -37,6 +37,20 @@ pub struct Settings {
     pub player_controls: PlayerControlMapping,
     /// The address of the matchmaking server to connect to for online games.
     pub matchmaking_server: String,
+    /// The number of frames our inputs are delayed by in network matches. When unset, it is picked
+    /// from the ping to the other players when the match starts.
+    pub network_input_delay: Maybe<u32>,
+    /// The maximum number of frames that are predicted ahead of the inputs of the other players in
+    /// network matches. When unset, it is picked from the ping to the other players when the match
+    /// starts.
+    pub network_prediction_window: Maybe<u32>,
+}
+
+impl Settings {
+    /// The largest input delay that can be set, in frames.
+    pub const MAX_NETWORK_INPUT_DELAY: u32 = 10;
+    /// The largest prediction window that can be set, in frames.
+    pub const MAX_NETWORK_PREDICTION_WINDOW: u32 = 15;
 }
 
 impl Default for Settings {
@@ -48,6 +62,8 @@ impl Default for Settings {
             fullscreen: true,
             player_controls: default(),
             matchmaking_server: default(),
+            network_input_delay: Maybe::Unset,
+            network_prediction_window: Maybe::Unset,
         }
     }
 }
@@ -0,0 +1,279 @@
+//! Spectating network matches.
+//!
+//! Spectators are connected to the socket of the match like the players are. Which peers spectate
+//! is chosen by the peers themselves, see [`NetworkSpectators`]. The spectators with socket indices
+//! after the last player aren't part of the GGRS session: the players never wait for their inputs,
+//! and one of them leaving doesn't stall the match. Instead, the host wraps its session runner in a
+//! [`SpectatorRelay`], which sends the inputs of every confirmed frame to them over the reliable
+//! channel. They run the match from those inputs with a [`SpectatorRunner`], a little behind the
+//! players.
+//!
+//! [`NetworkSpectators`]: crate::ui::network_game::NetworkSpectators
+
+use std::{collections::VecDeque, ops::Range};
+
+use bones_framework::networking::{
+    input::NetworkPlayerControl, socket::Socket, NetworkSocket, SocketTarget, SyncingInfo,
+};
+
+use crate::{
+    network_messages::{ReliableMessage, ReliableMessages},
+    prelude::*,
+    replay::ReplayFrame,
+    settings::PlayerControlMapping,
+};
+
+/// The number of frames to keep the inputs of in the [`NetworkInputHistory`]. Must be larger than
+/// the maximum prediction window.
+const INPUT_HISTORY_LEN: usize = 64;
+
+/// When a spectator has more than this many frames buffered, it runs two frames at a time until
+/// it has caught up.
+const MAX_BUFFERED_FRAMES: usize = FPS as usize / 2;
+
+pub fn session_plugin(session: &mut SessionBuilder) {
+    session
+        .init_resource::<NetworkInputHistory>()
+        .add_system_to_stage(CoreStage::First, record_network_inputs);
+}
+
+/// The dense inputs of the most recent frames of a network match, by network frame.
+///
+/// Frames that are re-simulated after a rollback replace the inputs that were predicted for them.
+#[derive(HasSchema, Clone, Default, Deref, DerefMut)]
+pub struct NetworkInputHistory(pub VecDeque<(i32, ReplayFrame)>);
+
+/// Records the player inputs of the current network frame. Must run before the AI systems, like the
+/// [`ReplayRecorder`][crate::replay::ReplayRecorder], since spectators re-compute the AI inputs.
+fn record_network_inputs(
+    inputs: Res<MatchInputs>,
+    syncing_info: Option<Res<SyncingInfo>>,
+    mut history: ResMut<NetworkInputHistory>,
+) {
+    let Some(syncing_info) = syncing_info else {
+        return;
+    };
+    let frame = syncing_info.current_frame();
+    history.retain(|(recorded, _)| *recorded < frame);
+    history.push_back((
+        frame,
+        std::array::from_fn(|i| bytemuck::cast(inputs.players[i].control.get_dense_input())),
+    ));
+    while history.len() > INPUT_HISTORY_LEN {
+        history.pop_front();
+    }
+}
+
+/// Network message with the inputs of confirmed frames, sent by the host to the spectators.
+#[derive(Serialize, Deserialize)]
+pub struct SpectatorInputs {
+    /// The number of times the match session was restarted before these frames, for a new round or
+    /// a rematch. Frames are counted from the start of each round.
+    pub round: u32,
+    /// The inputs of consecutive confirmed frames, following the frames sent before.
+    pub frames: Vec<ReplayFrame>,
+}
+
+/// Session runner of the host of a match with spectators, which relays the inputs of confirmed
+/// frames to the spectators after running the match with the wrapped runner.
+pub struct SpectatorRelay {
+    runner: Box<dyn SessionRunner>,
+    /// The socket indices of the spectators after the GGRS session.
+    spectators: Range<u32>,
+    round: u32,
+    last_sent_frame: Option<i32>,
+}
+
+impl SpectatorRelay {
+    pub fn new(runner: Box<dyn SessionRunner>, spectators: Range<u32>) -> Self {
+        Self {
+            runner,
+            spectators,
+            round: 0,
+            last_sent_frame: None,
+        }
+    }
+
+    /// Send the inputs of the frames that have been confirmed since the last step.
+    fn relay_confirmed_inputs(&mut self, world: &World) {
+        let Some(syncing_info) = world.get_resource::<SyncingInfo>() else {
+            return;
+        };
+        let Some(socket) = syncing_info.socket() else {
+            return;
+        };
+        let last_confirmed_frame = syncing_info.last_confirmed_frame();
+        let history = world.resource::<NetworkInputHistory>();
+        let confirmed = history
+            .iter()
+            .filter(|(frame, _)| {
+                *frame <= last_confirmed_frame && self.last_sent_frame.map_or(true, |f| *frame > f)
+            })
+            .collect::<Vec<_>>();
+        let Some((last_frame, _)) = confirmed.last() else {
+            return;
+        };
+        self.last_sent_frame = Some(*last_frame);
+
+        let message = ReliableMessage::SpectatorInputs(SpectatorInputs {
+            round: self.round,
+            frames: confirmed.iter().map(|(_, frame)| *frame).collect(),
+        })
+        .to_bytes();
+        for spectator in self.spectators.clone() {
+            socket.send_reliable(SocketTarget::Player(spectator), &message);
+        }
+    }
+}
+
+impl SessionRunner for SpectatorRelay {
+    fn step(&mut self, frame_start: Instant, world: &mut World, stages: &mut SystemStages) {
+        self.runner.step(frame_start, world, stages);
+        self.relay_confirmed_inputs(world);
+    }
+
+    fn restart_session(&mut self) {
+        self.runner.restart_session();
+        self.round += 1;
+        self.last_sent_frame = None;
+    }
+
+    fn disable_local_input(&mut self, disable_input: bool) {
+        self.runner.disable_local_input(disable_input);
+    }
+}
+
+/// Resource present in the match of a peer that is spectating it.
+#[derive(HasSchema, Clone)]
+#[schema(no_default)]
+pub struct Spectating {
+    /// The socket of the network match.
+    pub socket: Socket,
+    /// The local controls of the spectator, used to move the camera around.
+    pub control: PlayerControl,
+}
+
+/// Session runner of a spectator, which runs the match from the confirmed inputs relayed by the
+/// host.
+pub struct SpectatorRunner {
+    socket: Socket,
+    input_collector: PlayerInputCollector,
+    round: u32,
+    /// The inputs received from the host that haven't been run yet, along with their round.
+    frames: VecDeque<(u32, ReplayFrame)>,
+    accumulator: f64,
+    last_run: Option<Instant>,
+}
+
+impl SpectatorRunner {
+    pub fn new(socket: Socket) -> Self {
+        Self {
+            socket,
+            input_collector: default(),
+            round: 0,
+            frames: default(),
+            accumulator: 0.0,
+            last_run: None,
+        }
+    }
+
+    /// Queue the inputs that were received from the host.
+    fn receive_inputs(&mut self, world: &World) {
+        let messages = world.resource::<ReliableMessages>();
+        messages.receive(self.socket.recv_reliable());
+        for (_sender, message) in messages.take_spectator_inputs() {
+            if message.round >= self.round {
+                self.frames.extend(
+                    message
+                        .frames
+                        .into_iter()
+                        .map(|frame| (message.round, frame)),
+                );
+            }
+        }
+        // Checksums are only compared between players.
+        messages.take_checksum();
+    }
+}
+
+impl SessionRunner for SpectatorRunner {
+    fn step(&mut self, frame_start: Instant, world: &mut World, stages: &mut SystemStages) {
+        const STEP: f64 = 1.0 / FPS as f64;
+        let last_run = self.last_run.unwrap_or(frame_start);
+        self.accumulator += (frame_start - last_run).as_secs_f64();
+        self.last_run = Some(frame_start);
+
+        self.receive_inputs(world);
+        {
+            let keyboard = world.resource::<KeyboardInputs>();
+            let gamepad = world.resource::<GamepadInputs>();
+            self.input_collector.apply_inputs(
+                &world.resource::<PlayerControlMapping>(),
+                &keyboard,
+                &gamepad,
+            );
+        }
+
+        let loop_start = Instant::now();
+        while self.accumulator >= STEP {
+            // Wait for the host once the frames of this round have run out.
+            let Some((round, frame)) = self.frames.front().copied() else {
+                self.accumulator = 0.0;
+                break;
+            };
+            if round != self.round {
+                self.accumulator = 0.0;
+                break;
+            }
+            if (Instant::now() - loop_start).as_secs_f64() > STEP {
+                warn!("Frame took too long: couldn't keep up with the spectated match.");
+                self.accumulator = 0.0;
+                break;
+            }
+            // Catch up with the players by running frames without waiting when far behind.
+            if self.frames.len() <= MAX_BUFFERED_FRAMES {
+                self.accumulator -= STEP;
+            }
+            self.frames.pop_front();
+
+            // Spectators control the camera with any of their local controllers.
+            self.input_collector.update_just_pressed();
+            let control = self
+                .input_collector
+                .get_current_controls()
+                .values()
+                .find(|control| control.moving || control.jump_pressed)
+                .copied()
+                .unwrap_or_default();
+            self.input_collector.advance_frame();
+            world.insert_resource(Spectating {
+                socket: self.socket.clone(),
+                control,
+            });
+
+            world
+                .resource_mut::<Time>()
+                .advance_exact(Duration::from_secs_f64(STEP));
+            {
+                let mut player_inputs = world.resource_mut::<MatchInputs>();
+                for (player, dense) in player_inputs.players.iter_mut().zip(frame) {
+                    // AI players re-compute their inputs deterministically.
+                    if !player.is_ai {
+                        player.control.update_from_dense(&bytemuck::cast(dense));
+                    }
+                }
+            }
+
+            stages.run(world);
+        }
+    }
+
+    fn restart_session(&mut self) {
+        self.round += 1;
+        self.frames.retain(|(round, _)| *round >= self.round);
+        self.accumulator = 0.0;
+        self.last_run = None;
+    }
+
+    fn disable_local_input(&mut self, _disable_input: bool) {}
+}
@@ -1,5 +1,6 @@
 use crate::prelude::*;
 
+pub mod editor;
 pub mod main_menu;
 pub mod map_select;
 pub mod notification;
@@ -0,0 +1,1134 @@
+//! The in-game map editor.
+//!
+//! The editor opens on top of a running local match, see [`open_map_editor`]. Its tools and panels
+//! produce [`EditorInput`]s, which are handed to the match through the inputs of the first player
+//! and applied to the map by the [`MapManager`] while the match keeps running.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{prelude::*, PackMeta};
+
+use super::ImageMeta;
+
+/// The smallest height of the editor's view of the map, in pixels of the map.
+const MIN_VIEW_HEIGHT: f32 = 100.0;
+
+/// The largest height of the editor's view of the map, in pixels of the map.
+const MAX_VIEW_HEIGHT: f32 = 3000.0;
+
+/// How much the view zooms for each point scrolled with the mouse wheel.
+const ZOOM_SPEED: f32 = 0.002;
+
+/// The size of the tool buttons and of the tiles in the tile palette.
+const BUTTON_IMAGE_SIZE: f32 = 32.0;
+
+/// The grid is hidden when its cells get smaller than this on screen.
+const MIN_GRID_CELL_SIZE: f32 = 4.0;
+
+/// The width of the editor's side panels.
+const SIDE_PANEL_WIDTH: f32 = 260.0;
+
+pub fn session_plugin(session: &mut SessionBuilder) {
+    session.add_system_to_stage(Update, map_editor_system);
+}
+
+/// Open the map editor on top of the running match.
+pub fn open_map_editor(sessions: &mut Sessions) {
+    if let Some(session) = sessions.get_mut(SessionNames::MAP_EDITOR) {
+        session.world.insert_resource(MapEditorState {
+            open: true,
+            show_grid: true,
+            collision: TileCollisionKind::Solid,
+            ..default()
+        });
+    }
+}
+
+/// The tool used to edit the map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EditorTool {
+    /// Select elements, and drag them around to move them.
+    #[default]
+    Select,
+    /// Paint tiles with the left mouse button, and erase them with the right one.
+    Tiles,
+    /// Place elements with the left mouse button, and delete them with the right one.
+    Elements,
+}
+
+/// The state of the map editor.
+#[derive(HasSchema, Clone, Default)]
+pub struct MapEditorState {
+    /// Whether the editor is open.
+    pub open: bool,
+    pub tool: EditorTool,
+    /// The index of the layer being edited.
+    pub layer: u32,
+    /// The tile of the layer's tilemap that is painted with the tiles tool.
+    pub tile: Option<u32>,
+    /// The collision of the painted tiles.
+    pub collision: TileCollisionKind,
+    /// The element that is placed with the elements tool.
+    pub element: Option<Handle<ElementMeta>>,
+    /// The element selected with the select tool.
+    pub selected_entity: Option<Entity>,
+    pub show_grid: bool,
+    /// The element being dragged, and where it was grabbed, relative to its position.
+    drag: Option<(Entity, Vec2)>,
+    /// The last tile painted or erased while the mouse button is held, so that it isn't set again
+    /// every frame.
+    last_painted_tile: Option<UVec2>,
+    /// The name typed for the map, if it has been edited.
+    map_name: Option<String>,
+    /// The name typed for the layer being edited.
+    layer_name: String,
+    /// The name typed for a new layer.
+    new_layer_name: String,
+    /// Set to move the view back over the whole map.
+    reset_view: bool,
+    /// Set to save the map to the user map pack.
+    save: bool,
+    /// The path the map was saved to, or the error that prevented saving it.
+    save_result: Option<Result<String, String>>,
+    /// Set to check the map for problems.
+    check: bool,
+    /// The problems found the last time the map was checked.
+    issues: Option<Vec<MapIssue>>,
+    /// The inputs waiting to be applied by the match, which applies one input per frame.
+    pending: VecDeque<EditorInput>,
+}
+
+impl MapEditorState {
+    fn select_layer(&mut self, map: &EditedMap, layer: u32) {
+        self.layer = layer;
+        self.tile = None;
+        self.layer_name = map
+            .layers
+            .get(layer as usize)
+            .map(|layer| layer.name.to_string())
+            .unwrap_or_default();
+    }
+}
+
+/// What the editor needs to know about the map being edited, read from the match.
+struct EditedMap {
+    name: Ustr,
+    grid_size: UVec2,
+    tile_size: Vec2,
+    layers: Vec<EditedLayer>,
+    elements: Vec<EditedElement>,
+    can_undo: bool,
+    can_redo: bool,
+}
+
+struct EditedLayer {
+    name: Ustr,
+    tilemap: Option<Handle<Atlas>>,
+}
+
+struct EditedElement {
+    entity: Entity,
+    handle: Handle<ElementMeta>,
+    pos: Vec2,
+    layer: u32,
+}
+
+impl EditedMap {
+    /// Get the grid position of the tile at the given position on the map, if it is on the map.
+    fn tile_at(&self, pos: Vec2) -> Option<UVec2> {
+        let tile = (pos / self.tile_size).floor();
+        let size = self.grid_size.as_vec2();
+        (tile.x >= 0.0 && tile.y >= 0.0 && tile.x < size.x && tile.y < size.y)
+            .then(|| tile.as_uvec2())
+    }
+
+    /// Get the element at the given position on the map, preferring the ones on the given layer.
+    fn element_at(
+        &self,
+        pos: Vec2,
+        layer: u32,
+        asset_server: &AssetServer,
+    ) -> Option<&EditedElement> {
+        let hit = |element: &&EditedElement| {
+            let (min, max) = self.grab_rect(element, asset_server);
+            pos.cmpge(min).all() && pos.cmple(max).all()
+        };
+        let mut hits = self.elements.iter().rev().filter(hit);
+        let first = hits.next()?;
+        Some(
+            std::iter::once(first)
+                .chain(hits)
+                .find(|element| element.layer == layer)
+                .unwrap_or(first),
+        )
+    }
+
+    /// Get the corners of the rectangle an element is grabbed by in the editor.
+    fn grab_rect(&self, element: &EditedElement, asset_server: &AssetServer) -> (Vec2, Vec2) {
+        let editor = &asset_server.get(element.handle).editor;
+        let size = if editor.grab_size == Vec2::ZERO {
+            self.tile_size
+        } else {
+            editor.grab_size
+        };
+        let center = element.pos + editor.grab_offset;
+        (center - size / 2.0, center + size / 2.0)
+    }
+}
+
+/// The part of the map shown by the editor camera, used to convert between positions on the
+/// screen and positions on the map.
+struct EditorView {
+    center: Vec2,
+    height: f32,
+    screen: egui::Rect,
+}
+
+impl EditorView {
+    /// The size of a pixel of the map on the screen.
+    fn scale(&self) -> f32 {
+        self.screen.height() / self.height
+    }
+
+    fn to_screen(&self, pos: Vec2) -> egui::Pos2 {
+        let offset = (pos - self.center) * self.scale();
+        self.screen.center() + egui::vec2(offset.x, -offset.y)
+    }
+
+    fn to_map(&self, pos: egui::Pos2) -> Vec2 {
+        let offset = pos - self.screen.center();
+        self.center + vec2(offset.x, -offset.y) / self.scale()
+    }
+}
+
+fn map_editor_system(
+    world: &World,
+    ctx: Res<EguiCtx>,
+    mut sessions: ResMut<Sessions>,
+    mut state: ResMutInit<MapEditorState>,
+) {
+    let Some(session) = sessions.get_mut(SessionNames::GAME) else {
+        // The match has ended, and the editing with it.
+        if state.open {
+            *state = default();
+        }
+        return;
+    };
+    if !state.open {
+        return;
+    }
+    let Some(map) = session.world.run_system(read_edited_map, ()) else {
+        // The map hasn't been spawned yet.
+        return;
+    };
+    if state.layer as usize >= map.layers.len() || state.layer_name.is_empty() {
+        let layer = state.layer.min(map.layers.len().saturating_sub(1) as u32);
+        state.select_layer(&map, layer);
+    }
+
+    // Keyboard input is needed for naming the map and its layers, and the player shouldn't move
+    // while the map is being edited.
+    ctx.set_state(EguiInputSettings::default());
+    session.runner.disable_local_input(true);
+
+    // Move the view with the middle mouse button and zoom it with the mouse wheel.
+    let pointer_on_map = !ctx.is_pointer_over_area() && !ctx.wants_pointer_input();
+    let (pan, zoom) = ctx.input(|i| {
+        let pan = if i.pointer.middle_down() {
+            i.pointer.delta() / i.screen_rect().height()
+        } else {
+            egui::Vec2::ZERO
+        };
+        (vec2(-pan.x, pan.y), (-i.scroll_delta.y * ZOOM_SPEED).exp())
+    });
+    let (pan, zoom) = if pointer_on_map {
+        (pan, zoom)
+    } else {
+        (Vec2::ZERO, 1.0)
+    };
+    let reset_view = std::mem::take(&mut state.reset_view);
+    let Some((center, height)) = session
+        .world
+        .run_system(move_editor_camera, (pan, zoom, reset_view))
+    else {
+        return;
+    };
+    let view = EditorView {
+        center,
+        height,
+        screen: ctx.screen_rect(),
+    };
+
+    let mut close = false;
+    world.run_system(editor_panels, (&mut *state, &map, &view, &mut close));
+    if close {
+        session.world.run_system(release_editor_camera, ());
+        session.runner.disable_local_input(false);
+        *state = default();
+        return;
+    }
+
+    if std::mem::take(&mut state.check) {
+        state.issues = Some(session.world.run_system(
+            |map_manager: MapManager, asset_server: Res<AssetServer>| {
+                validate_map(&map_manager.to_map_meta(), &asset_server)
+            },
+            (),
+        ));
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if std::mem::take(&mut state.save) {
+        let map_meta = session
+            .world
+            .run_system(|map_manager: MapManager| map_manager.to_map_meta(), ());
+        let asset_server = world.resource::<AssetServer>();
+        state.save_result = Some(
+            crate::map_export::export_map(&map_meta, &asset_server)
+                .map(|path| path.display().to_string())
+                .map_err(|e| {
+                    warn!("Couldn't save map {}: {e}", map_meta.name);
+                    e.to_string()
+                }),
+        );
+    }
+
+    world.run_system(editor_pointer, (&mut *state, &map, &view, pointer_on_map));
+    world.run_system(editor_overlay, (&*state, &map, &view));
+
+    // The match applies one editor input per frame.
+    let mut inputs = session.world.resource_mut::<MatchInputs>();
+    if inputs.players[0].editor_input.is_none() {
+        inputs.players[0].editor_input = state.pending.pop_front();
+    }
+}
+
+/// Read the layers and elements of the map from the match.
+fn read_edited_map(
+    entities: Res<Entities>,
+    spawned_map_meta: Option<Res<SpawnedMapMeta>>,
+    tile_layers: Comp<TileLayer>,
+    spawned_map_layer_metas: Comp<SpawnedMapLayerMeta>,
+    element_handles: Comp<ElementHandle>,
+    transforms: Comp<Transform>,
+    history: Option<Res<MapEditHistory>>,
+) -> Option<EditedMap> {
+    let spawned_map_meta = spawned_map_meta?;
+    let mut layers = spawned_map_meta
+        .layer_names
+        .iter()
+        .map(|name| EditedLayer {
+            name: *name,
+            tilemap: None,
+        })
+        .collect::<Vec<_>>();
+    for (_, (tile_layer, layer_meta)) in
+        entities.iter_with((&tile_layers, &spawned_map_layer_metas))
+    {
+        if let Some(layer) = layers.get_mut(layer_meta.layer_idx as usize) {
+            layer.tilemap = Some(tile_layer.atlas).filter(|atlas| *atlas != Handle::default());
+        }
+    }
+    let elements = entities
+        .iter_with((&element_handles, &transforms, &spawned_map_layer_metas))
+        .map(|(entity, (handle, transform, layer_meta))| EditedElement {
+            entity,
+            handle: handle.0,
+            pos: transform.translation.truncate(),
+            layer: layer_meta.layer_idx,
+        })
+        .collect();
+
+    Some(EditedMap {
+        name: spawned_map_meta.name,
+        grid_size: spawned_map_meta.grid_size,
+        tile_size: spawned_map_meta.tile_size,
+        layers,
+        elements,
+        can_undo: history.as_ref().is_some_and(|history| history.can_undo()),
+        can_redo: history.as_ref().is_some_and(|history| history.can_redo()),
+    })
+}
+
+/// Take over the camera of the match, move it by the given fraction of the view height and zoom
+/// it by the given factor. Returns the center and the height of the view.
+fn move_editor_camera(
+    In((pan, zoom, reset)): In<(Vec2, f32, bool)>,
+    meta: Root<GameMeta>,
+    map: Res<LoadedMap>,
+    entities: Res<Entities>,
+    mut cameras: CompMut<Camera>,
+    mut camera_shakes: CompMut<CameraShake>,
+    mut camera_states: CompMut<CameraState>,
+) -> Option<(Vec2, f32)> {
+    let (_, (camera, camera_shake, camera_state)) = entities
+        .iter_with((&mut cameras, &mut camera_shakes, &mut camera_states))
+        .next()?;
+    camera_state.disable_controller = true;
+
+    let default_height = meta.core.camera.default_height;
+    let mut height = match camera.size {
+        CameraSize::FixedHeight(height) => height,
+        _ => default_height,
+    };
+    let mut center = camera_shake.center.truncate();
+    if reset {
+        height = default_height;
+        center = map.grid_size.as_vec2() * map.tile_size / 2.0;
+    }
+    center += pan * height;
+    height = (height * zoom).clamp(MIN_VIEW_HEIGHT, MAX_VIEW_HEIGHT);
+
+    camera.size = CameraSize::FixedHeight(height);
+    camera_shake.center = center.extend(camera_shake.center.z);
+    Some((center, height))
+}
+
+/// Give the camera back to the match.
+fn release_editor_camera(entities: Res<Entities>, mut camera_states: CompMut<CameraState>) {
+    for (_, camera_state) in entities.iter_with(&mut camera_states) {
+        camera_state.disable_controller = false;
+    }
+}
+
+/// Edit the map with the selected tool where the mouse is.
+fn editor_pointer(
+    mut param: In<(&mut MapEditorState, &EditedMap, &EditorView, bool)>,
+    ctx: Res<EguiCtx>,
+    asset_server: Res<AssetServer>,
+) {
+    let (state, map, view, pointer_on_map) = &mut *param;
+    let layer = state.layer;
+    let (hover_pos, primary_pressed, primary_down, secondary_pressed, secondary_down, delete) = ctx
+        .input(|i| {
+            (
+                i.pointer.hover_pos(),
+                i.pointer.primary_pressed(),
+                i.pointer.primary_down(),
+                i.pointer.secondary_pressed(),
+                i.pointer.secondary_down(),
+                i.key_pressed(egui::Key::Delete),
+            )
+        });
+    let pos = hover_pos.map(|pos| view.to_map(pos));
+
+    if !primary_down && !secondary_down {
+        state.last_painted_tile = None;
+    }
+
+    // Drop the dragged element where the mouse button is released.
+    if let Some((entity, grab_offset)) = state.drag {
+        if !primary_down {
+            if let Some(pos) = pos {
+                state.pending.push_back(EditorInput::MoveEntity {
+                    entity,
+                    pos: pos - grab_offset,
+                });
+            }
+            state.drag = None;
+        }
+        return;
+    }
+
+    if delete && !ctx.wants_keyboard_input() {
+        if let Some(entity) = state.selected_entity.take() {
+            state
+                .pending
+                .push_back(EditorInput::DeleteEntity { entity });
+        }
+    }
+
+    let Some(pos) = pos.filter(|_| *pointer_on_map) else {
+        return;
+    };
+    match state.tool {
+        EditorTool::Select => {
+            if primary_pressed {
+                let element = map.element_at(pos, layer, &asset_server);
+                state.selected_entity = element.map(|element| element.entity);
+                state.drag = element.map(|element| (element.entity, pos - element.pos));
+            }
+        }
+        EditorTool::Tiles => {
+            let Some(tile_pos) = map.tile_at(pos) else {
+                return;
+            };
+            if state.last_painted_tile == Some(tile_pos) {
+                return;
+            }
+            let tilemap_tile_idx = if secondary_down {
+                None
+            } else if let (true, Some(tile)) = (primary_down, state.tile) {
+                Some(tile)
+            } else {
+                return;
+            };
+            state.pending.push_back(EditorInput::SetTile {
+                layer: layer as u8,
+                pos: tile_pos,
+                tilemap_tile_idx,
+                collision: if tilemap_tile_idx.is_some() {
+                    state.collision
+                } else {
+                    TileCollisionKind::Empty
+                },
+            });
+            state.last_painted_tile = Some(tile_pos);
+        }
+        EditorTool::Elements => {
+            if let (true, Some(handle)) = (primary_pressed, state.element) {
+                state.pending.push_back(EditorInput::SpawnElement {
+                    handle,
+                    translation: pos,
+                    layer: layer as u8,
+                });
+            } else if secondary_pressed {
+                if let Some(element) = map.element_at(pos, layer, &asset_server) {
+                    state.pending.push_back(EditorInput::DeleteEntity {
+                        entity: element.entity,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Draw the grid, the edited tile and the elements of the edited layer over the map.
+fn editor_overlay(
+    param: In<(&MapEditorState, &EditedMap, &EditorView)>,
+    ctx: Res<EguiCtx>,
+    meta: Root<GameMeta>,
+    asset_server: Res<AssetServer>,
+) {
+    let (state, map, view) = *param;
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    let grid_color = egui::Color32::from_white_alpha(40);
+    let highlight_color = egui::Color32::from_rgb(255, 220, 0);
+    let map_size = map.grid_size.as_vec2() * map.tile_size;
+
+    // The grid, and the border of the map.
+    if state.show_grid && map.tile_size.min_element() * view.scale() >= MIN_GRID_CELL_SIZE {
+        let stroke = egui::Stroke::new(1.0, grid_color);
+        for x in 1..map.grid_size.x {
+            let x = x as f32 * map.tile_size.x;
+            painter.line_segment(
+                [
+                    view.to_screen(vec2(x, 0.0)),
+                    view.to_screen(vec2(x, map_size.y)),
+                ],
+                stroke,
+            );
+        }
+        for y in 1..map.grid_size.y {
+            let y = y as f32 * map.tile_size.y;
+            painter.line_segment(
+                [
+                    view.to_screen(vec2(0.0, y)),
+                    view.to_screen(vec2(map_size.x, y)),
+                ],
+                stroke,
+            );
+        }
+    }
+    painter.rect_stroke(
+        egui::Rect::from_two_pos(view.to_screen(Vec2::ZERO), view.to_screen(map_size)),
+        0.0,
+        egui::Stroke::new(2.0, egui::Color32::WHITE),
+    );
+
+    let hover_pos = ctx
+        .input(|i| i.pointer.hover_pos())
+        .map(|pos| view.to_map(pos));
+
+    // The tile under the mouse.
+    if let Some(tile_pos) = hover_pos
+        .filter(|_| state.tool == EditorTool::Tiles)
+        .and_then(|pos| map.tile_at(pos))
+    {
+        let min = tile_pos.as_vec2() * map.tile_size;
+        painter.rect_stroke(
+            egui::Rect::from_two_pos(view.to_screen(min), view.to_screen(min + map.tile_size)),
+            0.0,
+            egui::Stroke::new(2.0, highlight_color),
+        );
+    }
+
+    // The elements of the edited layer, and the selected element on any layer.
+    let font = meta.theme.font_styles.smaller.id();
+    for element in &map.elements {
+        let selected = state.selected_entity == Some(element.entity);
+        if element.layer != state.layer && !selected {
+            continue;
+        }
+        let (mut min, mut max) = map.grab_rect(element, &asset_server);
+        if let (Some((entity, grab_offset)), Some(pos)) = (state.drag, hover_pos) {
+            if entity == element.entity {
+                let offset = pos - grab_offset - element.pos;
+                min += offset;
+                max += offset;
+            }
+        }
+        let rect = egui::Rect::from_two_pos(view.to_screen(min), view.to_screen(max));
+        let color = if selected {
+            highlight_color
+        } else {
+            egui::Color32::from_white_alpha(120)
+        };
+        painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, color));
+
+        let element_meta = asset_server.get(element.handle);
+        if element_meta.editor.show_name || selected {
+            painter.text(
+                rect.center_top(),
+                egui::Align2::CENTER_BOTTOM,
+                element_meta.name.as_str(),
+                font.clone(),
+                color,
+            );
+        }
+    }
+}
+
+/// Show the editor's panels: the map and view settings at the top, the tools and layers on the
+/// left, and the tile palette or element browser on the right.
+fn editor_panels(
+    mut param: In<(&mut MapEditorState, &EditedMap, &EditorView, &mut bool)>,
+    ctx: Res<EguiCtx>,
+    meta: Root<GameMeta>,
+    localization: Localization<GameMeta>,
+    asset_server: Res<AssetServer>,
+    egui_textures: Res<EguiTextures>,
+) {
+    let (state, map, view, close) = &mut *param;
+    let bigger_font = meta
+        .theme
+        .font_styles
+        .bigger
+        .with_color(meta.theme.panel.font_color);
+    let normal_font = meta
+        .theme
+        .font_styles
+        .normal
+        .with_color(meta.theme.panel.font_color);
+    let smaller_font = meta
+        .theme
+        .font_styles
+        .smaller
+        .with_color(meta.theme.panel.font_color);
+    let small_button_style = &meta.theme.buttons.small;
+
+    // The map name and the view.
+    egui::TopBottomPanel::top("map-editor-top")
+        .frame(egui::Frame::none())
+        .show(&ctx, |ui| {
+            BorderedFrame::new(&meta.theme.panel.border)
+                .padding(meta.theme.panel.padding)
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.horizontal(|ui| {
+                        ui.label(bigger_font.rich(localization.get("map-editor")));
+                        ui.add_space(normal_font.size);
+
+                        ui.label(normal_font.rich(localization.get("name")));
+                        let map_name = state.map_name.get_or_insert_with(|| map.name.to_string());
+                        ui.add(
+                            egui::TextEdit::singleline(map_name)
+                                .font(normal_font.id())
+                                .desired_width(normal_font.size * 10.0),
+                        );
+                        if BorderedButton::themed(small_button_style, localization.get("rename"))
+                            .show(ui)
+                            .clicked()
+                        {
+                            let name = map_name.clone();
+                            state.pending.push_back(EditorInput::RenameMap { name });
+                        }
+                        ui.add_space(normal_font.size);
+
+                        let zoom = meta.core.camera.default_height / view.height * 100.0;
+                        ui.label(normal_font.rich(localization.get_with(
+                            "view-zoom",
+                            &fluent_args! { "percent" => zoom.round() as u32 },
+                        )));
+                        if BorderedButton::themed(
+                            small_button_style,
+                            localization.get("view-reset"),
+                        )
+                        .show(ui)
+                        .clicked()
+                        {
+                            state.reset_view = true;
+                        }
+                        ui.checkbox(
+                            &mut state.show_grid,
+                            normal_font.rich(localization.get("show-grid")),
+                        );
+                        ui.add_space(normal_font.size);
+
+                        let typing = ctx.wants_keyboard_input();
+                        let (undo_key, redo_key) = ctx.input(|i| {
+                            let command = i.modifiers.command && !typing;
+                            let redo = i.key_pressed(egui::Key::Y)
+                                || (i.modifiers.shift && i.key_pressed(egui::Key::Z));
+                            (
+                                command && !redo && i.key_pressed(egui::Key::Z),
+                                command && redo,
+                            )
+                        });
+                        let undo = ui
+                            .add_enabled_ui(map.can_undo, |ui| {
+                                BorderedButton::themed(small_button_style, localization.get("undo"))
+                                    .show(ui)
+                                    .clicked()
+                            })
+                            .inner;
+                        if map.can_undo && (undo || undo_key) {
+                            state.pending.push_back(EditorInput::Undo);
+                        }
+                        let redo = ui
+                            .add_enabled_ui(map.can_redo, |ui| {
+                                BorderedButton::themed(small_button_style, localization.get("redo"))
+                                    .show(ui)
+                                    .clicked()
+                            })
+                            .inner;
+                        if map.can_redo && (redo || redo_key) {
+                            state.pending.push_back(EditorInput::Redo);
+                        }
+
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if BorderedButton::themed(small_button_style, localization.get("close"))
+                                .show(ui)
+                                .clicked()
+                            {
+                                **close = true;
+                            }
+                            #[cfg(not(target_arch = "wasm32"))]
+                            if BorderedButton::themed(small_button_style, localization.get("save"))
+                                .show(ui)
+                                .clicked()
+                            {
+                                state.save = true;
+                            }
+                            match &state.save_result {
+                                Some(Ok(path)) => {
+                                    ui.label(smaller_font.rich(localization.get_with(
+                                        "map-saved",
+                                        &fluent_args! { "path" => path.as_str() },
+                                    )));
+                                }
+                                Some(Err(error)) => {
+                                    ui.label(smaller_font.rich(localization.get_with(
+                                        "map-save-failed",
+                                        &fluent_args! { "error" => error.as_str() },
+                                    )));
+                                }
+                                None => (),
+                            }
+
+                            let hover_pos = ui.input(|i| i.pointer.hover_pos());
+                            if let Some(tile) =
+                                hover_pos.and_then(|pos| map.tile_at(view.to_map(pos)))
+                            {
+                                ui.label(smaller_font.rich(localization.get_with(
+                                    "cursor-position",
+                                    &fluent_args! { "x" => tile.x, "y" => tile.y },
+                                )));
+                            }
+                        });
+                    });
+                });
+        });
+
+    // The tools and the layers.
+    egui::SidePanel::left("map-editor-left")
+        .frame(egui::Frame::none())
+        .resizable(false)
+        .exact_width(SIDE_PANEL_WIDTH)
+        .show(&ctx, |ui| {
+            BorderedFrame::new(&meta.theme.panel.border)
+                .padding(meta.theme.panel.padding)
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.set_min_height(ui.available_height());
+
+                    ui.horizontal(|ui| {
+                        let icons = &meta.theme.editor.icons;
+                        for (tool, icon) in [
+                            (EditorTool::Select, &icons.select),
+                            (EditorTool::Tiles, &icons.tiles),
+                            (EditorTool::Elements, &icons.elements),
+                        ] {
+                            if icon_button(ui, &egui_textures, icon, state.tool == tool).clicked() {
+                                state.tool = tool;
+                            }
+                        }
+                    });
+                    let tool_name = match state.tool {
+                        EditorTool::Select => "select",
+                        EditorTool::Tiles => "tiles",
+                        EditorTool::Elements => "elements",
+                    };
+                    ui.label(normal_font.rich(localization.get(tool_name)));
+                    ui.label(smaller_font.rich(localization.get(match state.tool {
+                        EditorTool::Select => "map-editor-select-hint",
+                        EditorTool::Tiles => "map-editor-tiles-hint",
+                        EditorTool::Elements => "map-editor-elements-hint",
+                    })));
+                    ui.label(smaller_font.rich(localization.get("map-editor-view-hint")));
+                    ui.add_space(normal_font.size);
+
+                    // The layers, from the top to the bottom of the map.
+                    ui.label(bigger_font.rich(localization.get("layers")));
+                    for (i, layer) in map.layers.iter().enumerate() {
+                        let icon = if layer.tilemap.is_some() {
+                            localization.get("tile-layer-icon")
+                        } else {
+                            localization.get("element-layer-icon")
+                        };
+                        let text = normal_font.rich(format!("{icon} {}", layer.name));
+                        if ui.selectable_label(state.layer == i as u32, text).clicked() {
+                            state.select_layer(map, i as u32);
+                        }
+                    }
+                    ui.add_space(normal_font.size / 2.0);
+
+                    let layer = state.layer;
+                    let layer_count = map.layers.len() as u32;
+                    ui.horizontal(|ui| {
+                        let move_button = |ui: &mut egui::Ui, text: &str, enabled: bool| {
+                            ui.scope(|ui| {
+                                ui.set_enabled(enabled);
+                                BorderedButton::themed(small_button_style, text).show(ui)
+                            })
+                            .inner
+                            .clicked()
+                        };
+                        if move_button(ui, "▲", layer > 0) {
+                            state.pending.push_back(EditorInput::MoveLayer {
+                                layer: layer as u8,
+                                down: false,
+                            });
+                            state.layer -= 1;
+                        }
+                        if move_button(ui, "▼", layer + 1 < layer_count) {
+                            state.pending.push_back(EditorInput::MoveLayer {
+                                layer: layer as u8,
+                                down: true,
+                            });
+                            state.layer += 1;
+                        }
+                        if move_button(ui, &localization.get("delete-layer"), layer_count > 0) {
+                            state
+                                .pending
+                                .push_back(EditorInput::DeleteLayer { layer: layer as u8 });
+                            state.layer_name.clear();
+                            state.selected_entity = None;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut state.layer_name)
+                                .font(normal_font.id())
+                                .desired_width(ui.available_width() - normal_font.size * 5.0),
+                        );
+                        if BorderedButton::themed(small_button_style, localization.get("rename"))
+                            .show(ui)
+                            .clicked()
+                            && !state.layer_name.trim().is_empty()
+                        {
+                            state.pending.push_back(EditorInput::RenameLayer {
+                                layer: layer as u8,
+                                name: state.layer_name.trim().to_string(),
+                            });
+                        }
+                    });
+                    ui.add_space(normal_font.size / 2.0);
+
+                    ui.label(normal_font.rich(localization.get("create-layer")));
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut state.new_layer_name)
+                                .font(normal_font.id())
+                                .desired_width(ui.available_width() - normal_font.size * 5.0),
+                        );
+                        if BorderedButton::themed(small_button_style, localization.get("create"))
+                            .show(ui)
+                            .clicked()
+                            && !state.new_layer_name.trim().is_empty()
+                        {
+                            let id = std::mem::take(&mut state.new_layer_name);
+                            state.pending.push_back(EditorInput::CreateLayer {
+                                id: id.trim().to_string(),
+                            });
+                        }
+                    });
+                    ui.add_space(normal_font.size);
+
+                    // The problems found in the map.
+                    if BorderedButton::themed(small_button_style, localization.get("map-check"))
+                        .show(ui)
+                        .clicked()
+                    {
+                        state.check = true;
+                    }
+                    if let Some(issues) = &state.issues {
+                        if issues.is_empty() {
+                            ui.label(smaller_font.rich(localization.get("map-check-ok")));
+                        }
+                        egui::ScrollArea::vertical()
+                            .id_source("map-issues")
+                            .show(ui, |ui| {
+                                for issue in issues {
+                                    ui.label(smaller_font.rich(issue.localized(&localization)));
+                                }
+                            });
+                    }
+                });
+        });
+
+    // The tile palette, the element browser, or the selected element.
+    egui::SidePanel::right("map-editor-right")
+        .frame(egui::Frame::none())
+        .resizable(false)
+        .exact_width(SIDE_PANEL_WIDTH)
+        .show(&ctx, |ui| {
+            BorderedFrame::new(&meta.theme.panel.border)
+                .padding(meta.theme.panel.padding)
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.set_min_height(ui.available_height());
+
+                    match state.tool {
+                        EditorTool::Select => {
+                            ui.label(bigger_font.rich(localization.get("element")));
+                            let selected = state.selected_entity.and_then(|entity| {
+                                map.elements.iter().find(|element| element.entity == entity)
+                            });
+                            let Some(element) = selected else {
+                                ui.label(
+                                    smaller_font.rich(localization.get("map-editor-no-selection")),
+                                );
+                                return;
+                            };
+                            let element_meta = asset_server.get(element.handle);
+                            ui.label(normal_font.rich(element_meta.name.as_str()));
+                            ui.label(smaller_font.rich(element_meta.category.as_str()));
+                            ui.label(
+                                smaller_font.rich(format!(
+                                    "[ {:.0}, {:.0} ]",
+                                    element.pos.x, element.pos.y
+                                )),
+                            );
+                            if BorderedButton::themed(
+                                small_button_style,
+                                localization.get("delete-element"),
+                            )
+                            .show(ui)
+                            .clicked()
+                            {
+                                state.pending.push_back(EditorInput::DeleteEntity {
+                                    entity: element.entity,
+                                });
+                                state.selected_entity = None;
+                            }
+                        }
+                        EditorTool::Tiles => {
+                            let tilemap = map
+                                .layers
+                                .get(state.layer as usize)
+                                .and_then(|layer| layer.tilemap);
+
+                            ui.label(bigger_font.rich(localization.get("collision")));
+                            ui.horizontal(|ui| {
+                                for (collision, label) in [
+                                    (TileCollisionKind::Solid, "solid"),
+                                    (TileCollisionKind::JumpThrough, "jump-through"),
+                                    (TileCollisionKind::Empty, "empty"),
+                                ] {
+                                    let text = smaller_font.rich(localization.get(label));
+                                    if ui
+                                        .selectable_label(state.collision == collision, text)
+                                        .clicked()
+                                    {
+                                        state.collision = collision;
+                                    }
+                                }
+                            });
+                            ui.add_space(normal_font.size / 2.0);
+
+                            ui.label(bigger_font.rich(localization.get("tilemap")));
+                            ui.horizontal_wrapped(|ui| {
+                                for atlas_handle in known_tilemaps(&meta, &asset_server) {
+                                    let atlas = asset_server.get(atlas_handle);
+                                    let selected = tilemap == Some(atlas_handle);
+                                    if tile_button(ui, &egui_textures, &atlas, 0, selected)
+                                        .clicked()
+                                        && !selected
+                                    {
+                                        state.pending.push_back(EditorInput::SetTilemap {
+                                            layer: state.layer as u8,
+                                            handle: Some(atlas_handle),
+                                        });
+                                        state.tile = None;
+                                    }
+                                }
+                            });
+                            ui.add_space(normal_font.size / 2.0);
+
+                            ui.label(bigger_font.rich(localization.get("tiles")));
+                            let Some(tilemap) = tilemap else {
+                                ui.label(
+                                    smaller_font.rich(localization.get("map-editor-no-tilemap")),
+                                );
+                                return;
+                            };
+                            let atlas = asset_server.get(tilemap);
+                            egui::ScrollArea::vertical().show(ui, |ui| {
+                                ui.spacing_mut().item_spacing = egui::Vec2::ZERO;
+                                ui.horizontal_wrapped(|ui| {
+                                    for idx in 0..atlas.columns * atlas.rows {
+                                        let selected = state.tile == Some(idx);
+                                        if tile_button(ui, &egui_textures, &atlas, idx, selected)
+                                            .clicked()
+                                        {
+                                            state.tile = Some(idx);
+                                        }
+                                    }
+                                });
+                            });
+                        }
+                        EditorTool::Elements => {
+                            ui.label(bigger_font.rich(localization.get("elements")));
+                            egui::ScrollArea::vertical().show(ui, |ui| {
+                                for (category, elements) in
+                                    elements_by_category(&meta, &asset_server)
+                                {
+                                    ui.collapsing(normal_font.rich(category), |ui| {
+                                        for (name, handle) in elements {
+                                            let selected = state.element == Some(handle);
+                                            if ui
+                                                .selectable_label(
+                                                    selected,
+                                                    smaller_font.rich(name.as_str()),
+                                                )
+                                                .clicked()
+                                            {
+                                                state.element = Some(handle);
+                                            }
+                                        }
+                                    });
+                                }
+                            });
+                        }
+                    }
+                });
+        });
+}
+
+/// Get the tilemaps used by the maps of the game and of the loaded packs.
+fn known_tilemaps(meta: &GameMeta, asset_server: &AssetServer) -> Vec<Handle<Atlas>> {
+    let mut maps = Vec::new();
+    maps.extend(meta.core.stable_maps.iter().copied());
+    maps.extend(meta.core.experimental_maps.iter().copied());
+    for pack in asset_server.packs() {
+        let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
+        maps.extend(pack_meta.maps.iter().copied());
+    }
+
+    let mut tilemaps = Vec::new();
+    for map in maps {
+        for layer in asset_server.get(map).layers.iter() {
+            if let Some(tilemap) = layer.tilemap.option() {
+                if !tilemaps.contains(&tilemap) {
+                    tilemaps.push(tilemap);
+                }
+            }
+        }
+    }
+    tilemaps
+}
+
+/// Get the names and handles of the map elements of the game and of the loaded packs, grouped by
+/// their category.
+fn elements_by_category(
+    meta: &GameMeta,
+    asset_server: &AssetServer,
+) -> BTreeMap<String, Vec<(String, Handle<ElementMeta>)>> {
+    let mut elements = Vec::new();
+    elements.extend(meta.core.map_elements.iter().copied());
+    for pack in asset_server.packs() {
+        let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
+        elements.extend(pack_meta.map_elements.iter().copied());
+    }
+
+    let mut categories = BTreeMap::<String, Vec<_>>::new();
+    for handle in elements {
+        let element_meta = asset_server.get(handle);
+        categories
+            .entry(element_meta.category.to_string())
+            .or_default()
+            .push((element_meta.name.to_string(), handle));
+    }
+    for elements in categories.values_mut() {
+        elements.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+    categories
+}
+
+/// Show a button with an icon of the editor theme.
+fn icon_button(
+    ui: &mut egui::Ui,
+    egui_textures: &EguiTextures,
+    icon: &ImageMeta,
+    selected: bool,
+) -> egui::Response {
+    let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+    image_button(ui, egui_textures, &icon.image, uv, selected)
+}
+
+/// Show a button with a tile of a tilemap.
+fn tile_button(
+    ui: &mut egui::Ui,
+    egui_textures: &EguiTextures,
+    atlas: &Atlas,
+    idx: u32,
+    selected: bool,
+) -> egui::Response {
+    let tile_pos = atlas.tile_pos(idx);
+    let uv_min = tile_pos / atlas.size();
+    let uv_max = (tile_pos + atlas.tile_size) / atlas.size();
+    let uv = egui::Rect::from_min_max(
+        egui::pos2(uv_min.x, uv_min.y),
+        egui::pos2(uv_max.x, uv_max.y),
+    );
+    image_button(ui, egui_textures, &atlas.image, uv, selected)
+}
+
+fn image_button(
+    ui: &mut egui::Ui,
+    egui_textures: &EguiTextures,
+    image: &Handle<Image>,
+    uv: egui::Rect,
+    selected: bool,
+) -> egui::Response {
+    let size = egui::vec2(BUTTON_IMAGE_SIZE, BUTTON_IMAGE_SIZE);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+    if let Some(texture_id) = egui_textures.0.get(image) {
+        let mut mesh = egui::Mesh {
+            texture_id: *texture_id,
+            ..default()
+        };
+        mesh.add_rect_with_uv(rect, uv, egui::Color32::WHITE);
+        ui.painter().add(mesh);
+    }
+    if selected || response.hovered() {
+        let color = if selected {
+            egui::Color32::from_rgb(255, 220, 0)
+        } else {
+            egui::Color32::WHITE
+        };
+        ui.painter()
+            .rect_stroke(rect, 0.0, egui::Stroke::new(2.0, color));
+    }
+    response
+}
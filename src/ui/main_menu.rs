@@ -3,6 +3,7 @@ use crate::prelude::*;
 use super::ImageMeta;
 
 mod credits;
+mod map_editor;
 mod map_select;
 pub mod player_select;
 pub(super) mod settings;
@@ -11,8 +12,14 @@ use shadow_rs::shadow;
 // Generate build info.
 shadow!(build_info);
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lobby;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod map_transfer;
 #[cfg(not(target_arch = "wasm32"))]
 mod network_game;
+#[cfg(not(target_arch = "wasm32"))]
+mod replays;
 
 #[derive(HasSchema, Debug, Default, Clone)]
 #[repr(C)]
@@ -80,6 +87,11 @@ pub enum MenuPage {
     },
     Credits,
     NetworkGame,
+    /// The lobby of a network match, where the players wait for everyone to be ready.
+    Lobby,
+    Replays,
+    /// Picking the map to open in the map editor.
+    MapEditor,
 }
 
 #[allow(clippy::const_is_empty)]
@@ -130,6 +142,11 @@ fn main_menu_system(world: &World) {
         }
     }
 
+    // Receive the messages of the network match setup before showing the page, since they may
+    // change it.
+    #[cfg(not(target_arch = "wasm32"))]
+    world.run_system(lobby::handle_lobby_messages, ());
+
     egui::CentralPanel::default()
         .frame(egui::Frame::none())
         .show(&ctx, |ui| match ctx.get_state::<MenuPage>() {
@@ -145,6 +162,17 @@ fn main_menu_system(world: &World) {
                 #[cfg(not(target_arch = "wasm32"))]
                 world.run_system(network_game::widget, ui)
             }
+            MenuPage::Lobby =>
+            {
+                #[cfg(not(target_arch = "wasm32"))]
+                world.run_system(lobby::widget, ui)
+            }
+            MenuPage::Replays =>
+            {
+                #[cfg(not(target_arch = "wasm32"))]
+                world.run_system(replays::widget, ui)
+            }
+            MenuPage::MapEditor => world.run_system(map_editor::widget, ui),
         });
 
     if close_settings_menu {
@@ -217,6 +245,28 @@ fn home_menu(
                     ui.ctx().set_state(MenuPage::NetworkGame);
                 }
 
+                // Replays
+                #[cfg(not(target_arch = "wasm32"))]
+                if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("replays"))
+                    .min_size(vec2(ui.available_width(), 0.0))
+                    .show(ui)
+                    .clicked()
+                {
+                    ui.ctx().set_state(MenuPage::Replays);
+                }
+
+                // Map editor
+                if BorderedButton::themed(
+                    &meta.theme.buttons.normal,
+                    localization.get("map-editor"),
+                )
+                .min_size(vec2(ui.available_width(), 0.0))
+                .show(ui)
+                .clicked()
+                {
+                    ui.ctx().set_state(MenuPage::MapEditor);
+                }
+
                 // Settings
                 if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("settings"))
                     .min_size(vec2(ui.available_width(), 0.0))
@@ -0,0 +1,937 @@
+//! The lobby of network matches.
+//!
+//! After the players have selected their fish and the host has picked the maps and match rules,
+//! everyone waits in the lobby, where they can chat and mark themselves ready. The host starts the
+//! match once all of the players are ready.
+//!
+//! Before anything else, the peers exchange a [`NetworkCompatibility`] handshake, and the match is
+//! left if any of them is running a different version of the game or has different packs loaded.
+//! The asset handles sent over the network are only valid between identical installs. Packs that
+//! only contain maps are the exception, since their maps are transferred by the host when they are
+//! selected, see [`map_transfer`](super::map_transfer).
+//!
+//! While in the lobby, the peers ping each other to measure the round trip time, which is used to
+//! pick the input delay and prediction window of the match, unless they are set in the settings.
+
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+
+use bones_framework::networking::{NetworkMatchSocket, SocketTarget};
+use fxhash::FxHasher64;
+
+use crate::ui::network_game::{NetworkSpectators, MAX_PEERS};
+use crate::ui::player_image::player_image;
+
+use super::map_select::start_match;
+use super::map_transfer::{is_map_pack, MapDownloads, MapTransferMessage};
+use super::player_select::{PlayerSelectMessage, PlayerSelectState};
+use super::MenuPage;
+use crate::{
+    network_messages::{ReliableMessage, ReliableMessages},
+    prelude::*,
+    settings::Settings,
+    NetworkMeta, PackMeta,
+};
+
+/// The maximum number of characters in a chat message.
+const MAX_CHAT_MESSAGE_LEN: usize = 120;
+
+/// The number of chat messages that are kept in the lobby.
+const CHAT_HISTORY_LEN: usize = 50;
+
+/// How often the round trip time to the other peers is measured, in seconds.
+const PING_INTERVAL: f64 = 1.0;
+
+/// How much a new round trip time measurement moves the smoothed round trip time.
+const PING_SMOOTHING: f32 = 0.25;
+
+/// The largest input delay that is picked from the ping, in frames. Longer delays make the game
+/// feel unresponsive, so higher latencies are covered by the prediction window instead.
+const MAX_AUTO_INPUT_DELAY: usize = 4;
+
+/// The smallest prediction window that is picked from the ping, in frames, so that short latency
+/// spikes don't stall the match.
+const MIN_AUTO_PREDICTION_WINDOW: usize = 4;
+
+/// A message sent between the peers of a network match before it starts.
+///
+/// Lobby messages are sent as [`ReliableMessage::Lobby`] over the reliable channel shared with the
+/// rest of the game.
+#[derive(Serialize, Deserialize)]
+pub enum LobbyMessage {
+    /// The handshake, sent by every peer before any other message, with whether the peer wants to
+    /// spectate the match. This must stay the first variant, with the compatibility information
+    /// first, so that it can be read by other versions of the game.
+    Hello {
+        compatibility: NetworkCompatibility,
+        spectate: bool,
+    },
+    /// A change in the player selection of the sender.
+    PlayerSelect(PlayerSelectMessage),
+    /// Part of a map sent by the host before the match setup.
+    MapTransfer(MapTransferMessage),
+    /// Sent by the host when it has selected the maps and rules of the match.
+    MatchSetup {
+        maps: MapPoolNetwork,
+        rules: MatchRules,
+    },
+    /// A chat message.
+    Chat(String),
+    /// Whether the sender is ready to start the match.
+    Ready(bool),
+    /// Sent by the host to start the match, with the input delay and prediction window to play with.
+    StartMatch(NetworkMeta),
+    /// Sent periodically to measure the round trip time, with the time it was sent at.
+    Ping(f64),
+    /// The reply to a [`LobbyMessage::Ping`], with the time of the ping.
+    Pong(f64),
+}
+
+impl LobbyMessage {
+    /// Send the message to all of the other peers.
+    pub fn send(self, socket: &NetworkMatchSocket) {
+        socket.send_reliable(SocketTarget::All, &ReliableMessage::Lobby(self).to_bytes());
+    }
+
+    /// Send the message to one of the other peers.
+    pub fn send_to(self, socket: &NetworkMatchSocket, player_idx: u32) {
+        socket.send_reliable(
+            SocketTarget::Player(player_idx),
+            &ReliableMessage::Lobby(self).to_bytes(),
+        );
+    }
+}
+
+/// Information about the game install of a peer, that must match on every peer of a network match.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NetworkCompatibility {
+    pub game_version: String,
+    /// The id and version of each loaded pack that isn't a map pack, sorted.
+    pub packs: Vec<String>,
+    /// A hash of the pack metadata of the loaded packs that aren't map packs.
+    pub pack_hash: u64,
+    /// A hash of the metadata of the maps that aren't from map packs.
+    pub map_hash: u64,
+    /// A hash of the metadata of all of the map elements.
+    pub element_hash: u64,
+}
+
+/// The reason a peer can't play with us.
+#[derive(thiserror::Error, Debug)]
+pub enum IncompatibilityError {
+    #[error("They are running game version {remote}, but we are running {local}.")]
+    GameVersion { local: String, remote: String },
+    #[error("They don't have the pack {0}.")]
+    MissingRemotePack(String),
+    #[error("We don't have their pack {0}.")]
+    MissingLocalPack(String),
+    #[error("Their packs are different from ours.")]
+    Packs,
+    #[error("Their maps are different from ours.")]
+    Maps,
+    #[error("Their map elements are different from ours.")]
+    Elements,
+}
+
+impl NetworkCompatibility {
+    /// Get the compatibility information of the local game install.
+    pub fn new(asset_server: &AssetServer) -> Self {
+        let meta = asset_server.root::<GameMeta>();
+
+        let mut packs = Vec::new();
+        let mut pack_handles = Vec::new();
+        let mut maps = Vec::new();
+        let mut elements = Vec::new();
+        maps.extend(meta.core.stable_maps.iter().copied());
+        maps.extend(meta.core.experimental_maps.iter().copied());
+        elements.extend(meta.core.map_elements.iter().copied());
+        for pack in asset_server.packs() {
+            let pack_handle = pack.root.typed::<PackMeta>();
+            let pack_meta = asset_server.get(pack_handle);
+            if is_map_pack(&pack_meta) {
+                continue;
+            }
+            packs.push(format!("{}@{}", pack.id, pack.version));
+            pack_handles.push(pack_handle);
+            maps.extend(pack_meta.maps.iter().copied());
+            elements.extend(pack_meta.map_elements.iter().copied());
+        }
+        packs.sort();
+
+        Self {
+            game_version: crate::game_version().to_string(),
+            packs,
+            pack_hash: hash_network_handles(asset_server, pack_handles),
+            map_hash: hash_network_handles(asset_server, maps),
+            element_hash: hash_network_handles(asset_server, elements),
+        }
+    }
+
+    /// Check whether a peer with the given compatibility information can play with us.
+    pub fn check(&self, remote: &Self) -> Result<(), IncompatibilityError> {
+        if self.game_version != remote.game_version {
+            return Err(IncompatibilityError::GameVersion {
+                local: self.game_version.clone(),
+                remote: remote.game_version.clone(),
+            });
+        }
+        if let Some(pack) = self.packs.iter().find(|p| !remote.packs.contains(p)) {
+            return Err(IncompatibilityError::MissingRemotePack(pack.clone()));
+        }
+        if let Some(pack) = remote.packs.iter().find(|p| !self.packs.contains(p)) {
+            return Err(IncompatibilityError::MissingLocalPack(pack.clone()));
+        }
+        if self.pack_hash != remote.pack_hash {
+            return Err(IncompatibilityError::Packs);
+        }
+        if self.map_hash != remote.map_hash {
+            return Err(IncompatibilityError::Maps);
+        }
+        if self.element_hash != remote.element_hash {
+            return Err(IncompatibilityError::Elements);
+        }
+        Ok(())
+    }
+}
+
+/// Hash the network handles, which identify the contents of the assets, independent of the order
+/// the assets were loaded in. The hashes are compared between peers that may run on different
+/// platforms, so the hasher must be stable.
+fn hash_network_handles<T: HasSchema>(
+    asset_server: &AssetServer,
+    handles: impl IntoIterator<Item = Handle<T>>,
+) -> u64 {
+    let mut handles = handles
+        .into_iter()
+        .map(|handle| postcard::to_allocvec(&handle.network_handle(asset_server)).unwrap())
+        .collect::<Vec<_>>();
+    handles.sort();
+    let mut hasher = FxHasher64::default();
+    handles.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The state of the lobby, stored in the egui context.
+#[derive(Clone, Default)]
+pub struct LobbyState {
+    /// The maps and rules selected by the host.
+    pub setup: Option<(MapPool, MatchRules)>,
+    /// The chat messages, along with the socket index of the peer that sent them.
+    pub chat: VecDeque<(u32, String)>,
+    /// The message being typed by the local peer.
+    pub chat_input: String,
+    /// Whether each player is ready to start the match.
+    pub ready: [bool; MAX_PLAYERS as usize],
+    /// Set when the host has started the match.
+    pub start: bool,
+    /// The input delay and prediction window picked by the host when it started the match.
+    pub tuning: Option<NetworkMeta>,
+    /// Whether we have sent our handshake.
+    pub hello_sent: bool,
+    /// The compatibility information of the local game install, computed when it is first needed.
+    pub compatibility: Option<NetworkCompatibility>,
+    /// Whether the handshake of each peer has been received and is compatible.
+    pub verified: [bool; MAX_PEERS as usize],
+    /// The match setup message of the host, kept until the maps it uses have been received.
+    pub pending_setup: Option<(MapPoolNetwork, MatchRules)>,
+    /// Why we left the match, if a peer is incompatible with us or a map couldn't be received.
+    pub error: Option<String>,
+    /// The time we last pinged the other peers at.
+    pub last_ping: f64,
+    /// The smoothed round trip time to each peer, in seconds.
+    pub rtt: [Option<f32>; MAX_PEERS as usize],
+}
+
+impl LobbyState {
+    /// The input delay and prediction window picked from the highest round trip time to the peers
+    /// of the GGRS session, or taken from the game metadata if it hasn't been measured yet. The
+    /// spectators after the session don't play in it, so their round trip times don't count.
+    pub fn auto_tuning(&self, meta: &NetworkMeta, session_size: u32) -> NetworkMeta {
+        let rtt = self.rtt.iter().take(session_size as usize).flatten();
+        match rtt.copied().reduce(f32::max) {
+            Some(rtt) => tuning_for_rtt(rtt),
+            None => *meta,
+        }
+    }
+
+    /// The input delay and prediction window to play the match with.
+    ///
+    /// These are the ones sent by the host, or the ones we would pick until the host has started
+    /// the match. Values set in the local settings override them, since the input delay and
+    /// prediction window of each peer don't need to match.
+    pub fn network_tuning(
+        &self,
+        meta: &NetworkMeta,
+        settings: &Settings,
+        session_size: u32,
+    ) -> NetworkMeta {
+        let mut tuning = self
+            .tuning
+            .unwrap_or_else(|| self.auto_tuning(meta, session_size));
+        if let Maybe::Set(delay) = settings.network_input_delay {
+            tuning.local_input_delay = delay as usize;
+        }
+        if let Maybe::Set(window) = settings.network_prediction_window {
+            tuning.max_prediction_window = window as usize;
+        }
+        tuning
+    }
+
+    /// Get the compatibility information of the local game install. The loaded packs that aren't
+    /// map packs don't change while the game is running, so it is only computed once.
+    fn compatibility(&mut self, asset_server: &AssetServer) -> &NetworkCompatibility {
+        self.compatibility
+            .get_or_insert_with(|| NetworkCompatibility::new(asset_server))
+    }
+
+    fn push_chat(&mut self, sender: u32, mut message: String) {
+        if let Some((idx, _)) = message.char_indices().nth(MAX_CHAT_MESSAGE_LEN) {
+            message.truncate(idx);
+        }
+        if self.chat.len() == CHAT_HISTORY_LEN {
+            self.chat.pop_front();
+        }
+        self.chat.push_back((sender, message));
+    }
+
+    /// Leave the match because of the given error.
+    fn leave_with_error(
+        &mut self,
+        ctx: &egui::Context,
+        socket: &NetworkMatchSocket,
+        error: String,
+    ) {
+        self.error = Some(error);
+        socket.close();
+        ctx.set_state(MenuPage::Lobby);
+    }
+
+    /// Apply the maps and rules selected by the host.
+    fn apply_match_setup(
+        &mut self,
+        ctx: &egui::Context,
+        maps: MapPoolNetwork,
+        rules: MatchRules,
+        asset_server: &AssetServer,
+        downloads: &MapDownloads,
+    ) {
+        info!("Match setup message received, moving to the lobby");
+        let maps = MapPool::from_network_with(maps, asset_server, |map| downloads.resolve(map));
+        self.setup = Some((maps, rules));
+        // Play with the rules of the host.
+        ctx.set_state(rules);
+        ctx.set_state(MenuPage::Lobby);
+    }
+}
+
+/// Pick the input delay and prediction window for the given round trip time, in seconds.
+///
+/// The input delay hides up to half of the latency, and the prediction window covers the rest, so
+/// that LAN matches get little delay and high latency matches don't roll back too far.
+fn tuning_for_rtt(rtt: f32) -> NetworkMeta {
+    let latency_frames = (rtt / 2.0 * FPS).ceil() as usize;
+    let local_input_delay = ((latency_frames + 1) / 2).min(MAX_AUTO_INPUT_DELAY);
+    let max_prediction_window = (latency_frames - local_input_delay + 2).clamp(
+        MIN_AUTO_PREDICTION_WINDOW,
+        Settings::MAX_NETWORK_PREDICTION_WINDOW as usize,
+    );
+    NetworkMeta {
+        max_prediction_window,
+        local_input_delay,
+    }
+}
+
+/// Receive the lobby messages of the network match, if any, and apply them to the player select and
+/// lobby states.
+///
+/// This runs on every menu page, so that messages sent while the local peer is on another page of
+/// the match setup aren't lost.
+pub fn handle_lobby_messages(
+    ctx: Res<EguiCtx>,
+    asset_server: Res<AssetServer>,
+    network_socket: Option<Res<NetworkMatchSocket>>,
+    network_spectators: Option<ResMut<NetworkSpectators>>,
+    mut downloads: ResMutInit<MapDownloads>,
+    messages: ResInit<ReliableMessages>,
+) {
+    let (Some(socket), Some(mut spectators)) = (network_socket, network_spectators) else {
+        return;
+    };
+    let mut lobby = ctx.get_state::<LobbyState>();
+
+    // Network play starts in player select, which is where we introduce ourselves.
+    if !lobby.hello_sent && matches!(ctx.get_state::<MenuPage>(), MenuPage::PlayerSelect) {
+        LobbyMessage::Hello {
+            compatibility: lobby.compatibility(&asset_server).clone(),
+            spectate: spectators.is_spectating(&socket),
+        }
+        .send(&socket);
+        lobby.hello_sent = true;
+        ctx.set_state(lobby.clone());
+    }
+
+    // Apply the match setup once the maps it uses have been received and loaded.
+    if lobby.pending_setup.is_some() && !downloads.is_busy(&asset_server) {
+        let (maps, rules) = lobby.pending_setup.take().unwrap();
+        lobby.apply_match_setup(&ctx, maps, rules, &asset_server, &downloads);
+        ctx.set_state(lobby.clone());
+    }
+
+    // Measure the round trip time to the other peers while the match is being set up.
+    let now = ctx.input(|i| i.time);
+    let setting_up = matches!(
+        ctx.get_state::<MenuPage>(),
+        MenuPage::PlayerSelect | MenuPage::MapSelect { .. } | MenuPage::Lobby
+    );
+    if lobby.hello_sent
+        && lobby.error.is_none()
+        && setting_up
+        && now - lobby.last_ping >= PING_INTERVAL
+    {
+        LobbyMessage::Ping(now).send(&socket);
+        lobby.last_ping = now;
+        ctx.set_state(lobby.clone());
+    }
+
+    messages.receive(socket.recv_reliable());
+    let messages = messages.take_lobby();
+    if messages.is_empty() {
+        return;
+    }
+
+    let local_compatibility = lobby.compatibility(&asset_server).clone();
+    let mut player_select = ctx.get_state::<PlayerSelectState>();
+    for (sender, message) in messages {
+        let verified = lobby
+            .verified
+            .get(sender as usize)
+            .copied()
+            .unwrap_or(false);
+
+        // Nothing but the handshake may be trusted until the peer is known to be compatible, since
+        // the asset handles it sends may not exist here.
+        match &message {
+            LobbyMessage::Hello { compatibility, .. } => {
+                if let Err(e) = local_compatibility.check(compatibility) {
+                    warn!("Leaving the network match, peer {sender} is incompatible: {e}");
+                    lobby.leave_with_error(&ctx, &socket, e.to_string());
+                    break;
+                }
+            }
+            _ if !verified => {
+                warn!("Ignoring lobby message from peer {sender} before their handshake");
+                continue;
+            }
+            _ => (),
+        }
+
+        match message {
+            LobbyMessage::Hello { spectate, .. } => {
+                info!("Peer {sender} is compatible, spectating: {spectate}");
+                if let Some(verified) = lobby.verified.get_mut(sender as usize) {
+                    *verified = true;
+                }
+                spectators.set(sender, spectate);
+            }
+            LobbyMessage::PlayerSelect(_) if spectators.is_peer_spectating(sender) => {
+                warn!("Ignoring player selection from peer {sender} who is spectating");
+            }
+            LobbyMessage::PlayerSelect(message) => {
+                player_select.apply_network_message(sender, message, &asset_server);
+            }
+            LobbyMessage::MapTransfer(message) if sender == 0 => {
+                if let Err(e) = downloads.handle_message(message, &asset_server) {
+                    warn!("Leaving the network match, couldn't receive the map: {e}");
+                    lobby.leave_with_error(&ctx, &socket, e.to_string());
+                    break;
+                }
+                ctx.set_state(MenuPage::Lobby);
+            }
+            LobbyMessage::MatchSetup { maps, rules }
+                if sender == 0 && downloads.is_busy(&asset_server) =>
+            {
+                lobby.pending_setup = Some((maps, rules));
+            }
+            LobbyMessage::MatchSetup { maps, rules } if sender == 0 => {
+                lobby.apply_match_setup(&ctx, maps, rules, &asset_server, &downloads);
+            }
+            LobbyMessage::Chat(message) => lobby.push_chat(sender, message),
+            LobbyMessage::Ready(ready) => {
+                if let Some(player_ready) = lobby.ready.get_mut(sender as usize) {
+                    *player_ready = ready;
+                }
+            }
+            LobbyMessage::StartMatch(tuning) if sender == 0 => {
+                lobby.tuning = Some(tuning);
+                lobby.start = true;
+            }
+            LobbyMessage::Ping(time) => LobbyMessage::Pong(time).send_to(&socket, sender),
+            LobbyMessage::Pong(time) => {
+                let sample = (now - time) as f32;
+                if let Some(rtt) = lobby.rtt.get_mut(sender as usize) {
+                    *rtt = Some(match *rtt {
+                        Some(rtt) => rtt + (sample - rtt) * PING_SMOOTHING,
+                        None => sample,
+                    });
+                }
+            }
+            LobbyMessage::MapTransfer(_)
+            | LobbyMessage::MatchSetup { .. }
+            | LobbyMessage::StartMatch(_) => {
+                warn!("Ignoring match setup message from peer {sender} who is not the host");
+            }
+        }
+    }
+    ctx.set_state(player_select);
+    ctx.set_state(lobby);
+}
+
+pub fn widget(
+    mut ui: In<&mut egui::Ui>,
+    world: &World,
+    meta: Root<GameMeta>,
+    localization: Localization<GameMeta>,
+    asset_server: Res<AssetServer>,
+    storage: Res<Storage>,
+    network_socket: Option<Res<NetworkMatchSocket>>,
+    network_spectators: Option<Res<NetworkSpectators>>,
+    downloads: ResInit<MapDownloads>,
+) {
+    let Some(socket) = network_socket else {
+        // The lobby is only used in network matches.
+        ui.ctx().set_state(MenuPage::Home);
+        return;
+    };
+    let network_spectators = network_spectators.map(|x| *x).unwrap_or_default();
+    let session_size = network_spectators.session_size(&socket);
+    let players = (0..session_size)
+        .filter(|idx| !network_spectators.is_peer_spectating(*idx))
+        .collect::<Vec<_>>();
+    let local_idx = socket.player_idx();
+    let is_host = local_idx == 0;
+    let is_spectating = network_spectators.is_spectating(&socket);
+
+    // Keyboard input is needed for typing chat messages.
+    ui.ctx().set_state(EguiInputSettings::default());
+
+    let mut lobby = ui.ctx().get_state::<LobbyState>();
+    let player_select = ui.ctx().get_state::<PlayerSelectState>();
+
+    // We left the match because of an incompatible peer or a failed map transfer.
+    if let Some(error) = &lobby.error {
+        let heading_text_style = &meta
+            .theme
+            .font_styles
+            .heading
+            .with_color(meta.theme.panel.font_color);
+        let normal_text_style = &meta
+            .theme
+            .font_styles
+            .normal
+            .with_color(meta.theme.panel.font_color);
+        ui.vertical_centered(|ui| {
+            ui.add_space(heading_text_style.size);
+            ui.label(heading_text_style.rich(localization.get("lobby-left-match")));
+            ui.add_space(normal_text_style.size);
+            ui.label(normal_text_style.rich(error.as_str()));
+            ui.add_space(normal_text_style.size);
+            if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("lobby-leave"))
+                .show(ui)
+                .focus_by_default(ui)
+                .clicked()
+            {
+                ui.ctx().set_state(MenuPage::Home);
+                ui.ctx().set_state(PlayerSelectState::default());
+                ui.ctx().set_state(LobbyState::default());
+            }
+        });
+        return;
+    }
+
+    // The host has started the match.
+    if lobby.start {
+        let Some((maps, _)) = lobby.setup.clone() else {
+            warn!("The match was started before its maps were received");
+            lobby.start = false;
+            ui.ctx().set_state(lobby);
+            return;
+        };
+        world.run_system(start_match, (ui.ctx(), maps));
+        return;
+    }
+
+    // Whether each peer spectates is only known once its handshake has been received.
+    let all_verified = (0..socket.player_count())
+        .filter(|idx| *idx != local_idx)
+        .all(|idx| lobby.verified.get(idx as usize).copied().unwrap_or(false));
+    let all_ready = all_verified
+        && players
+            .iter()
+            .map(|idx| *idx as usize)
+            .filter(|i| !player_select.slots[*i].is_empty())
+            .all(|i| lobby.ready[i]);
+
+    let heading_text_style = &meta
+        .theme
+        .font_styles
+        .heading
+        .with_color(meta.theme.panel.font_color);
+    let bigger_text_style = &meta
+        .theme
+        .font_styles
+        .bigger
+        .with_color(meta.theme.panel.font_color);
+    let normal_text_style = &meta
+        .theme
+        .font_styles
+        .normal
+        .with_color(meta.theme.panel.font_color);
+    let smaller_text_style = &meta
+        .theme
+        .font_styles
+        .smaller
+        .with_color(meta.theme.panel.font_color);
+    let normal_button_style = &meta.theme.buttons.normal;
+    let small_button_style = &meta.theme.buttons.small;
+
+    // Players are named after their player slot, and spectators in the order they joined in.
+    let peer_name = |idx: u32| {
+        if network_spectators.is_peer_spectating(idx) {
+            let spectator = (0..=idx)
+                .filter(|idx| network_spectators.is_peer_spectating(*idx))
+                .count();
+            localization
+                .get_with(
+                    "lobby-spectator",
+                    &fluent_args! { "spectator" => spectator },
+                )
+                .to_string()
+        } else {
+            localization
+                .get_with("lobby-player", &fluent_args! { "player" => idx + 1 })
+                .to_string()
+        }
+    };
+
+    ui.vertical_centered(|ui| {
+        ui.add_space(heading_text_style.size / 4.0);
+        ui.label(heading_text_style.rich(localization.get("lobby")));
+        if is_spectating && network_spectators.is_relayed(&socket) {
+            ui.label(normal_text_style.rich(localization.get("spectating-match")));
+        } else if is_spectating {
+            ui.label(normal_text_style.rich(localization.get("spectating-in-session")));
+        }
+        ui.add_space(normal_text_style.size);
+
+        // The players, with their fish and whether they are ready.
+        ui.columns(players.len().max(1), |columns| {
+            for (&idx, ui) in players.iter().zip(columns.iter_mut()) {
+                let i = idx as usize;
+                let slot = player_select.slots[i];
+                let Some(player_handle) = slot.selected_player() else {
+                    continue;
+                };
+                let player_meta = asset_server.get(player_handle);
+                let hat_meta = slot.selected_hat().map(|h| asset_server.get(h));
+
+                ui.vertical_centered(|ui| {
+                    ui.label(normal_text_style.rich(peer_name(i as u32)));
+                    if i as u32 == local_idx {
+                        ui.label(smaller_text_style.rich(localization.get("you-marker")));
+                    }
+                    let ready = if lobby.ready[i] {
+                        localization.get("lobby-ready")
+                    } else {
+                        localization.get("lobby-not-ready")
+                    };
+                    ui.label(smaller_text_style.rich(ready.to_string()));
+                    if let Some(rtt) = lobby.rtt[i] {
+                        ui.label(smaller_text_style.rich(localization.get_with(
+                            "lobby-ping",
+                            &fluent_args! { "ping" => (rtt * 1000.0).round() as u32 },
+                        )));
+                    }
+                    ui.label(smaller_text_style.rich(player_meta.name.as_str()));
+                    ui.set_max_width(ui.available_width().min(normal_text_style.size * 6.0));
+                    world.run_system(player_image, (ui, &player_meta, hat_meta.as_deref()));
+                });
+            }
+        });
+        ui.add_space(normal_text_style.size / 2.0);
+
+        // The input delay and prediction window the match will be played with.
+        let tuning = lobby.network_tuning(
+            &meta.network,
+            storage.get::<Settings>().unwrap(),
+            session_size,
+        );
+        ui.label(smaller_text_style.rich(localization.get_with(
+            "lobby-network-tuning",
+            &fluent_args! {
+                "delay" => tuning.local_input_delay,
+                "window" => tuning.max_prediction_window
+            },
+        )));
+        ui.add_space(normal_text_style.size);
+
+        // The progress of the map that is being received from the host.
+        if let Some(progress) = downloads.progress() {
+            ui.label(normal_text_style.rich(localization.get_with(
+                "lobby-downloading-map",
+                &fluent_args! { "progress" => (progress * 100.0).round() as u32 },
+            )));
+            ui.add_space(normal_text_style.size / 2.0);
+        }
+
+        // The maps and rules selected by the host.
+        if let Some((maps, rules)) = &lobby.setup {
+            let map_names = maps
+                .maps
+                .iter()
+                .map(|map| asset_server.get(*map).name.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            ui.label(bigger_text_style.rich(localization.get("lobby-maps")));
+            ui.label(normal_text_style.rich(map_names));
+            ui.add_space(normal_text_style.size / 2.0);
+
+            ui.label(bigger_text_style.rich(localization.get("match-rules")));
+            let time_limit = match rules.time_limit_secs {
+                0 => localization.get("no-time-limit").to_string(),
+                secs => format!("{}:{:02}", secs / 60, secs % 60),
+            };
+            let on_off = |on: bool| localization.get(if on { "on" } else { "off" }).to_string();
+            for (label, value) in [
+                (localization.get("lives"), rules.lives.to_string()),
+                (localization.get("time-limit"), time_limit),
+                (localization.get("sudden-death"), on_off(rules.sudden_death)),
+                (
+                    localization.get("friendly-fire"),
+                    on_off(rules.friendly_fire),
+                ),
+                (localization.get("teams"), on_off(player_select.team_mode)),
+            ] {
+                ui.label(normal_text_style.rich(format!("{label}: {value}")));
+            }
+        }
+        ui.add_space(normal_text_style.size);
+
+        // Chat
+        ui.label(bigger_text_style.rich(localization.get("lobby-chat")));
+        let chat_width = meta.main_menu.menu_width;
+        ui.allocate_ui(egui::vec2(chat_width, normal_text_style.size * 8.0), |ui| {
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .auto_shrink([false, false])
+                .show(ui, |ui| {
+                    for (sender, message) in &lobby.chat {
+                        ui.label(
+                            smaller_text_style.rich(format!("{}: {message}", peer_name(*sender))),
+                        );
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.add_space((ui.available_width() - chat_width).max(0.0) / 2.0);
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut lobby.chat_input)
+                    .char_limit(MAX_CHAT_MESSAGE_LEN)
+                    .font(normal_text_style.id())
+                    .desired_width(chat_width - normal_text_style.size * 5.0),
+            );
+            let send_button =
+                BorderedButton::themed(small_button_style, localization.get("lobby-send")).show(ui);
+            let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if (send_button.clicked() || submitted) && !lobby.chat_input.trim().is_empty() {
+                let message = std::mem::take(&mut lobby.chat_input);
+                LobbyMessage::Chat(message.clone()).send(&socket);
+                lobby.push_chat(local_idx, message);
+                if submitted {
+                    input.request_focus();
+                }
+            }
+        });
+
+        ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
+            ui.add_space(normal_button_style.font.size * 2.0);
+            ui.horizontal(|ui| {
+                let width = ui.available_width();
+                let button_width = width / 4.0;
+                let button_min_size = vec2(button_width, 0.0);
+                let button_spacing = (width - 3.0 * button_width) / 4.0;
+
+                ui.add_space(button_spacing);
+
+                // Leave button
+                if BorderedButton::themed(normal_button_style, localization.get("lobby-leave"))
+                    .min_size(button_min_size)
+                    .show(ui)
+                    .clicked()
+                {
+                    socket.close();
+                    ui.ctx().set_state(MenuPage::Home);
+                    ui.ctx().set_state(PlayerSelectState::default());
+                    lobby = default();
+                }
+
+                ui.add_space(button_spacing);
+
+                // Ready button, spectators don't need to be ready.
+                let local_ready = lobby.ready.get(local_idx as usize).copied();
+                let ready_button = ui
+                    .scope(|ui| {
+                        ui.set_enabled(!is_spectating);
+                        let label = if local_ready == Some(true) {
+                            localization.get("lobby-unready")
+                        } else {
+                            localization.get("lobby-ready")
+                        };
+                        BorderedButton::themed(normal_button_style, label)
+                            .min_size(button_min_size)
+                            .show(ui)
+                    })
+                    .inner;
+                if ready_button.clicked() && !is_spectating {
+                    let ready = local_ready != Some(true);
+                    lobby.ready[local_idx as usize] = ready;
+                    LobbyMessage::Ready(ready).send(&socket);
+                }
+
+                ui.add_space(button_spacing);
+
+                // Start button, only the host may start the match.
+                if is_host {
+                    let start_button = ui
+                        .scope(|ui| {
+                            ui.set_enabled(all_ready && lobby.setup.is_some());
+                            BorderedButton::themed(
+                                normal_button_style,
+                                localization.get("lobby-start-match"),
+                            )
+                            .min_size(button_min_size)
+                            .show(ui)
+                        })
+                        .inner;
+                    if start_button.clicked() {
+                        let tuning = lobby.auto_tuning(&meta.network, session_size);
+                        LobbyMessage::StartMatch(tuning).send(&socket);
+                        lobby.tuning = Some(tuning);
+                        lobby.start = true;
+                    }
+                } else {
+                    ui.add_sized(
+                        egui::vec2(button_width, normal_button_style.font.size),
+                        egui::Label::new(
+                            smaller_text_style.rich(localization.get("lobby-waiting-for-host")),
+                        ),
+                    );
+                }
+            });
+        });
+    });
+
+    ui.ctx().set_state(lobby);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tuning_covers_the_latency_with_delay_then_prediction() {
+        let tuning = |rtt| {
+            let tuning = tuning_for_rtt(rtt);
+            (tuning.local_input_delay, tuning.max_prediction_window)
+        };
+        // The prediction window never goes below the minimum, even on a LAN.
+        assert_eq!(tuning(0.0), (0, MIN_AUTO_PREDICTION_WINDOW));
+        assert_eq!(tuning(0.04), (1, MIN_AUTO_PREDICTION_WINDOW));
+        // Half of the latency is hidden by the input delay.
+        assert_eq!(tuning(0.25), (4, 6));
+        // The input delay stays responsive, and the prediction window covers the rest.
+        assert_eq!(tuning(0.5), (MAX_AUTO_INPUT_DELAY, 13));
+        assert_eq!(
+            tuning(1.0),
+            (
+                MAX_AUTO_INPUT_DELAY,
+                Settings::MAX_NETWORK_PREDICTION_WINDOW as usize
+            )
+        );
+    }
+
+    fn compatibility() -> NetworkCompatibility {
+        NetworkCompatibility {
+            game_version: "0.8.0".into(),
+            packs: vec!["core@0.8.0".into(), "weapons@1.0.0".into()],
+            pack_hash: 1,
+            map_hash: 2,
+            element_hash: 3,
+        }
+    }
+
+    #[test]
+    fn identical_installs_are_compatible() {
+        assert!(compatibility().check(&compatibility()).is_ok());
+    }
+
+    #[test]
+    fn game_version_must_match() {
+        let remote = NetworkCompatibility {
+            game_version: "0.9.0".into(),
+            ..compatibility()
+        };
+        assert!(matches!(
+            compatibility().check(&remote),
+            Err(IncompatibilityError::GameVersion { local, remote })
+                if local == "0.8.0" && remote == "0.9.0"
+        ));
+    }
+
+    #[test]
+    fn packs_must_be_loaded_on_both_sides() {
+        let mut remote = compatibility();
+        remote.packs.pop();
+        assert!(matches!(
+            compatibility().check(&remote),
+            Err(IncompatibilityError::MissingRemotePack(pack)) if pack == "weapons@1.0.0"
+        ));
+        assert!(matches!(
+            remote.check(&compatibility()),
+            Err(IncompatibilityError::MissingLocalPack(pack)) if pack == "weapons@1.0.0"
+        ));
+
+        // A different version of a pack is missing on both sides.
+        remote.packs.push("weapons@1.1.0".into());
+        assert!(matches!(
+            compatibility().check(&remote),
+            Err(IncompatibilityError::MissingRemotePack(pack)) if pack == "weapons@1.0.0"
+        ));
+    }
+
+    #[test]
+    fn hashes_must_match() {
+        let local = compatibility();
+        let check = |remote: NetworkCompatibility| local.check(&remote);
+        assert!(matches!(
+            check(NetworkCompatibility {
+                pack_hash: 10,
+                ..compatibility()
+            }),
+            Err(IncompatibilityError::Packs)
+        ));
+        assert!(matches!(
+            check(NetworkCompatibility {
+                map_hash: 20,
+                ..compatibility()
+            }),
+            Err(IncompatibilityError::Maps)
+        ));
+        assert!(matches!(
+            check(NetworkCompatibility {
+                element_hash: 30,
+                ..compatibility()
+            }),
+            Err(IncompatibilityError::Elements)
+        ));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::core::{JumpyDefaultMatchRunner, MatchPlugin};
+use crate::prelude::*;
+
+use crate::ui::editor::open_map_editor;
+use crate::ui::map_select::{map_select_menu, MapSelectAction};
+
+use super::MenuPage;
+
+/// Pick the map to open in the map editor.
+pub fn widget(ui: In<&mut egui::Ui>, world: &World) {
+    match world.run_system(map_select_menu, ()) {
+        MapSelectAction::None => (),
+        MapSelectAction::SelectMap(maps) => world.run_system(start_editor, (ui.ctx(), maps)),
+        MapSelectAction::GoBack => ui.ctx().set_state(MenuPage::Home),
+    }
+}
+
+/// Start a match on the given map with a single player, so that the map can be tried out by
+/// closing the editor, and open the map editor on top of it.
+fn start_editor(
+    input: In<(&egui::Context, MapPool)>,
+    meta: Root<GameMeta>,
+    mut sessions: ResMut<Sessions>,
+    mut session_options: ResMut<SessionOptions>,
+    assets: Res<AssetServer>,
+) {
+    let ctx = input.0;
+    let maps = input.1.clone();
+
+    session_options.delete = true;
+    ctx.set_state(MenuPage::Home);
+
+    sessions.start_game(MatchPlugin {
+        maps,
+        player_info: std::array::from_fn(|i| PlayerInput {
+            active: i == 0,
+            selected_player: meta.core.players[0],
+            control_source: (i == 0).then_some(ControlSource::Keyboard1),
+            ..default()
+        }),
+        plugins: meta.get_plugins(&assets),
+        session_runner: Box::<JumpyDefaultMatchRunner>::default(),
+        score: default(),
+        // A round with a single player never ends.
+        rules: default(),
+        random_seed: DEFAULT_RANDOM_SEED as u64,
+    });
+    open_map_editor(&mut sessions);
+}
@@ -1,43 +1,33 @@
 use crate::core::{JumpyDefaultMatchRunner, MatchPlugin};
 use crate::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::settings::Settings;
 
 use crate::ui::map_select::{map_select_menu, MapSelectAction};
 
 #[cfg(not(target_arch = "wasm32"))]
-use crate::ui::network_game::NetworkGameState;
+use crate::spectate::{SpectatorRelay, SpectatorRunner};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ui::network_game::{NetworkGameState, NetworkSpectators};
 
+#[cfg(not(target_arch = "wasm32"))]
+use super::lobby::{LobbyMessage, LobbyState};
+#[cfg(not(target_arch = "wasm32"))]
+use super::map_transfer::send_maps;
 use super::player_select::PlayerSelectState;
 use super::MenuPage;
 
 #[cfg(not(target_arch = "wasm32"))]
 use bones_framework::networking::{GgrsSessionRunner, GgrsSessionRunnerInfo, NetworkMatchSocket};
 
-/// Network message that may be sent when selecting a map.
-#[derive(Serialize, Deserialize)]
-pub enum MapSelectMessage {
-    SelectMap(MapPoolNetwork),
-}
-
 pub fn widget(
     ui: In<&mut egui::Ui>,
     world: &World,
-    meta: Root<GameMeta>,
-    mut sessions: ResMut<Sessions>,
-    mut session_options: ResMut<SessionOptions>,
-    assets: Res<AssetServer>,
-
+    #[cfg(not(target_arch = "wasm32"))] assets: Res<AssetServer>,
     #[cfg(not(target_arch = "wasm32"))] network_socket: Option<Res<NetworkMatchSocket>>,
 ) {
     let mut select_action = MapSelectAction::None;
 
-    // Get map select action from network
-    #[cfg(not(target_arch = "wasm32"))]
-    if let Some(MapSelectAction::SelectMap(map_meta)) =
-        handle_match_setup_messages(&network_socket, &assets)
-    {
-        select_action = MapSelectAction::SelectMap(map_meta);
-    }
-
     // If the `TEST_MAP` debug env var is present start the game with the map
     // matching the provided name.
     #[cfg(debug_assertions)]
@@ -87,120 +77,150 @@ pub fn widget(
         }
     }
 
-    // If no network action - update action from UI
+    // If no debug action - update action from UI
     if matches!(select_action, MapSelectAction::None) {
         select_action = world.run_system(map_select_menu, ());
-
-        #[cfg(not(target_arch = "wasm32"))]
-        // Replicate local action
-        replicate_map_select_action(&select_action, &network_socket, &assets);
     }
 
     match select_action {
         MapSelectAction::None => (),
         MapSelectAction::SelectMap(maps) => {
-            session_options.delete = true;
-            ui.ctx().set_state(MenuPage::Home);
-
+            // In network play, the maps and rules are sent to the other players and everyone moves
+            // to the lobby, where the host starts the match once every player is ready.
             #[cfg(not(target_arch = "wasm32"))]
-            let session_runner: Box<dyn SessionRunner> = match network_socket {
-                Some(socket) => {
-                    let random_seed = ui.ctx().get_state::<NetworkGameState>().random_seed();
-
-                    Box::new(GgrsSessionRunner::<NetworkInputConfig>::new(
-                        Some(FPS),
-                        GgrsSessionRunnerInfo::new(
-                            socket.ggrs_socket(),
-                            Some(meta.network.max_prediction_window),
-                            Some(meta.network.local_input_delay),
-                            random_seed,
-                        ),
-                    ))
+            if let Some(socket) = network_socket.as_ref() {
+                // Maps from map packs are sent first, in case the other players don't have them.
+                send_maps(&maps, &assets, socket);
+
+                info!("Sending network match setup message.");
+                let rules = ui.ctx().get_state::<MatchRules>();
+                LobbyMessage::MatchSetup {
+                    maps: maps.into_network(&assets),
+                    rules,
                 }
-                None => Box::<JumpyDefaultMatchRunner>::default(),
-            };
-            #[cfg(target_arch = "wasm32")]
-            let session_runner = Box::<JumpyDefaultMatchRunner>::default();
-
-            let player_select_state = ui.ctx().get_state::<PlayerSelectState>();
-            sessions.start_game(MatchPlugin {
-                maps,
-                player_info: std::array::from_fn(|i| {
-                    let slot = player_select_state.slots[i];
-
-                    PlayerInput {
-                        active: !slot.is_empty(),
-                        selected_player: slot
-                            .selected_player()
-                            .unwrap_or(player_select_state.players[0]),
-                        selected_hat: slot.selected_hat(),
-                        control_source: slot.user_control_source(),
-                        editor_input: default(),
-                        control: default(),
-                        is_ai: slot.is_ai(),
-                    }
-                }),
-                plugins: meta.get_plugins(&assets),
-                session_runner,
-                score: default(),
-            });
-            ui.ctx().set_state(PlayerSelectState::default());
+                .send(socket);
+
+                let mut lobby = ui.ctx().get_state::<LobbyState>();
+                lobby.setup = Some((maps, rules));
+                ui.ctx().set_state(lobby);
+                ui.ctx().set_state(MenuPage::Lobby);
+                return;
+            }
+
+            world.run_system(start_match, (ui.ctx(), maps));
         }
         MapSelectAction::GoBack => ui.ctx().set_state(MenuPage::PlayerSelect),
     }
 }
 
-/// Send a MapSelectMessage over network if local player has selected a map.
-#[cfg(not(target_arch = "wasm32"))]
-fn replicate_map_select_action(
-    action: &MapSelectAction,
-    socket: &Option<Res<NetworkMatchSocket>>,
-    asset_server: &AssetServer,
+/// Start the match with the players from player select, on the given maps and with the selected
+/// match rules.
+pub(super) fn start_match(
+    input: In<(&egui::Context, MapPool)>,
+    meta: Root<GameMeta>,
+    mut sessions: ResMut<Sessions>,
+    mut session_options: ResMut<SessionOptions>,
+    assets: Res<AssetServer>,
+    #[cfg(not(target_arch = "wasm32"))] storage: Res<Storage>,
+    #[cfg(not(target_arch = "wasm32"))] network_socket: Option<Res<NetworkMatchSocket>>,
+    #[cfg(not(target_arch = "wasm32"))] network_spectators: Option<Res<NetworkSpectators>>,
 ) {
-    use bones_framework::networking::SocketTarget;
-    if let Some(socket) = socket {
-        if let MapSelectAction::SelectMap(maps) = action {
-            info!("Sending network SelectMap message.");
-            socket.send_reliable(
-                SocketTarget::All,
-                &postcard::to_allocvec(&MapSelectMessage::SelectMap(
-                    maps.into_network(asset_server),
-                ))
-                .unwrap(),
-            );
-        }
-    }
-}
+    let ctx = input.0;
+    let maps = input.1.clone();
 
-#[cfg(not(target_arch = "wasm32"))]
-fn handle_match_setup_messages(
-    socket: &Option<Res<NetworkMatchSocket>>,
-    asset_server: &AssetServer,
-) -> Option<MapSelectAction> {
-    if let Some(socket) = socket {
-        let datas: Vec<(u32, Vec<u8>)> = socket.recv_reliable();
-
-        for (_player, data) in datas {
-            match postcard::from_bytes::<MapSelectMessage>(&data) {
-                Ok(message) => match message {
-                    MapSelectMessage::SelectMap(maps) => {
-                        info!("Map select message received, starting game");
-
-                        return Some(MapSelectAction::SelectMap(MapPool::from_network(
-                            maps,
-                            asset_server,
-                        )));
-                    }
-                },
-                Err(e) => {
-                    // TODO: The second player in an online match is having this triggered by
-                    // picking up a `ConfirmSelection` message, that might have been sent to
-                    // _itself_.
-                    warn!("Ignoring network message that was not understood: {e} data: {data:?}");
-                }
+    session_options.delete = true;
+    ctx.set_state(MenuPage::Home);
+
+    // Network matches are played with the seed given to every peer by the matchmaker.
+    #[cfg(not(target_arch = "wasm32"))]
+    let random_seed = match network_socket {
+        Some(_) => ctx.get_state::<NetworkGameState>().random_seed(),
+        None => DEFAULT_RANDOM_SEED as u64,
+    };
+    #[cfg(target_arch = "wasm32")]
+    let random_seed = DEFAULT_RANDOM_SEED as u64;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let session_runner: Box<dyn SessionRunner> = match network_socket {
+        Some(socket)
+            if network_spectators
+                .as_ref()
+                .is_some_and(|s| s.is_relayed(&socket)) =>
+        {
+            info!("Spectating network match");
+            Box::new(SpectatorRunner::new(socket.ggrs_socket()))
+        }
+        Some(socket) => {
+            // Spectators after the last player aren't part of the GGRS session, so that the players
+            // don't wait for them.
+            let spectators = network_spectators.map(|s| *s).unwrap_or_default();
+            let session_size = spectators.session_size(&socket);
+
+            let tuning = ctx.get_state::<LobbyState>().network_tuning(
+                &meta.network,
+                storage.get::<Settings>().unwrap(),
+                session_size,
+            );
+            info!(
+                "Starting network match with {} frames of input delay and a prediction window of {} frames",
+                tuning.local_input_delay, tuning.max_prediction_window
+            );
+            let mut info = GgrsSessionRunnerInfo::new(
+                socket.ggrs_socket(),
+                Some(tuning.max_prediction_window),
+                Some(tuning.local_input_delay),
+                random_seed,
+            );
+            info.player_count = session_size;
+            let runner = Box::new(GgrsSessionRunner::<NetworkInputConfig>::new(
+                Some(FPS),
+                info,
+            ));
+
+            // The host relays the confirmed inputs to the spectators.
+            if socket.player_idx() == 0 && session_size < socket.player_count() {
+                Box::new(SpectatorRelay::new(
+                    runner,
+                    session_size..socket.player_count(),
+                ))
+            } else {
+                runner
             }
         }
-    }
-
-    None
+        None => Box::<JumpyDefaultMatchRunner>::default(),
+    };
+    #[cfg(target_arch = "wasm32")]
+    let session_runner = Box::<JumpyDefaultMatchRunner>::default();
+
+    let player_select_state = ctx.get_state::<PlayerSelectState>();
+    sessions.start_game(MatchPlugin {
+        maps,
+        player_info: std::array::from_fn(|i| {
+            let slot = player_select_state.slots[i];
+
+            PlayerInput {
+                active: !slot.is_empty(),
+                selected_player: slot
+                    .selected_player()
+                    .unwrap_or(player_select_state.players[0]),
+                selected_hat: slot.selected_hat(),
+                control_source: slot.user_control_source(),
+                editor_input: default(),
+                control: default(),
+                is_ai: slot.is_ai(),
+                ai_difficulty: slot.ai_difficulty().unwrap_or_default(),
+                ai_behavior: slot.ai_behavior(),
+                team: (player_select_state.team_mode && !slot.is_empty())
+                    .then_some(player_select_state.teams[i]),
+            }
+        }),
+        plugins: meta.get_plugins(&assets),
+        session_runner,
+        score: default(),
+        rules: ctx.get_state::<MatchRules>(),
+        random_seed,
+    });
+    ctx.set_state(PlayerSelectState::default());
+    #[cfg(not(target_arch = "wasm32"))]
+    ctx.set_state(LobbyState::default());
 }
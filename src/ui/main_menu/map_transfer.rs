@@ -0,0 +1,584 @@
+//! Transfer of maps from the host of a network match to the other peers.
+//!
+//! When the host picks a map from a pack that only contains maps, the map file, its tileset atlases
+//! and the images they use are sent to the other peers in chunks, before the match setup. The peers
+//! write them into a temporary pack in the packs directory and load the map from there, so players
+//! don't need to install the same map packs before playing together.
+//!
+//! Only maps that use core elements can be transferred, since elements may come with scripts and
+//! other assets.
+
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path};
+
+use bones_framework::networking::NetworkMatchSocket;
+use fxhash::FxHasher64;
+
+use super::lobby::LobbyMessage;
+use crate::{
+    pack_paths::{find_pack_dir, parent_dir},
+    prelude::*,
+    PackMeta,
+};
+
+/// The size of the pieces the files of a map are sent in.
+const TRANSFER_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The largest file that may be transferred.
+const MAX_TRANSFER_FILE_SIZE: u32 = 16 * 1024 * 1024;
+
+/// The largest total size of the files of a map that may be transferred.
+const MAX_TRANSFER_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The most files a map that is transferred may have.
+const MAX_TRANSFER_FILES: usize = 256;
+
+/// The prefix of the folder names of the temporary packs that transferred maps are written to.
+pub const NETWORK_MAP_PACK_PREFIX: &str = ".network-map-";
+
+/// A message that is part of a map transfer.
+#[derive(Serialize, Deserialize)]
+pub enum MapTransferMessage {
+    /// Sent before the contents of the files of a map.
+    Start {
+        /// The id of the pack the map is from.
+        pack: String,
+        /// The handle of the map on the host.
+        map: NetworkHandle<MapMeta>,
+        /// The path of the map file, relative to the pack.
+        map_path: String,
+        /// The path, relative to the pack, and the size of each file.
+        files: Vec<(String, u32)>,
+    },
+    /// A piece of one of the files announced by the last [`MapTransferMessage::Start`].
+    Chunk {
+        /// The index of the file in the announced files.
+        file: u32,
+        /// The position of the piece in the file.
+        offset: u32,
+        data: Vec<u8>,
+    },
+}
+
+/// Errors that may occur while transferring a map.
+#[derive(thiserror::Error, Debug)]
+pub enum MapTransferError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Pack not found in the packs directory: {0}")]
+    PackNotFound(String),
+    #[error("Map not found in its pack: {0}")]
+    MapNotFound(String),
+    #[error("Invalid asset path: {0}")]
+    InvalidPath(String),
+    #[error("The map uses the element {0} from its pack, which can't be transferred")]
+    PackElement(String),
+    #[error("The file {0} is too large to be transferred")]
+    TooLarge(String),
+    #[error("The map is too large to be transferred: {0} bytes")]
+    MapTooLarge(u64),
+    #[error("The map has too many files to be transferred: {0}")]
+    TooManyFiles(usize),
+    #[error("Received more map data than was announced")]
+    UnexpectedData,
+    #[error("Received a piece of the map out of order")]
+    OutOfOrder,
+}
+
+/// Whether the pack only contains maps and tilesets, so that its maps may be transferred to peers
+/// that don't have it.
+pub fn is_map_pack(pack_meta: &PackMeta) -> bool {
+    pack_meta.plugins.is_empty()
+        && pack_meta.players.is_empty()
+        && pack_meta.player_hats.is_empty()
+        && pack_meta.ai_behaviors.is_empty()
+        && pack_meta.map_elements.is_empty()
+}
+
+/// Remove the maps transferred in previous network matches from the packs directory.
+pub fn remove_transferred_maps(packs_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(packs_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(NETWORK_MAP_PACK_PREFIX)
+        {
+            if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+                warn!("Couldn't remove transferred maps {:?}: {e}", entry.path());
+            }
+        }
+    }
+}
+
+/// Send the maps of the pool that come from map packs to the other peers.
+pub fn send_maps(maps: &MapPool, asset_server: &AssetServer, socket: &NetworkMatchSocket) {
+    let mut sent = Vec::new();
+    for map in maps.maps.iter().copied() {
+        if sent.contains(&map) {
+            continue;
+        }
+        sent.push(map);
+
+        for pack in asset_server.packs() {
+            let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
+            if !is_map_pack(&pack_meta) || !pack_meta.maps.iter().any(|m| *m == map) {
+                continue;
+            }
+            let pack_id = pack.id.to_string();
+            let map_name = asset_server.get(map).name;
+            match map_files(&crate::packs_dir(), &pack_id, &map_name) {
+                Ok((map_path, files)) => {
+                    info!("Sending map {map_name} to the other players");
+                    LobbyMessage::MapTransfer(MapTransferMessage::Start {
+                        pack: pack_id,
+                        map: map.network_handle(asset_server),
+                        map_path,
+                        files: files
+                            .iter()
+                            .map(|(path, data)| (path.clone(), data.len() as u32))
+                            .collect(),
+                    })
+                    .send(socket);
+                    for (file, (_, data)) in files.iter().enumerate() {
+                        for (i, chunk) in data.chunks(TRANSFER_CHUNK_SIZE).enumerate() {
+                            LobbyMessage::MapTransfer(MapTransferMessage::Chunk {
+                                file: file as u32,
+                                offset: (i * TRANSFER_CHUNK_SIZE) as u32,
+                                data: chunk.to_vec(),
+                            })
+                            .send(socket);
+                        }
+                    }
+                }
+                Err(e) => warn!("Couldn't send map {map_name} to the other players: {e}"),
+            }
+        }
+    }
+}
+
+/// Read the files of the map with the given name from the pack with the given id: the map file,
+/// and the tileset atlases and images it uses. Returns the path of the map file and the path and
+/// contents of every file, relative to the pack.
+fn map_files(
+    packs_dir: &Path,
+    pack_id: &str,
+    map_name: &str,
+) -> Result<(String, Vec<(String, Vec<u8>)>), MapTransferError> {
+    let pack_dir = find_pack_dir(packs_dir, pack_id)
+        .ok_or_else(|| MapTransferError::PackNotFound(pack_id.into()))?;
+    let read_yaml = |path: &str| -> Result<serde_yaml::Value, MapTransferError> {
+        Ok(serde_yaml::from_slice(&std::fs::read(
+            pack_dir.join(path),
+        )?)?)
+    };
+
+    // Find the map among the maps of the pack.
+    let pack_yaml = read_yaml("pack.yaml")?;
+    let root = pack_yaml["root"].as_str().unwrap_or_default();
+    let root = resolve_path("", root)?.ok_or_else(|| MapTransferError::InvalidPath(root.into()))?;
+    let root_yaml = read_yaml(&root)?;
+    let mut map_path = None;
+    for path in root_yaml["maps"].as_sequence().into_iter().flatten() {
+        let Some(path) = path.as_str() else {
+            continue;
+        };
+        let Some(path) = resolve_path(parent_dir(&root), path)? else {
+            continue;
+        };
+        if read_yaml(&path)?["name"].as_str() == Some(map_name) {
+            map_path = Some(path);
+            break;
+        }
+    }
+    let map_path = map_path.ok_or_else(|| MapTransferError::MapNotFound(map_name.into()))?;
+    let map_yaml = read_yaml(&map_path)?;
+    let map_dir = parent_dir(&map_path);
+
+    let mut paths = vec![map_path.clone()];
+    for layer in map_yaml["background"]["layers"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+    {
+        if let Some(image) = layer["image"].as_str() {
+            paths.extend(resolve_path(map_dir, image)?);
+        }
+    }
+    for layer in map_yaml["layers"].as_sequence().into_iter().flatten() {
+        for element in layer["elements"].as_sequence().into_iter().flatten() {
+            let element = element["element"].as_str().unwrap_or_default();
+            if resolve_path(map_dir, element)?.is_some() {
+                return Err(MapTransferError::PackElement(element.into()));
+            }
+        }
+        let Some(tilemap) = layer["tilemap"].as_str() else {
+            continue;
+        };
+        let Some(atlas_path) = resolve_path(map_dir, tilemap)? else {
+            continue;
+        };
+        let atlas_yaml = read_yaml(&atlas_path)?;
+        if let Some(image) = atlas_yaml["image"].as_str() {
+            paths.extend(resolve_path(parent_dir(&atlas_path), image)?);
+        }
+        paths.push(atlas_path);
+    }
+    paths.sort();
+    paths.dedup();
+
+    let files = paths
+        .into_iter()
+        .map(|path| {
+            let data = std::fs::read(pack_dir.join(&path))?;
+            if data.len() > MAX_TRANSFER_FILE_SIZE as usize {
+                return Err(MapTransferError::TooLarge(path));
+            }
+            Ok((path, data))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if files.len() > MAX_TRANSFER_FILES {
+        return Err(MapTransferError::TooManyFiles(files.len()));
+    }
+    let total = files.iter().map(|(_, data)| data.len() as u64).sum();
+    if total > MAX_TRANSFER_SIZE {
+        return Err(MapTransferError::MapTooLarge(total));
+    }
+    Ok((map_path, files))
+}
+
+/// Resolve an asset path referenced from a file in the given directory of a pack, to a path
+/// relative to the pack. Returns `None` for references to core assets, which every peer has.
+fn resolve_path(base_dir: &str, reference: &str) -> Result<Option<String>, MapTransferError> {
+    if let Some((pack, _)) = reference.split_once(":/") {
+        return if pack == "core" {
+            Ok(None)
+        } else {
+            Err(MapTransferError::InvalidPath(reference.into()))
+        };
+    }
+    crate::pack_paths::resolve_path(base_dir, reference)
+        .map(Some)
+        .ok_or_else(|| MapTransferError::InvalidPath(reference.into()))
+}
+
+/// Whether a path received from the host stays inside the temporary pack it is written to.
+///
+/// Backslashes and colons are rejected on every platform, since they start drive and network
+/// prefixes on Windows, and asset paths only use slashes.
+fn is_safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && !path.contains(['\\', ':'])
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Resource with the maps that are being, or have been, received from the host of the network
+/// match. It is replaced when a match is joined.
+#[derive(HasSchema, Clone, Default)]
+pub struct MapDownloads {
+    current: Option<MapDownload>,
+    /// The downloaded maps, along with the encoded handle of the map on the host.
+    downloaded: Vec<(Vec<u8>, Handle<MapMeta>)>,
+}
+
+/// A map that is being received.
+#[derive(Clone)]
+struct MapDownload {
+    pack_folder: String,
+    map: Vec<u8>,
+    map_path: String,
+    files: Vec<(String, u32)>,
+    /// The index of the file that is being received.
+    file: usize,
+    data: Vec<u8>,
+    received: u64,
+    total: u64,
+}
+
+impl MapDownload {
+    /// Start receiving the announced files of a map, if they may be transferred. The map is the
+    /// encoded handle of the map on the host.
+    fn new(
+        pack: String,
+        map: Vec<u8>,
+        map_path: String,
+        files: Vec<(String, u32)>,
+    ) -> Result<Self, MapTransferError> {
+        if files.len() > MAX_TRANSFER_FILES {
+            return Err(MapTransferError::TooManyFiles(files.len()));
+        }
+        for (path, size) in &files {
+            if !is_safe_path(path) {
+                return Err(MapTransferError::InvalidPath(path.clone()));
+            }
+            if *size > MAX_TRANSFER_FILE_SIZE {
+                return Err(MapTransferError::TooLarge(path.clone()));
+            }
+        }
+        let total = files.iter().map(|(_, size)| *size as u64).sum();
+        if total > MAX_TRANSFER_SIZE {
+            return Err(MapTransferError::MapTooLarge(total));
+        }
+        if !is_safe_path(&map_path) || !files.iter().any(|(path, _)| *path == map_path) {
+            return Err(MapTransferError::InvalidPath(map_path));
+        }
+
+        // Name the folder after a hash of the pack id, so that different packs never share a
+        // folder, whatever characters their ids contain.
+        let mut hasher = FxHasher64::default();
+        pack.hash(&mut hasher);
+        Ok(Self {
+            pack_folder: format!("{NETWORK_MAP_PACK_PREFIX}{:016x}", hasher.finish()),
+            map,
+            map_path,
+            total,
+            files,
+            file: 0,
+            data: Vec::new(),
+            received: 0,
+        })
+    }
+
+    /// Add a piece of the file that is being received. Pieces must arrive in order, each one
+    /// starting where the one before it ended, and mustn't go past the announced size of the file.
+    fn receive_chunk(
+        &mut self,
+        file: u32,
+        offset: u32,
+        data: Vec<u8>,
+    ) -> Result<(), MapTransferError> {
+        let (_, size) = self
+            .files
+            .get(self.file)
+            .ok_or(MapTransferError::UnexpectedData)?;
+        if file as usize != self.file || offset as usize != self.data.len() {
+            return Err(MapTransferError::OutOfOrder);
+        }
+        if self.data.len() + data.len() > *size as usize {
+            return Err(MapTransferError::UnexpectedData);
+        }
+        self.received += data.len() as u64;
+        self.data.extend(data);
+        Ok(())
+    }
+
+    /// Take the file that is being received if all of it has been received, along with its path,
+    /// and move on to the next file.
+    fn take_finished_file(&mut self) -> Option<(String, Vec<u8>)> {
+        let (path, size) = self.files.get(self.file)?;
+        if self.data.len() < *size as usize {
+            return None;
+        }
+        let path = path.clone();
+        self.file += 1;
+        Some((path, std::mem::take(&mut self.data)))
+    }
+
+    /// Whether all of the files have been received.
+    fn is_finished(&self) -> bool {
+        self.file == self.files.len()
+    }
+}
+
+impl MapDownloads {
+    /// Handle a map transfer message from the host.
+    pub fn handle_message(
+        &mut self,
+        message: MapTransferMessage,
+        asset_server: &AssetServer,
+    ) -> Result<(), MapTransferError> {
+        match message {
+            MapTransferMessage::Start {
+                pack,
+                map,
+                map_path,
+                files,
+            } => {
+                let map = postcard::to_allocvec(&map).unwrap();
+                self.current = Some(MapDownload::new(pack, map, map_path, files)?);
+            }
+            MapTransferMessage::Chunk { file, offset, data } => {
+                self.current
+                    .as_mut()
+                    .ok_or(MapTransferError::UnexpectedData)?
+                    .receive_chunk(file, offset, data)?;
+            }
+        }
+        self.write_finished_files(asset_server)
+    }
+
+    /// Write the files that have been received completely, and load the map once all of them have
+    /// been written.
+    fn write_finished_files(&mut self, asset_server: &AssetServer) -> Result<(), MapTransferError> {
+        let Some(download) = self.current.as_mut() else {
+            return Ok(());
+        };
+        let pack_dir = crate::packs_dir().join(&download.pack_folder);
+        while let Some((path, data)) = download.take_finished_file() {
+            let path = pack_dir.join(path);
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(path, data)?;
+        }
+        if !download.is_finished() {
+            return Ok(());
+        }
+
+        let download = self.current.take().unwrap();
+        info!("Received map {}, loading it", download.map_path);
+        let handle = asset_server
+            .load_asset(AssetLocRef {
+                path: Path::new(&format!("/{}", download.map_path)),
+                pack: Some(&download.pack_folder),
+            })
+            .typed::<MapMeta>();
+        self.downloaded.push((download.map, handle));
+        Ok(())
+    }
+
+    /// The progress of the map that is being received, from `0.0` to `1.0`.
+    pub fn progress(&self) -> Option<f32> {
+        self.current
+            .as_ref()
+            .map(|download| download.received as f32 / download.total.max(1) as f32)
+    }
+
+    /// Whether a map is still being received or loaded.
+    pub fn is_busy(&self, asset_server: &AssetServer) -> bool {
+        self.current.is_some()
+            || (!self.downloaded.is_empty() && !asset_server.load_progress.is_finished())
+    }
+
+    /// Get the downloaded map for the given map of the host, if it was downloaded.
+    pub fn resolve(&self, map: &NetworkHandle<MapMeta>) -> Option<Handle<MapMeta>> {
+        let map = postcard::to_allocvec(map).unwrap();
+        self.downloaded
+            .iter()
+            .find(|(downloaded, _)| *downloaded == map)
+            .map(|(_, handle)| *handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn download(files: &[(&str, u32)]) -> Result<MapDownload, MapTransferError> {
+        MapDownload::new(
+            "pack".into(),
+            Vec::new(),
+            files[0].0.into(),
+            files
+                .iter()
+                .map(|(path, size)| (path.to_string(), *size))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn safe_paths_stay_inside_the_pack() {
+        assert!(is_safe_path("map.yaml"));
+        assert!(is_safe_path("maps/tiles/atlas.atlas.yaml"));
+
+        assert!(!is_safe_path(""));
+        assert!(!is_safe_path(".."));
+        assert!(!is_safe_path("../map.yaml"));
+        assert!(!is_safe_path("maps/../../map.yaml"));
+        assert!(!is_safe_path("./map.yaml"));
+        assert!(!is_safe_path("/etc/passwd"));
+        assert!(!is_safe_path("C:\\map.yaml"));
+        assert!(!is_safe_path("C:/map.yaml"));
+        assert!(!is_safe_path("C:map.yaml"));
+        assert!(!is_safe_path("\\\\server\\share\\map.yaml"));
+        assert!(!is_safe_path("\\map.yaml"));
+        assert!(!is_safe_path("maps\\..\\..\\map.yaml"));
+    }
+
+    #[test]
+    fn transfers_over_the_limits_are_rejected() {
+        assert!(matches!(
+            download(&[("map.yaml", MAX_TRANSFER_FILE_SIZE + 1)]),
+            Err(MapTransferError::TooLarge(_))
+        ));
+        let files = vec![("map.yaml", MAX_TRANSFER_FILE_SIZE); 5];
+        assert!(matches!(
+            download(&files),
+            Err(MapTransferError::MapTooLarge(_))
+        ));
+        let files = vec![("map.yaml", 1); MAX_TRANSFER_FILES + 1];
+        assert!(matches!(
+            download(&files),
+            Err(MapTransferError::TooManyFiles(_))
+        ));
+        assert!(matches!(
+            download(&[("../map.yaml", 1)]),
+            Err(MapTransferError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn chunks_are_received_in_order() {
+        let mut download = download(&[("map.yaml", 4), ("atlas.yaml", 2)]).unwrap();
+        download.receive_chunk(0, 0, vec![1, 2]).unwrap();
+        assert_eq!(download.take_finished_file(), None);
+        download.receive_chunk(0, 2, vec![3, 4]).unwrap();
+        assert_eq!(
+            download.take_finished_file(),
+            Some(("map.yaml".into(), vec![1, 2, 3, 4]))
+        );
+        download.receive_chunk(1, 0, vec![5, 6]).unwrap();
+        assert_eq!(
+            download.take_finished_file(),
+            Some(("atlas.yaml".into(), vec![5, 6]))
+        );
+        assert!(download.is_finished());
+        assert_eq!(download.received, download.total);
+    }
+
+    #[test]
+    fn out_of_order_chunks_are_rejected() {
+        let mut download = download(&[("map.yaml", 4), ("atlas.yaml", 2)]).unwrap();
+        assert!(matches!(
+            download.receive_chunk(0, 2, vec![3, 4]),
+            Err(MapTransferError::OutOfOrder)
+        ));
+        assert!(matches!(
+            download.receive_chunk(1, 0, vec![5, 6]),
+            Err(MapTransferError::OutOfOrder)
+        ));
+    }
+
+    #[test]
+    fn duplicate_chunks_are_rejected() {
+        let mut download = download(&[("map.yaml", 4)]).unwrap();
+        download.receive_chunk(0, 0, vec![1, 2]).unwrap();
+        assert!(matches!(
+            download.receive_chunk(0, 0, vec![1, 2]),
+            Err(MapTransferError::OutOfOrder)
+        ));
+    }
+
+    #[test]
+    fn oversized_chunks_are_rejected() {
+        let mut download = download(&[("map.yaml", 4), ("atlas.yaml", 2)]).unwrap();
+        assert!(matches!(
+            download.receive_chunk(0, 0, vec![0; 5]),
+            Err(MapTransferError::UnexpectedData)
+        ));
+        download.receive_chunk(0, 0, vec![0; 4]).unwrap();
+        download.take_finished_file().unwrap();
+        download.receive_chunk(1, 0, vec![0; 2]).unwrap();
+        download.take_finished_file().unwrap();
+        assert!(matches!(
+            download.receive_chunk(2, 0, vec![0]),
+            Err(MapTransferError::UnexpectedData)
+        ));
+    }
+}
@@ -1,11 +1,18 @@
 #[cfg(not(target_arch = "wasm32"))]
-use bones_framework::networking::{NetworkMatchSocket, SocketTarget};
+use bones_framework::networking::NetworkMatchSocket;
 use smallvec::SmallVec;
 
+#[cfg(not(target_arch = "wasm32"))]
+use super::lobby::{LobbyMessage, LobbyState};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ui::network_game::NetworkSpectators;
 use crate::{ui::player_image::player_image, PackMeta};
 
 use super::*;
 
+/// The number of player panels shown side by side. Any more players are shown on another row.
+const PLAYER_SELECT_COLUMNS: usize = 4;
+
 #[derive(Default, Clone, Debug, HasSchema)]
 pub struct PlayerSelectState {
     pub slots: [PlayerSlot; MAX_PLAYERS as usize],
@@ -13,6 +20,12 @@ pub struct PlayerSelectState {
     pub players: Vec<Handle<PlayerMeta>>,
     /// Cache of available hats from the game and packs.
     pub hats: Vec<Option<Handle<HatMeta>>>,
+    /// Cache of available AI behaviors from packs.
+    pub ai_behaviors: Vec<Handle<AiBehaviorMeta>>,
+    /// Whether the players are playing in teams.
+    pub team_mode: bool,
+    /// The team of the player in each slot, if playing in teams.
+    pub teams: [Team; MAX_PLAYERS as usize],
 }
 
 impl PlayerSelectState {
@@ -22,6 +35,28 @@ impl PlayerSelectState {
             .any(|slot| slot.user_control_source() == Some(source))
     }
 
+    /// Turn team mode on or off. The players are split evenly between the teams when it is turned
+    /// on.
+    pub fn set_team_mode(&mut self, team_mode: bool) {
+        self.team_mode = team_mode;
+        self.teams = std::array::from_fn(Team::for_slot);
+    }
+
+    /// Whether the players that have joined are on more than one team, so that there is someone to
+    /// play against.
+    pub fn has_opposing_teams(&self) -> bool {
+        let mut teams = self
+            .slots
+            .iter()
+            .zip(&self.teams)
+            .filter(|(slot, _)| !slot.is_empty())
+            .map(|(_, team)| *team);
+        let Some(first) = teams.next() else {
+            return false;
+        };
+        teams.any(|team| team != first)
+    }
+
     /// Cache the hats and player assets in PlayerSelectState
     pub fn cache_player_and_hat_assets(
         &mut self,
@@ -41,6 +76,16 @@ impl PlayerSelectState {
             }
         }
 
+        // Cache the AI behavior list
+        if self.ai_behaviors.is_empty() {
+            for pack in asset_server.packs() {
+                let pack_meta = asset_server.get(pack.root.typed::<PackMeta>());
+                for behavior in pack_meta.ai_behaviors.iter() {
+                    self.ai_behaviors.push(*behavior);
+                }
+            }
+        }
+
         // Cache the hat list
         if self.hats.is_empty() {
             self.hats.push(None); // No hat selected
@@ -151,6 +196,16 @@ impl PlayerSlot {
         }
     }
 
+    pub fn ai_difficulty(&self) -> Option<AiDifficulty> {
+        self.control_source()
+            .and_then(PlayerSlotControlSource::ai_difficulty)
+    }
+
+    pub fn ai_behavior(&self) -> Option<Handle<AiBehaviorMeta>> {
+        self.control_source()
+            .and_then(PlayerSlotControlSource::ai_behavior)
+    }
+
     pub fn selected_hat(&self) -> Option<Handle<HatMeta>> {
         match self {
             Self::Empty | Self::SelectingLocalControlSource => None,
@@ -165,7 +220,11 @@ impl PlayerSlot {
 pub enum PlayerSlotControlSource {
     User(ControlSource),
     Remote,
-    Ai,
+    Ai {
+        difficulty: AiDifficulty,
+        /// The lua behavior controlling the AI, or [`None`] for the built-in AI.
+        behavior: Option<Handle<AiBehaviorMeta>>,
+    },
 }
 
 impl PlayerSlotControlSource {
@@ -174,7 +233,21 @@ impl PlayerSlotControlSource {
     }
 
     pub fn is_ai(self) -> bool {
-        matches!(self, Self::Ai)
+        matches!(self, Self::Ai { .. })
+    }
+
+    pub fn ai_difficulty(self) -> Option<AiDifficulty> {
+        match self {
+            Self::Ai { difficulty, .. } => Some(difficulty),
+            _ => None,
+        }
+    }
+
+    pub fn ai_behavior(self) -> Option<Handle<AiBehaviorMeta>> {
+        match self {
+            Self::Ai { behavior, .. } => behavior,
+            _ => None,
+        }
     }
 
     pub fn user_source(self) -> Option<ControlSource> {
@@ -265,6 +338,9 @@ pub enum PlayerSelectMessage {
     SelectPlayer(NetworkHandle<PlayerMeta>),
     SelectHat(Option<NetworkHandle<HatMeta>>),
     ConfirmSelection(bool),
+    SelectTeam(Team),
+    /// Sent by the host to turn team mode on or off.
+    SetTeamMode(bool),
 }
 
 pub fn widget(
@@ -275,18 +351,22 @@ pub fn widget(
     world: &World,
     asset_server: Res<AssetServer>,
     #[cfg(not(target_arch = "wasm32"))] network_socket: Option<Res<NetworkMatchSocket>>,
+    #[cfg(not(target_arch = "wasm32"))] network_spectators: Option<Res<NetworkSpectators>>,
 ) {
     let mut state = ui.ctx().get_state::<PlayerSelectState>();
+
+    // Spectators don't get a player slot, and the slots after the last player aren't used.
+    #[cfg(not(target_arch = "wasm32"))]
+    let network_spectators = network_spectators.map(|x| *x).unwrap_or_default();
+    #[cfg(not(target_arch = "wasm32"))]
+    let network_session_size = network_socket
+        .as_ref()
+        .map(|socket| network_spectators.session_size(socket));
     ui.ctx().set_state(EguiInputSettings {
         disable_keyboard_input: true,
         disable_gamepad_input: true,
     });
 
-    #[cfg(not(target_arch = "wasm32"))]
-    if let Some(socket) = network_socket.as_ref() {
-        handle_match_setup_messages(socket, &mut state, &asset_server);
-    }
-
     // Set player slot 0 using the debug env vars and go to the map select menu.
     // Default the player to Jumpy if none is provided.
     #[cfg(debug_assertions)]
@@ -356,9 +436,13 @@ pub fn widget(
             let is_local_player_slot = slot_id == socket.player_idx() as usize;
             let is_empty = slot.is_empty();
 
-            if slot_id >= socket.player_count() as usize {
+            if slot_id >= network_spectators.session_size(socket) as usize {
                 // unused slots in online don't need to be initialized
                 break;
+            } else if network_spectators.is_peer_spectating(slot_id as u32) {
+                // A peer may only be known to spectate once its handshake was received, after its
+                // slot was initialized.
+                *slot = PlayerSlot::Empty;
             } else if is_local_player_slot && is_empty {
                 *slot = PlayerSlot::SelectingLocalControlSource;
             } else if !is_local_player_slot && is_empty {
@@ -387,7 +471,16 @@ pub fn widget(
             unconfirmed_players += 1;
         }
     }
-    let may_continue = ready_players >= 1 && unconfirmed_players == 0 && at_least_one_non_ai_ready;
+    let may_continue = ready_players >= 1
+        && unconfirmed_players == 0
+        && at_least_one_non_ai_ready
+        && (!state.team_mode || state.has_opposing_teams());
+
+    // Online, only the slots of the connected players are shown.
+    #[cfg(target_arch = "wasm32")]
+    let visible_slots = MAX_PLAYERS as usize;
+    #[cfg(not(target_arch = "wasm32"))]
+    let visible_slots = network_session_size.unwrap_or(MAX_PLAYERS) as usize;
 
     #[cfg(not(target_arch = "wasm32"))]
     if let Some(socket) = network_socket.as_ref() {
@@ -409,6 +502,11 @@ pub fn widget(
         .font_styles
         .heading
         .with_color(meta.theme.panel.font_color);
+    let normal_text_style = &meta
+        .theme
+        .font_styles
+        .normal
+        .with_color(meta.theme.panel.font_color);
     let normal_button_style = &meta.theme.buttons.normal;
 
     ui.vertical_centered(|ui| {
@@ -427,7 +525,52 @@ pub fn widget(
         }
 
         ui.label(bigger_text_style.rich(localization.get("player-select-title")));
-        ui.add_space(normal_button_style.font.size);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(socket) = network_socket
+            .as_ref()
+            .filter(|socket| network_spectators.is_spectating(socket))
+        {
+            let message = if network_spectators.is_relayed(socket) {
+                "spectating-match"
+            } else {
+                "spectating-in-session"
+            };
+            ui.label(normal_text_style.rich(localization.get(message)));
+        }
+        ui.add_space(normal_button_style.font.size / 2.0);
+
+        // Team mode toggle, only the host may change it in network play.
+        #[cfg(target_arch = "wasm32")]
+        let is_host = true;
+        #[cfg(not(target_arch = "wasm32"))]
+        let is_host = network_socket
+            .as_ref()
+            .map_or(true, |socket| socket.player_idx() == 0);
+        let team_mode_label = format!(
+            "{}: {}",
+            localization.get("teams"),
+            localization.get(if state.team_mode { "on" } else { "off" })
+        );
+        let team_mode_button = ui
+            .scope(|ui| {
+                ui.set_enabled(is_host);
+                BorderedButton::themed(normal_button_style, team_mode_label).show(ui)
+            })
+            .inner;
+        if team_mode_button.clicked() {
+            let team_mode = !state.team_mode;
+            state.set_team_mode(team_mode);
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(socket) = network_socket.as_ref() {
+                let message = PlayerSelectMessage::SetTeamMode(team_mode);
+                LobbyMessage::PlayerSelect(message).send(socket);
+            }
+        }
+        if state.team_mode && !state.has_opposing_teams() {
+            ui.label(normal_text_style.rich(localization.get("teams-need-opponents")));
+        }
+        ui.add_space(normal_button_style.font.size / 2.0);
 
         ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
             ui.add_space(normal_button_style.font.size * 2.0);
@@ -459,6 +602,7 @@ pub fn widget(
                     #[cfg(not(target_arch = "wasm32"))]
                     if let Some(socket) = network_socket {
                         socket.close();
+                        ui.ctx().set_state(LobbyState::default());
                     }
                 }
 
@@ -491,14 +635,26 @@ pub fn widget(
             ui.vertical_centered(|ui| {
                 ui.set_width(ui.available_width() - normal_button_style.font.size * 2.0);
 
-                ui.columns(MAX_PLAYERS as usize, |columns| {
-                    for (i, ui) in columns.iter_mut().enumerate() {
-                        world.run_system(
-                            player_select_panel,
-                            (ui, u32::try_from(i).unwrap(), &mut state),
-                        )
-                    }
-                });
+                let rows = visible_slots.div_ceil(PLAYER_SELECT_COLUMNS).max(1);
+                let row_height = (ui.available_height()
+                    - ui.spacing().item_spacing.y * (rows - 1) as f32)
+                    / rows as f32;
+                for row in 0..rows {
+                    ui.allocate_ui(egui::vec2(ui.available_width(), row_height), |ui| {
+                        ui.columns(PLAYER_SELECT_COLUMNS, |columns| {
+                            for (column, ui) in columns.iter_mut().enumerate() {
+                                let i = row * PLAYER_SELECT_COLUMNS + column;
+                                if i >= visible_slots {
+                                    break;
+                                }
+                                world.run_system(
+                                    player_select_panel,
+                                    (ui, u32::try_from(i).unwrap(), &mut state),
+                                )
+                            }
+                        });
+                    });
+                }
             });
         });
     });
@@ -506,72 +662,82 @@ pub fn widget(
     ui.ctx().set_state(state);
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn handle_match_setup_messages(
-    network_socket: &NetworkMatchSocket,
-    player_select_state: &mut PlayerSelectState,
-    asset_server: &AssetServer,
-) {
-    let datas: Vec<(u32, Vec<u8>)> = network_socket.recv_reliable();
-
-    for (player, data) in datas {
-        match postcard::from_bytes::<PlayerSelectMessage>(&data) {
-            Ok(message) => match message {
-                PlayerSelectMessage::SelectPlayer(player_handle) => {
-                    let slot = player_select_state.slots[player as usize];
-                    let control_source = slot
-                        .control_source()
-                        .unwrap_or(PlayerSlotControlSource::Remote);
-                    let selected_player = player_handle.into_handle(asset_server);
-                    let current_hat = slot.selected_hat();
-                    player_select_state.slots[player as usize] = PlayerSlot::SelectingHat {
+impl PlayerSelectState {
+    /// Apply a player selection message that was received from the given player.
+    pub fn apply_network_message(
+        &mut self,
+        player: u32,
+        message: PlayerSelectMessage,
+        asset_server: &AssetServer,
+    ) {
+        match message {
+            PlayerSelectMessage::SelectPlayer(player_handle) => {
+                let slot = self.slots[player as usize];
+                let control_source = slot
+                    .control_source()
+                    .unwrap_or(PlayerSlotControlSource::Remote);
+                let selected_player = player_handle.into_handle(asset_server);
+                let current_hat = slot.selected_hat();
+                self.slots[player as usize] = PlayerSlot::SelectingHat {
+                    control_source,
+                    selected_player,
+                    current_hat,
+                };
+            }
+            PlayerSelectMessage::ConfirmSelection(confirmed) => {
+                let slot = self.slots[player as usize];
+                let control_source = slot
+                    .control_source()
+                    .unwrap_or(PlayerSlotControlSource::Remote);
+                let selected_player = slot.selected_player().unwrap_or_else(|| {
+                    warn!(
+                        "got confirm message while in empty state, falling back to default player"
+                    );
+                    self.players[0]
+                });
+                let hat = slot.selected_hat();
+                self.slots[player as usize] = if confirmed {
+                    PlayerSlot::Ready {
                         control_source,
                         selected_player,
-                        current_hat,
-                    };
-                }
-                PlayerSelectMessage::ConfirmSelection(confirmed) => {
-                    let slot = player_select_state.slots[player as usize];
-                    let control_source = slot
-                        .control_source()
-                        .unwrap_or(PlayerSlotControlSource::Remote);
-                    let selected_player = slot.selected_player().unwrap_or_else(|| {
-                            warn!("got confirm message while in empty state, falling back to default player");
-                            player_select_state.players[0]
-                        });
-                    let hat = slot.selected_hat();
-                    player_select_state.slots[player as usize] = if confirmed {
-                        PlayerSlot::Ready {
-                            control_source,
-                            selected_player,
-                            selected_hat: hat,
-                        }
-                    } else {
-                        PlayerSlot::SelectingHat {
-                            control_source,
-                            selected_player,
-                            current_hat: hat,
-                        }
-                    };
-                }
-                PlayerSelectMessage::SelectHat(hat_handle) => {
-                    let slot = player_select_state.slots[player as usize];
-                    let control_source = slot
-                        .control_source()
-                        .unwrap_or(PlayerSlotControlSource::Remote);
-                    let selected_player = slot.selected_player().unwrap_or_else(|| {
-                            warn!("got confirm message while in empty state, falling back to default player");
-                            player_select_state.players[0]
-                        });
-                    let current_hat = hat_handle.map(|h| h.into_handle(asset_server));
-                    player_select_state.slots[player as usize] = PlayerSlot::SelectingHat {
+                        selected_hat: hat,
+                    }
+                } else {
+                    PlayerSlot::SelectingHat {
                         control_source,
                         selected_player,
-                        current_hat,
-                    };
+                        current_hat: hat,
+                    }
+                };
+            }
+            PlayerSelectMessage::SelectTeam(team) => {
+                self.teams[player as usize] = team;
+            }
+            PlayerSelectMessage::SetTeamMode(team_mode) => {
+                if player == 0 {
+                    self.set_team_mode(team_mode);
+                } else {
+                    warn!("Ignoring team mode change from player {player} who is not the host");
                 }
-            },
-            Err(e) => warn!("Ignoring network message that was not understood: {e}"),
+            }
+            PlayerSelectMessage::SelectHat(hat_handle) => {
+                let slot = self.slots[player as usize];
+                let control_source = slot
+                    .control_source()
+                    .unwrap_or(PlayerSlotControlSource::Remote);
+                let selected_player = slot.selected_player().unwrap_or_else(|| {
+                    warn!(
+                        "got confirm message while in empty state, falling back to default player"
+                    );
+                    self.players[0]
+                });
+                let current_hat = hat_handle.map(|h| h.into_handle(asset_server));
+                self.slots[player as usize] = PlayerSlot::SelectingHat {
+                    control_source,
+                    selected_player,
+                    current_hat,
+                };
+            }
         }
     }
 }
@@ -585,6 +751,7 @@ fn player_select_panel(
     mapping: Res<PlayerControlMapping>,
     world: &World,
     #[cfg(not(target_arch = "wasm32"))] network_socket: Option<Res<NetworkMatchSocket>>,
+    #[cfg(not(target_arch = "wasm32"))] network_spectators: Option<Res<NetworkSpectators>>,
 ) {
     let (ui, slot_id, state) = &mut *params;
     let slot_id = *slot_id;
@@ -594,8 +761,10 @@ fn player_select_panel(
 
     #[cfg(not(target_arch = "wasm32"))]
     if let Some(socket) = network_socket {
-        // Don't show panels for non-connected players.
-        if slot_id + 1 > socket.player_count() {
+        // Don't show panels for non-connected players or spectators.
+        if slot_id + 1 > socket.player_count()
+            || network_spectators.is_some_and(|s| s.is_peer_spectating(slot_id))
+        {
             return;
         }
     }
@@ -631,7 +800,7 @@ fn player_select_panel(
         if let Some(socket) = network_socket {
             let network_handle = handle.network_handle(&asset_server);
             let message = PlayerSelectMessage::SelectPlayer(network_handle);
-            socket.send_reliable(SocketTarget::All, &postcard::to_allocvec(&message).unwrap());
+            LobbyMessage::PlayerSelect(message).send(socket);
         }
     };
 
@@ -640,7 +809,7 @@ fn player_select_panel(
         if let Some(socket) = network_socket {
             let network_handle = handle.map(|h| h.network_handle(&asset_server));
             let message = PlayerSelectMessage::SelectHat(network_handle);
-            socket.send_reliable(SocketTarget::All, &postcard::to_allocvec(&message).unwrap());
+            LobbyMessage::PlayerSelect(message).send(socket);
         }
     };
 
@@ -648,7 +817,7 @@ fn player_select_panel(
     let net_send_confirm = |confirm| {
         if let Some(socket) = network_socket {
             let message = PlayerSelectMessage::ConfirmSelection(confirm);
-            socket.send_reliable(SocketTarget::All, &postcard::to_allocvec(&message).unwrap());
+            LobbyMessage::PlayerSelect(message).send(socket);
         }
     };
 
@@ -969,6 +1138,59 @@ fn player_select_panel(
                         }
                     });
 
+                    if let Some(mut difficulty) = slot.ai_difficulty() {
+                        let mut behavior = slot.ai_behavior();
+                        if ai_settings_editor(
+                            ui,
+                            slot_id,
+                            &meta,
+                            &localization,
+                            &asset_server,
+                            &state.ai_behaviors,
+                            &mut difficulty,
+                            &mut behavior,
+                        ) {
+                            next_state = Some(PlayerSlot::Ready {
+                                control_source: PlayerSlotControlSource::Ai {
+                                    difficulty,
+                                    behavior,
+                                },
+                                selected_player,
+                                selected_hat: slot.selected_hat(),
+                            });
+                        }
+                    }
+
+                    if state.team_mode {
+                        let team = state.teams[slot_id as usize];
+                        let may_change_team = match network_local_player_slot {
+                            Some(local_slot) => local_slot == slot_id,
+                            None => true,
+                        };
+                        ui.label(
+                            normal_font
+                                .with_color(team.color())
+                                .rich(localization.get(team.name_key())),
+                        );
+                        if may_change_team
+                            && BorderedButton::themed(
+                                &meta.theme.buttons.small,
+                                localization.get("switch-team"),
+                            )
+                            .show(ui)
+                            .clicked()
+                        {
+                            let team = team.next();
+                            state.teams[slot_id as usize] = team;
+
+                            #[cfg(not(target_arch = "wasm32"))]
+                            if let Some(socket) = network_socket {
+                                let message = PlayerSelectMessage::SelectTeam(team);
+                                LobbyMessage::PlayerSelect(message).send(socket);
+                            }
+                        }
+                    }
+
                     display_fish(ui, selected_player, slot.selected_hat());
                 });
 
@@ -1003,7 +1225,10 @@ fn player_select_panel(
                             let player_idx =
                                 THREAD_RNG.with(|rng| rng.usize(0..state.players.len()));
                             next_state = Some(PlayerSlot::Ready {
-                                control_source: PlayerSlotControlSource::Ai,
+                                control_source: PlayerSlotControlSource::Ai {
+                                    difficulty: default(),
+                                    behavior: None,
+                                },
                                 selected_player: state.players[player_idx],
                                 selected_hat: None,
                             });
@@ -1021,3 +1246,83 @@ fn player_select_panel(
         state.slots[slot_id as usize] = slot;
     }
 }
+
+/// Edit the behavior and difficulty of an AI player, returning whether they were changed.
+fn ai_settings_editor(
+    ui: &mut egui::Ui,
+    slot_id: u32,
+    meta: &GameMeta,
+    localization: &Localization<GameMeta>,
+    asset_server: &AssetServer,
+    behaviors: &[Handle<AiBehaviorMeta>],
+    difficulty: &mut AiDifficulty,
+    behavior: &mut Option<Handle<AiBehaviorMeta>>,
+) -> bool {
+    let normal_font = meta
+        .theme
+        .font_styles
+        .normal
+        .with_color(meta.theme.panel.font_color);
+    let small_button = &meta.theme.buttons.small;
+    let mut changed = false;
+
+    // The built-in AI followed by the behaviors from packs.
+    if !behaviors.is_empty() {
+        let behavior_idx = behavior
+            .and_then(|behavior| behaviors.iter().position(|h| *h == behavior))
+            .map_or(0, |i| i + 1);
+        let options = behaviors.len() + 1;
+        ui.label(normal_font.rich(localization.get("ai-behavior")));
+        ui.horizontal(|ui| {
+            let mut next_idx = None;
+            if BorderedButton::themed(small_button, "<").show(ui).clicked() {
+                next_idx = Some((behavior_idx + options - 1) % options);
+            }
+            let name = match behavior {
+                Some(behavior) => asset_server.get(*behavior).name.to_string(),
+                None => localization.get("ai-behavior-built-in").to_string(),
+            };
+            ui.label(normal_font.rich(name));
+            if BorderedButton::themed(small_button, ">").show(ui).clicked() {
+                next_idx = Some((behavior_idx + 1) % options);
+            }
+            if let Some(idx) = next_idx {
+                *behavior = idx.checked_sub(1).map(|i| behaviors[i]);
+                changed = true;
+            }
+        });
+    }
+
+    ui.label(normal_font.rich(localization.get("ai-difficulty")));
+    ui.horizontal(|ui| {
+        if BorderedButton::themed(small_button, "<").show(ui).clicked() {
+            *difficulty = difficulty.prev();
+            changed = true;
+        }
+        ui.label(normal_font.rich(localization.get(difficulty.name_key())));
+        if BorderedButton::themed(small_button, ">").show(ui).clicked() {
+            *difficulty = difficulty.next();
+            changed = true;
+        }
+    });
+
+    if let AiDifficulty::Custom(settings) = difficulty {
+        egui::Grid::new(("ai-difficulty-grid", slot_id)).show(ui, |ui| {
+            for (label, value, range) in [
+                ("ai-reaction-time", &mut settings.reaction_time, 0.1..=1.5),
+                ("ai-pause-chance", &mut settings.pause_chance, 0.0..=1.0),
+                ("ai-speed", &mut settings.speed, 0.1..=1.0),
+                ("ai-aim-accuracy", &mut settings.aim_accuracy, 0.0..=1.0),
+                ("ai-item-usage", &mut settings.item_usage, 0.0..=1.0),
+            ] {
+                ui.label(normal_font.rich(localization.get(label)));
+                changed |= ui
+                    .add(egui::Slider::new(value, range).step_by(0.05))
+                    .changed();
+                ui.end_row();
+            }
+        });
+    }
+
+    changed
+}
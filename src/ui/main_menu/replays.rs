@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+
+use crate::replay::{list_replays, Replay};
+
+use super::*;
+
+/// The replay files listed by the replays menu. The replays directory is read when the menu is
+/// opened and when the list is refreshed, instead of every frame.
+#[derive(Clone, Default)]
+pub struct ReplayList(Option<Arc<Vec<PathBuf>>>);
+
+/// Lists the saved replays and starts playing back the selected one.
+pub fn widget(
+    mut ui: In<&mut egui::Ui>,
+    meta: Root<GameMeta>,
+    localization: Localization<GameMeta>,
+    input: Res<GlobalPlayerControls>,
+    assets: Res<AssetServer>,
+    mut sessions: ResMut<Sessions>,
+    mut session_options: ResMut<SessionOptions>,
+) {
+    let outer_margin = egui::style::Margin::symmetric(
+        ui.available_width() * 0.1,
+        meta.theme.font_styles.bigger.size,
+    );
+
+    BorderedFrame::new(&meta.theme.panel.border)
+        .margin(outer_margin)
+        .padding(meta.theme.panel.padding)
+        .show(*ui, |ui| {
+            let normal_size = meta.theme.font_styles.normal.size;
+            let bigger_font = meta
+                .theme
+                .font_styles
+                .bigger
+                .with_color(meta.theme.panel.font_color);
+
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    meta.theme
+                        .font_styles
+                        .heading
+                        .rich(localization.get("replays")),
+                );
+            });
+            ui.set_min_width(ui.available_width());
+
+            ui.with_layout(egui::Layout::bottom_up(egui::Align::Min), |ui| {
+                ui.add_space(normal_size / 2.0);
+
+                // Back button
+                if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("back"))
+                    .show(ui)
+                    .focus_by_default(ui)
+                    .clicked()
+                    || input.values().any(|x| x.menu_back_just_pressed)
+                {
+                    ui.ctx().set_state(MenuPage::Home);
+                    // Read the replays directory again the next time the menu is opened.
+                    ui.ctx().set_state(ReplayList::default());
+                    return;
+                }
+
+                // Refresh button
+                let mut replay_list = ui.ctx().get_state::<ReplayList>();
+                if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("reload"))
+                    .show(ui)
+                    .clicked()
+                {
+                    replay_list.0 = None;
+                }
+                let replays = if let Some(replays) = replay_list.0.clone() {
+                    replays
+                } else {
+                    let replays = Arc::new(list_replays());
+                    replay_list.0 = Some(replays.clone());
+                    ui.ctx().set_state(replay_list);
+                    replays
+                };
+
+                ui.add_space(normal_size / 2.0);
+                ui.label(
+                    meta.theme
+                        .font_styles
+                        .normal
+                        .with_color(meta.theme.panel.font_color)
+                        .rich(localization.get("replays-one-round")),
+                );
+
+                ui.with_layout(default(), |ui| {
+                    if replays.is_empty() {
+                        ui.label(bigger_font.rich(localization.get("no-replays")));
+                        return;
+                    }
+
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+                        for path in replays.iter() {
+                            let name = path
+                                .file_stem()
+                                .map(|stem| stem.to_string_lossy().into_owned())
+                                .unwrap_or_default();
+
+                            if BorderedButton::themed(&meta.theme.buttons.normal, name)
+                                .min_size(vec2(ui.available_width(), 0.0))
+                                .show(ui)
+                                .clicked()
+                            {
+                                match Replay::load(path) {
+                                    Ok(replay) => {
+                                        session_options.delete = true;
+                                        ui.ctx().set_state(MenuPage::Home);
+                                        ui.ctx().set_state(ReplayList::default());
+                                        sessions.start_game(replay.into_match_plugin(&assets));
+                                    }
+                                    Err(e) => {
+                                        error!("Could not load replay {}: {e}", path.display())
+                                    }
+                                }
+                            }
+                        }
+                    });
+                });
+            });
+        });
+}
@@ -18,9 +18,14 @@ pub(super) fn widget(
         .normal
         .with_color(meta.theme.panel.font_color);
 
+    let small_button_style = &meta.theme.buttons.small;
+
     if *should_reset {
         state.modified_settings.matchmaking_server =
             meta.default_settings.matchmaking_server.clone();
+        state.modified_settings.network_input_delay = meta.default_settings.network_input_delay;
+        state.modified_settings.network_prediction_window =
+            meta.default_settings.network_prediction_window;
     }
 
     ui.add_space(bigger_font.size / 2.0);
@@ -30,8 +35,64 @@ pub(super) fn widget(
 
         ui.add(
             egui::TextEdit::singleline(&mut state.modified_settings.matchmaking_server)
-                .font(normal_font)
+                .font(normal_font.id())
                 .desired_width(ui.available_width() - bigger_font.size * 2.0),
         );
     });
+
+    ui.add_space(bigger_font.size / 2.0);
+
+    // Input delay and prediction window, in frames. Unset values are picked from the ping to the
+    // other players when a match starts.
+    let settings = &mut state.modified_settings;
+    egui::Grid::new("network-tuning-grid")
+        .spacing(egui::vec2(normal_font.size, normal_font.size / 2.0))
+        .show(ui, |ui| {
+            for (label, value, max) in [
+                (
+                    "input-delay",
+                    &mut settings.network_input_delay,
+                    Settings::MAX_NETWORK_INPUT_DELAY,
+                ),
+                (
+                    "prediction-window",
+                    &mut settings.network_prediction_window,
+                    Settings::MAX_NETWORK_PREDICTION_WINDOW,
+                ),
+            ] {
+                ui.label(bigger_font.rich(localization.get(label)));
+                ui.horizontal(|ui| {
+                    if BorderedButton::themed(small_button_style, "<")
+                        .show(ui)
+                        .clicked()
+                    {
+                        *value = match *value {
+                            Maybe::Set(0) | Maybe::Unset => Maybe::Unset,
+                            Maybe::Set(frames) => Maybe::Set(frames - 1),
+                        };
+                    }
+                    let text = match *value {
+                        Maybe::Set(frames) => frames.to_string(),
+                        Maybe::Unset => localization.get("network-tuning-auto").to_string(),
+                    };
+                    ui.add_sized(
+                        egui::vec2(normal_font.size * 3.0, normal_font.size),
+                        egui::Label::new(normal_font.rich(text)),
+                    );
+                    if BorderedButton::themed(small_button_style, ">")
+                        .show(ui)
+                        .clicked()
+                    {
+                        *value = match *value {
+                            Maybe::Unset => Maybe::Set(0),
+                            Maybe::Set(frames) => Maybe::Set((frames + 1).min(max)),
+                        };
+                    }
+                });
+                ui.end_row();
+            }
+        });
+
+    ui.add_space(normal_font.size / 2.0);
+    ui.label(normal_font.rich(localization.get("network-tuning-hint")));
 }
@@ -10,12 +10,6 @@ pub enum MapSelectAction {
     GoBack,
 }
 
-/// Network message that may be sent when selecting a map.
-#[derive(Serialize, Deserialize)]
-pub enum MapSelectMessage {
-    SelectMap(NetworkHandle<MapMeta>),
-}
-
 pub fn map_select_menu(
     asset_server: Res<AssetServer>,
     meta: Root<GameMeta>,
@@ -71,6 +65,15 @@ pub fn map_select_menu(
 
                         MapSelectAction::None
                     } else {
+                        // The rules can only be changed before the match starts, not from the
+                        // pause menu.
+                        if matches!(menu_page_state, MenuPage::MapSelect { .. }) {
+                            let mut rules = ui.ctx().get_state::<MatchRules>();
+                            match_rules_editor(ui, &meta, &localization, &mut rules);
+                            ui.ctx().set_state(rules);
+                            ui.add_space(meta.theme.font_styles.normal.size);
+                        }
+
                         egui::ScrollArea::vertical()
                             .show(ui, |ui| {
                                 ui.vertical_centered_justified(|ui| {
@@ -147,3 +150,86 @@ pub fn map_select_menu(
         })
         .inner
 }
+
+/// Widget for editing the [`MatchRules`].
+fn match_rules_editor(
+    ui: &mut egui::Ui,
+    meta: &GameMeta,
+    localization: &Localization<GameMeta>,
+    rules: &mut MatchRules,
+) {
+    let normal_font = meta
+        .theme
+        .font_styles
+        .normal
+        .with_color(meta.theme.panel.font_color);
+    let small_button = &meta.theme.buttons.small;
+
+    ui.vertical_centered(|ui| {
+        ui.label(normal_font.rich(localization.get("match-rules")));
+    });
+
+    egui::Grid::new("match-rules-grid").show(ui, |ui| {
+        // Lives
+        ui.label(normal_font.rich(localization.get("lives")));
+        if BorderedButton::themed(small_button, "<").show(ui).clicked() {
+            rules.lives = rules.lives.saturating_sub(1).max(1);
+        }
+        ui.label(normal_font.rich(rules.lives.to_string()));
+        if BorderedButton::themed(small_button, ">").show(ui).clicked() {
+            rules.lives = (rules.lives + 1).min(MAX_LIVES);
+        }
+        ui.end_row();
+
+        // Time limit
+        let time_limit_idx = TIME_LIMIT_OPTIONS
+            .iter()
+            .position(|secs| *secs == rules.time_limit_secs)
+            .unwrap_or(0);
+        ui.label(normal_font.rich(localization.get("time-limit")));
+        if BorderedButton::themed(small_button, "<").show(ui).clicked() {
+            rules.time_limit_secs = TIME_LIMIT_OPTIONS
+                [(time_limit_idx + TIME_LIMIT_OPTIONS.len() - 1) % TIME_LIMIT_OPTIONS.len()];
+        }
+        let time_limit = match rules.time_limit_secs {
+            0 => localization.get("no-time-limit").to_string(),
+            secs => format!("{}:{:02}", secs / 60, secs % 60),
+        };
+        ui.label(normal_font.rich(time_limit));
+        if BorderedButton::themed(small_button, ">").show(ui).clicked() {
+            rules.time_limit_secs =
+                TIME_LIMIT_OPTIONS[(time_limit_idx + 1) % TIME_LIMIT_OPTIONS.len()];
+        }
+        ui.end_row();
+
+        // Sudden death, only used when there is a time limit.
+        ui.add_enabled_ui(rules.time_limit_secs > 0, |ui| {
+            ui.label(normal_font.rich(localization.get("sudden-death")));
+        });
+        ui.label("");
+        let sudden_death = localization.get(if rules.sudden_death { "on" } else { "off" });
+        if ui
+            .add_enabled_ui(rules.time_limit_secs > 0, |ui| {
+                BorderedButton::themed(small_button, sudden_death.to_string())
+                    .show(ui)
+                    .clicked()
+            })
+            .inner
+        {
+            rules.sudden_death = !rules.sudden_death;
+        }
+        ui.end_row();
+
+        // Friendly fire, only matters when playing in teams.
+        ui.label(normal_font.rich(localization.get("friendly-fire")));
+        ui.label("");
+        let friendly_fire = localization.get(if rules.friendly_fire { "on" } else { "off" });
+        if BorderedButton::themed(small_button, friendly_fire.to_string())
+            .show(ui)
+            .clicked()
+        {
+            rules.friendly_fire = !rules.friendly_fire;
+        }
+        ui.end_row();
+    });
+}
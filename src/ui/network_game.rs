@@ -1,14 +1,85 @@
-use bones_framework::networking::online::{
-    OnlineMatchmaker, OnlineMatchmakerResponse, PlayerIdxAssignment,
+use bones_framework::networking::{
+    online::{OnlineMatchmaker, OnlineMatchmakerResponse, PlayerIdxAssignment},
+    NetworkMatchSocket,
 };
 
-use crate::prelude::*;
+use crate::{network_messages::ReliableMessages, prelude::*};
 
-use super::main_menu::MenuPage;
+use super::main_menu::{map_transfer::MapDownloads, MenuPage};
 
 /// Game id for matchmaking
 const GAME_ID: &str = "jumpy";
 
+/// The LAN service property that advertises the number of player slots of a server.
+const PLAYERS_PROPERTY: &str = "players";
+/// The LAN service property that advertises the number of spectator slots of a server.
+const SPECTATORS_PROPERTY: &str = "spectators";
+
+/// The most spectator slots a match can have, on top of its player slots.
+pub const MAX_SPECTATORS: u32 = 4;
+
+/// The most peers a network match can have.
+pub const MAX_PEERS: u32 = MAX_PLAYERS + MAX_SPECTATORS;
+
+/// The peers of a network match that spectate instead of playing, by socket index.
+///
+/// Every peer chooses whether to spectate before joining, and tells the others in its
+/// [`LobbyMessage::Hello`](super::main_menu::lobby::LobbyMessage::Hello). The local choice is set
+/// when the match is joined and the choices of the other peers when their handshake is received.
+/// Peers whose socket index doesn't fit in a player slot always spectate. The slots of spectators
+/// are left empty in player select, so that they don't spawn a player in the match.
+///
+/// The GGRS session of a match is made of the peers up to the last one that plays, since the socket
+/// index of a peer is its GGRS handle. Spectators after the last player aren't part of it, instead
+/// the host relays the confirmed inputs of the players to them, see [`crate::spectate`]. Spectators
+/// that got a lower socket index than a player stay in the session without a player, so the match
+/// waits for them like it waits for the players.
+#[derive(HasSchema, Clone, Copy, Debug, Default)]
+pub struct NetworkSpectators {
+    spectating: [bool; MAX_PEERS as usize],
+}
+
+impl NetworkSpectators {
+    /// Create the spectators of a match that was just joined, with the choice of the local peer.
+    pub fn new(socket: &NetworkMatchSocket, spectate: bool) -> Self {
+        let mut spectators = Self::default();
+        spectators.set(socket.player_idx(), spectate);
+        spectators
+    }
+
+    /// Set whether the peer with the given socket index spectates.
+    pub fn set(&mut self, player_idx: u32, spectate: bool) {
+        if let Some(spectating) = self.spectating.get_mut(player_idx as usize) {
+            *spectating = spectate;
+        }
+    }
+
+    /// Whether the peer with the given socket index spectates.
+    pub fn is_peer_spectating(&self, player_idx: u32) -> bool {
+        player_idx >= MAX_PLAYERS || self.spectating[player_idx as usize]
+    }
+
+    /// Whether the local peer of the given socket is spectating.
+    pub fn is_spectating(&self, socket: &NetworkMatchSocket) -> bool {
+        self.is_peer_spectating(socket.player_idx())
+    }
+
+    /// Get the number of peers in the GGRS session of the match of the given socket, which goes
+    /// up to the last peer that plays.
+    pub fn session_size(&self, socket: &NetworkMatchSocket) -> u32 {
+        (0..socket.player_count())
+            .rev()
+            .find(|idx| !self.is_peer_spectating(*idx))
+            .map_or(0, |idx| idx + 1)
+    }
+
+    /// Whether the local peer of the given socket spectates from the inputs relayed by the host,
+    /// outside of the GGRS session.
+    pub fn is_relayed(&self, socket: &NetworkMatchSocket) -> bool {
+        socket.player_idx() >= self.session_size(socket)
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub enum NetworkGameAction {
     #[default]
@@ -23,12 +94,14 @@ pub enum LanMode {
     Host {
         service_name: String,
         player_count: u32,
+        spectator_count: u32,
     },
 }
 
 #[derive(Eq, PartialEq, Clone)]
 pub struct OnlineState {
     player_count: u32,
+    spectator_count: u32,
     matchmaking_server: String,
 }
 
@@ -36,6 +109,7 @@ impl Default for OnlineState {
     fn default() -> Self {
         Self {
             player_count: 2,
+            spectator_count: 0,
             matchmaking_server: String::new(),
         }
     }
@@ -83,6 +157,8 @@ pub struct NetworkGameState {
     service_info: Option<lan::ServerInfo>,
     status: NetworkGameStatus,
     joined_players: usize,
+    /// Whether the local peer wants to spectate the match instead of playing.
+    spectate: bool,
     lan_servers: Vec<lan::ServerInfo>,
     ping_update_timer: Timer,
     random_seed: u64,
@@ -97,6 +173,7 @@ impl Default for NetworkGameState {
             status: default(),
             lan_servers: default(),
             joined_players: default(),
+            spectate: default(),
             ping_update_timer: Timer::new(Duration::from_secs(1), TimerMode::Repeating),
             random_seed: DEFAULT_RANDOM_SEED as u64,
         }
@@ -212,6 +289,7 @@ pub fn network_game_menu(
                                                 *mode = LanMode::Host {
                                                     service_name: localization.get("fish-fight").into_owned(),
                                                     player_count: 2,
+                                                    spectator_count: 0,
                                                 };
                                             }
 
@@ -255,12 +333,31 @@ pub fn network_game_menu(
                         status,
                         ping_update_timer,
                         joined_players,
+                        spectate,
                         random_seed
                     } = &mut state;
 
                     ui.separator();
                     ui.add_space(normal_text_style.size);
 
+                    // Whether we play or spectate is sent to the other peers once the match is
+                    // joined, so it can't change while joining, hosting or searching.
+                    ui.scope(|ui| {
+                        ui.set_enabled(*status == NetworkGameStatus::Idle);
+                        let spectate_label = format!(
+                            "{}: {}",
+                            localization.get("spectate"),
+                            localization.get(if *spectate { "on" } else { "off" })
+                        );
+                        if BorderedButton::themed(small_button_style, spectate_label)
+                            .show(ui)
+                            .clicked()
+                        {
+                            *spectate = !*spectate;
+                        }
+                    });
+                    ui.add_space(normal_text_style.size / 2.0);
+
                     match match_kind {
                         // LAN game
                         MatchKind::Lan(mode) => match mode {
@@ -280,6 +377,7 @@ pub fn network_game_menu(
 
                                     ui.indent("servers", |ui| {
                                         for server in lan_servers.iter() {
+                                            let spectator_slots = server_slots(server, SPECTATORS_PROPERTY);
                                             ui.horizontal(|ui| {
                                                 if BorderedButton::themed(
                                                     &meta.theme.buttons.normal,
@@ -294,6 +392,13 @@ pub fn network_game_menu(
                                                     *status = NetworkGameStatus::Joining;
                                                 }
 
+                                                if let Some(players) = server.service.get_property_val_str(PLAYERS_PROPERTY) {
+                                                    ui.label(normal_text_style.rich(format!("🐟 {players}")));
+                                                }
+                                                if spectator_slots > 0 {
+                                                    ui.label(normal_text_style.rich(format!("👁 {spectator_slots}")));
+                                                }
+
                                                 let label_text = egui::RichText::new(format!(
                                                     "🖧 {}ms",
                                                     server
@@ -322,7 +427,7 @@ pub fn network_game_menu(
                                     );
 
                                     if let Some(lan_socket) = lan::wait_game_start() {
-                                        world.resources.insert(lan_socket);
+                                        join_network_match(world, lan_socket, *spectate);
                                         *status = default();
                                        ui.ctx().set_state(MenuPage::PlayerSelect);
                                     }
@@ -333,6 +438,7 @@ pub fn network_game_menu(
                             LanMode::Host {
                                 service_name,
                                 player_count,
+                                spectator_count,
                             } => {
                                 ui.scope(|ui| {
                                     ui.set_enabled(*status != NetworkGameStatus::Hosting);
@@ -381,6 +487,16 @@ pub fn network_game_menu(
 
                                         *service_name = service_name.replace(' ', "-");
                                     });
+                                    ui.add_space(normal_text_style.size / 2.0);
+                                    ui.horizontal(|ui| {
+                                        spectator_count_buttons(
+                                            ui,
+                                            &localization,
+                                            normal_text_style,
+                                            small_button_style,
+                                            spectator_count,
+                                        );
+                                    });
                                 });
 
                                 let (is_recreated, service_info) = RUNTIME.block_on(async {
@@ -401,13 +517,21 @@ pub fn network_game_menu(
                                     .clicked()
                                     {
                                         *status = NetworkGameStatus::Hosting;
-                                        lan::start_server(service_info.clone(), *player_count);
+                                        service_info.service = advertise_slots(
+                                            &service_info.service,
+                                            *player_count,
+                                            *spectator_count,
+                                        );
+                                        lan::start_server(
+                                            service_info.clone(),
+                                            *player_count + *spectator_count,
+                                        );
                                     }
 
                                 // If we are hosting a match currently
                                 } else if *status == NetworkGameStatus::Hosting {
                                     if let Some(socket) = lan::wait_players(joined_players, service_info) {
-                                        world.resources.insert(socket);
+                                        join_network_match(world, socket, *spectate);
                                         *status = default();
                                         ui.ctx().set_state(MenuPage::PlayerSelect);
                                     }
@@ -424,13 +548,15 @@ pub fn network_game_menu(
                                             *status = NetworkGameStatus::Idle;
                                         }
 
+                                        // Add one to count the host. Which of the peers spectate
+                                        // is only known once they are in the match.
                                         ui.label(
                                             normal_text_style.rich(
                                             format!(
                                                 "{} {} / {}",
-                                                localization.get("players"),
-                                                *joined_players + 1, // Add one to count the host.
-                                                player_count
+                                                localization.get("joined"),
+                                                *joined_players + 1,
+                                                *player_count + *spectator_count
                                             ))
                                         );
                                     });
@@ -441,6 +567,7 @@ pub fn network_game_menu(
                         // Online game
                         MatchKind::Online(OnlineState {
                             player_count,
+                            spectator_count,
                             matchmaking_server,
                         }) => {
                             // Get the matchmaking server from the settings.
@@ -482,6 +609,16 @@ pub fn network_game_menu(
                                     }
                                 });
                             });
+                            ui.horizontal(|ui| {
+                                ui.set_enabled(*status == NetworkGameStatus::Idle);
+                                spectator_count_buttons(
+                                    ui,
+                                    &localization,
+                                    normal_text_style,
+                                    small_button_style,
+                                    spectator_count,
+                                );
+                            });
 
                             ui.add_space(normal_text_style.size);
 
@@ -496,8 +633,10 @@ pub fn network_game_menu(
                                     {
                                         *status = NetworkGameStatus::Matchmaking(MatchmakingStatus::Connecting);
                                         let server = matchmaking_server.parse().expect("invalid server id");
-                                        let custom_match_data = vec![];
-                                        OnlineMatchmaker::start_search_for_match(server, GAME_ID.to_string(), *player_count, custom_match_data, PlayerIdxAssignment::Random).unwrap();
+                                        // Only requests for the same number of spectator slots are matched together.
+                                        // Which peers spectate is sent in their handshake once the match is joined.
+                                        let custom_match_data = postcard::to_allocvec(spectator_count).unwrap();
+                                        OnlineMatchmaker::start_search_for_match(server, GAME_ID.to_string(), *player_count + *spectator_count, custom_match_data, PlayerIdxAssignment::Random).unwrap();
                                         info!("Connecting to matchmaker to search for match...");
                                     }
                                 },
@@ -515,7 +654,7 @@ pub fn network_game_menu(
                                                                 player_count: _,
                                                                 random_seed: new_random_seed,
                                             } => {
-                                                world.resources.insert(socket);
+                                                join_network_match(world, socket, *spectate);
                                                 *status = NetworkGameStatus::default();
                                                 *random_seed = new_random_seed;
                                                 ui.ctx().set_state(MenuPage::PlayerSelect);
@@ -555,7 +694,7 @@ pub fn network_game_menu(
                                                     localization.get_with("waiting-for-players",
                                                         &fluent_args! {
                                                             "current" => *joined_players,
-                                                            "total" => *player_count
+                                                            "total" => *player_count + *spectator_count
                                                         }).to_string()
                                                 },
                                                 _ => {
@@ -621,3 +760,101 @@ pub fn network_game_menu(
 
     });
 }
+
+/// Make the socket of a network match that was just joined available to the menus, along with
+/// whether the local peer wants to spectate it.
+fn join_network_match(world: &World, socket: NetworkMatchSocket, spectate: bool) {
+    // Messages and maps left over from a previous match mustn't be mistaken for ones of this one.
+    if let Some(messages) = world.get_resource::<ReliableMessages>() {
+        messages.clear();
+    }
+    world.resources.insert(MapDownloads::default());
+    world
+        .resources
+        .insert(NetworkSpectators::new(&socket, spectate));
+    world.resources.insert(socket);
+}
+
+/// Show the buttons that change the number of spectator slots of a match.
+fn spectator_count_buttons(
+    ui: &mut egui::Ui,
+    localization: &Localization<GameMeta>,
+    text_style: &FontMeta,
+    button_style: &ButtonThemeMeta,
+    spectator_count: &mut u32,
+) {
+    ui.label(text_style.rich(localization.get("spectator-count")));
+    ui.add_space(text_style.size);
+    ui.scope(|ui| {
+        ui.set_enabled(*spectator_count > 0);
+        if BorderedButton::themed(button_style, "-")
+            .min_size(vec2(text_style.size * 2.0, 0.0))
+            .show(ui)
+            .clicked()
+        {
+            *spectator_count = spectator_count.saturating_sub(1);
+        }
+    });
+    ui.label(text_style.rich(spectator_count.to_string()));
+    ui.scope(|ui| {
+        ui.set_enabled(*spectator_count < MAX_SPECTATORS);
+        if BorderedButton::themed(button_style, "+")
+            .min_size(vec2(text_style.size * 2.0, 0.0))
+            .show(ui)
+            .clicked()
+        {
+            *spectator_count = (*spectator_count + 1).min(MAX_SPECTATORS);
+        }
+    });
+}
+
+/// Get the number of slots that a LAN server advertises in the given service property, or zero if
+/// it doesn't advertise any.
+fn server_slots(server: &lan::ServerInfo, property: &str) -> u32 {
+    server
+        .service
+        .get_property_val_str(property)
+        .and_then(|slots| slots.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Add the number of player and spectator slots of a LAN server to the properties of its service.
+fn advertise_slots(
+    service: &mdns_sd::ServiceInfo,
+    player_count: u32,
+    spectator_count: u32,
+) -> mdns_sd::ServiceInfo {
+    let mut properties: std::collections::HashMap<String, String> = service
+        .get_properties()
+        .iter()
+        .map(|property| (property.key().to_string(), property.val_str().to_string()))
+        .collect();
+    properties.insert(PLAYERS_PROPERTY.to_string(), player_count.to_string());
+    properties.insert(SPECTATORS_PROPERTY.to_string(), spectator_count.to_string());
+
+    let instance_name = service
+        .get_fullname()
+        .trim_end_matches(service.get_type())
+        .trim_end_matches('.');
+    let addresses = service
+        .get_addresses()
+        .iter()
+        .map(|address| address.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    match mdns_sd::ServiceInfo::new(
+        service.get_type(),
+        instance_name,
+        service.get_hostname(),
+        addresses.as_str(),
+        service.get_port(),
+        properties,
+    ) {
+        Ok(advertised) if service.is_addr_auto() => advertised.enable_addr_auto(),
+        Ok(advertised) => advertised,
+        Err(e) => {
+            warn!("Could not advertise the slots of the LAN server: {e}");
+            service.clone()
+        }
+    }
+}
@@ -2,8 +2,19 @@ use std::borrow::BorrowMut;
 
 use crate::prelude::*;
 
+/// How long the toast about a player that left the match is shown for.
+const PLAYER_LEFT_TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// The toasts shown about players that left the match and were handed to the AI.
+#[derive(Clone, Default)]
+struct PlayerLeftToasts {
+    /// The players that left, and when the toast about them was first shown.
+    toasts: Vec<(PlayerIdx, Instant)>,
+}
+
 #[allow(unused_variables)]
 pub fn session_plugin(session: &mut SessionBuilder) {
+    session.add_system_to_stage(Update, round_timer);
     #[cfg(not(target_arch = "wasm32"))]
     session.add_system_to_stage(Update, network_disconnect_notify);
 }
@@ -39,6 +50,8 @@ pub fn network_disconnect_notify(
         };
     }
 
+    world.run_system(player_left_toasts, &*sessions);
+
     if all_players_disconnected {
         egui::CentralPanel::default()
             .frame(egui::Frame::none())
@@ -68,6 +81,59 @@ pub fn network_disconnect_notify(
     }
 }
 
+/// Show a toast for every player that left the match and was handed to the AI.
+fn player_left_toasts(
+    sessions: In<&Sessions>,
+    ctx: Res<EguiCtx>,
+    meta: Root<GameMeta>,
+    localization: Localization<GameMeta>,
+) {
+    let mut state = ctx.get_state::<PlayerLeftToasts>();
+    let now = Instant::now();
+
+    match sessions
+        .get(SessionNames::GAME)
+        .and_then(|session| session.world.resources.get::<DisconnectedPlayerTakeover>())
+    {
+        Some(takeover) => {
+            for player in &takeover.replaced {
+                if !state.toasts.iter().any(|(toasted, _)| toasted == player) {
+                    state.toasts.push((*player, now));
+                }
+            }
+        }
+        // Forget the toasts once the match is over.
+        None if sessions.get(SessionNames::GAME).is_none() => state.toasts.clear(),
+        None => (),
+    }
+
+    let visible_toasts = state
+        .toasts
+        .iter()
+        .filter(|(_, shown_at)| now.duration_since(*shown_at) < PLAYER_LEFT_TOAST_DURATION)
+        .map(|(player, _)| *player)
+        .collect::<Vec<_>>();
+    if !visible_toasts.is_empty() {
+        egui::Area::new("player_left_toasts")
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 20.0))
+            .interactable(false)
+            .show(&ctx, |ui| {
+                for player in visible_toasts {
+                    BorderedFrame::new(&meta.theme.panel.border)
+                        .padding(meta.theme.panel.padding)
+                        .show(ui, |ui| {
+                            ui.label(meta.theme.font_styles.normal.rich(localization.get_with(
+                                "player-left-ai-takeover",
+                                &fluent_args! { "player" => player.0 + 1 },
+                            )));
+                        });
+                }
+            });
+    }
+
+    ctx.set_state(state);
+}
+
 fn network_disconnect_notify_ui(
     mut param: In<(&mut egui::Ui, &mut Sessions)>,
     localization: Localization<GameMeta>,
@@ -103,3 +169,45 @@ fn network_disconnect_notify_ui(
         }
     });
 }
+
+/// Show the time left in the round when it has a time limit, and when it went into sudden death.
+fn round_timer(
+    ctx: Res<EguiCtx>,
+    sessions: Res<Sessions>,
+    meta: Root<GameMeta>,
+    localization: Localization<GameMeta>,
+) {
+    let Some(game_session) = sessions.get(SessionNames::GAME) else {
+        return;
+    };
+    let world = &game_session.world;
+    let (Some(rules), Some(state)) = (
+        world.get_resource::<MatchRules>(),
+        world.get_resource::<RoundRulesState>(),
+    ) else {
+        return;
+    };
+    let text = match state.phase {
+        RoundPhase::Normal => match state.time_left(&rules) {
+            Some(time_left) => {
+                // Round up, so that the timer shows `0:00` only once the time is up.
+                let secs = time_left.as_secs() + u64::from(time_left.subsec_nanos() > 0);
+                format!("{}:{:02}", secs / 60, secs % 60)
+            }
+            None => return,
+        },
+        RoundPhase::SuddenDeath => localization.get("sudden-death").to_string(),
+        RoundPhase::TimeUp { .. } => localization.get("time-up").to_string(),
+    };
+
+    egui::Area::new("round_timer")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-20.0, 20.0))
+        .interactable(false)
+        .show(&ctx, |ui| {
+            BorderedFrame::new(&meta.theme.panel.border)
+                .padding(meta.theme.panel.padding)
+                .show(ui, |ui| {
+                    ui.label(meta.theme.font_styles.heading.rich(text));
+                });
+        });
+}
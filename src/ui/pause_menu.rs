@@ -1,7 +1,10 @@
 use std::ops::Deref;
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::spectate::Spectating;
 use crate::{core::JumpyDefaultMatchRunner, prelude::*};
 
+use super::editor::open_map_editor;
 use super::scoring::ScoringMenuState;
 
 #[derive(Clone, Debug, Copy, Default)]
@@ -34,6 +37,7 @@ fn pause_menu_system(
     let mut restart_game = false;
     let mut close_pause_menu = false;
     let mut close_settings_menu = false;
+    let mut open_editor = false;
     let mut select_map = None;
     if let Some(session) = sessions.get_mut(SessionNames::GAME) {
         let pause_pressed = controls.values().any(|x| x.pause_just_pressed);
@@ -42,7 +46,8 @@ fn pause_menu_system(
         let is_online = session
             .world
             .get_resource::<SyncingInfo>()
-            .map_or(false, |x| x.is_online());
+            .map_or(false, |x| x.is_online())
+            || session.world.get_resource::<Spectating>().is_some();
 
         #[cfg(target_arch = "wasm32")]
         let is_online = false;
@@ -78,6 +83,7 @@ fn pause_menu_system(
                                             &mut restart_game,
                                             &mut back_to_menu,
                                             &mut close_pause_menu,
+                                            &mut open_editor,
                                             is_online,
                                         ),
                                     );
@@ -129,13 +135,10 @@ fn pause_menu_system(
         sessions.restart_game(None, false);
         pause_menu.menu_open = false;
     } else if let Some(maps) = select_map {
-        let match_info = sessions
-            .get(SessionNames::GAME)
-            .unwrap()
-            .world
-            .resource::<MatchInputs>()
-            .deref()
-            .clone();
+        let game_world = &sessions.get(SessionNames::GAME).unwrap().world;
+        let match_info = game_world.resource::<MatchInputs>().deref().clone();
+        let rules = *game_world.resource::<MatchRules>();
+        let random_seed = **game_world.resource::<RandomSeed>();
         sessions.end_game();
         sessions.create_with(SessionNames::GAME, |builder| {
             builder.install_plugin(crate::core::MatchPlugin {
@@ -148,11 +151,18 @@ fn pause_menu_system(
                 plugins: meta.get_plugins(&assets),
                 session_runner: Box::<JumpyDefaultMatchRunner>::default(),
                 score: default(),
+                rules,
+                random_seed,
             });
         });
         pause_menu.menu_open = false;
     }
 
+    if open_editor {
+        open_map_editor(&mut sessions);
+        pause_menu.menu_open = false;
+    }
+
     if close_pause_menu {
         pause_menu.menu_open = false;
     }
@@ -169,6 +179,7 @@ fn main_pause_menu(
         &mut bool,
         &mut bool,
         &mut bool,
+        &mut bool,
         bool,
     )>,
     meta: Root<GameMeta>,
@@ -176,7 +187,8 @@ fn main_pause_menu(
     controls: Res<GlobalPlayerControls>,
     scoring_menu: Res<ScoringMenuState>,
 ) {
-    let (ui, session, restart_game, back_to_menu, close_pause_menu, is_online) = &mut *param;
+    let (ui, session, restart_game, back_to_menu, close_pause_menu, open_editor, is_online) =
+        &mut *param;
 
     // Unpause the game
     if controls.values().any(|x| x.pause_just_pressed) {
@@ -255,20 +267,29 @@ fn main_pause_menu(
             }
         });
 
-        // Re-add edit button once map editor is back in game.
-        //
-        // Edit button
-        // ui.scope(|ui| {
-        //     if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("edit"))
-        //         .min_size(vec2(width, 0.0))
-        //         .show(ui)
-        //         .clicked()
-        //     {
-        //         // TODO: show editor.
-        //         pause_session(false, *is_online, session, false);
-        //         **close_pause_menu = true;
-        //     }
-        // });
+        // Edit button, the map editor only edits the local map.
+        if !*is_online
+            && BorderedButton::themed(&meta.theme.buttons.normal, localization.get("edit"))
+                .min_size(vec2(width, 0.0))
+                .show(ui)
+                .clicked()
+        {
+            pause_session(false, *is_online, session, false);
+            **open_editor = true;
+        }
+
+        // Save replay button
+        #[cfg(not(target_arch = "wasm32"))]
+        if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("save-replay"))
+            .min_size(vec2(width, 0.0))
+            .show(ui)
+            .clicked()
+        {
+            match crate::replay::Replay::from_world(&session.world).save() {
+                Ok(path) => info!("Saved replay to {}", path.display()),
+                Err(e) => error!("Could not save replay: {e}"),
+            }
+        }
 
         // Main menu button
         if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("main-menu"))
@@ -4,9 +4,15 @@ use std::ops::Deref;
 #[cfg(not(target_arch = "wasm32"))]
 use bones_framework::networking::{socket::Socket, NetworkSocket, SocketTarget, SyncingInfo};
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    network_messages::{ReliableMessage, ReliableMessages},
+    spectate::Spectating,
+};
+
 use crate::prelude::*;
 
-use super::player_image::player_image;
+use super::{main_menu::MenuPage, player_image::player_image};
 
 pub fn session_plugin(session: &mut SessionBuilder) {
     session.add_system_to_stage(Update, scoring_menu_system);
@@ -22,6 +28,8 @@ pub struct ScoringMenuState {
     pub ready_players: HashSet<PlayerIdx>,
     pub match_score: MatchScore,
     pub next_maps: Option<MapPool>,
+    /// Players that left from the match complete screen, so a rematch is no longer possible.
+    pub left_players: HashSet<PlayerIdx>,
 }
 
 impl ScoringMenuState {
@@ -35,45 +43,52 @@ struct PlayerScoreInfo {
     pub entity: Entity,
     pub player_idx: PlayerIdx,
     pub score: u32,
+    pub stats: PlayerStats,
+    /// The element the player killed the most other players with, and how many.
+    pub favorite_element: Option<(Handle<ElementMeta>, u32)>,
 }
 
-const SCORING_MESSAGE_MAGIC: u8 = 183;
-
+/// Network message sent by the scoring menu.
 #[derive(Serialize, Deserialize)]
-struct ScoringMessage {
-    pub data: ScoringMessageEnum,
-    pub magic: u8,
-}
-
-#[derive(Serialize, Deserialize)]
-enum ScoringMessageEnum {
+pub enum ScoringMessage {
     PlayerReady(u32),
-}
-
-impl From<ScoringMessageEnum> for ScoringMessage {
-    fn from(value: ScoringMessageEnum) -> Self {
-        Self {
-            data: value,
-            magic: SCORING_MESSAGE_MAGIC,
-        }
-    }
+    /// The player has left the match from the match complete screen.
+    PlayerLeft(u32),
 }
 
 /// The width of each player panel
 const PLAYER_PANEL_WIDTH: f32 = 200.0;
 
+/// The smallest gap between player panels, panels are narrowed to keep it when there are many
+/// players.
+const MIN_PLAYER_PANEL_SPACING: f32 = 10.0;
+
 /// If Player won the match or is tied for win
 enum PlayerWon {
     Tied,
+    /// Won the match alone, or together with their team.
     SoleWinner,
 }
 
+/// The choice made by a local player on the match complete screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum MatchCompleteAction {
+    #[default]
+    None,
+    /// Ready up for another match with the same players.
+    Rematch,
+    /// Go back to player select, or to the network game menu in network play.
+    ChangePlayers,
+    MainMenu,
+}
+
 fn scoring_menu_system(
     meta: Root<GameMeta>,
     mut sessions: ResMut<Sessions>,
     ctx: Res<EguiCtx>,
     mut state: ResMut<ScoringMenuState>,
     controls: Res<GlobalPlayerControls>,
+    #[cfg(not(target_arch = "wasm32"))] messages: ResInit<ReliableMessages>,
     world: &World,
 ) {
     if !state.active {
@@ -84,6 +99,7 @@ fn scoring_menu_system(
 
     let mut continue_game = false;
     let mut game_won = false;
+    let mut action = MatchCompleteAction::None;
     if let Some(session) = sessions.get_mut(SessionNames::GAME) {
         let player_indices = session.world.components.get::<PlayerIdx>();
         let player_indices_ref = player_indices.borrow();
@@ -94,7 +110,18 @@ fn scoring_menu_system(
         let network_socket: Option<Socket> = session
             .world
             .get_resource::<SyncingInfo>()
-            .and_then(|x| x.socket().cloned());
+            .and_then(|x| x.socket().cloned())
+            .or_else(|| {
+                session
+                    .world
+                    .get_resource::<Spectating>()
+                    .map(|spectating| spectating.socket.clone())
+            });
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let is_online = network_socket.is_some();
+        #[cfg(target_arch = "wasm32")]
+        let is_online = false;
 
         // Build Vec<PlayerScoreInfo> sorted by player indices
         let mut player_entities: Vec<(Entity, &PlayerIdx)> =
@@ -106,29 +133,22 @@ fn scoring_menu_system(
                 entity: x.0,
                 player_idx: *x.1,
                 score: state.match_score.score(*x.1),
+                stats: state.match_score.stats(*x.1),
+                favorite_element: state.match_score.element_kills(*x.1).first().copied(),
             })
             .collect();
 
         // Contains any players who have broken win threshold. If multiple players have winning scores,
         // contains player with highest score. If players are tied, tied players are all included.
-        let mut winning_players = Vec::<PlayerIdx>::default();
-
-        let win_threshold = meta.core.config.winning_score_threshold;
-        let mut highest_score = 0;
-
-        for score_info in player_score_info.iter() {
-            let score = score_info.score;
-            if score >= win_threshold && score >= highest_score {
-                if score > highest_score {
-                    // New winning player, clear prev winners
-                    winning_players.clear();
-                    highest_score = score;
-                }
-
-                winning_players.push(score_info.player_idx);
-            }
-        }
-        if winning_players.len() == 1 {
+        let winning_players = state
+            .match_score
+            .leading_players(meta.core.config.winning_score_threshold);
+        // The game is won by a single player, or by a team whose players are all tied.
+        if state
+            .match_score
+            .winners(meta.core.config.winning_score_threshold, &match_inputs)
+            .is_some()
+        {
             game_won = true;
         }
 
@@ -140,19 +160,10 @@ fn scoring_menu_system(
                         && !state.ready_players.contains(*player_idx)
                     {
                         #[cfg(not(target_arch = "wasm32"))]
-                        {
-                            if let Some(socket) = network_socket.as_ref() {
-                                socket.send_reliable(
-                                    SocketTarget::All,
-                                    &postcard::to_allocvec(&ScoringMessage::from(
-                                        ScoringMessageEnum::PlayerReady(player_idx.0),
-                                    ))
-                                    .unwrap(),
-                                );
-
-                                debug!("Send message local player {} ready", player_idx.0);
-                            }
-                        }
+                        send_scoring_message(
+                            network_socket.as_ref(),
+                            ScoringMessage::PlayerReady(player_idx.0),
+                        );
 
                         state.ready_players.insert(**player_idx);
                     }
@@ -162,7 +173,7 @@ fn scoring_menu_system(
 
         #[cfg(not(target_arch = "wasm32"))]
         if let Some(socket) = network_socket.as_ref() {
-            handle_scoring_messages(socket, &mut state);
+            handle_scoring_messages(socket, &messages, &mut state);
         }
 
         // Check if all non-ai players are ready
@@ -177,7 +188,8 @@ fn scoring_menu_system(
                 all_players_ready = false;
             }
         }
-        if all_players_ready {
+        // There is no rematch if someone left.
+        if all_players_ready && state.left_players.is_empty() {
             continue_game = true;
         }
 
@@ -191,7 +203,7 @@ fn scoring_menu_system(
                 let player_panel_spacing = 20.0;
                 let max_width =
                     (PLAYER_PANEL_WIDTH + player_panel_spacing) * player_score_info.len() as f32;
-                let x_margin = (screen_rect.width() - max_width) / 2.0;
+                let x_margin = ((screen_rect.width() - max_width) / 2.0).max(0.0);
 
                 // x margin is dynamic, y margin percentage of screen
                 let outer_margin =
@@ -202,7 +214,7 @@ fn scoring_menu_system(
                         .margin(outer_margin)
                         .padding(meta.theme.panel.padding)
                         .show(ui, |ui| {
-                            world.run_system(
+                            action = world.run_system(
                                 scoring_menu,
                                 (
                                     ui,
@@ -210,17 +222,72 @@ fn scoring_menu_system(
                                     &*match_inputs,
                                     &state,
                                     &winning_players,
+                                    is_online,
                                 ),
                             );
                         });
                 });
             });
+
+        match action {
+            MatchCompleteAction::None => (),
+            MatchCompleteAction::Rematch => {
+                // Ready up all of the local players at once.
+                for (_, player_idx) in player_entities.iter() {
+                    if match_inputs
+                        .get_control_source(player_idx.0 as usize)
+                        .is_some()
+                        && !state.ready_players.contains(*player_idx)
+                    {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        send_scoring_message(
+                            network_socket.as_ref(),
+                            ScoringMessage::PlayerReady(player_idx.0),
+                        );
+
+                        state.ready_players.insert(**player_idx);
+                    }
+                }
+            }
+            MatchCompleteAction::ChangePlayers | MatchCompleteAction::MainMenu => {
+                // Let the other players know that we won't be there for a rematch.
+                #[cfg(not(target_arch = "wasm32"))]
+                for (_, player_idx) in player_entities.iter() {
+                    if match_inputs
+                        .get_control_source(player_idx.0 as usize)
+                        .is_some()
+                    {
+                        send_scoring_message(
+                            network_socket.as_ref(),
+                            ScoringMessage::PlayerLeft(player_idx.0),
+                        );
+                    }
+                }
+
+                let menu_page = match action {
+                    // A network match can't change its players, so find a new match instead.
+                    MatchCompleteAction::ChangePlayers if is_online => MenuPage::NetworkGame,
+                    MatchCompleteAction::ChangePlayers => MenuPage::PlayerSelect,
+                    _ => MenuPage::Home,
+                };
+                ctx.set_state(menu_page);
+            }
+        }
     } else {
         error!("Scoring menu failed to load score from existing game session. Closing scoring UI.");
         state.reset();
     }
 
-    if continue_game {
+    if matches!(
+        action,
+        MatchCompleteAction::ChangePlayers | MatchCompleteAction::MainMenu
+    ) {
+        state.reset();
+        sessions.add_command(Box::new(|sessions: &mut Sessions| {
+            sessions.end_game();
+            sessions.start_menu();
+        }));
+    } else if continue_game {
         state.reset();
         let next_maps = state.next_maps.clone();
         let reset_score = game_won;
@@ -237,19 +304,26 @@ fn scoring_menu(
         &MatchInputs,
         &ScoringMenuState,
         &Vec<PlayerIdx>,
+        bool,
     )>,
     meta: Root<GameMeta>,
     localization: Localization<GameMeta>,
     world: &World,
-) {
-    let (ui, player_score_info, match_inputs, menu_state, winning_players) = &mut *param;
+) -> MatchCompleteAction {
+    let (ui, player_score_info, match_inputs, menu_state, winning_players, is_online) = &mut *param;
+    let match_complete = menu_state
+        .match_score
+        .winners(meta.core.config.winning_score_threshold, match_inputs)
+        .is_some();
 
     // Scoring heading label
     ui.vertical_centered(|ui| {
-        let (text, color) = match winning_players.len() {
-            0 => ("intermission", meta.theme.panel.font_color),
-            1 => ("match-complete", meta.theme.colors.positive),
-            _ => ("tied-for-win", meta.theme.panel.font_color),
+        let (text, color) = if match_complete {
+            ("match-complete", meta.theme.colors.positive)
+        } else if winning_players.is_empty() {
+            ("intermission", meta.theme.panel.font_color)
+        } else {
+            ("tied-for-win", meta.theme.panel.font_color)
         };
 
         ui.label(
@@ -259,51 +333,146 @@ fn scoring_menu(
                 .rich(localization.get(text))
                 .color(color),
         );
+
+        if match_complete {
+            ui.label(
+                meta.theme
+                    .font_styles
+                    .normal
+                    .rich(localization.get_with(
+                        "rounds-played",
+                        &fluent_args! {
+                            "rounds" => menu_state.match_score.rounds_completed()
+                        },
+                    ))
+                    .color(meta.theme.panel.font_color),
+            );
+        }
     });
 
+    // Once the match is complete, the players are shown on a podium from first to last place.
+    let mut ordered_players = player_score_info.iter().collect::<Vec<_>>();
+    if match_complete {
+        ordered_players.sort_by_key(|info| std::cmp::Reverse(info.score));
+    }
+
     ui.vertical_centered(|ui| {
         ui.horizontal_centered(|ui| {
-            let player_count = player_score_info.len();
-            let available_spacing = ui.available_width() - PLAYER_PANEL_WIDTH * player_count as f32;
+            let player_count = ordered_players.len();
+            let panel_width = ((ui.available_width()
+                - MIN_PLAYER_PANEL_SPACING * (player_count + 1) as f32)
+                / player_count as f32)
+                .min(PLAYER_PANEL_WIDTH);
+            let available_spacing = ui.available_width() - panel_width * player_count as f32;
 
             // Compute how much space to use in gaps between panels
             let spacing = available_spacing / (player_count + 1) as f32;
             ui.add_space(spacing);
-            for (i, _) in player_score_info.iter().enumerate() {
-                let player_input = match_inputs.players.get(i).unwrap();
-                let player_score_info = player_score_info.get(i).unwrap();
-                let player_idx = player_score_info.player_idx;
+            for info in ordered_players.iter().copied() {
+                let player_idx = info.player_idx;
+                let player_input = match_inputs.players.get(player_idx.0 as usize).unwrap();
 
                 let ready = menu_state.ready_players.contains(&player_idx);
-                let player_won =
-                    if winning_players.contains(&player_idx) && winning_players.len() == 1 {
-                        Some(PlayerWon::SoleWinner)
-                    } else if winning_players.contains(&player_idx) {
-                        Some(PlayerWon::Tied)
-                    } else {
-                        None
-                    };
+                let player_won = if winning_players.contains(&player_idx) && match_complete {
+                    Some(PlayerWon::SoleWinner)
+                } else if winning_players.contains(&player_idx) {
+                    Some(PlayerWon::Tied)
+                } else {
+                    None
+                };
+
+                // Players with the same score share a place on the podium.
+                let place = match_complete.then(|| {
+                    1 + player_score_info
+                        .iter()
+                        .filter(|other| other.score > info.score)
+                        .count()
+                });
 
                 world.run_system(
                     player_score_panel,
-                    (ui, player_input, player_score_info, ready, player_won),
+                    (
+                        ui,
+                        player_input,
+                        info,
+                        ready,
+                        player_won,
+                        place,
+                        panel_width,
+                    ),
                 );
                 ui.add_space(spacing);
             }
         });
     });
 
-    let match_complete = winning_players.len() == 1;
-    ui.horizontal(|ui| {
-        if match_complete {
+    let mut action = MatchCompleteAction::None;
+    if match_complete {
+        if !menu_state.left_players.is_empty() {
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    meta.theme
+                        .font_styles
+                        .normal
+                        .rich(localization.get("player-left-match"))
+                        .color(meta.theme.colors.negative),
+                );
+            });
+        }
+
+        ui.add_space(meta.theme.font_styles.normal.size / 2.0);
+        ui.horizontal(|ui| {
+            let button_width = ui.available_width() / 3.0 - ui.spacing().item_spacing.x;
+
+            ui.add_enabled_ui(menu_state.left_players.is_empty(), |ui| {
+                if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("rematch"))
+                    .min_size(vec2(button_width, 0.0))
+                    .show(ui)
+                    .focus_by_default(ui)
+                    .clicked()
+                {
+                    action = MatchCompleteAction::Rematch;
+                }
+            });
+
+            if BorderedButton::themed(
+                &meta.theme.buttons.normal,
+                localization.get("change-players"),
+            )
+            .min_size(vec2(button_width, 0.0))
+            .show(ui)
+            .clicked()
+            {
+                action = MatchCompleteAction::ChangePlayers;
+            }
+
+            if BorderedButton::themed(&meta.theme.buttons.normal, localization.get("main-menu"))
+                .min_size(vec2(button_width, 0.0))
+                .show(ui)
+                .clicked()
+            {
+                action = MatchCompleteAction::MainMenu;
+            }
+        });
+
+        ui.label(
+            meta.theme
+                .font_styles
+                .normal
+                .rich(localization.get("press-confirm-to-play-again"))
+                .color(meta.theme.panel.font_color),
+        );
+        if *is_online {
             ui.label(
                 meta.theme
                     .font_styles
                     .normal
-                    .rich(localization.get("press-confirm-to-play-again"))
+                    .rich(localization.get("rematch-waits-for-all-players"))
                     .color(meta.theme.panel.font_color),
             );
-        } else {
+        }
+    } else {
+        ui.horizontal(|ui| {
             ui.label(
                 meta.theme
                     .font_styles
@@ -311,8 +480,10 @@ fn scoring_menu(
                     .rich(localization.get("press-confirm-to-ready-up"))
                     .color(meta.theme.panel.font_color),
             );
-        }
-    });
+        });
+    }
+
+    action
 }
 
 fn player_score_panel(
@@ -322,21 +493,23 @@ fn player_score_panel(
         &PlayerScoreInfo,
         bool,
         Option<PlayerWon>,
+        Option<usize>,
+        f32,
     )>,
     meta: Root<GameMeta>,
     assets: Res<AssetServer>,
     localization: Localization<GameMeta>,
     world: &World,
 ) {
-    let (ui, player_input, player_score_info, ready, won) = &mut *params;
+    let (ui, player_input, player_score_info, ready, won, place, panel_width) = &mut *params;
     let panel = &meta.theme.panel;
 
     BorderedFrame::new(&panel.border)
         .padding(panel.padding)
         .show(ui, |ui| {
-            // PLAYER_PANEL_WIDTH is total space for entire bordered frame, remove space lost to padding
+            // panel_width is total space for entire bordered frame, remove space lost to padding
             // and use this for inner contents.
-            ui.set_width(PLAYER_PANEL_WIDTH - panel.padding.left - panel.padding.right);
+            ui.set_width(*panel_width - panel.padding.left - panel.padding.right);
 
             ui.vertical_centered(|ui| {
                 ui.label(
@@ -351,6 +524,21 @@ fn player_score_panel(
                         .color(meta.theme.panel.font_color),
                 );
 
+                if let Some(place) = place {
+                    ui.label(
+                        meta.theme
+                            .font_styles
+                            .heading
+                            .rich(localization.get_with(
+                                "podium-place",
+                                &fluent_args! {
+                                    "place" => *place
+                                },
+                            ))
+                            .color(meta.theme.panel.font_color),
+                    );
+                }
+
                 match won {
                     Some(won) => {
                         let text = match won {
@@ -381,12 +569,50 @@ fn player_score_panel(
                         .bigger
                         .rich(format!(
                             "{}: {}",
-                            localization.get("score"),
+                            localization.get(if place.is_some() {
+                                "rounds-won"
+                            } else {
+                                "score"
+                            }),
                             player_score_info.score,
                         ))
                         .color(meta.theme.panel.font_color),
                 );
 
+                let stat_font = meta
+                    .theme
+                    .font_styles
+                    .normal
+                    .with_color(meta.theme.panel.font_color);
+                let stats = &player_score_info.stats;
+                ui.label(stat_font.rich(localization.get_with(
+                    "kills-deaths",
+                    &fluent_args! {
+                        "kills" => stats.kills,
+                        "deaths" => stats.deaths
+                    },
+                )));
+
+                // Show the full breakdown once the match is complete.
+                if place.is_some() {
+                    for (label, value) in [
+                        ("suicides", stats.suicides),
+                        ("items-picked-up", stats.items_picked_up),
+                        ("shots-fired", stats.shots_fired),
+                    ] {
+                        ui.label(stat_font.rich(format!("{}: {value}", localization.get(label))));
+                    }
+                    if let Some((element, kills)) = player_score_info.favorite_element {
+                        ui.label(stat_font.rich(localization.get_with(
+                            "favorite-element",
+                            &fluent_args! {
+                                "element" => assets.get(element).name.to_string(),
+                                "kills" => kills
+                            },
+                        )));
+                    }
+                }
+
                 if !player_input.is_ai {
                     let (ready_str, color) = match *ready {
                         true => ("ready", meta.theme.colors.positive),
@@ -416,22 +642,37 @@ fn player_score_panel(
 #[cfg(not(target_arch = "wasm32"))]
 fn handle_scoring_messages(
     network_socket: &(impl NetworkSocket + ?Sized),
+    messages: &ReliableMessages,
     state: &mut ScoringMenuState,
 ) {
     // TODO handle disconnects
-    let datas: Vec<(u32, Vec<u8>)> = network_socket.recv_reliable();
+    messages.receive(network_socket.recv_reliable());
     let local_player_idx = network_socket.player_idx();
-    for (_, data) in datas {
-        match postcard::from_bytes::<ScoringMessage>(&data) {
-            Ok(message) => match message.data {
-                ScoringMessageEnum::PlayerReady(player) => {
-                    if message.magic == SCORING_MESSAGE_MAGIC && player != local_player_idx {
-                        state.ready_players.insert(PlayerIdx(player));
-                        debug!("Received message player {} ready", player);
-                    }
+    for (_, message) in messages.take_scoring() {
+        match message {
+            ScoringMessage::PlayerReady(player) => {
+                if player != local_player_idx {
+                    state.ready_players.insert(PlayerIdx(player));
+                    debug!("Received message player {} ready", player);
+                }
+            }
+            ScoringMessage::PlayerLeft(player) => {
+                if player != local_player_idx {
+                    state.left_players.insert(PlayerIdx(player));
+                    debug!("Received message player {} left", player);
                 }
-            },
-            Err(e) => warn!("Ignoring network message that was not understood: {e}"),
+            }
         }
     }
 }
+
+/// Send a scoring message to the other players, if in network play.
+#[cfg(not(target_arch = "wasm32"))]
+fn send_scoring_message(network_socket: Option<&Socket>, message: ScoringMessage) {
+    if let Some(socket) = network_socket {
+        socket.send_reliable(
+            SocketTarget::All,
+            &ReliableMessage::Scoring(message).to_bytes(),
+        );
+    }
+}